@@ -0,0 +1,148 @@
+//! Prints the unwind information that framehop uses for an address in a binary.
+//!
+//! Usage: `cargo run --example dump_unwind_info --features rule-table -- <path> [<address>]`,
+//! where `<address>` is a hexadecimal address as shown by a disassembler for that
+//! binary. Without an address, the unwind rule for every address in the text section is
+//! printed as a tab-separated table, for diffing across framehop versions.
+
+use std::ops::Range;
+
+use framehop::aarch64::{CacheAarch64, UnwinderAarch64};
+use framehop::x86_64::{CacheX86_64, UnwinderX86_64};
+use framehop::{FrameAddress, Module, ModuleSvmaInfo, ModuleUnwindData, TextByteData, Unwinder};
+use object::{Architecture, Object, ObjectSection, ObjectSegment};
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let path = match args.next() {
+        Some(path) => path,
+        None => {
+            eprintln!("Usage: dump_unwind_info <path> [<address>]");
+            std::process::exit(1);
+        }
+    };
+    let address = args.next().map(|address| {
+        match u64::from_str_radix(address.trim_start_matches("0x"), 16) {
+            Ok(address) => FrameAddress::from_instruction_pointer(address),
+            Err(err) => {
+                eprintln!("Invalid address {address}: {err}");
+                std::process::exit(1);
+            }
+        }
+    });
+    let data = std::fs::read(&path).expect("Could not read the file");
+    let file = object::File::parse(&data[..]).expect("Could not parse the object file");
+    let base_svma = base_svma(&file);
+    let module = module_for_object(&path, &file, base_svma, data.len() as u64);
+
+    let mut out = String::new();
+    match file.architecture() {
+        Architecture::X86_64 => {
+            let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+            unwinder.add_module(module);
+            let mut cache = CacheX86_64::new();
+            match address {
+                Some(address) => unwinder.dump_unwind_info(address, &mut cache, &mut out),
+                None => unwinder.write_rule_table(base_svma, &mut cache, &mut out),
+            }
+        }
+        Architecture::Aarch64 => {
+            let mut unwinder = UnwinderAarch64::<Vec<u8>>::new();
+            unwinder.add_module(module);
+            let mut cache = CacheAarch64::new();
+            match address {
+                Some(address) => unwinder.dump_unwind_info(address, &mut cache, &mut out),
+                None => unwinder.write_rule_table(base_svma, &mut cache, &mut out),
+            }
+        }
+        architecture => {
+            eprintln!("Unsupported architecture {architecture:?}");
+            std::process::exit(1);
+        }
+    }
+    .expect("Writing to a String doesn't fail");
+    print!("{out}");
+}
+
+/// The address that relative addresses are based on: the `__TEXT` segment address for
+/// mach-O, and the image base otherwise.
+fn base_svma<'data: 'file, 'file>(file: &'file object::File<'data>) -> u64 {
+    match file
+        .segments()
+        .find(|segment| segment.name() == Ok(Some("__TEXT")))
+    {
+        Some(text_segment) => text_segment.address(),
+        None => file.relative_address_base(),
+    }
+}
+
+/// Creates a module which is mapped at its own SVMAs, so that addresses from a
+/// disassembler can be used directly.
+fn module_for_object<'data: 'file, 'file>(
+    path: &str,
+    file: &'file object::File<'data>,
+    base_svma: u64,
+    len: u64,
+) -> Module<Vec<u8>> {
+    fn section_data<'a>(section: &impl ObjectSection<'a>) -> Option<Vec<u8>> {
+        section.data().ok().map(|data| data.to_owned())
+    }
+    fn svma_range<'a>(section: &Option<impl ObjectSection<'a>>) -> Option<Range<u64>> {
+        section
+            .as_ref()
+            .map(|section| section.address()..section.address() + section.size())
+    }
+
+    let text = file.section_by_name(".text");
+    let stubs = file.section_by_name("__stubs");
+    let stub_helper = file.section_by_name("__stub_helper");
+    let text_env = file.section_by_name("__text_env");
+    let unwind_info = file.section_by_name("__unwind_info");
+    let eh_frame = file.section_by_name(".eh_frame");
+    let eh_frame_hdr = file.section_by_name(".eh_frame_hdr");
+    let debug_frame = file.section_by_name(".debug_frame");
+    let got = file.section_by_name(".got");
+
+    let unwind_data = match (
+        unwind_info.as_ref().and_then(section_data),
+        eh_frame.as_ref().and_then(section_data),
+        eh_frame_hdr.as_ref().and_then(section_data),
+        debug_frame.as_ref().and_then(section_data),
+    ) {
+        (Some(unwind_info), eh_frame, _, _) => {
+            ModuleUnwindData::CompactUnwindInfoAndEhFrame(unwind_info, eh_frame)
+        }
+        (None, Some(eh_frame), Some(eh_frame_hdr), _) => {
+            ModuleUnwindData::EhFrameHdrAndEhFrame(eh_frame_hdr, eh_frame)
+        }
+        (None, Some(eh_frame), None, _) => ModuleUnwindData::EhFrame(eh_frame),
+        (None, None, _, Some(debug_frame)) => ModuleUnwindData::DebugFrame(debug_frame),
+        (None, None, _, None) => ModuleUnwindData::None,
+    };
+
+    // The code bytes let framehop recognize signal trampolines.
+    let text_data = text.as_ref().and_then(|text| {
+        let data = text.data().ok()?.to_owned();
+        let start = text.address();
+        Some(TextByteData::new(data, start..start + text.size()))
+    });
+
+    Module::new(
+        path.to_string(),
+        base_svma..base_svma + len,
+        base_svma,
+        ModuleSvmaInfo {
+            base_svma,
+            text: svma_range(&text),
+            text_env: svma_range(&text_env),
+            stubs: svma_range(&stubs),
+            stub_helper: svma_range(&stub_helper),
+            eh_frame: svma_range(&eh_frame),
+            eh_frame_hdr: svma_range(&eh_frame_hdr),
+            got: svma_range(&got),
+            address_size: if file.is_64() { 8 } else { 4 },
+        },
+        unwind_data,
+        text_data,
+    )
+}
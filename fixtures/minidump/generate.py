@@ -0,0 +1,133 @@
+#!/usr/bin/env python3
+"""Generate linux-x86_64.dmp, a minimal minidump of one x86_64 Linux thread.
+
+The dump has a system info stream, one module without a CodeView record, one thread
+whose context is stopped in that module, and the thread's stack memory. The stack
+holds a chain of frame records:
+
+    rbp = 0x7ff010: caller rbp 0x7ff040, return address 0x100300
+          0x7ff040: caller rbp 0x7ff070, return address 0x100200
+          0x7ff070: caller rbp 0,        return address 0
+"""
+
+import os
+import struct
+
+MODULE_NAME = "/usr/lib/libexample.so"
+MODULE_BASE = 0x100000
+MODULE_SIZE = 0x1000
+THREAD_ID = 1234
+RIP = 0x100400
+RSP = 0x7FF000
+RBP = 0x7FF010
+STACK_START = 0x7FF000
+STACK_SIZE = 0x100
+
+STREAM_THREAD_LIST = 3
+STREAM_MODULE_LIST = 4
+STREAM_MEMORY_LIST = 5
+STREAM_SYSTEM_INFO = 7
+
+PROCESSOR_ARCHITECTURE_AMD64 = 9
+PLATFORM_LINUX = 0x8201
+CONTEXT_AMD64_CONTROL_INTEGER = 0x100000 | 0x1 | 0x2
+CONTEXT_AMD64_SIZE = 0x4D0
+
+
+def stack_bytes():
+    slots = [0] * (STACK_SIZE // 8)
+
+    def frame_record(address, rbp, return_address):
+        slots[(address - STACK_START) // 8] = rbp
+        slots[(address - STACK_START) // 8 + 1] = return_address
+
+    frame_record(0x7FF010, 0x7FF040, 0x100300)
+    frame_record(0x7FF040, 0x7FF070, 0x100200)
+    frame_record(0x7FF070, 0, 0)
+    return struct.pack("<%dQ" % len(slots), *slots)
+
+
+def context_bytes():
+    context = bytearray(CONTEXT_AMD64_SIZE)
+    struct.pack_into("<I", context, 0x30, CONTEXT_AMD64_CONTROL_INTEGER)
+    struct.pack_into("<Q", context, 0x98, RSP)
+    struct.pack_into("<Q", context, 0xA0, RBP)
+    struct.pack_into("<Q", context, 0xF8, RIP)
+    return bytes(context)
+
+
+def minidump_string(s):
+    utf16 = s.encode("utf-16-le")
+    return struct.pack("<I", len(utf16)) + utf16 + b"\0\0"
+
+
+class Writer:
+    def __init__(self):
+        self.data = bytearray()
+
+    def append(self, data):
+        """Append data at a 4-byte aligned offset and return its location."""
+        self.data += b"\0" * (-len(self.data) % 4)
+        rva = len(self.data)
+        self.data += data
+        return len(data), rva
+
+
+def main():
+    w = Writer()
+    header_size = 32
+    stream_count = 4
+    w.append(b"\0" * (header_size + 12 * stream_count))
+
+    stack_location = w.append(stack_bytes())
+    context_location = w.append(context_bytes())
+    name_rva = w.append(minidump_string(MODULE_NAME))[1]
+
+    system_info = struct.pack(
+        "<HHHBBIIIIIHH24x",
+        PROCESSOR_ARCHITECTURE_AMD64,
+        6,  # ProcessorLevel
+        0,  # ProcessorRevision
+        1,  # NumberOfProcessors
+        0,  # ProductType
+        0,  # MajorVersion
+        0,  # MinorVersion
+        0,  # BuildNumber
+        PLATFORM_LINUX,
+        0,  # CSDVersionRva
+        0,  # SuiteMask
+        0,  # Reserved2
+    )
+    stack_descriptor = struct.pack("<QII", STACK_START, *stack_location)
+    thread = (
+        struct.pack("<IIIIQ", THREAD_ID, 0, 0, 0, 0)
+        + stack_descriptor
+        + struct.pack("<II", *context_location)
+    )
+    module = (
+        struct.pack("<QIIII", MODULE_BASE, MODULE_SIZE, 0, 0, name_rva)
+        + b"\0" * 52  # VersionInfo
+        + struct.pack("<IIIIQQ", 0, 0, 0, 0, 0, 0)  # CvRecord, MiscRecord, Reserved
+    )
+
+    streams = [
+        (STREAM_SYSTEM_INFO, w.append(system_info)),
+        (STREAM_THREAD_LIST, w.append(struct.pack("<I", 1) + thread)),
+        (STREAM_MODULE_LIST, w.append(struct.pack("<I", 1) + module)),
+        (STREAM_MEMORY_LIST, w.append(struct.pack("<I", 1) + stack_descriptor)),
+    ]
+    assert len(streams) == stream_count
+
+    struct.pack_into(
+        "<IIIIIIQ", w.data, 0, 0x504D444D, 0xA793, stream_count, header_size, 0, 0, 0
+    )
+    for i, (stream_type, (size, rva)) in enumerate(streams):
+        struct.pack_into("<III", w.data, header_size + 12 * i, stream_type, size, rva)
+
+    path = os.path.join(os.path.dirname(os.path.abspath(__file__)), "linux-x86_64.dmp")
+    with open(path, "wb") as f:
+        f.write(w.data)
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,84 @@
+use framehop::aarch64::{CacheAarch64, UnwindRegsAarch64, UnwinderAarch64};
+use framehop::x86_64::{CacheX86_64, UnwindRegsX86_64, UnwinderX86_64};
+use framehop::{Module, ModuleSvmaInfo, ModuleUnwindData, TextByteData, Unwinder};
+
+/// The address at which the fuzzed module is mapped in the fake process.
+pub const BASE_AVMA: u64 = 0x10_0000;
+
+/// Stop after this many frames, in case the fuzzer finds a stack that never ends.
+const MAX_FRAMES: usize = 64;
+
+/// Splits the fuzzer input into the module-relative address of the first frame and
+/// the remaining bytes.
+pub fn split_input(data: &[u8]) -> Option<(u32, &[u8])> {
+    if data.len() < 4 {
+        return None;
+    }
+    let (pc_offset, rest) = data.split_at(4);
+    let pc_offset = u32::from_le_bytes([pc_offset[0], pc_offset[1], pc_offset[2], pc_offset[3]]);
+    Some((pc_offset % 0x1_0000, rest))
+}
+
+/// Creates a module that covers `BASE_AVMA..BASE_AVMA + 0x1_0000`, and whose text
+/// section contains `text`.
+pub fn make_module(
+    unwind_data: ModuleUnwindData<Vec<u8>>,
+    text: &[u8],
+    unwind_section: Option<std::ops::Range<u64>>,
+) -> Module<Vec<u8>> {
+    let text_len = text.len() as u64;
+    Module::new(
+        "fuzzed".to_string(),
+        BASE_AVMA..BASE_AVMA + 0x1_0000,
+        BASE_AVMA,
+        ModuleSvmaInfo {
+            base_svma: 0,
+            text: Some(0..text_len),
+            text_env: None,
+            stubs: None,
+            stub_helper: None,
+            eh_frame: unwind_section,
+            eh_frame_hdr: None,
+            got: None,
+            address_size: 8,
+        },
+        unwind_data,
+        Some(TextByteData::new(
+            text.to_vec(),
+            BASE_AVMA..BASE_AVMA + text_len,
+        )),
+    )
+}
+
+/// Unwinds a stack starting at `BASE_AVMA + pc_offset` with both the x86_64 and the
+/// aarch64 unwinder. The stack contents are made up from the addresses that are read,
+/// so that the unwinder keeps going for a few frames.
+pub fn unwind_both_archs(pc_offset: u32, make_module: impl Fn() -> Module<Vec<u8>>) {
+    let pc = BASE_AVMA + u64::from(pc_offset);
+    let mut read_stack = |addr: u64| match addr % 16 {
+        0 => Ok(addr.wrapping_add(0x40)),
+        _ => Ok(BASE_AVMA + (addr % 0x1_0000)),
+    };
+
+    let mut unwinder = UnwinderX86_64::new();
+    unwinder.add_module(make_module());
+    let mut cache = CacheX86_64::<_>::new();
+    let regs = UnwindRegsX86_64::new(pc, 0x7000_0000, 0x7000_0100);
+    let mut iter = unwinder.iter_frames(pc, regs, &mut cache, &mut read_stack);
+    for _ in 0..MAX_FRAMES {
+        if !matches!(iter.next(), Ok(Some(_))) {
+            break;
+        }
+    }
+
+    let mut unwinder = UnwinderAarch64::new();
+    unwinder.add_module(make_module());
+    let mut cache = CacheAarch64::<_>::new();
+    let regs = UnwindRegsAarch64::new(pc, 0x7000_0000, 0x7000_0100);
+    let mut iter = unwinder.iter_frames(pc, regs, &mut cache, &mut read_stack);
+    for _ in 0..MAX_FRAMES {
+        if !matches!(iter.next(), Ok(Some(_))) {
+            break;
+        }
+    }
+}
@@ -0,0 +1,21 @@
+#![no_main]
+
+mod common;
+
+use framehop::ModuleUnwindData;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((pc_offset, unwind_info)) = common::split_input(data) else {
+        return;
+    };
+    // Also use the input as the text section, so that instruction analysis of
+    // prologues and epilogues is exercised on garbage bytes too.
+    common::unwind_both_archs(pc_offset, || {
+        common::make_module(
+            ModuleUnwindData::CompactUnwindInfoAndEhFrame(unwind_info.to_vec(), None),
+            unwind_info,
+            None,
+        )
+    });
+});
@@ -0,0 +1,19 @@
+#![no_main]
+
+mod common;
+
+use framehop::ModuleUnwindData;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((pc_offset, debug_frame)) = common::split_input(data) else {
+        return;
+    };
+    common::unwind_both_archs(pc_offset, || {
+        common::make_module(
+            ModuleUnwindData::DebugFrame(debug_frame.to_vec()),
+            &[],
+            None,
+        )
+    });
+});
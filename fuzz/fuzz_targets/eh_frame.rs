@@ -0,0 +1,19 @@
+#![no_main]
+
+mod common;
+
+use framehop::ModuleUnwindData;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((pc_offset, eh_frame)) = common::split_input(data) else {
+        return;
+    };
+    common::unwind_both_archs(pc_offset, || {
+        common::make_module(
+            ModuleUnwindData::EhFrame(eh_frame.to_vec()),
+            &[],
+            Some(0x2_0000..0x2_0000 + eh_frame.len() as u64),
+        )
+    });
+});
@@ -0,0 +1,24 @@
+#![no_main]
+
+mod common;
+
+use framehop::ModuleUnwindData;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((pc_offset, rest)) = common::split_input(data) else {
+        return;
+    };
+    // The first byte decides where the .eh_frame_hdr ends and the .eh_frame begins.
+    let Some((&hdr_len, rest)) = rest.split_first() else {
+        return;
+    };
+    let (eh_frame_hdr, eh_frame) = rest.split_at(usize::from(hdr_len).min(rest.len()));
+    common::unwind_both_archs(pc_offset, || {
+        common::make_module(
+            ModuleUnwindData::EhFrameHdrAndEhFrame(eh_frame_hdr.to_vec(), eh_frame.to_vec()),
+            &[],
+            Some(0x2_0000..0x2_0000 + eh_frame.len() as u64),
+        )
+    });
+});
@@ -1,6 +1,7 @@
 use super::unwind_rule::UnwindRuleAarch64;
 use super::unwindregs::UnwindRegsAarch64;
 use crate::arch::Arch;
+use crate::fpo::FpoUnwinding;
 
 /// The Aarch64 CPU architecture.
 pub struct ArchAarch64;
@@ -8,3 +9,6 @@ impl Arch for ArchAarch64 {
     type UnwindRule = UnwindRuleAarch64;
     type UnwindRegs = UnwindRegsAarch64;
 }
+
+// FPO data only exists for 32-bit x86 code.
+impl FpoUnwinding for ArchAarch64 {}
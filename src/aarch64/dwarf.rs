@@ -27,6 +27,7 @@ impl DwarfUnwinding for ArchAarch64 {
     fn unwind_frame<F, R, S>(
         unwind_info: &UnwindTableRow<R, S>,
         encoding: Encoding,
+        max_expression_steps: u32,
         regs: &mut Self::UnwindRegs,
         is_first_frame: bool,
         read_stack: &mut F,
@@ -48,7 +49,7 @@ impl DwarfUnwinding for ArchAarch64 {
             }
         }
 
-        let cfa = eval_cfa_rule::<R, _, S>(cfa_rule, encoding, regs)
+        let cfa = eval_cfa_rule::<R, _, S>(cfa_rule, encoding, max_expression_steps, regs)
             .ok_or(DwarfUnwinderError::CouldNotRecoverCfa)?;
 
         let lr = regs.lr();
@@ -59,19 +60,51 @@ impl DwarfUnwinding for ArchAarch64 {
             if cfa <= sp {
                 return Err(DwarfUnwinderError::StackPointerMovedBackwards);
             }
-            let fp = eval_register_rule::<R, F, _, S>(fp_rule, cfa, encoding, fp, regs, read_stack)
-                .ok_or(DwarfUnwinderError::CouldNotRecoverFramePointer)?;
-            let lr = eval_register_rule::<R, F, _, S>(lr_rule, cfa, encoding, lr, regs, read_stack)
-                .ok_or(DwarfUnwinderError::CouldNotRecoverReturnAddress)?;
+            let fp = eval_register_rule::<R, F, _, S>(
+                fp_rule,
+                cfa,
+                encoding,
+                max_expression_steps,
+                fp,
+                regs,
+                read_stack,
+            )
+            .ok_or(DwarfUnwinderError::CouldNotRecoverFramePointer)?;
+            let lr = eval_register_rule::<R, F, _, S>(
+                lr_rule,
+                cfa,
+                encoding,
+                max_expression_steps,
+                lr,
+                regs,
+                read_stack,
+            )
+            .ok_or(DwarfUnwinderError::CouldNotRecoverReturnAddress)?;
             (fp, lr)
         } else {
             // For the first frame, be more lenient when encountering errors.
             // TODO: Find evidence of what this gives us. I think on macOS the prologue often has Unknown register rules
             // and we only encounter prologues for the first frame.
-            let fp = eval_register_rule::<R, F, _, S>(fp_rule, cfa, encoding, fp, regs, read_stack)
-                .unwrap_or(fp);
-            let lr = eval_register_rule::<R, F, _, S>(lr_rule, cfa, encoding, lr, regs, read_stack)
-                .unwrap_or(lr);
+            let fp = eval_register_rule::<R, F, _, S>(
+                fp_rule,
+                cfa,
+                encoding,
+                max_expression_steps,
+                fp,
+                regs,
+                read_stack,
+            )
+            .unwrap_or(fp);
+            let lr = eval_register_rule::<R, F, _, S>(
+                lr_rule,
+                cfa,
+                encoding,
+                max_expression_steps,
+                lr,
+                regs,
+                read_stack,
+            )
+            .unwrap_or(lr);
             (fp, lr)
         };
 
@@ -82,6 +115,10 @@ impl DwarfUnwinding for ArchAarch64 {
         Ok(UnwindResult::Uncacheable(lr))
     }
 
+    fn register_name(register: Register) -> Option<&'static str> {
+        AArch64::register_name(register)
+    }
+
     fn rule_if_uncovered_by_fde() -> Self::UnwindRule {
         UnwindRuleAarch64::NoOpIfFirstFrameOtherwiseFp
     }
@@ -110,7 +147,22 @@ fn translate_into_unwind_rule<R: gimli::Reader>(
                 let lr_cfa_offset = register_rule_to_cfa_offset(lr_rule)?;
                 let fp_cfa_offset = register_rule_to_cfa_offset(fp_rule)?;
                 match (lr_cfa_offset, fp_cfa_offset) {
-                    (None, Some(_)) => Err(ConversionError::RestoringFpButNotLr),
+                    (None, Some(fp_cfa_offset)) => {
+                        // fp was saved as an ordinary callee-saved register, and the
+                        // return address is still in lr. That only holds for the first
+                        // frame, so the rule fails with DidNotAdvance in other frames.
+                        if let RegisterRule::Undefined = lr_rule {
+                            return Err(ConversionError::RestoringFpButNotLr);
+                        }
+                        let fp_storage_offset_from_sp_by_8 = offset
+                            .checked_add(fp_cfa_offset)
+                            .and_then(|fp_offset| i16::try_from(fp_offset / 8).ok())
+                            .ok_or(ConversionError::FpStorageOffsetDoesNotFit)?;
+                        Ok(UnwindRuleAarch64::OffsetSpAndRestoreFp {
+                            sp_offset_by_16,
+                            fp_storage_offset_from_sp_by_8,
+                        })
+                    }
                     (None, None) => {
                         if let RegisterRule::Undefined = lr_rule {
                             // If the return address is undefined, this could have two reasons:
@@ -132,21 +184,24 @@ fn translate_into_unwind_rule<R: gimli::Reader>(
                         }
                     }
                     (Some(lr_cfa_offset), None) => {
-                        let lr_storage_offset_from_sp_by_8 =
-                            i16::try_from((offset + lr_cfa_offset) / 8)
-                                .map_err(|_| ConversionError::LrStorageOffsetDoesNotFit)?;
+                        let lr_storage_offset_from_sp_by_8 = offset
+                            .checked_add(lr_cfa_offset)
+                            .and_then(|lr_offset| i16::try_from(lr_offset / 8).ok())
+                            .ok_or(ConversionError::LrStorageOffsetDoesNotFit)?;
                         Ok(UnwindRuleAarch64::OffsetSpAndRestoreLr {
                             sp_offset_by_16,
                             lr_storage_offset_from_sp_by_8,
                         })
                     }
                     (Some(lr_cfa_offset), Some(fp_cfa_offset)) => {
-                        let lr_storage_offset_from_sp_by_8 =
-                            i16::try_from((offset + lr_cfa_offset) / 8)
-                                .map_err(|_| ConversionError::LrStorageOffsetDoesNotFit)?;
-                        let fp_storage_offset_from_sp_by_8 =
-                            i16::try_from((offset + fp_cfa_offset) / 8)
-                                .map_err(|_| ConversionError::FpStorageOffsetDoesNotFit)?;
+                        let lr_storage_offset_from_sp_by_8 = offset
+                            .checked_add(lr_cfa_offset)
+                            .and_then(|lr_offset| i16::try_from(lr_offset / 8).ok())
+                            .ok_or(ConversionError::LrStorageOffsetDoesNotFit)?;
+                        let fp_storage_offset_from_sp_by_8 = offset
+                            .checked_add(fp_cfa_offset)
+                            .and_then(|fp_offset| i16::try_from(fp_offset / 8).ok())
+                            .ok_or(ConversionError::FpStorageOffsetDoesNotFit)?;
                         Ok(UnwindRuleAarch64::OffsetSpAndRestoreFpAndLr {
                             sp_offset_by_16,
                             fp_storage_offset_from_sp_by_8,
@@ -165,12 +220,14 @@ fn translate_into_unwind_rule<R: gimli::Reader>(
                 } else {
                     let sp_offset_from_fp_by_8 = u16::try_from(offset / 8)
                         .map_err(|_| ConversionError::SpOffsetFromFpDoesNotFit)?;
-                    let lr_storage_offset_from_fp_by_8 =
-                        i16::try_from((offset + lr_cfa_offset) / 8)
-                            .map_err(|_| ConversionError::LrStorageOffsetDoesNotFit)?;
-                    let fp_storage_offset_from_fp_by_8 =
-                        i16::try_from((offset + fp_cfa_offset) / 8)
-                            .map_err(|_| ConversionError::FpStorageOffsetDoesNotFit)?;
+                    let lr_storage_offset_from_fp_by_8 = offset
+                        .checked_add(lr_cfa_offset)
+                        .and_then(|lr_offset| i16::try_from(lr_offset / 8).ok())
+                        .ok_or(ConversionError::LrStorageOffsetDoesNotFit)?;
+                    let fp_storage_offset_from_fp_by_8 = offset
+                        .checked_add(fp_cfa_offset)
+                        .and_then(|fp_offset| i16::try_from(fp_offset / 8).ok())
+                        .ok_or(ConversionError::FpStorageOffsetDoesNotFit)?;
                     Ok(UnwindRuleAarch64::UseFramepointerWithOffsets {
                         sp_offset_from_fp_by_8,
                         fp_storage_offset_from_fp_by_8,
@@ -183,3 +240,56 @@ fn translate_into_unwind_rule<R: gimli::Reader>(
         CfaRule::Expression(_) => Err(ConversionError::CfaIsExpression),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::Error;
+    use crate::test_utils::TestStack;
+    use crate::unwind_rule::UnwindRule;
+    use gimli::{EndianSlice, LittleEndian};
+
+    type R = EndianSlice<'static, LittleEndian>;
+
+    #[test]
+    fn test_restore_fp_but_not_lr() {
+        // After `stp x29, x19, [sp, #-0x10]!`: the CFA is sp + 16 and fp is at CFA - 16.
+        let cfa_rule = CfaRule::<R>::RegisterAndOffset {
+            register: AArch64::SP,
+            offset: 16,
+        };
+        let fp_rule = RegisterRule::<R>::Offset(-16);
+        let rule = translate_into_unwind_rule(&cfa_rule, &fp_rule, &RegisterRule::SameValue);
+        let rule = rule.unwrap();
+        assert_eq!(
+            rule,
+            UnwindRuleAarch64::OffsetSpAndRestoreFp {
+                sp_offset_by_16: 1,
+                fp_storage_offset_from_sp_by_8: 0,
+            }
+        );
+
+        // In the first frame, lr is the return address.
+        let stack = TestStack::from([0x40, 0x1234, 0, 0]);
+        let mut read_stack = |addr| stack.read(addr);
+        let mut regs = UnwindRegsAarch64::new(0x100300, 0x0, 0x20);
+        assert_eq!(
+            rule.exec(true, &mut regs, &mut read_stack),
+            Ok(Some(0x100300))
+        );
+        assert_eq!(regs.sp(), 0x10);
+        assert_eq!(regs.fp(), 0x40);
+
+        // In other frames, lr is the return address of the callee, which points into
+        // this function, so the rule can't find the return address.
+        let mut regs = UnwindRegsAarch64::new(0x100300, 0x0, 0x20);
+        assert_eq!(
+            rule.exec(false, &mut regs, &mut read_stack),
+            Err(Error::DidNotAdvance)
+        );
+
+        // An undefined return address can't be taken from lr.
+        let rule = translate_into_unwind_rule(&cfa_rule, &fp_rule, &RegisterRule::Undefined);
+        assert!(matches!(rule, Err(ConversionError::RestoringFpButNotLr)));
+    }
+}
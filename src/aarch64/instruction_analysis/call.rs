@@ -0,0 +1,38 @@
+/// Returns the length of the call instruction which ends at `return_address_offset`,
+/// if the instruction before it is a `bl`, `blr`, or one of the authenticating `blr`
+/// variants. All aarch64 instructions are 4 bytes long.
+pub fn call_instruction_len_before(
+    text_bytes: &[u8],
+    return_address_offset: usize,
+) -> Option<usize> {
+    let start = return_address_offset.checked_sub(4)?;
+    let word = u32::from_le_bytes(text_bytes[start..return_address_offset].try_into().ok()?);
+    // bl imm26
+    let is_bl = word & 0xfc00_0000 == 0x9400_0000;
+    // blr xN
+    let is_blr = word & 0xffff_fc1f == 0xd63f_0000;
+    // blraa, blraaz, blrab, blrabz
+    let is_blra = word & 0xfeff_f800 == 0xd63f_0800;
+    if is_bl || is_blr || is_blra {
+        Some(4)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_call_instruction_len() {
+        // bl 0x1234; blr x8; mov x29, sp
+        let bytes = [
+            0x8d, 0x04, 0x00, 0x94, 0x00, 0x01, 0x3f, 0xd6, 0xfd, 0x03, 0x00, 0x91,
+        ];
+        assert_eq!(call_instruction_len_before(&bytes, 4), Some(4));
+        assert_eq!(call_instruction_len_before(&bytes, 8), Some(4));
+        assert_eq!(call_instruction_len_before(&bytes, 12), None);
+        assert_eq!(call_instruction_len_before(&bytes, 2), None);
+    }
+}
@@ -0,0 +1,120 @@
+use super::super::unwind_rule::UnwindRuleAarch64;
+
+/// The lazy binding trampoline of the dynamic linker, e.g. glibc's
+/// `_dl_runtime_resolve`, is entered from PLT0, which stored x16 and lr below sp with
+/// `stp x16, x30, [sp, #-16]!`. The trampoline saves a large register block below
+/// that, calls the resolver with `bl`, which overwrites lr, and restores everything
+/// before it branches to the resolved function:
+///
+/// ```plain
+/// stp    x8, x9, [sp, #-0xd0]!
+/// stp    x6, x7, [sp, #0x10]
+/// ...
+/// bl     _dl_fixup
+/// ...
+/// ldp    x8, x9, [sp], #0xd0
+/// ldp    x16, x30, [sp], #16
+/// br     x16
+/// ```
+///
+/// All instructions are 4 bytes long, so the stack offset and whether lr is still on
+/// the stack are found by decoding from the start of the function up to `pc_offset`.
+/// Only writeback loads and stores and immediate adds and subtracts change sp;
+/// anything else that writes sp makes this return `None`. `function_bytes` starts at
+/// the start of the trampoline.
+pub fn unwind_rule_from_dl_trampoline(
+    function_bytes: &[u8],
+    pc_offset: usize,
+) -> Option<UnwindRuleAarch64> {
+    if !pc_offset.is_multiple_of(4) {
+        return None;
+    }
+    // The bytes between sp and the CFA, which is the sp before PLT0.
+    let mut sp_offset: i64 = 16;
+    let mut lr_on_stack = true;
+    for word in function_bytes.get(..pc_offset)?.chunks_exact(4) {
+        let word = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+        let rd = word & 0x1f;
+        let rn = (word >> 5) & 0x1f;
+        let rt2 = (word >> 10) & 0x1f;
+        // Sign-extended.
+        let imm7 = (i64::from((word >> 15) & 0x7f) << 57) >> 57;
+        let imm12_shift = if word & (1 << 22) != 0 { 12 } else { 0 };
+        let imm12 = i64::from((word >> 10) & 0xfff) << imm12_shift;
+        match word & 0xffc0_0000 {
+            // stp xt1, xt2, [sp, #imm]!
+            0xa980_0000 if rn == 31 => sp_offset -= imm7 * 8,
+            // ldp xt1, xt2, [sp], #imm
+            0xa8c0_0000 if rn == 31 => {
+                sp_offset -= imm7 * 8;
+                if rt2 == 30 {
+                    lr_on_stack = false;
+                }
+            }
+            // stp qt1, qt2, [sp, #imm]! and ldp qt1, qt2, [sp], #imm
+            0xad80_0000 | 0xacc0_0000 if rn == 31 => sp_offset -= imm7 * 16,
+            // sub sp, sp, #imm and add sp, sp, #imm
+            0xd100_0000 | 0xd140_0000 if rd == 31 && rn == 31 => sp_offset += imm12,
+            0x9100_0000 | 0x9140_0000 if rd == 31 && rn == 31 => sp_offset -= imm12,
+            // Other adds and subtracts with sp as the destination, e.g. `mov sp, x0`.
+            0xd100_0000 | 0xd140_0000 | 0x9100_0000 | 0x9140_0000 if rd == 31 => return None,
+            _ => {}
+        }
+    }
+    if sp_offset < 0 || sp_offset % 16 != 0 {
+        return None;
+    }
+    let sp_offset_by_16 = u16::try_from(sp_offset / 16).ok()?;
+    if lr_on_stack {
+        Some(UnwindRuleAarch64::OffsetSpAndRestoreLr {
+            sp_offset_by_16,
+            lr_storage_offset_from_sp_by_8: i16::try_from((sp_offset - 8) / 8).ok()?,
+        })
+    } else if sp_offset_by_16 == 0 {
+        Some(UnwindRuleAarch64::NoOp)
+    } else {
+        Some(UnwindRuleAarch64::OffsetSp { sp_offset_by_16 })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_dl_runtime_resolve() {
+        #[rustfmt::skip]
+        let words: [u32; 7] = [
+            0xa9b327e8, // stp x8, x9, [sp, #-0xd0]!
+            0xa9011fe6, // stp x6, x7, [sp, #0x10]
+            0x94000000, // bl _dl_fixup
+            0xaa0003f0, // mov x16, x0
+            0xa8cd27e8, // ldp x8, x9, [sp], #0xd0
+            0xa8c17bf0, // ldp x16, x30, [sp], #0x10
+            0xd61f0200, // br x16
+        ];
+        let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+        let restore_lr = |sp_offset_by_16, lr_storage_offset_from_sp_by_8| {
+            Some(UnwindRuleAarch64::OffsetSpAndRestoreLr {
+                sp_offset_by_16,
+                lr_storage_offset_from_sp_by_8,
+            })
+        };
+        assert_eq!(unwind_rule_from_dl_trampoline(&bytes, 0), restore_lr(1, 1));
+        assert_eq!(
+            unwind_rule_from_dl_trampoline(&bytes, 4),
+            restore_lr(14, 27)
+        );
+        // The return address after the call to the resolver.
+        assert_eq!(
+            unwind_rule_from_dl_trampoline(&bytes, 12),
+            restore_lr(14, 27)
+        );
+        assert_eq!(unwind_rule_from_dl_trampoline(&bytes, 20), restore_lr(1, 1));
+        assert_eq!(
+            unwind_rule_from_dl_trampoline(&bytes, 24),
+            Some(UnwindRuleAarch64::NoOp)
+        );
+        assert_eq!(unwind_rule_from_dl_trampoline(&bytes, 2), None);
+    }
+}
@@ -356,7 +356,7 @@ impl EpilogueDetectorAarch64 {
             let reg_loc = if is_postindexed_writeback {
                 self.sp_offset
             } else {
-                self.sp_offset + imm7
+                self.sp_offset.saturating_add(imm7)
             };
             let pair_reg_1 = (word & 0b11111) as u16;
             if pair_reg_1 == 29 {
@@ -366,12 +366,12 @@ impl EpilogueDetectorAarch64 {
             }
             let pair_reg_2 = ((word >> 10) & 0b11111) as u16;
             if pair_reg_2 == 29 {
-                self.fp_offset_from_initial_sp = Some(reg_loc + 8);
+                self.fp_offset_from_initial_sp = Some(reg_loc.saturating_add(8));
             } else if pair_reg_2 == 30 {
-                self.lr_offset_from_initial_sp = Some(reg_loc + 8);
+                self.lr_offset_from_initial_sp = Some(reg_loc.saturating_add(8));
             }
             if is_preindexed_writeback || is_postindexed_writeback {
-                self.sp_offset += imm7;
+                self.sp_offset = self.sp_offset.saturating_add(imm7);
             }
             return EpilogueStepResult::NeedMore;
         }
@@ -390,7 +390,7 @@ impl EpilogueDetectorAarch64 {
             if shift_immediate_by_12 {
                 imm12 <<= 12
             }
-            self.sp_offset += imm12;
+            self.sp_offset = self.sp_offset.saturating_add(imm12);
             return EpilogueStepResult::NeedMore;
         }
         EpilogueStepResult::FoundBodyInstruction(UnexpectedInstructionType::Unknown)
@@ -418,7 +418,10 @@ pub fn unwind_rule_from_detected_epilogue(
                     sp_offset_by_16,
                     lr_storage_offset_from_sp_by_8: i16::try_from(lr_offset / 8).ok()?,
                 },
-                (Some(_), None) => return None,
+                (Some(fp_offset), None) => UnwindRuleAarch64::OffsetSpAndRestoreFp {
+                    sp_offset_by_16,
+                    fp_storage_offset_from_sp_by_8: i16::try_from(fp_offset / 8).ok()?,
+                },
                 (Some(fp_offset), Some(lr_offset)) => {
                     UnwindRuleAarch64::OffsetSpAndRestoreFpAndLr {
                         sp_offset_by_16,
@@ -1,11 +1,19 @@
 use super::arch::ArchAarch64;
 use crate::instruction_analysis::InstructionAnalysis;
 
+mod call;
+mod dl_trampoline;
 mod epilogue;
+mod plt;
 mod prologue;
+mod sigreturn;
 
+use call::call_instruction_len_before;
+use dl_trampoline::unwind_rule_from_dl_trampoline;
 use epilogue::unwind_rule_from_detected_epilogue;
+use plt::unwind_rule_from_plt;
 use prologue::unwind_rule_from_detected_prologue;
+use sigreturn::unwind_rule_from_detected_sigreturn_trampoline;
 
 impl InstructionAnalysis for ArchAarch64 {
     fn rule_from_prologue_analysis(
@@ -22,4 +30,26 @@ impl InstructionAnalysis for ArchAarch64 {
     ) -> Option<Self::UnwindRule> {
         unwind_rule_from_detected_epilogue(text_bytes, pc_offset)
     }
+
+    fn call_instruction_len(text_bytes: &[u8], return_address_offset: usize) -> Option<usize> {
+        call_instruction_len_before(text_bytes, return_address_offset)
+    }
+
+    fn rule_from_sigreturn_trampoline_analysis(
+        text_bytes: &[u8],
+        pc_offset: usize,
+    ) -> Option<Self::UnwindRule> {
+        unwind_rule_from_detected_sigreturn_trampoline(text_bytes, pc_offset)
+    }
+
+    fn rule_from_plt_analysis(plt_bytes: &[u8], pc_offset: usize) -> Option<Self::UnwindRule> {
+        unwind_rule_from_plt(plt_bytes, pc_offset)
+    }
+
+    fn rule_from_dl_trampoline_analysis(
+        function_bytes: &[u8],
+        pc_offset: usize,
+    ) -> Option<Self::UnwindRule> {
+        unwind_rule_from_dl_trampoline(function_bytes, pc_offset)
+    }
 }
@@ -0,0 +1,63 @@
+use super::super::unwind_rule::UnwindRuleAarch64;
+
+/// `bti c`, which starts PLT0 in binaries built with branch target identification.
+const BTI_C: u32 = 0xd503245f;
+
+/// `stp x16, x30, [sp, #-16]!`, the first instruction of PLT0.
+const STP_X16_X30_PRE_INDEX: u32 = 0xa9bf7bf0;
+
+/// PLT stubs don't set up a frame and leave lr alone, so the caller is found through
+/// lr. The exception is PLT0, the lazy binding stub at the start of `.plt`, which
+/// stores x16 and lr below sp before it jumps to the dynamic linker. `plt_bytes` starts
+/// at the start of the section.
+pub fn unwind_rule_from_plt(plt_bytes: &[u8], pc_offset: usize) -> Option<UnwindRuleAarch64> {
+    let word = |offset: usize| -> Option<u32> {
+        let bytes = plt_bytes.get(offset..offset + 4)?;
+        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    };
+    let stp_offset = if word(0)? == BTI_C { 4 } else { 0 };
+    // PLT0 is 32 bytes long.
+    if pc_offset > stp_offset && pc_offset < 32 && word(stp_offset)? == STP_X16_X30_PRE_INDEX {
+        return Some(UnwindRuleAarch64::OffsetSp { sp_offset_by_16: 1 });
+    }
+    Some(UnwindRuleAarch64::NoOp)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_plt() {
+        #[rustfmt::skip]
+        let bytes = [
+            // PLT0: stp x16, x30, [sp, #-16]!; adrp x16, ...; ldr x17, [x16, ...];
+            // add x16, x16, ...; br x17; nop; nop; nop
+            0xf0, 0x7b, 0xbf, 0xa9,
+            0x90, 0x00, 0x00, 0x90,
+            0x11, 0xfe, 0x47, 0xf9,
+            0x10, 0xe2, 0x3f, 0x91,
+            0x20, 0x02, 0x1f, 0xd6,
+            0x1f, 0x20, 0x03, 0xd5,
+            0x1f, 0x20, 0x03, 0xd5,
+            0x1f, 0x20, 0x03, 0xd5,
+            // PLT1: adrp x16, ...; ldr x17, [x16, ...]; add x16, x16, ...; br x17
+            0x90, 0x00, 0x00, 0xb0,
+            0x11, 0x02, 0x40, 0xf9,
+            0x10, 0x02, 0x00, 0x91,
+            0x20, 0x02, 0x1f, 0xd6,
+        ];
+        assert_eq!(
+            unwind_rule_from_plt(&bytes, 0),
+            Some(UnwindRuleAarch64::NoOp)
+        );
+        assert_eq!(
+            unwind_rule_from_plt(&bytes, 16),
+            Some(UnwindRuleAarch64::OffsetSp { sp_offset_by_16: 1 })
+        );
+        assert_eq!(
+            unwind_rule_from_plt(&bytes, 36),
+            Some(UnwindRuleAarch64::NoOp)
+        );
+    }
+}
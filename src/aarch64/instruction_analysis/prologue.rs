@@ -175,7 +175,7 @@ impl PrologueDetectorAarch64 {
             let is_postindexed_writeback = writeback_bits == 0b01; // TODO: are there postindexed stores? What do they mean?
             if is_preindexed_writeback || is_postindexed_writeback {
                 let imm7 = (((((word >> 15) & 0b1111111) as i16) << 9) >> 6) as i32;
-                self.sp_offset -= imm7; // - to undo the instruction
+                self.sp_offset = self.sp_offset.saturating_sub(imm7); // - to undo the instruction
             }
             return PrologueStepResult::ValidPrologueInstruction;
         }
@@ -195,7 +195,7 @@ impl PrologueDetectorAarch64 {
             if shift_immediate_by_12 {
                 imm12 <<= 12
             }
-            self.sp_offset += imm12; // + to undo the sub instruction
+            self.sp_offset = self.sp_offset.saturating_add(imm12); // + to undo the sub instruction
             return PrologueStepResult::ValidPrologueInstruction;
         }
         PrologueStepResult::UnexpectedInstruction(UnexpectedInstructionType::Unknown)
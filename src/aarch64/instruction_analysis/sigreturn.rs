@@ -0,0 +1,53 @@
+use super::super::unwind_rule::UnwindRuleAarch64;
+
+/// `mov x8, #139; svc #0`, the body of the vDSO's `__kernel_rt_sigreturn`.
+const KERNEL_RT_SIGRETURN: [u8; 8] = [0x68, 0x11, 0x80, 0xd2, 0x01, 0x00, 0x00, 0xd4];
+
+/// Offset of the `svc` instruction in `KERNEL_RT_SIGRETURN`.
+const SVC_OFFSET: usize = 4;
+
+pub fn unwind_rule_from_detected_sigreturn_trampoline(
+    text_bytes: &[u8],
+    pc_offset: usize,
+) -> Option<UnwindRuleAarch64> {
+    // The handler returns to the start of the trampoline. If we were interrupted
+    // in the trampoline itself, pc can also be on the svc instruction.
+    let at_start = text_bytes[pc_offset..].starts_with(&KERNEL_RT_SIGRETURN);
+    let at_svc = pc_offset
+        .checked_sub(SVC_OFFSET)
+        .is_some_and(|start| text_bytes[start..].starts_with(&KERNEL_RT_SIGRETURN));
+    if at_start || at_svc {
+        Some(UnwindRuleAarch64::RestoreFromLinuxSigframe)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_kernel_rt_sigreturn() {
+        let bytes = [
+            0x1f, 0x20, 0x03, 0xd5, 0x68, 0x11, 0x80, 0xd2, 0x01, 0x00, 0x00, 0xd4,
+        ];
+        let rule = Some(UnwindRuleAarch64::RestoreFromLinuxSigframe);
+        assert_eq!(
+            unwind_rule_from_detected_sigreturn_trampoline(&bytes, 0),
+            None
+        );
+        assert_eq!(
+            unwind_rule_from_detected_sigreturn_trampoline(&bytes, 4),
+            rule
+        );
+        assert_eq!(
+            unwind_rule_from_detected_sigreturn_trampoline(&bytes, 8),
+            rule
+        );
+        assert_eq!(
+            unwind_rule_from_detected_sigreturn_trampoline(&bytes, 12),
+            None
+        );
+    }
+}
@@ -63,6 +63,15 @@ impl CompactUnwindInfoUnwinding for ArchAarch64 {
         Ok(r)
     }
 
+    fn describe_opcode(opcode: u32) -> (String, Option<u32>) {
+        let opcode = OpcodeArm64::parse(opcode);
+        let fde_offset = match opcode {
+            OpcodeArm64::Dwarf { eh_frame_fde } => Some(eh_frame_fde),
+            _ => None,
+        };
+        (opcode.to_string(), fde_offset)
+    }
+
     fn rule_for_stub_helper(
         offset: u32,
     ) -> Result<CuiUnwindResult<UnwindRuleAarch64>, CompactUnwindInfoUnwinderError> {
@@ -1,8 +1,11 @@
-use super::unwindregs::UnwindRegsAarch64;
+use super::unwindregs::{LrHint, UnwindRegsAarch64};
 use crate::add_signed::checked_add_signed;
+use crate::display_utils::{Load, RegOffset};
 use crate::error::Error;
 
-use crate::unwind_rule::UnwindRule;
+use crate::unwind_rule::{check_frame_pointer, UnwindRule};
+
+use std::fmt::Display;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum UnwindRuleAarch64 {
@@ -29,6 +32,15 @@ pub enum UnwindRuleAarch64 {
         sp_offset_by_16: u16,
         lr_storage_offset_from_sp_by_8: i16,
     },
+    /// (sp, fp, lr) = (sp + 16x, *(sp + 8y), lr)
+    /// Only possible for the first frame, like `OffsetSp`: in other frames, lr no
+    /// longer holds the return address, so this rule fails with `DidNotAdvance`.
+    /// Used in leaf functions which save fp as an ordinary callee-saved register,
+    /// e.g. with `stp x29, x19, [sp, #-0x10]!`, and keep the return address in lr.
+    OffsetSpAndRestoreFp {
+        sp_offset_by_16: u16,
+        fp_storage_offset_from_sp_by_8: i16,
+    },
     /// (sp, fp, lr) = (sp + 16x, *(sp + 8y), *(sp + 8z))
     OffsetSpAndRestoreFpAndLr {
         sp_offset_by_16: u16,
@@ -43,6 +55,123 @@ pub enum UnwindRuleAarch64 {
         fp_storage_offset_from_fp_by_8: i16,
         lr_storage_offset_from_fp_by_8: i16,
     },
+    /// (pc, sp, fp, lr) = (*(sp + 568), *(sp + 560), *(sp + 544), *(sp + 552))
+    /// Used in the Linux `__kernel_rt_sigreturn` trampoline, where sp points to the
+    /// `rt_sigframe`. The new pc is the interrupted instruction, not a return address.
+    RestoreFromLinuxSigframe,
+    /// (pc, sp, fp, lr) = (*(fp + 448), *(fp + 440), *(fp + 424), *(fp + 432))
+    /// Used in macOS's `_sigtramp`, whose frame record sits right below the signal
+    /// frame built by the kernel. The new pc is the interrupted instruction, not a
+    /// return address.
+    RestoreFromMacosSigtramp,
+    /// (lr, sp, fp) = (*(fp + 8), fp + 16, *fp), where fp + 16 may be below sp
+    /// Used in functions like `__morestack`, which run their callee on a new stack
+    /// segment and keep their own frame record on the previous segment.
+    UseFramePointerAcrossStackSwitch,
+}
+
+/// Prints the rule as formulas for the caller's registers, where `sp'`, `fp'` and
+/// `lr'` are the caller's values. The return address is `lr'`.
+impl Display for UnwindRuleAarch64 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            UnwindRuleAarch64::NoOp => write!(f, "sp' = sp; fp' = fp; lr' = lr"),
+            UnwindRuleAarch64::NoOpIfFirstFrameOtherwiseFp => write!(
+                f,
+                "if first frame: sp' = sp; fp' = fp; lr' = lr; \
+                 otherwise: sp' = fp + 0x10; fp' = *fp; lr' = *(fp + 0x8)"
+            ),
+            UnwindRuleAarch64::OffsetSp { sp_offset_by_16 } => {
+                let sp_offset = i64::from(sp_offset_by_16) * 16;
+                write!(
+                    f,
+                    "sp' = {}; fp' = fp; lr' = lr",
+                    RegOffset("sp", sp_offset)
+                )
+            }
+            UnwindRuleAarch64::OffsetSpIfFirstFrameOtherwiseStackEndsHere { sp_offset_by_16 } => {
+                let sp_offset = i64::from(sp_offset_by_16) * 16;
+                write!(
+                    f,
+                    "if first frame: sp' = {}; fp' = fp; lr' = lr; otherwise: end of stack",
+                    RegOffset("sp", sp_offset)
+                )
+            }
+            UnwindRuleAarch64::OffsetSpAndRestoreLr {
+                sp_offset_by_16,
+                lr_storage_offset_from_sp_by_8,
+            } => {
+                let sp_offset = i64::from(sp_offset_by_16) * 16;
+                let lr_storage_offset = i64::from(lr_storage_offset_from_sp_by_8) * 8;
+                write!(
+                    f,
+                    "sp' = {}; fp' = fp; lr' = {}",
+                    RegOffset("sp", sp_offset),
+                    Load("sp", lr_storage_offset)
+                )
+            }
+            UnwindRuleAarch64::OffsetSpAndRestoreFp {
+                sp_offset_by_16,
+                fp_storage_offset_from_sp_by_8,
+            } => {
+                let sp_offset = i64::from(sp_offset_by_16) * 16;
+                let fp_storage_offset = i64::from(fp_storage_offset_from_sp_by_8) * 8;
+                write!(
+                    f,
+                    "sp' = {}; fp' = {}; lr' = lr",
+                    RegOffset("sp", sp_offset),
+                    Load("sp", fp_storage_offset)
+                )
+            }
+            UnwindRuleAarch64::OffsetSpAndRestoreFpAndLr {
+                sp_offset_by_16,
+                fp_storage_offset_from_sp_by_8,
+                lr_storage_offset_from_sp_by_8,
+            } => {
+                let sp_offset = i64::from(sp_offset_by_16) * 16;
+                let fp_storage_offset = i64::from(fp_storage_offset_from_sp_by_8) * 8;
+                let lr_storage_offset = i64::from(lr_storage_offset_from_sp_by_8) * 8;
+                write!(
+                    f,
+                    "sp' = {}; fp' = {}; lr' = {}",
+                    RegOffset("sp", sp_offset),
+                    Load("sp", fp_storage_offset),
+                    Load("sp", lr_storage_offset)
+                )
+            }
+            UnwindRuleAarch64::UseFramePointer => {
+                write!(f, "sp' = fp + 0x10; fp' = *fp; lr' = *(fp + 0x8)")
+            }
+            UnwindRuleAarch64::UseFramepointerWithOffsets {
+                sp_offset_from_fp_by_8,
+                fp_storage_offset_from_fp_by_8,
+                lr_storage_offset_from_fp_by_8,
+            } => {
+                let sp_offset = i64::from(sp_offset_from_fp_by_8) * 8;
+                let fp_storage_offset = i64::from(fp_storage_offset_from_fp_by_8) * 8;
+                let lr_storage_offset = i64::from(lr_storage_offset_from_fp_by_8) * 8;
+                write!(
+                    f,
+                    "sp' = {}; fp' = {}; lr' = {}",
+                    RegOffset("fp", sp_offset),
+                    Load("fp", fp_storage_offset),
+                    Load("fp", lr_storage_offset)
+                )
+            }
+            UnwindRuleAarch64::RestoreFromLinuxSigframe => write!(
+                f,
+                "pc' = *(sp + 0x238); sp' = *(sp + 0x230); fp' = *(sp + 0x220); lr' = *(sp + 0x228)"
+            ),
+            UnwindRuleAarch64::RestoreFromMacosSigtramp => write!(
+                f,
+                "pc' = *(fp + 0x1c0); sp' = *(fp + 0x1b8); fp' = *(fp + 0x1a8); lr' = *(fp + 0x1b0)"
+            ),
+            UnwindRuleAarch64::UseFramePointerAcrossStackSwitch => write!(
+                f,
+                "sp' = fp + 0x10 (may switch stacks); fp' = *fp; lr' = *(fp + 0x8)"
+            ),
+        }
+    }
 }
 
 impl UnwindRule for UnwindRuleAarch64 {
@@ -57,6 +186,22 @@ impl UnwindRule for UnwindRuleAarch64 {
     fn fallback_rule() -> Self {
         UnwindRuleAarch64::UseFramePointer
     }
+    fn rule_for_linux_sigreturn_trampoline() -> Self {
+        UnwindRuleAarch64::RestoreFromLinuxSigframe
+    }
+    fn rule_for_macos_sigtramp() -> Self {
+        UnwindRuleAarch64::RestoreFromMacosSigtramp
+    }
+    fn rule_for_stack_switch() -> Self {
+        UnwindRuleAarch64::UseFramePointerAcrossStackSwitch
+    }
+    fn resumes_interrupted_code(self) -> bool {
+        matches!(
+            self,
+            UnwindRuleAarch64::RestoreFromLinuxSigframe
+                | UnwindRuleAarch64::RestoreFromMacosSigtramp
+        )
+    }
 
     fn exec<F>(
         self,
@@ -79,14 +224,18 @@ impl UnwindRule for UnwindRuleAarch64 {
                 (lr, sp, fp)
             }
             UnwindRuleAarch64::NoOpIfFirstFrameOtherwiseFp => {
-                if is_first_frame {
+                if is_first_frame && regs.lr_hint() != LrHint::Clobbered {
                     (lr, sp, fp)
                 } else {
                     let fp = regs.fp();
                     let new_sp = fp.checked_add(16).ok_or(Error::IntegerOverflow)?;
+                    check_frame_pointer(fp, sp, 8)?;
                     let new_lr =
                         read_stack(fp + 8).map_err(|_| Error::CouldNotReadStack(fp + 8))?;
                     let new_fp = read_stack(fp).map_err(|_| Error::CouldNotReadStack(fp))?;
+                    if new_fp == fp {
+                        return Err(Error::FramePointerPointsToItself(fp));
+                    }
                     if new_sp <= sp {
                         return Err(Error::FramepointerUnwindingMovedBackwards);
                     }
@@ -122,6 +271,22 @@ impl UnwindRule for UnwindRuleAarch64 {
                     read_stack(lr_location).map_err(|_| Error::CouldNotReadStack(lr_location))?;
                 (new_lr, new_sp, fp)
             }
+            UnwindRuleAarch64::OffsetSpAndRestoreFp {
+                sp_offset_by_16,
+                fp_storage_offset_from_sp_by_8,
+            } => {
+                if !is_first_frame {
+                    return Err(Error::DidNotAdvance);
+                }
+                let sp_offset = u64::from(sp_offset_by_16) * 16;
+                let new_sp = sp.checked_add(sp_offset).ok_or(Error::IntegerOverflow)?;
+                let fp_storage_offset = i64::from(fp_storage_offset_from_sp_by_8) * 8;
+                let fp_location =
+                    checked_add_signed(sp, fp_storage_offset).ok_or(Error::IntegerOverflow)?;
+                let new_fp =
+                    read_stack(fp_location).map_err(|_| Error::CouldNotReadStack(fp_location))?;
+                (lr, new_sp, new_fp)
+            }
             UnwindRuleAarch64::OffsetSpAndRestoreFpAndLr {
                 sp_offset_by_16,
                 fp_storage_offset_from_sp_by_8,
@@ -180,13 +345,30 @@ impl UnwindRule for UnwindRuleAarch64 {
                 //  ^ sp                    ^ fp
                 //
                 // So: *fp is the caller's frame pointer, and *(fp + 8) is the return address.
+                //
+                // This doesn't work in the first frame if the function has not stored fp
+                // and lr yet, or doesn't store them at all because it's a leaf function.
+                // Then the frame record belongs to the caller. We can only know this if
+                // we've been told.
+                if is_first_frame && regs.lr_hint() == LrHint::ValidReturnAddress {
+                    let return_address = regs.lr_mask().strip_ptr_auth(lr);
+                    if return_address == 0 {
+                        return Ok(None);
+                    }
+                    regs.set_lr_hint(LrHint::Unknown);
+                    return Ok(Some(return_address));
+                }
                 let fp = regs.fp();
                 let new_sp = fp.checked_add(16).ok_or(Error::IntegerOverflow)?;
+                check_frame_pointer(fp, sp, 8)?;
                 let new_lr = read_stack(fp + 8).map_err(|_| Error::CouldNotReadStack(fp + 8))?;
                 let new_fp = read_stack(fp).map_err(|_| Error::CouldNotReadStack(fp))?;
                 if new_fp == 0 {
                     return Ok(None);
                 }
+                if new_fp == fp {
+                    return Err(Error::FramePointerPointsToItself(fp));
+                }
                 if new_fp <= fp || new_sp <= sp {
                     return Err(Error::FramepointerUnwindingMovedBackwards);
                 }
@@ -220,6 +402,67 @@ impl UnwindRule for UnwindRuleAarch64 {
                 }
                 (new_lr, new_sp, new_fp)
             }
+            UnwindRuleAarch64::UseFramePointerAcrossStackSwitch => {
+                // Like UseFramePointer, but the stack segments are separate allocations,
+                // so the previous segment can be at any address.
+                let new_sp = fp.checked_add(16).ok_or(Error::IntegerOverflow)?;
+                if !fp.is_multiple_of(8) {
+                    return Err(Error::MisalignedFramePointer(fp));
+                }
+                let new_lr = read_stack(fp + 8).map_err(|_| Error::CouldNotReadStack(fp + 8))?;
+                let new_fp = read_stack(fp).map_err(|_| Error::CouldNotReadStack(fp))?;
+                if new_fp == 0 {
+                    return Ok(None);
+                }
+                if new_fp == fp {
+                    return Err(Error::FramePointerPointsToItself(fp));
+                }
+                (new_lr, new_sp, new_fp)
+            }
+            UnwindRuleAarch64::RestoreFromLinuxSigframe => {
+                // The rt_sigframe starts with a 128 byte siginfo, followed by the ucontext
+                // whose uc_mcontext is at offset 176. The sigcontext has the fault address
+                // followed by x0..x30, sp and pc.
+                let read = |read_stack: &mut F, offset: u64| {
+                    let location = sp.checked_add(offset).ok_or(Error::IntegerOverflow)?;
+                    read_stack(location).map_err(|_| Error::CouldNotReadStack(location))
+                };
+                let new_fp = read(read_stack, 544)?;
+                let new_lr = read(read_stack, 552)?;
+                let new_sp = read(read_stack, 560)?;
+                let new_pc = read(read_stack, 568)?;
+                let new_pc = regs.lr_mask().strip_ptr_auth(new_pc);
+                if new_pc == 0 {
+                    return Ok(None);
+                }
+                regs.set_lr(new_lr);
+                regs.set_sp(new_sp);
+                regs.set_fp(new_fp);
+                regs.set_lr_hint(LrHint::Unknown);
+                return Ok(Some(new_pc));
+            }
+            UnwindRuleAarch64::RestoreFromMacosSigtramp => {
+                // The signal frame starts at _sigtramp's CFA (fp + 16) with a 104 byte
+                // siginfo and a 56 byte ucontext, followed by the mcontext. The mcontext
+                // has a 16 byte exception state followed by x0..x28, fp, lr, sp and pc.
+                let read = |read_stack: &mut F, offset: u64| {
+                    let location = fp.checked_add(offset).ok_or(Error::IntegerOverflow)?;
+                    read_stack(location).map_err(|_| Error::CouldNotReadStack(location))
+                };
+                let new_fp = read(read_stack, 424)?;
+                let new_lr = read(read_stack, 432)?;
+                let new_sp = read(read_stack, 440)?;
+                let new_pc = read(read_stack, 448)?;
+                let new_pc = regs.lr_mask().strip_ptr_auth(new_pc);
+                if new_pc == 0 {
+                    return Ok(None);
+                }
+                regs.set_lr(new_lr);
+                regs.set_sp(new_sp);
+                regs.set_fp(new_fp);
+                regs.set_lr_hint(LrHint::Unknown);
+                return Ok(Some(new_pc));
+            }
         };
         let return_address = regs.lr_mask().strip_ptr_auth(new_lr);
         if return_address == 0 {
@@ -231,6 +474,8 @@ impl UnwindRule for UnwindRuleAarch64 {
         regs.set_lr(new_lr);
         regs.set_sp(new_sp);
         regs.set_fp(new_fp);
+        // The hint only describes the first frame.
+        regs.set_lr_hint(LrHint::Unknown);
 
         Ok(Some(return_address))
     }
@@ -239,6 +484,29 @@ impl UnwindRule for UnwindRuleAarch64 {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::test_utils::TestStack;
+
+    #[test]
+    fn test_display() {
+        let rule = UnwindRuleAarch64::OffsetSpAndRestoreFpAndLr {
+            sp_offset_by_16: 3,
+            fp_storage_offset_from_sp_by_8: 2,
+            lr_storage_offset_from_sp_by_8: 3,
+        };
+        assert_eq!(
+            rule.to_string(),
+            "sp' = sp + 0x30; fp' = *(sp + 0x10); lr' = *(sp + 0x18)"
+        );
+        let rule = UnwindRuleAarch64::UseFramepointerWithOffsets {
+            sp_offset_from_fp_by_8: 2,
+            fp_storage_offset_from_fp_by_8: 0,
+            lr_storage_offset_from_fp_by_8: 1,
+        };
+        assert_eq!(
+            rule.to_string(),
+            "sp' = fp + 0x10; fp' = *fp; lr' = *(fp + 0x8)"
+        );
+    }
 
     #[test]
     fn test_basic() {
@@ -261,4 +529,128 @@ mod test {
         let res = UnwindRuleAarch64::UseFramePointer.exec(false, &mut regs, &mut read_stack);
         assert_eq!(res, Ok(None));
     }
+
+    #[test]
+    fn test_offset_sp_and_restore_fp() {
+        // A leaf function that ran `stp x29, x19, [sp, #-0x10]!`.
+        let stack = TestStack::from([1, 2, 0x40, 3, 4, 5, 6, 7, 0, 0]);
+        let mut read_stack = |addr| stack.read(addr);
+        let rule = UnwindRuleAarch64::OffsetSpAndRestoreFp {
+            sp_offset_by_16: 1,
+            fp_storage_offset_from_sp_by_8: 0,
+        };
+        assert_eq!(rule.to_string(), "sp' = sp + 0x10; fp' = *sp; lr' = lr");
+        let mut regs = UnwindRegsAarch64::new(0x100300, 0x10, 0x1234);
+        let res = rule.exec(true, &mut regs, &mut read_stack);
+        assert_eq!(res, Ok(Some(0x100300)));
+        assert_eq!(regs.sp(), 0x20);
+        assert_eq!(regs.fp(), 0x40);
+        let mut regs = UnwindRegsAarch64::new(0x100300, 0x10, 0x1234);
+        let res = rule.exec(false, &mut regs, &mut read_stack);
+        assert_eq!(res, Err(Error::DidNotAdvance));
+    }
+
+    #[test]
+    fn test_degenerate_frame_pointers() {
+        let stack = TestStack::from([1, 2, 3, 4, 0x20, 0x100200, 5, 6]);
+        let mut read_stack = |addr| stack.read(addr);
+
+        // fp points at a frame record which points at itself.
+        let mut regs = UnwindRegsAarch64::new(0x100300, 0x10, 0x20);
+        let res = UnwindRuleAarch64::UseFramePointer.exec(false, &mut regs, &mut read_stack);
+        assert_eq!(res, Err(Error::FramePointerPointsToItself(0x20)));
+        let res =
+            UnwindRuleAarch64::NoOpIfFirstFrameOtherwiseFp.exec(false, &mut regs, &mut read_stack);
+        assert_eq!(res, Err(Error::FramePointerPointsToItself(0x20)));
+
+        // fp is below sp.
+        let mut regs = UnwindRegsAarch64::new(0x100300, 0x20, 0x10);
+        let res = UnwindRuleAarch64::UseFramePointer.exec(false, &mut regs, &mut read_stack);
+        assert_eq!(res, Err(Error::FramePointerBelowStackPointer(0x10)));
+
+        // fp is misaligned.
+        let mut regs = UnwindRegsAarch64::new(0x100300, 0x10, 0x24);
+        let res = UnwindRuleAarch64::UseFramePointer.exec(false, &mut regs, &mut read_stack);
+        assert_eq!(res, Err(Error::MisalignedFramePointer(0x24)));
+        assert_eq!(regs.fp(), 0x24);
+    }
+
+    #[test]
+    fn test_stack_switch() {
+        // The callee runs on a new stack segment at 0x80, the frame record of
+        // __morestack is on the previous segment at 0x20.
+        let stack = TestStack::zeroed(24).with_frame_record(0x20, 0x40, 0x100200);
+        let mut read_stack = |addr| stack.read(addr);
+        let mut regs = UnwindRegsAarch64::new(0x100300, 0x80, 0x20);
+        let res = UnwindRuleAarch64::UseFramePointer.exec(false, &mut regs, &mut read_stack);
+        assert_eq!(res, Err(Error::FramePointerBelowStackPointer(0x20)));
+        let res = UnwindRuleAarch64::UseFramePointerAcrossStackSwitch.exec(
+            false,
+            &mut regs,
+            &mut read_stack,
+        );
+        assert_eq!(res, Ok(Some(0x100200)));
+        assert_eq!(regs.sp(), 0x30);
+        assert_eq!(regs.fp(), 0x40);
+    }
+
+    #[test]
+    fn test_linux_sigframe() {
+        let stack = TestStack::zeroed(72)
+            .with(68 * 8, 0x1234) // x29
+            .with(69 * 8, 0x100600) // x30
+            .with(70 * 8, 0x400) // sp
+            .with(71 * 8, 0x100500); // pc
+        let mut read_stack = |addr| stack.read(addr);
+        let mut regs = UnwindRegsAarch64::new(0x100300, 0x0, 0x20);
+        let res =
+            UnwindRuleAarch64::RestoreFromLinuxSigframe.exec(false, &mut regs, &mut read_stack);
+        assert_eq!(res, Ok(Some(0x100500)));
+        assert_eq!(regs.lr(), 0x100600);
+        assert_eq!(regs.sp(), 0x400);
+        assert_eq!(regs.fp(), 0x1234);
+    }
+
+    #[test]
+    fn test_macos_sigtramp() {
+        // fp is 0x20, so the mcontext starts at 0x20 + 16 + 160.
+        let stack = TestStack::zeroed(64)
+            .with(57 * 8, 0x1234) // fp
+            .with(58 * 8, 0x100600) // lr
+            .with(59 * 8, 0x400) // sp
+            .with(60 * 8, 0x100500); // pc
+        let mut read_stack = |addr| stack.read(addr);
+        let mut regs = UnwindRegsAarch64::new(0x100300, 0x0, 0x20);
+        let res =
+            UnwindRuleAarch64::RestoreFromMacosSigtramp.exec(false, &mut regs, &mut read_stack);
+        assert_eq!(res, Ok(Some(0x100500)));
+        assert_eq!(regs.lr(), 0x100600);
+        assert_eq!(regs.sp(), 0x400);
+        assert_eq!(regs.fp(), 0x1234);
+    }
+
+    #[test]
+    fn test_lr_hint() {
+        let stack = TestStack::frame_chain();
+        let mut read_stack = |addr| stack.read(addr);
+
+        // Without unwind information, a leaf function's return address is in lr.
+        let mut regs =
+            UnwindRegsAarch64::new(0x100300, 0x10, 0x20).with_lr_hint(LrHint::ValidReturnAddress);
+        let res = UnwindRuleAarch64::UseFramePointer.exec(true, &mut regs, &mut read_stack);
+        assert_eq!(res, Ok(Some(0x100300)));
+        assert_eq!(regs.sp(), 0x10);
+        assert_eq!(regs.fp(), 0x20);
+        assert_eq!(regs.lr_hint(), LrHint::Unknown);
+        let res = UnwindRuleAarch64::UseFramePointer.exec(false, &mut regs, &mut read_stack);
+        assert_eq!(res, Ok(Some(0x100200)));
+
+        // If lr has been clobbered, the frame record is used even for the first frame.
+        let mut regs = UnwindRegsAarch64::new(0x100300, 0x10, 0x20).with_lr_hint(LrHint::Clobbered);
+        let res =
+            UnwindRuleAarch64::NoOpIfFirstFrameOtherwiseFp.exec(true, &mut regs, &mut read_stack);
+        assert_eq!(res, Ok(Some(0x100200)));
+        assert_eq!(regs.sp(), 0x30);
+        assert_eq!(regs.fp(), 0x40);
+    }
 }
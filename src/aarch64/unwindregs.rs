@@ -1,6 +1,7 @@
 use std::fmt::Debug;
 
 use crate::display_utils::HexNum;
+use crate::unwind_regs::UnwindRegs;
 
 /// The registers used for unwinding on Aarch64. We only need lr (x30), sp (x31),
 /// and fp (x29).
@@ -14,6 +15,28 @@ pub struct UnwindRegsAarch64 {
     lr: u64,
     sp: u64,
     fp: u64,
+    lr_hint: LrHint,
+}
+
+/// What the caller knows about the lr register in the first frame.
+///
+/// Without unwind information, the unwinder has to guess whether the first frame's
+/// return address is in lr or in the frame record that fp points to. Guessing wrong
+/// either duplicates the second frame or drops it. If you know better, for example
+/// because the sample was taken right after a `bl`, or because the profiler knows
+/// that the current function is a leaf function, pass that knowledge on with
+/// [`UnwindRegsAarch64::with_lr_hint`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum LrHint {
+    /// Nothing is known, use the unwinder's heuristics.
+    #[default]
+    Unknown,
+    /// lr holds the return address of the first frame, and the frame record that fp
+    /// points to belongs to the caller.
+    ValidReturnAddress,
+    /// lr has been overwritten, so the return address needs to be read from the
+    /// frame record.
+    Clobbered,
 }
 
 /// Aarch64 CPUs support special instructions which interpret pointers as pair
@@ -74,6 +97,7 @@ impl UnwindRegsAarch64 {
             lr,
             sp,
             fp,
+            lr_hint: LrHint::Unknown,
         }
     }
 
@@ -90,9 +114,29 @@ impl UnwindRegsAarch64 {
             lr: code_ptr_auth_mask.strip_ptr_auth(lr),
             sp,
             fp,
+            lr_hint: LrHint::Unknown,
         }
     }
 
+    /// Set what is known about the lr register in the first frame. The hint only
+    /// affects the first frame, and only for addresses without unwind information.
+    pub fn with_lr_hint(mut self, lr_hint: LrHint) -> Self {
+        self.lr_hint = lr_hint;
+        self
+    }
+
+    /// Get the hint about the lr register in the first frame.
+    #[inline(always)]
+    pub fn lr_hint(&self) -> LrHint {
+        self.lr_hint
+    }
+
+    /// Set the hint about the lr register in the first frame.
+    #[inline(always)]
+    pub fn set_lr_hint(&mut self, lr_hint: LrHint) {
+        self.lr_hint = lr_hint
+    }
+
     /// Get the [`PtrAuthMask`] which we apply to the `lr` value.
     #[inline(always)]
     pub fn lr_mask(&self) -> PtrAuthMask {
@@ -136,6 +180,21 @@ impl UnwindRegsAarch64 {
     }
 }
 
+impl UnwindRegs for UnwindRegsAarch64 {
+    fn sp(&self) -> u64 {
+        self.sp
+    }
+    fn fp(&self) -> u64 {
+        self.fp
+    }
+    fn with_unknown_registers(&self, placeholder: u64) -> Self {
+        let mut regs = *self;
+        regs.set_lr(placeholder);
+        regs.set_fp(placeholder);
+        regs
+    }
+}
+
 impl Debug for UnwindRegsAarch64 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("UnwindRegsAarch64")
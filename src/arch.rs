@@ -1,6 +1,7 @@
+use crate::unwind_regs::UnwindRegs;
 use crate::unwind_rule::UnwindRule;
 
 pub trait Arch {
-    type UnwindRegs;
+    type UnwindRegs: UnwindRegs;
     type UnwindRule: UnwindRule<UnwindRegs = Self::UnwindRegs>;
 }
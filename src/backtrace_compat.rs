@@ -0,0 +1,117 @@
+use std::ffi::c_void;
+
+use crate::unwind_iterator::UnwindIterator;
+use crate::unwinder::Unwinder;
+use crate::FrameAddress;
+
+/// A frame with the same accessors as `backtrace::Frame`, for applications that are
+/// built around the `backtrace` crate. Created by [`trace`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Frame {
+    address: FrameAddress,
+    module_base_address: Option<u64>,
+}
+
+impl Frame {
+    /// The instruction pointer of the frame: the address of the next instruction to
+    /// execute, which is the return address for all frames but the first.
+    pub fn ip(&self) -> *mut c_void {
+        self.address.address() as *mut c_void
+    }
+
+    /// Like `backtrace::Frame::symbol_address`. Framehop doesn't know where functions
+    /// start, so this returns [`Frame::ip`], as `backtrace` does on platforms where the
+    /// start isn't known either. Use [`resolve`](Frame::resolve) to find the symbol.
+    pub fn symbol_address(&self) -> *mut c_void {
+        self.ip()
+    }
+
+    /// The base address of the module that contains the frame, if it is in one of the
+    /// unwinder's modules.
+    pub fn module_base_address(&self) -> Option<*mut c_void> {
+        self.module_base_address
+            .map(|address| address as *mut c_void)
+    }
+
+    /// The frame's address with framehop's distinction between instruction pointers
+    /// and return addresses.
+    pub fn frame_address(&self) -> FrameAddress {
+        self.address
+    }
+
+    /// Resolve the frame to symbols with `backtrace::resolve`, like
+    /// `backtrace::resolve_frame`.
+    pub fn resolve<C: FnMut(&backtrace::Symbol)>(&self, callback: C) {
+        backtrace::resolve(self.ip(), callback)
+    }
+}
+
+/// Walk the stack like `backtrace::trace`, calling `callback` for every frame until it
+/// returns `false` or the walk ends. The stack is walked with framehop's unwinder and
+/// cache, which is much faster than `backtrace::trace` for repeated captures.
+///
+/// `iter` is usually created from the registers of [`capture_regs`](crate::capture_regs),
+/// for the current thread.
+pub fn trace<U, F, C>(iter: &mut UnwindIterator<'_, '_, '_, U, F>, mut callback: C)
+where
+    U: Unwinder + ?Sized,
+    F: FnMut(u64) -> Result<u64, ()>,
+    C: FnMut(&Frame) -> bool,
+{
+    while let Ok(Some(address)) = iter.next() {
+        let module_base_address = iter
+            .module_relative_address(address.address())
+            .map(|(_, relative_address)| address.address() - u64::from(relative_address));
+        let frame = Frame {
+            address,
+            module_base_address,
+        };
+        if !callback(&frame) {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::{test_module, TestStack};
+    use crate::x86_64::{CacheX86_64, UnwindRegsX86_64, UnwinderX86_64};
+
+    #[test]
+    fn test_trace() {
+        let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        unwinder.add_module(test_module(0x100000..0x100400, None));
+        // The middle frame returns into unknown code.
+        let stack = TestStack::frame_chain().with(0x28, 0x500000);
+        let mut read_stack = |addr| stack.read(addr);
+        let mut cache = CacheX86_64::new();
+        let regs = UnwindRegsX86_64::new(0x100300, 0x10, 0x20);
+        let mut iter = unwinder.iter_frames(0x100300, regs, &mut cache, &mut read_stack);
+        let mut frames = Vec::new();
+        trace(&mut iter, |frame| {
+            frames.push((
+                frame.ip() as u64,
+                frame.module_base_address().map(|a| a as u64),
+            ));
+            true
+        });
+        assert_eq!(
+            frames,
+            vec![
+                (0x100300, Some(0x100000)),
+                (0x500000, None),
+                (0x100100, Some(0x100000)),
+            ]
+        );
+
+        // Returning false stops the walk.
+        let mut iter = unwinder.iter_frames(0x100300, regs, &mut cache, &mut read_stack);
+        let mut count = 0;
+        trace(&mut iter, |_| {
+            count += 1;
+            false
+        });
+        assert_eq!(count, 1);
+    }
+}
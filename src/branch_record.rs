@@ -0,0 +1,68 @@
+/// The kind of a taken branch in a [`BranchRecord`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BranchKind {
+    /// A call instruction. `from` is the address of the call instruction.
+    Call,
+    /// A return instruction. `to` is the return address in the caller.
+    Return,
+    /// Any other branch, such as a jump or a conditional branch. These are ignored.
+    Other,
+}
+
+/// One entry of the CPU's last branch record, for example Intel LBR or Arm BRBE, as
+/// reported with `PERF_SAMPLE_BRANCH_STACK` and `PERF_SAMPLE_BRANCH_TYPE_SAVE`.
+///
+/// See [`UnwindIterator::with_branch_records`](crate::UnwindIterator::with_branch_records).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BranchRecord {
+    /// The address of the branch instruction.
+    pub from: u64,
+    /// The branch target.
+    pub to: u64,
+    /// The kind of branch.
+    pub kind: BranchKind,
+}
+
+/// The call sites of the calls in `records` which haven't returned yet, innermost
+/// first. `records` is ordered from the most recent branch to the oldest one.
+///
+/// Every return is matched with the most recent call before it, so tail calls,
+/// `longjmp` and exceptions confuse the result.
+pub(crate) fn pending_call_sites(records: &[BranchRecord]) -> Vec<u64> {
+    let mut pending_returns = 0usize;
+    let mut call_sites = Vec::new();
+    for record in records {
+        match record.kind {
+            BranchKind::Return => pending_returns += 1,
+            BranchKind::Call if pending_returns > 0 => pending_returns -= 1,
+            BranchKind::Call => call_sites.push(record.from),
+            BranchKind::Other => {}
+        }
+    }
+    call_sites
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record(from: u64, to: u64, kind: BranchKind) -> BranchRecord {
+        BranchRecord { from, to, kind }
+    }
+
+    #[test]
+    fn test_pending_call_sites() {
+        // Oldest first: f calls g, g calls h, h returns, g jumps, g calls k, and the
+        // sample is in k. A return from before the first record is never matched.
+        let records = [
+            record(0x2100, 0x4000, BranchKind::Call),
+            record(0x2080, 0x20c0, BranchKind::Other),
+            record(0x3010, 0x2055, BranchKind::Return),
+            record(0x2050, 0x3000, BranchKind::Call),
+            record(0x1100, 0x2000, BranchKind::Call),
+            record(0x0800, 0x1050, BranchKind::Return),
+        ];
+        assert_eq!(pending_call_sites(&records), vec![0x2100, 0x1100]);
+        assert_eq!(pending_call_sites(&[]), Vec::<u64>::new());
+    }
+}
@@ -0,0 +1,79 @@
+use std::arch::asm;
+
+use crate::UnwindRegsNative;
+
+/// Capture the instruction pointer and the unwind registers of the calling thread.
+///
+/// This function is always inlined into its caller, so the returned values describe
+/// the caller's frame: the instruction pointer points into the calling function, and
+/// the stack pointer is the caller's stack pointer. Pass the returned values to
+/// [`Unwinder::iter_frames`](crate::Unwinder::iter_frames) to get a backtrace of the
+/// current thread; the stack can be read directly from memory as long as the walk
+/// stays within the current thread's stack.
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+pub fn capture_regs() -> (u64, UnwindRegsNative) {
+    let (ip, sp, bp): (u64, u64, u64);
+    // Safety: This only reads registers.
+    unsafe {
+        asm!(
+            "lea {ip}, [rip]",
+            "mov {sp}, rsp",
+            "mov {bp}, rbp",
+            ip = out(reg) ip,
+            sp = out(reg) sp,
+            bp = out(reg) bp,
+            options(nomem, nostack, preserves_flags),
+        );
+    }
+    (ip, UnwindRegsNative::new(ip, sp, bp))
+}
+
+/// Capture the instruction pointer and the unwind registers of the calling thread.
+///
+/// This function is always inlined into its caller, so the returned values describe
+/// the caller's frame: the instruction pointer points into the calling function, and
+/// the stack pointer is the caller's stack pointer. Pass the returned values to
+/// [`Unwinder::iter_frames`](crate::Unwinder::iter_frames) to get a backtrace of the
+/// current thread; the stack can be read directly from memory as long as the walk
+/// stays within the current thread's stack.
+///
+/// The captured `lr` is whatever the register holds at this point. In non-leaf
+/// functions it usually no longer contains the caller's return address, but the
+/// unwind information for the calling function takes care of that.
+#[cfg(target_arch = "aarch64")]
+#[inline(always)]
+pub fn capture_regs() -> (u64, UnwindRegsNative) {
+    let (pc, sp, fp, lr): (u64, u64, u64, u64);
+    // Safety: This only reads registers.
+    unsafe {
+        asm!(
+            "adr {pc}, .",
+            "mov {sp}, sp",
+            "mov {fp}, x29",
+            "mov {lr}, x30",
+            pc = out(reg) pc,
+            sp = out(reg) sp,
+            fp = out(reg) fp,
+            lr = out(reg) lr,
+            options(nomem, nostack, preserves_flags),
+        );
+    }
+    (pc, UnwindRegsNative::new(lr, sp, fp))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_capture_regs() {
+        let local = 0u64;
+        let (pc, regs) = capture_regs();
+        let local_address = &local as *const u64 as u64;
+        assert_ne!(pc, 0);
+        assert!(regs.sp() <= local_address);
+        assert!(local_address - regs.sp() < 0x10000);
+        std::hint::black_box(local);
+    }
+}
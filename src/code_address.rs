@@ -19,7 +19,33 @@ pub enum FrameAddress {
     ReturnAddress(NonZeroU64),
 }
 
+/// How the address of the first frame was obtained. This decides whether the address
+/// is adjusted before unwind information is looked up for it, see
+/// [`FrameAddress::address_for_lookup`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub enum FrameAddressKind {
+    /// The thread was interrupted, for example by a timer interrupt or a signal, or it
+    /// was suspended. The address is the next instruction that the thread will execute.
+    /// This is also the kind for perf samples with `PERF_RECORD_MISC_EXACT_IP`: their
+    /// address is exact, but it is looked up the same way.
+    #[default]
+    Interrupted,
+    /// The address is a return address, for example because the registers were
+    /// captured by a function which has since returned to the first frame.
+    ReturnAddress,
+}
+
 impl FrameAddress {
+    /// Create a [`FrameAddress`] for the first frame, depending on how its address
+    /// was obtained. This returns `None` for a [`FrameAddressKind::ReturnAddress`] of
+    /// zero.
+    pub fn from_first_frame_address(address: u64, kind: FrameAddressKind) -> Option<Self> {
+        match kind {
+            FrameAddressKind::Interrupted => Some(Self::from_instruction_pointer(address)),
+            FrameAddressKind::ReturnAddress => Self::from_return_address(address),
+        }
+    }
+
     /// Create a [`FrameAddress::InstructionPointer`].
     pub fn from_instruction_pointer(ip: u64) -> Self {
         FrameAddress::InstructionPointer(ip)
@@ -72,4 +98,55 @@ impl FrameAddress {
             FrameAddress::ReturnAddress(_) => true,
         }
     }
+
+    /// The lookup address (see [`FrameAddress::address_for_lookup`]) relative to the
+    /// base address (AVMA) of the module which contains it. This is the address that
+    /// symbol tables keyed by relative addresses expect, for example those of
+    /// Breakpad symbol files.
+    ///
+    /// Returns `None` if the lookup address is below `base_avma` or more than 4GB
+    /// above it.
+    pub fn relative_address_for_lookup(self, base_avma: u64) -> Option<u32> {
+        u32::try_from(self.address_for_lookup().checked_sub(base_avma)?).ok()
+    }
+
+    /// The lookup address (see [`FrameAddress::address_for_lookup`]) as an SVMA, i.e.
+    /// as an address stated in the module's object file, which is what DWARF debug
+    /// info and ELF symbol tables use. `base_avma` is the base address of the module
+    /// in the process and `base_svma` is the one stated in the object, as in
+    /// [`ModuleSvmaInfo::base_svma`](crate::ModuleSvmaInfo::base_svma); the difference
+    /// between them is the module's bias.
+    ///
+    /// Returns `None` if the lookup address is below `base_avma`.
+    pub fn svma_for_lookup(self, base_avma: u64, base_svma: u64) -> Option<u64> {
+        let relative_address = self.address_for_lookup().checked_sub(base_avma)?;
+        Some(base_svma.wrapping_add(relative_address))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_module_addresses() {
+        let return_address = FrameAddress::from_return_address(0x7f0000001235).unwrap();
+        assert_eq!(
+            return_address.relative_address_for_lookup(0x7f0000000000),
+            Some(0x1234)
+        );
+        assert_eq!(
+            return_address.svma_for_lookup(0x7f0000000000, 0x100000000),
+            Some(0x100001234)
+        );
+        assert_eq!(
+            return_address.relative_address_for_lookup(0x7f0000002000),
+            None
+        );
+        assert_eq!(return_address.relative_address_for_lookup(0x10000), None);
+
+        let ip = FrameAddress::from_instruction_pointer(0x401000);
+        assert_eq!(ip.relative_address_for_lookup(0x400000), Some(0x1000));
+        assert_eq!(ip.svma_for_lookup(0x400000, 0), Some(0x1000));
+    }
 }
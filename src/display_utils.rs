@@ -1,4 +1,5 @@
-use std::fmt::{Binary, Debug, LowerHex};
+use std::cmp::Ordering;
+use std::fmt::{Debug, Display, LowerHex};
 
 pub struct HexNum<N: LowerHex>(pub N);
 
@@ -8,10 +9,30 @@ impl<N: LowerHex> Debug for HexNum<N> {
     }
 }
 
-pub struct BinNum<N: Binary>(pub N);
+/// A register plus a byte offset, displayed as e.g. `sp + 0x18`.
+pub struct RegOffset(pub &'static str, pub i64);
 
-impl<N: Binary> Debug for BinNum<N> {
+impl Display for RegOffset {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        Binary::fmt(&self.0, f)
+        let RegOffset(reg, offset) = *self;
+        match offset.cmp(&0) {
+            Ordering::Equal => write!(f, "{reg}"),
+            Ordering::Greater => write!(f, "{reg} + 0x{:x}", offset.unsigned_abs()),
+            Ordering::Less => write!(f, "{reg} - 0x{:x}", offset.unsigned_abs()),
+        }
+    }
+}
+
+/// A load from a register plus a byte offset, displayed as e.g. `*(sp + 0x18)`.
+pub struct Load(pub &'static str, pub i64);
+
+impl Display for Load {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Load(reg, offset) = *self;
+        if offset == 0 {
+            write!(f, "*{reg}")
+        } else {
+            write!(f, "*({})", RegOffset(reg, offset))
+        }
     }
 }
@@ -1,4 +1,4 @@
-use std::{marker::PhantomData, ops::Range};
+use std::{fmt, marker::PhantomData, ops::Range};
 
 use gimli::{
     BaseAddresses, CfaRule, CieOrFde, DebugFrame, EhFrame, EhFrameHdr, Encoding, EndianSlice,
@@ -7,9 +7,19 @@ use gimli::{
     UnwindContextStorage, UnwindOffset, UnwindSection, UnwindTableRow, Value,
 };
 
-use crate::{arch::Arch, unwind_result::UnwindResult, ModuleSvmaInfo};
+use crate::{
+    arch::Arch,
+    exception_handling::{EhPointer, ExceptionHandlingInfo},
+    instruction_analysis::InstructionAnalysis,
+    macho::TextBytes,
+    unwind_result::UnwindResult,
+    unwind_rule::UnwindRule,
+    ModuleSvmaInfo, UnwindLimits,
+};
 
+/// Why DWARF CFI unwinding failed for an address.
 #[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum DwarfUnwinderError {
     #[error("Could not get the FDE for the supplied offset: {0}")]
     FdeFromOffsetFailed(#[source] gimli::Error),
@@ -31,6 +41,25 @@ pub enum DwarfUnwinderError {
 
     #[error("Could not recover the frame pointer")]
     CouldNotRecoverFramePointer,
+
+    #[error("The CIE and FDE contain more call frame instructions than the limit allows")]
+    TooManyCfiInstructions,
+}
+
+impl DwarfUnwinderError {
+    /// A stable numeric code for this error, see [`Error::code`](crate::Error::code).
+    pub fn code(&self) -> u32 {
+        match self {
+            DwarfUnwinderError::FdeFromOffsetFailed(_) => 301,
+            DwarfUnwinderError::UnwindInfoForAddressFailed(_) => 302,
+            DwarfUnwinderError::StackPointerMovedBackwards => 303,
+            DwarfUnwinderError::DidNotAdvance => 304,
+            DwarfUnwinderError::CouldNotRecoverCfa => 305,
+            DwarfUnwinderError::CouldNotRecoverReturnAddress => 306,
+            DwarfUnwinderError::CouldNotRecoverFramePointer => 307,
+            DwarfUnwinderError::TooManyCfiInstructions => 308,
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -55,6 +84,7 @@ pub trait DwarfUnwinding: Arch {
     fn unwind_frame<F, R, S>(
         unwind_info: &UnwindTableRow<R, S>,
         encoding: Encoding,
+        max_expression_steps: u32,
         regs: &mut Self::UnwindRegs,
         is_first_frame: bool,
         read_stack: &mut F,
@@ -65,6 +95,9 @@ pub trait DwarfUnwinding: Arch {
         S: UnwindContextStorage<R> + EvaluationStorage<R>;
 
     fn rule_if_uncovered_by_fde() -> Self::UnwindRule;
+
+    /// The name of a DWARF register, for dumping unwind tables.
+    fn register_name(register: Register) -> Option<&'static str>;
 }
 
 pub enum UnwindSectionType {
@@ -72,18 +105,35 @@ pub enum UnwindSectionType {
     DebugFrame,
 }
 
-pub struct DwarfUnwinder<'a, R: Reader, A: DwarfUnwinding + ?Sized, S: UnwindContextStorage<R>> {
+pub struct DwarfUnwinder<
+    'a,
+    R: Reader,
+    A: DwarfUnwinding + InstructionAnalysis + ?Sized,
+    S: UnwindContextStorage<R>,
+> {
     unwind_section_data: R,
     unwind_section_type: UnwindSectionType,
     eh_frame_hdr: Option<ParsedEhFrameHdr<EndianSlice<'a, R::Endian>>>,
     unwind_context: &'a mut UnwindContext<R, S>,
     base_svma: u64,
     bases: BaseAddresses,
+    /// The size of absolute pointers and of `.debug_frame` addresses, see
+    /// [`ModuleSvmaInfo::address_size`].
+    address_size: u8,
+    limits: UnwindLimits,
+    /// The rule for FDEs which are marked as signal trampolines.
+    signal_trampoline_rule: A::UnwindRule,
+    /// The module's code bytes, for prologue analysis in the first frame.
+    text_bytes: Option<TextBytes<'a>>,
     _arch: PhantomData<A>,
 }
 
-impl<'a, R: Reader, A: DwarfUnwinding, S: UnwindContextStorage<R> + EvaluationStorage<R>>
-    DwarfUnwinder<'a, R, A, S>
+impl<
+        'a,
+        R: Reader,
+        A: DwarfUnwinding + InstructionAnalysis,
+        S: UnwindContextStorage<R> + EvaluationStorage<R>,
+    > DwarfUnwinder<'a, R, A, S>
 {
     pub fn new(
         unwind_section_data: R,
@@ -91,15 +141,13 @@ impl<'a, R: Reader, A: DwarfUnwinding, S: UnwindContextStorage<R> + EvaluationSt
         eh_frame_hdr_data: Option<&'a [u8]>,
         unwind_context: &'a mut UnwindContext<R, S>,
         svma_info: &ModuleSvmaInfo,
+        limits: &UnwindLimits,
     ) -> Self {
         let bases = base_addresses_for_sections(svma_info);
         let eh_frame_hdr = match eh_frame_hdr_data {
             Some(eh_frame_hdr_data) => {
                 let hdr = EhFrameHdr::new(eh_frame_hdr_data, unwind_section_data.endian());
-                match hdr.parse(&bases, 8) {
-                    Ok(hdr) => Some(hdr),
-                    Err(_) => None,
-                }
+                hdr.parse(&bases, svma_info.address_size).ok()
             }
             None => None,
         };
@@ -110,12 +158,28 @@ impl<'a, R: Reader, A: DwarfUnwinding, S: UnwindContextStorage<R> + EvaluationSt
             unwind_context,
             bases,
             base_svma: svma_info.base_svma,
+            address_size: svma_info.address_size,
+            limits: *limits,
+            signal_trampoline_rule: A::UnwindRule::rule_for_linux_sigreturn_trampoline(),
+            text_bytes: None,
             _arch: PhantomData,
         }
     }
 
+    /// Supply the module's code bytes. In the first frame, they are used to check
+    /// whether the address is still in the function's prologue.
+    pub fn set_text_bytes(&mut self, text_bytes: Option<TextBytes<'a>>) {
+        self.text_bytes = text_bytes;
+    }
+
+    /// Replace the rule used for signal trampoline FDEs, which defaults to the Linux
+    /// sigreturn rule.
+    pub fn set_signal_trampoline_rule(&mut self, rule: A::UnwindRule) {
+        self.signal_trampoline_rule = rule;
+    }
+
     pub fn get_fde_offset_for_relative_address(&self, rel_lookup_address: u32) -> Option<u32> {
-        let lookup_svma = self.base_svma + rel_lookup_address as u64;
+        let lookup_svma = self.base_svma.wrapping_add(rel_lookup_address as u64);
         let eh_frame_hdr = self.eh_frame_hdr.as_ref()?;
         let table = eh_frame_hdr.table()?;
         let fde_ptr = table.lookup(lookup_svma, &self.bases).ok()?;
@@ -134,25 +198,224 @@ impl<'a, R: Reader, A: DwarfUnwinding, S: UnwindContextStorage<R> + EvaluationSt
     where
         F: FnMut(u64) -> Result<u64, ()>,
     {
-        let lookup_svma = self.base_svma + rel_lookup_address as u64;
+        // A bogus base_svma can make this wrap around. No FDE will cover the
+        // resulting address, so there is no need to report an error here.
+        let lookup_svma = self.base_svma.wrapping_add(rel_lookup_address as u64);
         let unwind_section_data = self.unwind_section_data.clone();
+        // unwind_info borrows self, so copy the fields that are needed afterwards.
+        let max_expression_steps = self.limits.max_dwarf_expression_steps;
+        let text_bytes = self.text_bytes;
+        let base_svma = self.base_svma;
+        let signal_trampoline_rule = self.signal_trampoline_rule;
         let unwind_info = match self.unwind_section_type {
             UnwindSectionType::EhFrame => {
                 let mut eh_frame = EhFrame::from(unwind_section_data);
-                eh_frame.set_address_size(8);
+                eh_frame.set_address_size(self.address_size);
                 self.unwind_info_for_fde(eh_frame, lookup_svma, fde_offset)
             }
             UnwindSectionType::DebugFrame => {
                 let mut debug_frame = DebugFrame::from(unwind_section_data);
-                debug_frame.set_address_size(8);
+                debug_frame.set_address_size(self.address_size);
                 self.unwind_info_for_fde(debug_frame, lookup_svma, fde_offset)
             }
         };
         if let Err(DwarfUnwinderError::UnwindInfoForAddressFailed(_)) = unwind_info {
             return Ok(UnwindResult::ExecRule(A::rule_if_uncovered_by_fde()));
         }
-        let (unwind_info, encoding) = unwind_info?;
-        A::unwind_frame::<F, R, S>(unwind_info, encoding, regs, is_first_frame, read_stack)
+        let (unwind_info, fde_properties) = unwind_info?;
+        if fde_properties.is_signal_trampoline {
+            // The "S" augmentation marks the FDE of a sigreturn trampoline. The CFI
+            // for these is usually an expression describing the signal frame layout,
+            // which we know how to handle directly.
+            return Ok(UnwindResult::ExecRule(signal_trampoline_rule));
+        }
+        if is_first_frame && fde_properties.starts_at_function_entry {
+            if let Some(rule) = Self::rule_from_prologue_analysis(
+                text_bytes,
+                base_svma,
+                &fde_properties,
+                rel_lookup_address,
+            ) {
+                return Ok(UnwindResult::ExecRule(rule));
+            }
+        }
+        A::unwind_frame::<F, R, S>(
+            unwind_info,
+            fde_properties.encoding,
+            max_expression_steps,
+            regs,
+            is_first_frame,
+            read_stack,
+        )
+    }
+
+    /// Writes the FDE at `fde_offset` and its unwind table to `out`, in a format
+    /// similar to `llvm-dwarfdump --eh-frame`. The row which covers `rel_lookup_address`
+    /// is marked with `>`. Parse errors are written to `out` too.
+    pub fn dump_fde<W: fmt::Write>(
+        &mut self,
+        rel_lookup_address: u32,
+        fde_offset: u32,
+        out: &mut W,
+    ) -> fmt::Result {
+        let lookup_svma = self.base_svma.wrapping_add(rel_lookup_address as u64);
+        let unwind_section_data = self.unwind_section_data.clone();
+        match self.unwind_section_type {
+            UnwindSectionType::EhFrame => {
+                let mut eh_frame = EhFrame::from(unwind_section_data);
+                eh_frame.set_address_size(self.address_size);
+                self.dump_fde_in_section(eh_frame, lookup_svma, fde_offset, out)
+            }
+            UnwindSectionType::DebugFrame => {
+                let mut debug_frame = DebugFrame::from(unwind_section_data);
+                debug_frame.set_address_size(self.address_size);
+                self.dump_fde_in_section(debug_frame, lookup_svma, fde_offset, out)
+            }
+        }
+    }
+
+    /// The personality routine and LSDA of the FDE at `fde_offset`, with addresses
+    /// translated to AVMAs of a module loaded at `base_avma`.
+    pub fn exception_handling_info(
+        &self,
+        fde_offset: u32,
+        base_avma: u64,
+    ) -> Result<ExceptionHandlingInfo, DwarfUnwinderError> {
+        let unwind_section_data = self.unwind_section_data.clone();
+        match self.unwind_section_type {
+            UnwindSectionType::EhFrame => {
+                let mut eh_frame = EhFrame::from(unwind_section_data);
+                eh_frame.set_address_size(self.address_size);
+                self.exception_handling_info_in_section(eh_frame, fde_offset, base_avma)
+            }
+            UnwindSectionType::DebugFrame => {
+                let mut debug_frame = DebugFrame::from(unwind_section_data);
+                debug_frame.set_address_size(self.address_size);
+                self.exception_handling_info_in_section(debug_frame, fde_offset, base_avma)
+            }
+        }
+    }
+
+    fn exception_handling_info_in_section<US: UnwindSection<R>>(
+        &self,
+        unwind_section: US,
+        fde_offset: u32,
+        base_avma: u64,
+    ) -> Result<ExceptionHandlingInfo, DwarfUnwinderError> {
+        let fde = unwind_section
+            .fde_from_offset(
+                &self.bases,
+                US::Offset::from(R::Offset::from_u32(fde_offset)),
+                US::cie_from_offset,
+            )
+            .map_err(DwarfUnwinderError::FdeFromOffsetFailed)?;
+        let to_pointer = |pointer| EhPointer::from_gimli_svma(pointer, self.base_svma, base_avma);
+        Ok(ExceptionHandlingInfo {
+            function_start: fde
+                .initial_address()
+                .wrapping_sub(self.base_svma)
+                .wrapping_add(base_avma),
+            is_function_entry: !changes_initial_row(fde.instructions(&unwind_section, &self.bases)),
+            personality: fde.personality().map(to_pointer),
+            lsda: fde.lsda().map(to_pointer),
+        })
+    }
+
+    fn dump_fde_in_section<US: UnwindSection<R>, W: fmt::Write>(
+        &mut self,
+        unwind_section: US,
+        lookup_svma: u64,
+        fde_offset: u32,
+        out: &mut W,
+    ) -> fmt::Result {
+        let fde = match unwind_section.fde_from_offset(
+            &self.bases,
+            US::Offset::from(R::Offset::from_u32(fde_offset)),
+            US::cie_from_offset,
+        ) {
+            Ok(fde) => fde,
+            Err(err) => {
+                return writeln!(
+                    out,
+                    "Could not parse the FDE at offset 0x{fde_offset:x}: {err}"
+                )
+            }
+        };
+        let cie = fde.cie();
+        writeln!(
+            out,
+            "FDE at offset 0x{:x}, pc 0x{:x}..0x{:x}",
+            fde_offset,
+            fde.initial_address(),
+            fde.initial_address().saturating_add(fde.len())
+        )?;
+        write!(
+            out,
+            "CIE at offset 0x{:x}, code alignment {}, data alignment {}, return address in ",
+            cie.offset().into_u64(),
+            cie.code_alignment_factor(),
+            cie.data_alignment_factor()
+        )?;
+        write_register::<A, W>(out, cie.return_address_register())?;
+        if cie.is_signal_trampoline() {
+            write!(out, ", signal trampoline")?;
+        }
+        writeln!(out)?;
+
+        let instruction_count = count_instructions(
+            cie.instructions(&unwind_section, &self.bases),
+            self.limits.max_cfi_instructions_per_frame,
+        ) + count_instructions(
+            fde.instructions(&unwind_section, &self.bases),
+            self.limits.max_cfi_instructions_per_frame,
+        );
+        if instruction_count > self.limits.max_cfi_instructions_per_frame {
+            return writeln!(out, "{}", DwarfUnwinderError::TooManyCfiInstructions);
+        }
+        let mut table = match fde.rows(&unwind_section, &self.bases, self.unwind_context) {
+            Ok(table) => table,
+            Err(err) => return writeln!(out, "Could not evaluate the instructions: {err}"),
+        };
+        loop {
+            let row = match table.next_row() {
+                Ok(Some(row)) => row,
+                Ok(None) => return Ok(()),
+                Err(err) => return writeln!(out, "Could not evaluate the instructions: {err}"),
+            };
+            let marker = if row.contains(lookup_svma) { '>' } else { ' ' };
+            write!(
+                out,
+                "{} 0x{:x}..0x{:x}: CFA=",
+                marker,
+                row.start_address(),
+                row.end_address()
+            )?;
+            match row.cfa() {
+                CfaRule::RegisterAndOffset { register, offset } => {
+                    write_register::<A, W>(out, *register)?;
+                    write!(out, "{offset:+}")?;
+                }
+                CfaRule::Expression(_) => write!(out, "<expression>")?,
+            }
+            for (register, rule) in row.registers() {
+                write!(out, ", ")?;
+                write_register::<A, W>(out, *register)?;
+                match rule {
+                    RegisterRule::Undefined => write!(out, "=undefined")?,
+                    RegisterRule::SameValue => write!(out, "=same")?,
+                    RegisterRule::Offset(offset) => write!(out, "=[CFA{offset:+}]")?,
+                    RegisterRule::ValOffset(offset) => write!(out, "=CFA{offset:+}")?,
+                    RegisterRule::Register(register) => {
+                        write!(out, "=")?;
+                        write_register::<A, W>(out, *register)?;
+                    }
+                    RegisterRule::Expression(_) => write!(out, "=[<expression>]")?,
+                    RegisterRule::ValExpression(_) => write!(out, "=<expression>")?,
+                    RegisterRule::Architectural => write!(out, "=architectural")?,
+                }
+            }
+            writeln!(out)?;
+        }
     }
 
     fn unwind_info_for_fde<US: UnwindSection<R>>(
@@ -160,14 +423,32 @@ impl<'a, R: Reader, A: DwarfUnwinding, S: UnwindContextStorage<R> + EvaluationSt
         unwind_section: US,
         lookup_svma: u64,
         fde_offset: u32,
-    ) -> Result<(&UnwindTableRow<R, S>, Encoding), DwarfUnwinderError> {
+    ) -> Result<(&UnwindTableRow<R, S>, FdeProperties), DwarfUnwinderError> {
         let fde = unwind_section.fde_from_offset(
             &self.bases,
             US::Offset::from(R::Offset::from_u32(fde_offset)),
             US::cie_from_offset,
         );
         let fde = fde.map_err(DwarfUnwinderError::FdeFromOffsetFailed)?;
-        let encoding = fde.cie().encoding();
+        let fde_properties = FdeProperties {
+            encoding: fde.cie().encoding(),
+            is_signal_trampoline: fde.cie().is_signal_trampoline(),
+            function_svma_range: fde.initial_address()
+                ..fde.initial_address().saturating_add(fde.len()),
+            starts_at_function_entry: !changes_initial_row(
+                fde.instructions(&unwind_section, &self.bases),
+            ),
+        };
+        let instruction_count = count_instructions(
+            fde.cie().instructions(&unwind_section, &self.bases),
+            self.limits.max_cfi_instructions_per_frame,
+        ) + count_instructions(
+            fde.instructions(&unwind_section, &self.bases),
+            self.limits.max_cfi_instructions_per_frame,
+        );
+        if instruction_count > self.limits.max_cfi_instructions_per_frame {
+            return Err(DwarfUnwinderError::TooManyCfiInstructions);
+        }
         let unwind_info: &UnwindTableRow<_, _> = fde
             .unwind_info_for_address(
                 &unwind_section,
@@ -176,7 +457,91 @@ impl<'a, R: Reader, A: DwarfUnwinding, S: UnwindContextStorage<R> + EvaluationSt
                 lookup_svma,
             )
             .map_err(DwarfUnwinderError::UnwindInfoForAddressFailed)?;
-        Ok((unwind_info, encoding))
+        Ok((unwind_info, fde_properties))
+    }
+
+    /// Unwind rows in CFI are only as precise as the compiler made them, and some
+    /// compilers don't describe every instruction of the prologue. If we have the code
+    /// bytes, decode the instructions from the start of the function up to the address.
+    fn rule_from_prologue_analysis(
+        text_bytes: Option<TextBytes<'_>>,
+        base_svma: u64,
+        fde_properties: &FdeProperties,
+        rel_lookup_address: u32,
+    ) -> Option<A::UnwindRule> {
+        let text_bytes = text_bytes?;
+        let function_svma_range = &fde_properties.function_svma_range;
+        let function_start =
+            u32::try_from(function_svma_range.start.checked_sub(base_svma)?).ok()?;
+        let function_end = u32::try_from(function_svma_range.end.checked_sub(base_svma)?).ok()?;
+        let function_bytes = text_bytes.bytes_for_relative_range(function_start, function_end)?;
+        let pc_offset = rel_lookup_address.checked_sub(function_start)? as usize;
+        if pc_offset > function_bytes.len() {
+            return None;
+        }
+        A::rule_from_function_start_analysis(function_bytes, pc_offset)
+    }
+}
+
+/// What we need to know about an FDE, in addition to its unwind table row.
+struct FdeProperties {
+    encoding: Encoding,
+    /// Whether the CIE has the "S" augmentation.
+    is_signal_trampoline: bool,
+    function_svma_range: Range<u64>,
+    /// Whether the FDE starts at the entry of a function. This is false for the cold
+    /// parts of functions which were split into hot and cold parts, and prologue
+    /// analysis from the FDE start would be wrong for them.
+    starts_at_function_entry: bool,
+}
+
+fn write_register<A: DwarfUnwinding + ?Sized, W: fmt::Write>(
+    out: &mut W,
+    register: Register,
+) -> fmt::Result {
+    match A::register_name(register) {
+        Some(name) => write!(out, "{name}"),
+        None => write!(out, "reg{}", register.0),
+    }
+}
+
+/// Counts the instructions in `instructions`, but stops counting once `limit` is
+/// exceeded. Malformed instructions end the count; gimli reports them when the
+/// instructions are evaluated.
+fn count_instructions<R: Reader>(
+    mut instructions: gimli::CallFrameInstructionIter<'_, R>,
+    limit: usize,
+) -> usize {
+    let mut count = 0;
+    while count <= limit {
+        match instructions.next() {
+            Ok(Some(_)) => count += 1,
+            _ => break,
+        }
+    }
+    count
+}
+
+/// Whether the FDE's instructions change the row at the FDE's start address, i.e.
+/// whether the code at the start doesn't run with the CIE's initial rules, which
+/// describe a function entry. Compilers emit such FDEs for the cold parts of functions
+/// which were split into hot and cold parts, e.g. `foo.cold` in `.text.unlikely`: the
+/// cold part is jumped to from the hot part, with the hot part's stack frame.
+fn changes_initial_row<R: Reader>(
+    mut instructions: gimli::CallFrameInstructionIter<'_, R>,
+) -> bool {
+    loop {
+        match instructions.next() {
+            Ok(Some(
+                gimli::CallFrameInstruction::Nop | gimli::CallFrameInstruction::ArgsSize { .. },
+            )) => {}
+            Ok(Some(
+                gimli::CallFrameInstruction::AdvanceLoc { .. }
+                | gimli::CallFrameInstruction::SetLoc { .. },
+            )) => return false,
+            Ok(Some(_)) => return true,
+            Ok(None) | Err(_) => return false,
+        }
     }
 }
 
@@ -285,7 +650,7 @@ impl DwarfCfiIndex {
     ) -> Result<Self, DwarfCfiIndexError> {
         let bases = base_addresses_for_sections(svma_info);
         let mut eh_frame = EhFrame::from(EndianSlice::new(eh_frame_data, LittleEndian));
-        eh_frame.set_address_size(8);
+        eh_frame.set_address_size(svma_info.address_size);
 
         Self::try_new(eh_frame, bases, svma_info.base_svma)
     }
@@ -296,7 +661,7 @@ impl DwarfCfiIndex {
     ) -> Result<Self, DwarfCfiIndexError> {
         let bases = base_addresses_for_sections(svma_info);
         let mut debug_frame = DebugFrame::from(EndianSlice::new(debug_frame_data, LittleEndian));
-        debug_frame.set_address_size(8);
+        debug_frame.set_address_size(svma_info.address_size);
 
         Self::try_new(debug_frame, bases, svma_info.base_svma)
     }
@@ -318,6 +683,7 @@ pub trait DwarfUnwindRegs {
 pub fn eval_cfa_rule<R: Reader, UR: DwarfUnwindRegs, S: EvaluationStorage<R>>(
     rule: &CfaRule<R>,
     encoding: Encoding,
+    max_expression_steps: u32,
     regs: &UR,
 ) -> Option<u64> {
     match rule {
@@ -325,16 +691,20 @@ pub fn eval_cfa_rule<R: Reader, UR: DwarfUnwindRegs, S: EvaluationStorage<R>>(
             let val = regs.get(*register)?;
             u64::try_from(i64::try_from(val).ok()?.checked_add(*offset)?).ok()
         }
-        CfaRule::Expression(expr) => eval_expr::<R, UR, S>(expr.clone(), encoding, regs),
+        CfaRule::Expression(expr) => {
+            eval_expr::<R, UR, S>(expr.clone(), encoding, max_expression_steps, regs)
+        }
     }
 }
 
 fn eval_expr<R: Reader, UR: DwarfUnwindRegs, S: EvaluationStorage<R>>(
     expr: Expression<R>,
     encoding: Encoding,
+    max_steps: u32,
     regs: &UR,
 ) -> Option<u64> {
     let mut eval = Evaluation::<R, S>::new_in(expr.0, encoding);
+    eval.set_max_iterations(max_steps);
     let mut result = eval.evaluate().ok()?;
     loop {
         match result {
@@ -358,6 +728,7 @@ pub fn eval_register_rule<R, F, UR, S>(
     rule: RegisterRule<R>,
     cfa: u64,
     encoding: Encoding,
+    max_expression_steps: u32,
     val: u64,
     regs: &UR,
     read_stack: &mut F,
@@ -381,10 +752,12 @@ where
         }
         RegisterRule::Register(register) => regs.get(register),
         RegisterRule::Expression(expr) => {
-            let val = eval_expr::<R, UR, S>(expr, encoding, regs)?;
+            let val = eval_expr::<R, UR, S>(expr, encoding, max_expression_steps, regs)?;
             read_stack(val).ok()
         }
-        RegisterRule::ValExpression(expr) => eval_expr::<R, UR, S>(expr, encoding, regs),
+        RegisterRule::ValExpression(expr) => {
+            eval_expr::<R, UR, S>(expr, encoding, max_expression_steps, regs)
+        }
         RegisterRule::Architectural => {
             // Unimplemented
             // TODO: Find out what the architectural rules for x86_64 and for aarch64 are, if any.
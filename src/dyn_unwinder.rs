@@ -0,0 +1,285 @@
+use std::ops::Deref;
+
+use crate::aarch64::UnwindRegsAarch64;
+use crate::error::Error;
+use crate::unwinder::{Module, Unwinder};
+use crate::x86::UnwindRegsX86;
+use crate::x86_64::UnwindRegsX86_64;
+use crate::FrameAddress;
+
+/// An object-safe version of [`Unwinder`], for applications which keep unwinders for
+/// different CPU architectures or with different cache types behind one
+/// `Box<dyn DynUnwinder<D>>`.
+///
+/// Create one with [`ErasedUnwinder::new`]. The unwinder owns its cache, the
+/// registers are passed as a [`DynUnwindRegs`], and stack memory is read with a
+/// `&mut dyn FnMut` callback. This costs a dynamic call per stack read, so prefer the
+/// generic [`Unwinder`] API where the architecture is known at compile time.
+///
+/// ```
+/// use framehop::aarch64::{CacheAarch64, UnwinderAarch64};
+/// use framehop::x86_64::{CacheX86_64, UnwindRegsX86_64, UnwinderX86_64};
+/// use framehop::{DynUnwinder, ErasedUnwinder};
+///
+/// let mut unwinders: Vec<Box<dyn DynUnwinder<Vec<u8>>>> = vec![
+///     Box::new(ErasedUnwinder::new(
+///         UnwinderX86_64::<Vec<u8>>::new(),
+///         CacheX86_64::new(),
+///     )),
+///     Box::new(ErasedUnwinder::new(
+///         UnwinderAarch64::<Vec<u8>>::new(),
+///         CacheAarch64::new(),
+///     )),
+/// ];
+/// let regs = UnwindRegsX86_64::new(0x1000, 0x10, 0x20);
+/// let mut frames = Vec::new();
+/// let mut read_stack = |_| Err(());
+/// for unwinder in &mut unwinders {
+///     let result = unwinder.unwind_stack(0x1000, regs.into(), &mut read_stack, &mut frames);
+///     if let Err(err) = result {
+///         println!("The stack walk ended early: {err}");
+///     }
+/// }
+/// ```
+pub trait DynUnwinder<D: Deref<Target = [u8]>> {
+    /// See [`Unwinder::add_module`].
+    fn add_module(&mut self, module: Module<D>);
+
+    /// See [`Unwinder::remove_module`].
+    fn remove_module(&mut self, module_avma_range_start: u64);
+
+    /// See [`Unwinder::max_known_code_address`].
+    fn max_known_code_address(&self) -> u64;
+
+    /// See [`Unwinder::is_known_code_address`].
+    fn is_known_code_address(&self, address: u64) -> bool;
+
+    /// Walk the stack starting at `pc` and `regs`, and append the frames to `frames`.
+    /// Stops at the end of the stack or at the first error, which is returned. The
+    /// frames that were found before the error stay in `frames`.
+    ///
+    /// Returns [`DynUnwinderError::WrongArchitecture`] without walking if `regs` are
+    /// for a different CPU architecture than the unwinder.
+    fn unwind_stack(
+        &mut self,
+        pc: u64,
+        regs: DynUnwindRegs,
+        read_stack: &mut dyn FnMut(u64) -> Result<u64, ()>,
+        frames: &mut Vec<FrameAddress>,
+    ) -> Result<(), DynUnwinderError>;
+}
+
+/// The unwind registers for a [`DynUnwinder`], for any of the supported CPU
+/// architectures.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DynUnwindRegs {
+    X86_64(UnwindRegsX86_64),
+    Aarch64(UnwindRegsAarch64),
+    X86(UnwindRegsX86),
+}
+
+impl From<UnwindRegsX86_64> for DynUnwindRegs {
+    fn from(regs: UnwindRegsX86_64) -> Self {
+        DynUnwindRegs::X86_64(regs)
+    }
+}
+
+impl From<UnwindRegsAarch64> for DynUnwindRegs {
+    fn from(regs: UnwindRegsAarch64) -> Self {
+        DynUnwindRegs::Aarch64(regs)
+    }
+}
+
+impl From<UnwindRegsX86> for DynUnwindRegs {
+    fn from(regs: UnwindRegsX86) -> Self {
+        DynUnwindRegs::X86(regs)
+    }
+}
+
+impl TryFrom<DynUnwindRegs> for UnwindRegsX86_64 {
+    type Error = DynUnwinderError;
+
+    fn try_from(regs: DynUnwindRegs) -> Result<Self, Self::Error> {
+        match regs {
+            DynUnwindRegs::X86_64(regs) => Ok(regs),
+            _ => Err(DynUnwinderError::WrongArchitecture),
+        }
+    }
+}
+
+impl TryFrom<DynUnwindRegs> for UnwindRegsAarch64 {
+    type Error = DynUnwinderError;
+
+    fn try_from(regs: DynUnwindRegs) -> Result<Self, Self::Error> {
+        match regs {
+            DynUnwindRegs::Aarch64(regs) => Ok(regs),
+            _ => Err(DynUnwinderError::WrongArchitecture),
+        }
+    }
+}
+
+impl TryFrom<DynUnwindRegs> for UnwindRegsX86 {
+    type Error = DynUnwinderError;
+
+    fn try_from(regs: DynUnwindRegs) -> Result<Self, Self::Error> {
+        match regs {
+            DynUnwindRegs::X86(regs) => Ok(regs),
+            _ => Err(DynUnwinderError::WrongArchitecture),
+        }
+    }
+}
+
+/// Why [`DynUnwinder::unwind_stack`] stopped early.
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DynUnwinderError {
+    #[error("The registers are for a different CPU architecture than the unwinder")]
+    WrongArchitecture,
+
+    #[error("Unwinding failed: {0}")]
+    Unwind(#[from] Error),
+}
+
+/// An [`Unwinder`] together with its cache, which implements [`DynUnwinder`].
+pub struct ErasedUnwinder<U: Unwinder> {
+    unwinder: U,
+    cache: U::Cache,
+}
+
+impl<U: Unwinder> ErasedUnwinder<U> {
+    /// Combine `unwinder` with the `cache` that it will use for all walks.
+    pub fn new(unwinder: U, cache: U::Cache) -> Self {
+        Self { unwinder, cache }
+    }
+
+    /// The wrapped unwinder, for configuration which isn't part of [`DynUnwinder`].
+    pub fn unwinder_mut(&mut self) -> &mut U {
+        &mut self.unwinder
+    }
+}
+
+impl<D, U> DynUnwinder<D> for ErasedUnwinder<U>
+where
+    D: Deref<Target = [u8]>,
+    U: Unwinder<Module = Module<D>>,
+    U::UnwindRegs: TryFrom<DynUnwindRegs, Error = DynUnwinderError>,
+{
+    fn add_module(&mut self, module: Module<D>) {
+        self.unwinder.add_module(module);
+    }
+
+    fn remove_module(&mut self, module_avma_range_start: u64) {
+        self.unwinder.remove_module(module_avma_range_start);
+    }
+
+    fn max_known_code_address(&self) -> u64 {
+        self.unwinder.max_known_code_address()
+    }
+
+    fn is_known_code_address(&self, address: u64) -> bool {
+        self.unwinder.is_known_code_address(address)
+    }
+
+    fn unwind_stack(
+        &mut self,
+        pc: u64,
+        regs: DynUnwindRegs,
+        read_stack: &mut dyn FnMut(u64) -> Result<u64, ()>,
+        frames: &mut Vec<FrameAddress>,
+    ) -> Result<(), DynUnwinderError> {
+        let regs = U::UnwindRegs::try_from(regs)?;
+        let mut read_stack = read_stack;
+        let mut iter = self
+            .unwinder
+            .iter_frames(pc, regs, &mut self.cache, &mut read_stack);
+        while let Some(frame) = iter.next()? {
+            frames.push(frame);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::aarch64::{CacheAarch64, UnwinderAarch64};
+    use crate::test_utils::{test_module, TestStack};
+    use crate::unwinder::{ModuleSvmaInfo, ModuleUnwindData};
+    use crate::x86::{CacheX86, UnwinderX86};
+    use crate::x86_64::{CacheX86_64, UnwinderX86_64};
+    use crate::StackSlice;
+
+    #[test]
+    fn test_heterogeneous_unwinders() {
+        let mut unwinders: Vec<Box<dyn DynUnwinder<Vec<u8>>>> = vec![
+            Box::new(ErasedUnwinder::new(
+                UnwinderX86_64::<Vec<u8>>::new(),
+                CacheX86_64::new(),
+            )),
+            Box::new(ErasedUnwinder::new(
+                UnwinderAarch64::<Vec<u8>>::new(),
+                CacheAarch64::new(),
+            )),
+        ];
+        for unwinder in &mut unwinders {
+            unwinder.add_module(test_module(0x100000..0x100400, None));
+            assert_eq!(unwinder.max_known_code_address(), 0x100400);
+        }
+
+        let stack = TestStack::frame_chain();
+        let mut read_stack = |addr| stack.read(addr);
+        let mut frames = Vec::new();
+        let regs = UnwindRegsX86_64::new(0x100300, 0x10, 0x20);
+        unwinders[0]
+            .unwind_stack(0x100300, regs.into(), &mut read_stack, &mut frames)
+            .unwrap();
+        let addresses: Vec<u64> = frames.iter().map(|frame| frame.address()).collect();
+        assert_eq!(addresses, vec![0x100300, 0x100200, 0x100100]);
+
+        frames.clear();
+        assert_eq!(
+            unwinders[1].unwind_stack(0x100300, regs.into(), &mut read_stack, &mut frames),
+            Err(DynUnwinderError::WrongArchitecture)
+        );
+        assert!(frames.is_empty());
+    }
+
+    #[test]
+    fn test_x86_unwinder() {
+        let mut unwinder: Box<dyn DynUnwinder<Vec<u8>>> = Box::new(ErasedUnwinder::new(
+            UnwinderX86::<Vec<u8>>::new(),
+            CacheX86::new(),
+        ));
+        unwinder.add_module(Module::new(
+            "lib".to_string(),
+            0x100000..0x100400,
+            0x100000,
+            ModuleSvmaInfo {
+                text: Some(0..0x400),
+                address_size: 4,
+                ..Default::default()
+            },
+            ModuleUnwindData::None,
+            None,
+        ));
+
+        let stack: [u32; 12] = [1, 2, 3, 4, 0x20, 0x100200, 5, 6, 0x0, 0x0, 0x0, 0x0];
+        let bytes: Vec<u8> = stack.iter().flat_map(|v| v.to_le_bytes()).collect();
+        let stack = StackSlice::new(0, bytes);
+        let mut read_stack = |addr: u64| stack.read_u64(addr).ok_or(());
+        let mut frames = Vec::new();
+        let regs = UnwindRegsX86::new(0x100300, 0x8, 0x10);
+        unwinder
+            .unwind_stack(0x100300, regs.into(), &mut read_stack, &mut frames)
+            .unwrap();
+        let addresses: Vec<u64> = frames.iter().map(|frame| frame.address()).collect();
+        assert_eq!(addresses, vec![0x100300, 0x100200]);
+
+        frames.clear();
+        let regs = UnwindRegsX86_64::new(0x100300, 0x8, 0x10);
+        assert_eq!(
+            unwinder.unwind_stack(0x100300, regs.into(), &mut read_stack, &mut frames),
+            Err(DynUnwinderError::WrongArchitecture)
+        );
+    }
+}
@@ -1,8 +1,10 @@
 use crate::dwarf::DwarfUnwinderError;
+use crate::fpo::FpoUnwinderError;
 use crate::macho::CompactUnwindInfoUnwinderError;
 
 /// The error type used in this crate.
 #[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum Error {
     #[error("Could not read stack memory at 0x{0:x}")]
     CouldNotReadStack(u64),
@@ -10,6 +12,15 @@ pub enum Error {
     #[error("Frame pointer unwinding moved backwards")]
     FramepointerUnwindingMovedBackwards,
 
+    #[error("Frame pointer 0x{0:x} points to a frame record that points back to itself")]
+    FramePointerPointsToItself(u64),
+
+    #[error("Frame pointer 0x{0:x} is below the stack pointer")]
+    FramePointerBelowStackPointer(u64),
+
+    #[error("Frame pointer 0x{0:x} is misaligned")]
+    MisalignedFramePointer(u64),
+
     #[error("Neither the code address nor the stack pointer changed, would loop")]
     DidNotAdvance,
 
@@ -18,9 +29,62 @@ pub enum Error {
 
     #[error("Return address is null")]
     ReturnAddressIsNull,
+
+    #[error("Could not find the saved register state in the signal frame")]
+    CouldNotFindSignalContext,
+
+    #[error("Stack address 0x{0:x} is outside of the stack bounds")]
+    OutOfStackBounds(u64),
+
+    #[error("Unwinding returned to an earlier frame with return address 0x{0:x}, would loop")]
+    UnwindingCycle(u64),
+
+    /// Unwinding needed to read stack memory at this address, which is past the end
+    /// of the captured stack bytes. This is not an unwinding failure: the stack was
+    /// simply copied only partially, for example because perf captures a fixed number
+    /// of bytes. See [`UnwindIterator::with_captured_stack`](crate::UnwindIterator::with_captured_stack).
+    #[error("Stack address 0x{0:x} is past the end of the captured stack bytes")]
+    StackTruncated(u64),
+
+    /// The unwind information for this address is malformed or doesn't cover it. Only
+    /// returned in [`UnwindMode::Strict`](crate::UnwindMode::Strict); otherwise the
+    /// unwinder falls back to frame pointer unwinding.
+    #[error("The unwind information is unusable: {0}")]
+    UnusableUnwindInfo(#[source] ModuleError),
+
+    /// Unwinding the frame at this address needs a register which wasn't known when
+    /// the walk started. See
+    /// [`UnwindIterator::with_unknown_registers`](crate::UnwindIterator::with_unknown_registers).
+    #[error("Unwinding the frame at 0x{0:x} needs a register that was not supplied")]
+    NeedsUnknownRegister(u64),
+
+    /// The first frame has no usable unwind information, and the frame pointer can't
+    /// be trusted in it because the code was declared to be built without frame
+    /// pointers. See the concrete unwinder's `set_trust_first_frame_pointer` method.
+    #[error("The first frame needs the frame pointer, which is not trusted")]
+    UntrustedFramePointer,
 }
 
+/// An [`UnwinderError`] together with the module and the address where it happened.
 #[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("{error} at 0x{address:x} (module 0x{module_avma_range_start:x} + 0x{relative_address:x})")]
+pub struct ModuleError {
+    /// The address that was looked up in the unwind information. For return
+    /// addresses, this is an address inside the call instruction.
+    pub address: u64,
+    /// The start of the module's address range, which identifies the module, as in
+    /// [`Unwinder::remove_module`](crate::Unwinder::remove_module).
+    pub module_avma_range_start: u64,
+    /// `address`, relative to the module's base address.
+    pub relative_address: u32,
+    /// What went wrong.
+    #[source]
+    pub error: UnwinderError,
+}
+
+/// Why the unwind information of a module could not be used for an address.
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum UnwinderError {
     #[error("Compact Unwind Info unwinding failed: {0}")]
     CompactUnwindInfo(#[source] CompactUnwindInfoUnwinderError),
@@ -34,11 +98,64 @@ pub enum UnwinderError {
     #[error("No unwind data for the module containing the address")]
     NoModuleUnwindData,
 
+    #[error("The unwind data for the module containing the address could not be parsed")]
+    UnparseableModuleUnwindData,
+
     #[error(".eh_frame_hdr was not successful in looking up the address in the table")]
     EhFrameHdrCouldNotFindAddress,
 
     #[error("Failed to look up the address in the DwarfCfiIndex search table")]
     DwarfCfiIndexCouldNotFindAddress,
+
+    #[error("FPO unwinding failed: {0}")]
+    Fpo(#[from] FpoUnwinderError),
+
+    #[error("No FPO data covers the address")]
+    FpoDataCouldNotFindAddress,
+}
+
+impl Error {
+    /// A stable numeric code for this error, for reporting it across an FFI boundary or
+    /// to telemetry without matching on the message. Codes are never changed or
+    /// reused. [`Error::UnusableUnwindInfo`] has the code of the [`UnwinderError`] it
+    /// wraps, so that the code always identifies the most specific error.
+    pub fn code(&self) -> u32 {
+        match self {
+            Error::CouldNotReadStack(_) => 101,
+            Error::FramepointerUnwindingMovedBackwards => 102,
+            Error::FramePointerPointsToItself(_) => 103,
+            Error::FramePointerBelowStackPointer(_) => 104,
+            Error::MisalignedFramePointer(_) => 105,
+            Error::DidNotAdvance => 106,
+            Error::IntegerOverflow => 107,
+            Error::ReturnAddressIsNull => 108,
+            Error::CouldNotFindSignalContext => 109,
+            Error::OutOfStackBounds(_) => 110,
+            Error::UnwindingCycle(_) => 111,
+            Error::StackTruncated(_) => 112,
+            Error::UnusableUnwindInfo(err) => err.error.code(),
+            Error::NeedsUnknownRegister(_) => 113,
+            Error::UntrustedFramePointer => 114,
+        }
+    }
+}
+
+impl UnwinderError {
+    /// A stable numeric code for this error, see [`Error::code`]. Errors which wrap a
+    /// DWARF, `__unwind_info` or FPO error have the code of the wrapped error.
+    pub fn code(&self) -> u32 {
+        match self {
+            UnwinderError::CompactUnwindInfo(err) => err.code(),
+            UnwinderError::Dwarf(err) => err.code(),
+            UnwinderError::NoDwarfData => 201,
+            UnwinderError::NoModuleUnwindData => 202,
+            UnwinderError::UnparseableModuleUnwindData => 203,
+            UnwinderError::EhFrameHdrCouldNotFindAddress => 204,
+            UnwinderError::DwarfCfiIndexCouldNotFindAddress => 205,
+            UnwinderError::Fpo(err) => err.code(),
+            UnwinderError::FpoDataCouldNotFindAddress => 206,
+        }
+    }
 }
 
 impl From<CompactUnwindInfoUnwinderError> for UnwinderError {
@@ -49,3 +166,28 @@ impl From<CompactUnwindInfoUnwinderError> for UnwinderError {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_codes() {
+        assert_eq!(Error::CouldNotReadStack(0x10).code(), 101);
+        assert_eq!(UnwinderError::NoModuleUnwindData.code(), 202);
+
+        // Wrapping errors report the code of the most specific error.
+        let dwarf_error = DwarfUnwinderError::CouldNotRecoverCfa;
+        let error = Error::UnusableUnwindInfo(ModuleError {
+            address: 0x1234,
+            module_avma_range_start: 0x1000,
+            relative_address: 0x234,
+            error: UnwinderError::Dwarf(dwarf_error),
+        });
+        assert_eq!(error.code(), dwarf_error.code());
+        let cui_error = CompactUnwindInfoUnwinderError::FunctionHasNoInfo;
+        assert_eq!(UnwinderError::from(cui_error).code(), cui_error.code());
+        let cui_dwarf_error = CompactUnwindInfoUnwinderError::BadDwarfUnwinding(dwarf_error);
+        assert_eq!(cui_dwarf_error.code(), dwarf_error.code());
+    }
+}
@@ -0,0 +1,336 @@
+use std::ops::Range;
+
+use crate::unwinder::Unwinder;
+
+/// The kind of an ETW image event, from the event's opcode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ImageEventKind {
+    /// Opcode 10: the image was loaded while the trace was running.
+    Load,
+    /// Opcode 2: the image was unloaded.
+    Unload,
+    /// Opcode 3: the image was already loaded when the trace started (rundown).
+    DcStart,
+    /// Opcode 4: the image was still loaded when the trace ended (rundown).
+    DcEnd,
+}
+
+impl ImageEventKind {
+    /// The kind for the opcode of an event of the `Image_Load` class, or `None` for
+    /// other opcodes.
+    pub fn from_opcode(opcode: u8) -> Option<Self> {
+        match opcode {
+            10 => Some(ImageEventKind::Load),
+            2 => Some(ImageEventKind::Unload),
+            3 => Some(ImageEventKind::DcStart),
+            4 => Some(ImageEventKind::DcEnd),
+            _ => None,
+        }
+    }
+}
+
+/// Why [`ImageEvent::parse`] failed.
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum EtwParseError {
+    #[error("The event data ended unexpectedly")]
+    UnexpectedEnd,
+
+    #[error("The file name is not terminated")]
+    UnterminatedFileName,
+}
+
+/// An ETW image load or unload event from the kernel's image provider.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImageEvent {
+    pub kind: ImageEventKind,
+    /// The timestamp from the event header, in the trace's clock.
+    pub timestamp: u64,
+    pub process_id: u32,
+    /// The address that the image was loaded at.
+    pub image_base: u64,
+    pub image_size: u64,
+    /// The checksum from the PE header.
+    pub checksum: u32,
+    /// The timestamp from the PE header, which identifies the image together with
+    /// `image_size`.
+    pub time_date_stamp: u32,
+    /// The path of the image file, often as an NT device path like
+    /// `\Device\HarddiskVolume3\Windows\System32\ntdll.dll`.
+    pub path: String,
+}
+
+impl ImageEvent {
+    /// Parse the user data of an `Image_Load` class event (version 2 or later).
+    /// `is_64_bit` says whether pointers in the event are 8 bytes long, which is the
+    /// case for events from 64-bit systems.
+    pub fn parse(
+        kind: ImageEventKind,
+        timestamp: u64,
+        data: &[u8],
+        is_64_bit: bool,
+    ) -> Result<Self, EtwParseError> {
+        let mut reader = Reader { data, is_64_bit };
+        let image_base = reader.read_pointer()?;
+        let image_size = reader.read_pointer()?;
+        let process_id = reader.read_u32()?;
+        let checksum = reader.read_u32()?;
+        let time_date_stamp = reader.read_u32()?;
+        let _reserved0 = reader.read_u32()?;
+        let _default_base = reader.read_pointer()?;
+        for _ in 0..4 {
+            let _reserved = reader.read_u32()?;
+        }
+        let path = reader.read_utf16_string()?;
+        Ok(Self {
+            kind,
+            timestamp,
+            process_id,
+            image_base,
+            image_size,
+            checksum,
+            time_date_stamp,
+            path,
+        })
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    is_64_bit: bool,
+}
+
+impl Reader<'_> {
+    fn read_bytes<const N: usize>(&mut self) -> Result<[u8; N], EtwParseError> {
+        let bytes = self.data.get(..N).ok_or(EtwParseError::UnexpectedEnd)?;
+        let mut array = [0; N];
+        array.copy_from_slice(bytes);
+        self.data = &self.data[N..];
+        Ok(array)
+    }
+
+    fn read_u32(&mut self) -> Result<u32, EtwParseError> {
+        Ok(u32::from_le_bytes(self.read_bytes()?))
+    }
+
+    fn read_pointer(&mut self) -> Result<u64, EtwParseError> {
+        if self.is_64_bit {
+            Ok(u64::from_le_bytes(self.read_bytes()?))
+        } else {
+            Ok(u64::from(self.read_u32()?))
+        }
+    }
+
+    fn read_utf16_string(&mut self) -> Result<String, EtwParseError> {
+        let mut units = Vec::new();
+        loop {
+            let unit = match self.read_bytes::<2>() {
+                Ok(bytes) => u16::from_le_bytes(bytes),
+                Err(_) => return Err(EtwParseError::UnterminatedFileName),
+            };
+            if unit == 0 {
+                return Ok(String::from_utf16_lossy(&units));
+            }
+            units.push(unit);
+        }
+    }
+}
+
+/// An image in a [`ModuleTimeline`], with the time range during which it was loaded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimelineModule {
+    pub image_base: u64,
+    pub image_size: u64,
+    pub checksum: u32,
+    pub time_date_stamp: u32,
+    pub path: String,
+    /// When the image was loaded, or 0 if it was already loaded when the trace started.
+    pub load_timestamp: u64,
+    /// When the image was unloaded, or `None` if it wasn't unloaded during the trace.
+    pub unload_timestamp: Option<u64>,
+}
+
+impl TimelineModule {
+    /// The address range of the image.
+    pub fn avma_range(&self) -> Range<u64> {
+        self.image_base..self.image_base.saturating_add(self.image_size)
+    }
+
+    /// Whether the image was loaded at `timestamp`.
+    pub fn is_loaded_at(&self, timestamp: u64) -> bool {
+        self.load_timestamp <= timestamp
+            && self
+                .unload_timestamp
+                .is_none_or(|unload_timestamp| timestamp < unload_timestamp)
+    }
+}
+
+/// The images of one process over the course of an ETW trace, built from its image
+/// events, so that samples can be unwound with the modules that were loaded when
+/// they were taken. This is the module half of an ETW-based profiler; framehop does
+/// the unwinding half.
+///
+/// Feed all image events of the trace to [`ModuleTimeline::add_event`], then look up
+/// the modules for each sample's timestamp. Framehop doesn't read image files, so
+/// [`ModuleTimeline::add_modules_at`] takes a callback which creates the
+/// [`Module`](crate::Module) for an image.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ModuleTimeline {
+    process_id: u32,
+    modules: Vec<TimelineModule>,
+}
+
+impl ModuleTimeline {
+    /// A timeline for the process with the ID `process_id`.
+    pub fn new(process_id: u32) -> Self {
+        Self {
+            process_id,
+            modules: Vec::new(),
+        }
+    }
+
+    /// Apply an image event. Events for other processes and `DcEnd` events are
+    /// ignored. Unload events without a matching load are ignored too, because
+    /// images can be unloaded before the rundown of a trace which started late.
+    pub fn add_event(&mut self, event: &ImageEvent) {
+        if event.process_id != self.process_id {
+            return;
+        }
+        let load_timestamp = match event.kind {
+            ImageEventKind::Load => event.timestamp,
+            ImageEventKind::DcStart => 0,
+            ImageEventKind::Unload => {
+                if let Some(module) = self.modules.iter_mut().rev().find(|module| {
+                    module.image_base == event.image_base && module.unload_timestamp.is_none()
+                }) {
+                    module.unload_timestamp = Some(event.timestamp);
+                }
+                return;
+            }
+            ImageEventKind::DcEnd => return,
+        };
+        self.modules.push(TimelineModule {
+            image_base: event.image_base,
+            image_size: event.image_size,
+            checksum: event.checksum,
+            time_date_stamp: event.time_date_stamp,
+            path: event.path.clone(),
+            load_timestamp,
+            unload_timestamp: None,
+        });
+    }
+
+    /// All images of the process, in the order of their load events.
+    pub fn modules(&self) -> &[TimelineModule] {
+        &self.modules
+    }
+
+    /// The images that were loaded at `timestamp`.
+    pub fn modules_at(&self, timestamp: u64) -> impl Iterator<Item = &TimelineModule> {
+        self.modules
+            .iter()
+            .filter(move |module| module.is_loaded_at(timestamp))
+    }
+
+    /// The image that contained `address` at `timestamp`.
+    pub fn module_for_address(&self, address: u64, timestamp: u64) -> Option<&TimelineModule> {
+        self.modules_at(timestamp)
+            .find(|module| module.avma_range().contains(&address))
+    }
+
+    /// Add the images that were loaded at `timestamp` to `unwinder`, with modules from
+    /// `create_module`. Images for which it returns `None` are skipped.
+    pub fn add_modules_at<U, F>(&self, timestamp: u64, unwinder: &mut U, mut create_module: F)
+    where
+        U: Unwinder,
+        F: FnMut(&TimelineModule) -> Option<U::Module>,
+    {
+        for module in self.modules_at(timestamp) {
+            if let Some(module) = create_module(module) {
+                unwinder.add_module(module);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn event_data(image_base: u64, image_size: u64, process_id: u32, path: &str) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&image_base.to_le_bytes());
+        data.extend_from_slice(&image_size.to_le_bytes());
+        data.extend_from_slice(&process_id.to_le_bytes());
+        data.extend_from_slice(&0x1234u32.to_le_bytes());
+        data.extend_from_slice(&0x5678u32.to_le_bytes());
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(&0x180000000u64.to_le_bytes());
+        data.extend_from_slice(&[0; 16]);
+        for unit in path.encode_utf16().chain([0]) {
+            data.extend_from_slice(&unit.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn test_parse() {
+        let data = event_data(0x7ffa0000, 0x2000, 42, r"\Windows\System32\ntdll.dll");
+        let event = ImageEvent::parse(ImageEventKind::Load, 100, &data, true).unwrap();
+        assert_eq!(
+            event,
+            ImageEvent {
+                kind: ImageEventKind::Load,
+                timestamp: 100,
+                process_id: 42,
+                image_base: 0x7ffa0000,
+                image_size: 0x2000,
+                checksum: 0x1234,
+                time_date_stamp: 0x5678,
+                path: r"\Windows\System32\ntdll.dll".to_string(),
+            }
+        );
+        assert_eq!(
+            ImageEvent::parse(ImageEventKind::Load, 100, &data[..20], true),
+            Err(EtwParseError::UnexpectedEnd)
+        );
+        assert_eq!(
+            ImageEvent::parse(ImageEventKind::Load, 100, &data[..data.len() - 2], true),
+            Err(EtwParseError::UnterminatedFileName)
+        );
+    }
+
+    #[test]
+    fn test_timeline() {
+        let event = |kind, timestamp, image_base, process_id| {
+            let data = event_data(image_base, 0x1000, process_id, "a.dll");
+            ImageEvent::parse(kind, timestamp, &data, true).unwrap()
+        };
+        let mut timeline = ModuleTimeline::new(42);
+        timeline.add_event(&event(ImageEventKind::DcStart, 10, 0x10000, 42));
+        timeline.add_event(&event(ImageEventKind::Load, 20, 0x20000, 42));
+        timeline.add_event(&event(ImageEventKind::Load, 20, 0x30000, 7));
+        timeline.add_event(&event(ImageEventKind::Unload, 30, 0x20000, 42));
+        timeline.add_event(&event(ImageEventKind::Load, 40, 0x20000, 42));
+        timeline.add_event(&event(ImageEventKind::DcEnd, 50, 0x10000, 42));
+        assert_eq!(timeline.modules().len(), 3);
+
+        let bases_at = |timestamp| {
+            timeline
+                .modules_at(timestamp)
+                .map(|module| module.image_base)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(bases_at(5), vec![0x10000]);
+        assert_eq!(bases_at(25), vec![0x10000, 0x20000]);
+        assert_eq!(bases_at(35), vec![0x10000]);
+        assert_eq!(bases_at(45), vec![0x10000, 0x20000]);
+        assert_eq!(
+            timeline
+                .module_for_address(0x20800, 45)
+                .map(|module| module.load_timestamp),
+            Some(40)
+        );
+        assert_eq!(timeline.module_for_address(0x30800, 25), None);
+    }
+}
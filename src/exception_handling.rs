@@ -0,0 +1,45 @@
+/// The exception handling information of a function, from its DWARF FDE: what a
+/// two-phase exception handling runtime needs to call the function's personality
+/// routine during the search and cleanup phases.
+///
+/// Returned by `exception_handling_info` on
+/// [`UnwinderX86_64`](crate::x86_64::UnwinderX86_64) and
+/// [`UnwinderAarch64`](crate::aarch64::UnwinderAarch64). All addresses are AVMAs, i.e.
+/// addresses in the process's address space.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ExceptionHandlingInfo {
+    /// The start address of the function that contains the looked-up address. Call
+    /// site tables in the LSDA are relative to this address.
+    pub function_start: u64,
+    /// Whether `function_start` is the entry of a function. This is false for the
+    /// cold part of a function which the compiler split into hot and cold parts, e.g.
+    /// `foo.cold` in `.text.unlikely`: the cold part has its own FDE, and its LSDA call
+    /// sites are relative to the start of the cold part, but it is not a function of its
+    /// own. The unwind information doesn't link it to the hot part, so symbolicate it
+    /// with the symbol table instead of reporting `function_start` as a function.
+    pub is_function_entry: bool,
+    /// The personality routine, from the "P" augmentation of the CIE.
+    pub personality: Option<EhPointer>,
+    /// The language-specific data area, from the "L" augmentation of the CIE.
+    pub lsda: Option<EhPointer>,
+}
+
+/// A pointer in an [`ExceptionHandlingInfo`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EhPointer {
+    /// The address itself.
+    Direct(u64),
+    /// The address of a pointer-sized slot, usually in the GOT, which holds the
+    /// address. Read it from the process's memory.
+    Indirect(u64),
+}
+
+impl EhPointer {
+    pub(crate) fn from_gimli_svma(pointer: gimli::Pointer, base_svma: u64, base_avma: u64) -> Self {
+        let to_avma = |svma: u64| svma.wrapping_sub(base_svma).wrapping_add(base_avma);
+        match pointer {
+            gimli::Pointer::Direct(svma) => EhPointer::Direct(to_avma(svma)),
+            gimli::Pointer::Indirect(svma) => EhPointer::Indirect(to_avma(svma)),
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::aarch64::{CacheAarch64, PtrAuthMask, UnwindRegsAarch64, UnwinderAarch64};
+use crate::unwinder::{Module, ModuleSvmaInfo, ModuleUnwindData, TextByteData, Unwinder};
+use crate::x86_64::{CacheX86_64, UnwindRegsX86_64, UnwinderX86_64};
+
+/// A recording of one stack walk, with everything that is needed to run it again: the
+/// initial registers, the stack memory that was read, and the modules with their
+/// unwind sections. The frames of the recorded walk are stored too.
+///
+/// Fixtures make wrong stacks reproducible. Record the walk with
+/// [`UnwindFixture::record`], serialize the fixture with any serde format, and run the
+/// walk again with [`UnwindFixture::replay`], for example in a unit test.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct UnwindFixture {
+    /// The address of the first frame.
+    pub pc: u64,
+    /// The initial registers. Their variant selects the CPU architecture.
+    pub regs: FixtureRegs,
+    /// The 8-byte stack words that the walk read, by address.
+    pub stack: BTreeMap<u64, u64>,
+    /// The modules of the process.
+    pub modules: Vec<FixtureModule>,
+    /// The addresses of the frames of the recorded walk.
+    pub frames: Vec<u64>,
+}
+
+/// The initial registers of an [`UnwindFixture`].
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FixtureRegs {
+    /// See [`UnwindRegsX86_64`].
+    X86_64 { ip: u64, sp: u64, bp: u64 },
+    /// See [`UnwindRegsAarch64`].
+    Aarch64 {
+        lr: u64,
+        sp: u64,
+        fp: u64,
+        ptr_auth_mask: u64,
+    },
+}
+
+impl From<UnwindRegsX86_64> for FixtureRegs {
+    fn from(regs: UnwindRegsX86_64) -> Self {
+        FixtureRegs::X86_64 {
+            ip: regs.ip(),
+            sp: regs.sp(),
+            bp: regs.bp(),
+        }
+    }
+}
+
+impl From<UnwindRegsAarch64> for FixtureRegs {
+    fn from(regs: UnwindRegsAarch64) -> Self {
+        FixtureRegs::Aarch64 {
+            lr: regs.lr(),
+            sp: regs.sp(),
+            fp: regs.fp(),
+            ptr_auth_mask: regs.lr_mask().0,
+        }
+    }
+}
+
+/// A module of an [`UnwindFixture`], with the same information as a [`Module`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FixtureModule {
+    pub name: String,
+    pub avma_range: Range<u64>,
+    pub base_avma: u64,
+    pub svma_info: ModuleSvmaInfo,
+    pub unwind_data: FixtureUnwindData,
+    /// The code bytes and the address range that they cover, see [`TextByteData`].
+    pub text_data: Option<(Vec<u8>, Range<u64>)>,
+}
+
+/// The unwind sections of a [`FixtureModule`], see [`ModuleUnwindData`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum FixtureUnwindData {
+    CompactUnwindInfoAndEhFrame(Vec<u8>, Option<Vec<u8>>),
+    EhFrameHdrAndEhFrame(Vec<u8>, Vec<u8>),
+    EhFrame(Vec<u8>),
+    DebugFrame(Vec<u8>),
+    None,
+}
+
+impl FixtureModule {
+    /// Create a [`Module`] for replaying.
+    pub fn to_module(&self) -> Module<Vec<u8>> {
+        let unwind_data = match &self.unwind_data {
+            FixtureUnwindData::CompactUnwindInfoAndEhFrame(unwind_info, eh_frame) => {
+                ModuleUnwindData::CompactUnwindInfoAndEhFrame(unwind_info.clone(), eh_frame.clone())
+            }
+            FixtureUnwindData::EhFrameHdrAndEhFrame(eh_frame_hdr, eh_frame) => {
+                ModuleUnwindData::EhFrameHdrAndEhFrame(eh_frame_hdr.clone(), eh_frame.clone())
+            }
+            FixtureUnwindData::EhFrame(eh_frame) => ModuleUnwindData::EhFrame(eh_frame.clone()),
+            FixtureUnwindData::DebugFrame(debug_frame) => {
+                ModuleUnwindData::DebugFrame(debug_frame.clone())
+            }
+            FixtureUnwindData::None => ModuleUnwindData::None,
+        };
+        let text_data = self
+            .text_data
+            .as_ref()
+            .map(|(bytes, avma_range)| TextByteData::new(bytes.clone(), avma_range.clone()));
+        Module::new(
+            self.name.clone(),
+            self.avma_range.clone(),
+            self.base_avma,
+            self.svma_info.clone(),
+            unwind_data,
+            text_data,
+        )
+    }
+}
+
+impl UnwindFixture {
+    /// Walk the stack with `unwinder` and record the walk. `modules` must describe the
+    /// modules that were added to `unwinder`.
+    pub fn record<U, F>(
+        unwinder: &U,
+        modules: Vec<FixtureModule>,
+        pc: u64,
+        regs: U::UnwindRegs,
+        cache: &mut U::Cache,
+        read_stack: &mut F,
+    ) -> Self
+    where
+        U: Unwinder,
+        U::UnwindRegs: Into<FixtureRegs>,
+        F: FnMut(u64) -> Result<u64, ()>,
+    {
+        let mut stack = BTreeMap::new();
+        let mut recording_read_stack = |address| {
+            let value = read_stack(address)?;
+            stack.insert(address, value);
+            Ok(value)
+        };
+        let frames = walk(unwinder, pc, regs, cache, &mut recording_read_stack);
+        Self {
+            pc,
+            regs: regs.into(),
+            stack,
+            modules,
+            frames,
+        }
+    }
+
+    /// Walk the stack again, with the recorded stack memory and modules, and return the
+    /// addresses of the frames. Compare them with [`UnwindFixture::frames`] to check
+    /// whether the walk still produces the same stack.
+    pub fn replay(&self) -> Vec<u64> {
+        let mut read_stack = |address| self.stack.get(&address).copied().ok_or(());
+        match self.regs {
+            FixtureRegs::X86_64 { ip, sp, bp } => {
+                let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+                for module in &self.modules {
+                    unwinder.add_module(module.to_module());
+                }
+                let mut cache = CacheX86_64::new();
+                let regs = UnwindRegsX86_64::new(ip, sp, bp);
+                walk(&unwinder, self.pc, regs, &mut cache, &mut read_stack)
+            }
+            FixtureRegs::Aarch64 {
+                lr,
+                sp,
+                fp,
+                ptr_auth_mask,
+            } => {
+                let mut unwinder = UnwinderAarch64::<Vec<u8>>::new();
+                for module in &self.modules {
+                    unwinder.add_module(module.to_module());
+                }
+                let mut cache = CacheAarch64::new();
+                let regs = UnwindRegsAarch64::new_with_ptr_auth_mask(
+                    PtrAuthMask(ptr_auth_mask),
+                    lr,
+                    sp,
+                    fp,
+                );
+                walk(&unwinder, self.pc, regs, &mut cache, &mut read_stack)
+            }
+        }
+    }
+}
+
+fn walk<U, F>(
+    unwinder: &U,
+    pc: u64,
+    regs: U::UnwindRegs,
+    cache: &mut U::Cache,
+    read_stack: &mut F,
+) -> Vec<u64>
+where
+    U: Unwinder,
+    F: FnMut(u64) -> Result<u64, ()>,
+{
+    let mut iter = unwinder.iter_frames(pc, regs, cache, read_stack);
+    let mut frames = Vec::new();
+    while let Ok(Some(frame)) = iter.next() {
+        frames.push(frame.address());
+    }
+    frames
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::{test_svma_info, TestStack};
+
+    #[test]
+    fn test_record_and_replay() {
+        let module = FixtureModule {
+            name: "lib".to_string(),
+            avma_range: 0x100000..0x100400,
+            base_avma: 0x100000,
+            svma_info: test_svma_info(Some(0..0x400)),
+            unwind_data: FixtureUnwindData::None,
+            text_data: None,
+        };
+        let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        unwinder.add_module(module.to_module());
+        let stack = TestStack::frame_chain();
+        let mut read_stack = |addr| stack.read(addr);
+        let mut cache = CacheX86_64::new();
+        let fixture = UnwindFixture::record(
+            &unwinder,
+            vec![module],
+            0x100300,
+            UnwindRegsX86_64::new(0x100300, 0x10, 0x20),
+            &mut cache,
+            &mut read_stack,
+        );
+        assert_eq!(fixture.frames, vec![0x100300, 0x100200, 0x100100]);
+        assert_eq!(fixture.stack.get(&0x28), Some(&0x100200));
+
+        let json = serde_json::to_string(&fixture).unwrap();
+        let fixture: UnwindFixture = serde_json::from_str(&json).unwrap();
+        assert_eq!(fixture.replay(), fixture.frames);
+    }
+}
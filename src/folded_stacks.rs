@@ -0,0 +1,122 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::FrameAddress;
+
+/// Collects stacks in the "folded" (or "collapsed") text format that flame graph
+/// tools such as `flamegraph.pl` and `inferno` read: one line per distinct stack,
+/// with the frames from the root to the leaf separated by `;`, followed by a space
+/// and the number of samples with that stack.
+///
+/// Frames are named by a symbolizer callback, which receives each frame's
+/// [`FrameAddress`]. Use [`FrameAddress::address_for_lookup`] to find the function of
+/// a return address. Frames for which the callback returns `None` are written as hex
+/// addresses. `;` and line breaks in names are replaced by `_`, so that they don't
+/// break the format.
+///
+/// ```
+/// use framehop::{FoldedStacks, FrameAddress};
+///
+/// let leaf = FrameAddress::from_instruction_pointer(0x1010);
+/// let caller = FrameAddress::from_return_address(0x2020).unwrap();
+/// let mut stacks = FoldedStacks::new();
+/// let mut symbolize = |frame: FrameAddress| match frame.address_for_lookup() {
+///     0x2000..=0x2fff => Some("main".to_string()),
+///     _ => None,
+/// };
+/// stacks.add_stack(&[leaf, caller], 1, &mut symbolize);
+/// stacks.add_stack(&[leaf, caller], 2, &mut symbolize);
+///
+/// let mut out = String::new();
+/// stacks.write_to(&mut out).unwrap();
+/// assert_eq!(out, "main;0x1010 3\n");
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FoldedStacks {
+    counts: BTreeMap<String, u64>,
+}
+
+impl FoldedStacks {
+    /// An empty collection.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `count` samples of the stack with `frames`, which are ordered from the leaf
+    /// to the root, as the [`UnwindIterator`](crate::UnwindIterator) yields them.
+    /// Empty stacks are ignored.
+    pub fn add_stack<S>(&mut self, frames: &[FrameAddress], count: u64, symbolize: &mut S)
+    where
+        S: FnMut(FrameAddress) -> Option<String>,
+    {
+        if frames.is_empty() {
+            return;
+        }
+        let line = fold_stack(frames, symbolize);
+        *self.counts.entry(line).or_insert(0) += count;
+    }
+
+    /// Whether no stacks were added.
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// Write one line per distinct stack to `out`, sorted by the folded stack.
+    pub fn write_to<W: fmt::Write>(&self, out: &mut W) -> fmt::Result {
+        for (stack, count) in &self.counts {
+            writeln!(out, "{stack} {count}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Join the names of `frames`, which are ordered from the leaf to the root, into the
+/// stack part of a folded line. See [`FoldedStacks`].
+pub fn fold_stack<S>(frames: &[FrameAddress], symbolize: &mut S) -> String
+where
+    S: FnMut(FrameAddress) -> Option<String>,
+{
+    let mut line = String::new();
+    for (index, frame) in frames.iter().rev().enumerate() {
+        if index != 0 {
+            line.push(';');
+        }
+        match symbolize(*frame) {
+            Some(name) => line.extend(name.chars().map(|c| match c {
+                ';' | '\n' | '\r' => '_',
+                c => c,
+            })),
+            None => line += &format!("0x{:x}", frame.address()),
+        }
+    }
+    line
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_folded_stacks() {
+        let frames = [
+            FrameAddress::from_instruction_pointer(0x1010),
+            FrameAddress::from_return_address(0x2020).unwrap(),
+            FrameAddress::from_return_address(0x3030).unwrap(),
+        ];
+        let mut symbolize = |frame: FrameAddress| match frame.address_for_lookup() {
+            0x2000..=0x2fff => Some("ns::f<a;b>".to_string()),
+            0x3000..=0x3fff => Some("main".to_string()),
+            _ => None,
+        };
+        let mut stacks = FoldedStacks::new();
+        assert!(stacks.is_empty());
+        stacks.add_stack(&frames, 1, &mut symbolize);
+        stacks.add_stack(&frames[1..], 5, &mut symbolize);
+        stacks.add_stack(&frames, 2, &mut symbolize);
+        stacks.add_stack(&[], 1, &mut symbolize);
+
+        let mut out = String::new();
+        stacks.write_to(&mut out).unwrap();
+        assert_eq!(out, "main;ns::f<a_b> 5\nmain;ns::f<a_b>;0x1010 3\n");
+    }
+}
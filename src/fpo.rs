@@ -0,0 +1,239 @@
+use crate::arch::Arch;
+
+/// The size of an `FPO_DATA` record.
+const FPO_DATA_SIZE: usize = 16;
+
+/// What kind of frame a function in an [`FpoData`] record has, from the `cbFrame`
+/// field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FpoFrameType {
+    /// `FRAME_FPO`: a regular function, which may or may not set up an ebp frame.
+    Fpo,
+    /// `FRAME_TRAP`: a kernel trap frame.
+    Trap,
+    /// `FRAME_TSS`: a kernel task state segment.
+    Tss,
+    /// `FRAME_NONFPO`: a function which was compiled without frame pointer omission.
+    NonFpo,
+}
+
+/// The frame pointer omission (FPO) data of a function in a 32-bit x86 Windows
+/// binary, parsed from its `FPO_DATA` record. FPO data is in the PDB of the binary,
+/// or, for older binaries, in the `IMAGE_DEBUG_TYPE_FPO` entry of the binary's debug
+/// directory. It describes the stack frame of the function after its prologue.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FpoData {
+    /// `ulOffStart`: the start of the function, relative to the module's base address.
+    pub function_start: u32,
+    /// `cbProcSize`: the size of the function in bytes.
+    pub function_size: u32,
+    /// `cdwLocals`: the size of the local variables, in units of 4 bytes.
+    pub locals_size_by_4: u32,
+    /// `cdwParams`: the size of the parameters, in units of 4 bytes.
+    pub params_size_by_4: u16,
+    /// `cbProlog`: the size of the prologue in bytes.
+    pub prolog_size: u8,
+    /// `cbRegs`: the number of registers which the prologue pushes.
+    pub saved_regs: u8,
+    /// `fHasSEH`: whether the function has a structured exception handler.
+    pub has_seh: bool,
+    /// `fUseBP`: whether the function sets up an ebp frame.
+    pub uses_bp: bool,
+    /// `cbFrame`: the kind of frame.
+    pub frame_type: FpoFrameType,
+}
+
+impl FpoData {
+    /// Parse an `FPO_DATA` record.
+    pub fn parse(record: &[u8; FPO_DATA_SIZE]) -> Self {
+        let u32_at = |offset: usize| {
+            u32::from_le_bytes([
+                record[offset],
+                record[offset + 1],
+                record[offset + 2],
+                record[offset + 3],
+            ])
+        };
+        // cbProlog : 8, cbRegs : 3, fHasSEH : 1, fUseBP : 1, reserved : 1, cbFrame : 2
+        let bits = u16::from_le_bytes([record[14], record[15]]);
+        let frame_type = match bits >> 14 {
+            0 => FpoFrameType::Fpo,
+            1 => FpoFrameType::Trap,
+            2 => FpoFrameType::Tss,
+            _ => FpoFrameType::NonFpo,
+        };
+        Self {
+            function_start: u32_at(0),
+            function_size: u32_at(4),
+            locals_size_by_4: u32_at(8),
+            params_size_by_4: u16::from_le_bytes([record[12], record[13]]),
+            prolog_size: (bits & 0xff) as u8,
+            saved_regs: ((bits >> 8) & 0x7) as u8,
+            has_seh: bits & (1 << 11) != 0,
+            uses_bp: bits & (1 << 12) != 0,
+            frame_type,
+        }
+    }
+
+    /// Whether the function covers `relative_address`.
+    fn contains(&self, relative_address: u32) -> bool {
+        matches!(
+            relative_address.checked_sub(self.function_start),
+            Some(offset) if offset < self.function_size
+        )
+    }
+}
+
+/// The FPO records of a module, sorted by function start. The records in a PDB are
+/// usually sorted already, but nothing guarantees it, so they are sorted when the
+/// module is added.
+pub(crate) struct FpoIndex {
+    records: Vec<FpoData>,
+}
+
+impl FpoIndex {
+    /// Parse the `FPO_DATA` records in `data`. Returns `None` if `data` isn't a whole
+    /// number of records.
+    pub fn try_new(data: &[u8]) -> Option<Self> {
+        if !data.len().is_multiple_of(FPO_DATA_SIZE) {
+            return None;
+        }
+        let mut records: Vec<FpoData> = data
+            .chunks_exact(FPO_DATA_SIZE)
+            .filter_map(|chunk| chunk.try_into().ok())
+            .map(FpoData::parse)
+            .collect();
+        records.sort_by_key(|record| record.function_start);
+        Some(Self { records })
+    }
+
+    /// The record of the function which covers `relative_address`.
+    pub fn lookup(&self, relative_address: u32) -> Option<&FpoData> {
+        let index = self
+            .records
+            .partition_point(|record| record.function_start <= relative_address);
+        let record = self.records.get(index.checked_sub(1)?)?;
+        if record.contains(relative_address) {
+            Some(record)
+        } else {
+            None
+        }
+    }
+}
+
+/// Why a function's FPO data couldn't be used for an address.
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FpoUnwinderError {
+    #[error("FPO data can only be used on 32-bit x86")]
+    UnsupportedArchitecture,
+
+    #[error("The function has a kernel trap or TSS frame, which FPO data doesn't describe")]
+    UnsupportedFrameType,
+
+    #[error("Stack size does not fit into the rule representation")]
+    StackSizeDoesNotFit,
+
+    #[error("The address is inside the function's prologue, which FPO data doesn't describe")]
+    InsidePrologue,
+}
+
+impl FpoUnwinderError {
+    /// A stable numeric code for this error, see [`Error::code`](crate::Error::code).
+    pub fn code(&self) -> u32 {
+        match self {
+            FpoUnwinderError::UnsupportedArchitecture => 601,
+            FpoUnwinderError::UnsupportedFrameType => 602,
+            FpoUnwinderError::StackSizeDoesNotFit => 603,
+            FpoUnwinderError::InsidePrologue => 604,
+        }
+    }
+}
+
+pub trait FpoUnwinding: Arch {
+    /// The rule for the address at `offset_within_function` in the function that `fpo`
+    /// describes. FPO data only exists for 32-bit x86 code.
+    fn rule_for_fpo_data(
+        _fpo: &FpoData,
+        _is_first_frame: bool,
+        _offset_within_function: u32,
+    ) -> Result<Self::UnwindRule, FpoUnwinderError> {
+        Err(FpoUnwinderError::UnsupportedArchitecture)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record(function_start: u32, function_size: u32, bits: u16) -> [u8; FPO_DATA_SIZE] {
+        let mut record = [0; FPO_DATA_SIZE];
+        record[0..4].copy_from_slice(&function_start.to_le_bytes());
+        record[4..8].copy_from_slice(&function_size.to_le_bytes());
+        record[8..12].copy_from_slice(&3u32.to_le_bytes());
+        record[12..14].copy_from_slice(&2u16.to_le_bytes());
+        record[14..16].copy_from_slice(&bits.to_le_bytes());
+        record
+    }
+
+    #[test]
+    fn test_parse() {
+        // cbProlog 6, cbRegs 2, fUseBP, FRAME_NONFPO
+        let fpo = FpoData::parse(&record(0x1000, 0x40, 0xd206));
+        assert_eq!(
+            fpo,
+            FpoData {
+                function_start: 0x1000,
+                function_size: 0x40,
+                locals_size_by_4: 3,
+                params_size_by_4: 2,
+                prolog_size: 6,
+                saved_regs: 2,
+                has_seh: false,
+                uses_bp: true,
+                frame_type: FpoFrameType::NonFpo,
+            }
+        );
+    }
+
+    #[test]
+    fn test_lookup() {
+        let data: Vec<u8> = [record(0x2000, 0x10, 0), record(0x1000, 0x40, 0)].concat();
+        let index = FpoIndex::try_new(&data).unwrap();
+        assert_eq!(
+            index.lookup(0x1000).map(|fpo| fpo.function_start),
+            Some(0x1000)
+        );
+        assert_eq!(
+            index.lookup(0x103f).map(|fpo| fpo.function_start),
+            Some(0x1000)
+        );
+        assert_eq!(index.lookup(0x1040), None);
+        assert_eq!(
+            index.lookup(0x2008).map(|fpo| fpo.function_start),
+            Some(0x2000)
+        );
+        assert_eq!(index.lookup(0xfff), None);
+        assert!(FpoIndex::try_new(&data[..20]).is_none());
+    }
+
+    #[test]
+    fn test_rule_in_prologue() {
+        use crate::x86::{ArchX86, UnwindRuleX86};
+
+        // cbProlog 6, cbRegs 2, FRAME_FPO
+        let fpo = FpoData::parse(&record(0x1000, 0x40, 0x0206));
+        let after_prologue = Ok(UnwindRuleX86::OffsetSpWithUnknownBp { sp_offset_by_4: 6 });
+        assert_eq!(
+            ArchX86::rule_for_fpo_data(&fpo, true, 0),
+            Ok(UnwindRuleX86::JustReturn)
+        );
+        assert_eq!(
+            ArchX86::rule_for_fpo_data(&fpo, true, 3),
+            Err(FpoUnwinderError::InsidePrologue)
+        );
+        assert_eq!(ArchX86::rule_for_fpo_data(&fpo, true, 6), after_prologue);
+        // Return addresses are never inside the prologue.
+        assert_eq!(ArchX86::rule_for_fpo_data(&fpo, false, 3), after_prologue);
+    }
+}
@@ -0,0 +1,22 @@
+/// How much a consumer can trust a frame that was produced by the unwinder.
+///
+/// Frames further up the stack are only as good as the frames below them: once a
+/// frame is recovered incorrectly, all its callers are usually garbage too. Consumers
+/// can use this to gray out dubious tails of a stack instead of trusting them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FrameConfidence {
+    /// The frame was recovered using unwind information for the code address, or it
+    /// is the initial instruction pointer.
+    Exact,
+    /// The frame was recovered by following the frame pointer chain. This is what
+    /// happens for code that isn't covered by any module or unwind information. It
+    /// is correct as long as the callee maintained a frame pointer.
+    FramePointer,
+    /// The frame was found by scanning the stack for values that look like return
+    /// addresses. Such frames may be stale values left on the stack.
+    Scanned,
+    /// Return address validation was requested and the address does not fall into
+    /// any known module, or call site verification was requested and the return
+    /// address does not follow a call instruction. This frame is likely garbage.
+    Implausible,
+}
@@ -0,0 +1,24 @@
+use crate::FrameAddress;
+
+/// A frame for which the module's unwind information and frame pointer unwinding
+/// disagree about the caller.
+///
+/// Produced by [`Unwinder::check_frame_divergence`](crate::Unwinder::check_frame_divergence)
+/// and collected by
+/// [`UnwindIterator::with_divergence_validation`](crate::UnwindIterator::with_divergence_validation).
+/// For functions that maintain a frame pointer, a divergence usually means that the
+/// compiler emitted wrong CFI for this address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameDivergence {
+    /// The address of the frame that was unwound.
+    pub address: FrameAddress,
+    /// The return address according to the unwind information (DWARF CFI or compact
+    /// unwind info).
+    pub cfi_return_address: u64,
+    /// The caller's stack pointer according to the unwind information.
+    pub cfi_sp: u64,
+    /// The return address according to frame pointer unwinding.
+    pub frame_pointer_return_address: u64,
+    /// The caller's stack pointer according to frame pointer unwinding.
+    pub frame_pointer_sp: u64,
+}
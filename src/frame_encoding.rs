@@ -0,0 +1,234 @@
+/// One frame of a stack in the compact encoding of [`encode_frames`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FrameRecord {
+    /// The index of the frame's module in the caller's module list, or `None` if the
+    /// address isn't in a known module.
+    pub module_index: Option<u32>,
+    /// The address relative to the module's base address, or the absolute address if
+    /// `module_index` is `None`.
+    pub address: u64,
+    /// Bits for the caller's use, for example the
+    /// [`FrameAddressKind`](crate::FrameAddressKind) or the
+    /// [`FrameConfidence`](crate::FrameConfidence) of the frame. They are stored as-is.
+    pub flags: u8,
+}
+
+/// Why [`decode_frames`] failed.
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FrameDecodeError {
+    #[error("The encoded frames ended unexpectedly")]
+    UnexpectedEnd,
+
+    #[error("A variable-length integer is too long")]
+    VarintTooLong,
+
+    #[error("A decoded value is out of range")]
+    ValueOutOfRange,
+}
+
+/// Append `frames` to `out` in a compact binary encoding, for storing large numbers of
+/// sampled stacks. Decode them with [`decode_frames`]. Several stacks can be appended
+/// to the same buffer.
+///
+/// The stack starts with its frame count. A frame in the same module as the previous
+/// frame stores only the change in address, with the lowest bit cleared. Other frames
+/// store the lowest bit set, followed by the change in module index and the full
+/// address. Each frame ends with the flags byte. Numbers are LEB128 varints and
+/// changes are zigzag encoded, so that consecutive frames in the same module usually
+/// take three bytes.
+pub fn encode_frames(frames: &[FrameRecord], out: &mut Vec<u8>) {
+    write_varint(out, frames.len() as u64);
+    let mut prev_module = 0u64;
+    let mut prev_address = 0u64;
+    for frame in frames {
+        let module = module_tag(frame.module_index);
+        let address_delta = zigzag(frame.address.wrapping_sub(prev_address) as i64);
+        // Deltas which don't fit next to the bit are stored like a module change.
+        if module == prev_module && address_delta >> 63 == 0 {
+            write_varint(out, address_delta << 1);
+        } else {
+            write_varint(out, 1);
+            write_varint(out, zigzag(module.wrapping_sub(prev_module) as i64));
+            write_varint(out, frame.address);
+        }
+        out.push(frame.flags);
+        prev_module = module;
+        prev_address = frame.address;
+    }
+}
+
+/// Decode one stack that was encoded with [`encode_frames`] from the start of `data`,
+/// append its frames to `out`, and return the number of bytes that were consumed.
+/// Decode the next stack from the remaining bytes.
+pub fn decode_frames(data: &[u8], out: &mut Vec<FrameRecord>) -> Result<usize, FrameDecodeError> {
+    let mut reader = Reader { data, pos: 0 };
+    let count = reader.read_varint()?;
+    let count = usize::try_from(count).map_err(|_| FrameDecodeError::ValueOutOfRange)?;
+    // Every frame takes at least two bytes, which bounds the allocation for
+    // malformed input.
+    out.reserve(count.min(data.len() / 2));
+    let mut prev_module = 0u64;
+    let mut prev_address = 0u64;
+    for _ in 0..count {
+        let header = reader.read_varint()?;
+        let (module, address) = if header & 1 == 0 {
+            let address_delta = unzigzag(header >> 1) as u64;
+            (prev_module, prev_address.wrapping_add(address_delta))
+        } else {
+            let module = prev_module.wrapping_add(unzigzag(reader.read_varint()?) as u64);
+            (module, reader.read_varint()?)
+        };
+        let flags = reader.read_u8()?;
+        let module_index = match module {
+            0 => None,
+            tag => Some(u32::try_from(tag - 1).map_err(|_| FrameDecodeError::ValueOutOfRange)?),
+        };
+        out.push(FrameRecord {
+            module_index,
+            address,
+            flags,
+        });
+        prev_module = module;
+        prev_address = address;
+    }
+    Ok(reader.pos)
+}
+
+/// 0 for frames outside of known modules, the module index plus one otherwise.
+fn module_tag(module_index: Option<u32>) -> u64 {
+    match module_index {
+        Some(index) => u64::from(index) + 1,
+        None => 0,
+    }
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn read_u8(&mut self) -> Result<u8, FrameDecodeError> {
+        let byte = *self
+            .data
+            .get(self.pos)
+            .ok_or(FrameDecodeError::UnexpectedEnd)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn read_varint(&mut self) -> Result<u64, FrameDecodeError> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.read_u8()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(FrameDecodeError::VarintTooLong)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let stacks = [
+            vec![
+                FrameRecord {
+                    module_index: Some(3),
+                    address: 0x1c2f0,
+                    flags: 0,
+                },
+                FrameRecord {
+                    module_index: Some(3),
+                    address: 0x1b004,
+                    flags: 1,
+                },
+                FrameRecord {
+                    module_index: None,
+                    address: 0xffff_ffff_ff60_0000,
+                    flags: 1,
+                },
+                FrameRecord {
+                    module_index: Some(0),
+                    address: 0x2a10,
+                    flags: 1,
+                },
+                FrameRecord {
+                    module_index: Some(u32::MAX),
+                    address: u64::MAX,
+                    flags: 0xff,
+                },
+            ],
+            vec![],
+        ];
+        let mut data = Vec::new();
+        for stack in &stacks {
+            encode_frames(stack, &mut data);
+        }
+
+        let mut remaining = &data[..];
+        for stack in &stacks {
+            let mut frames = Vec::new();
+            let consumed = decode_frames(remaining, &mut frames).unwrap();
+            assert_eq!(&frames, stack);
+            remaining = &remaining[consumed..];
+        }
+        assert!(remaining.is_empty());
+    }
+
+    #[test]
+    fn test_compact() {
+        // Frames in the same module with nearby addresses take three bytes: two for the
+        // address delta and one for the flags.
+        let frames: Vec<FrameRecord> = (0..100)
+            .map(|i| FrameRecord {
+                module_index: Some(1),
+                address: 0x10000 + i * 0x40,
+                flags: 1,
+            })
+            .collect();
+        let mut data = Vec::new();
+        encode_frames(&frames, &mut data);
+        // The first frame stores its module and full address.
+        assert_eq!(data.len(), 1 + 6 + 99 * 3);
+    }
+
+    #[test]
+    fn test_malformed() {
+        let mut frames = Vec::new();
+        assert_eq!(
+            decode_frames(&[], &mut frames),
+            Err(FrameDecodeError::UnexpectedEnd)
+        );
+        assert_eq!(
+            decode_frames(&[2, 2, 0x10, 0], &mut frames),
+            Err(FrameDecodeError::UnexpectedEnd)
+        );
+        assert_eq!(
+            decode_frames(&[0xff; 11], &mut frames),
+            Err(FrameDecodeError::VarintTooLong)
+        );
+    }
+}
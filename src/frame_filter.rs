@@ -0,0 +1,15 @@
+/// What [`UnwindIterator`](crate::UnwindIterator) should do with a frame, as decided by
+/// the filter that was set with
+/// [`UnwindIterator::with_frame_filter`](crate::UnwindIterator::with_frame_filter).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameFilterAction {
+    /// Yield the frame and continue the walk.
+    Yield,
+    /// Don't yield the frame, but continue the walk with its caller.
+    Skip,
+    /// Yield the frame and end the walk, for example at `main`.
+    YieldAndStop,
+    /// End the walk without yielding the frame, for example at the boundary to a
+    /// runtime's internal frames.
+    Stop,
+}
@@ -0,0 +1,43 @@
+use crate::error::Error;
+
+/// The result of following the frame pointer chain of a thread, from the concrete
+/// unwinder's `check_frame_pointer_chain` method.
+///
+/// Samplers can use this to decide at runtime whether cheap frame pointer walking
+/// gives complete stacks for a thread, or whether they need to copy its stack and
+/// unwind it with the unwind information.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FramePointerChain {
+    /// The number of frame records that were followed, i.e. the number of return
+    /// addresses that were found.
+    pub depth: usize,
+    /// How many of those return addresses are outside of all modules known to the
+    /// unwinder. Return addresses in unknown code usually mean that a frame pointer
+    /// pointed at something other than a frame record.
+    pub unknown_return_addresses: usize,
+    /// Why the walk ended.
+    pub end: FramePointerChainEnd,
+}
+
+/// Why [`FramePointerChain`] stopped following the chain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FramePointerChainEnd {
+    /// A frame record had a null return address, which marks the end of the stack.
+    Terminated,
+    /// The maximum depth was reached.
+    MaxDepth,
+    /// A frame record could not be read, or it didn't lead further up the stack.
+    Broken(Error),
+}
+
+impl FramePointerChain {
+    /// Whether the chain is intact: it ended at the end of the stack or at the
+    /// maximum depth, and all return addresses are in known modules.
+    pub fn is_intact(&self) -> bool {
+        self.unknown_return_addresses == 0
+            && matches!(
+                self.end,
+                FramePointerChainEnd::Terminated | FramePointerChainEnd::MaxDepth
+            )
+    }
+}
@@ -0,0 +1,113 @@
+use crate::error::UnwinderError;
+
+/// How a frame was found, for auditing the quality of stack walks.
+///
+/// Collected by
+/// [`UnwindIterator::with_provenance`](crate::UnwindIterator::with_provenance), with
+/// one entry per yielded frame. For frames found by unwinding, this describes how the
+/// previous frame was unwound.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FrameProvenance {
+    /// Where the frame came from.
+    pub source: FrameSource,
+    /// The name of the module whose code was unwound, as passed to
+    /// [`Module::new`](crate::Module::new). `None` if the address isn't in a known
+    /// module, if the module's name is empty, or if no module was involved.
+    pub module: Option<String>,
+    /// The unwind rule that was executed, formatted with its `Display` implementation,
+    /// for example `sp' = sp + 0x28; bp' = *(sp + 0x18); ra = *(sp' - 8)`. `None` if
+    /// the frame wasn't found with a rule, for example because the DWARF CFI needed a
+    /// full register state or because the frame came from the shadow stack.
+    pub rule: Option<String>,
+    /// Whether the rule was found in the unwinder cache. Cached rules don't remember
+    /// whether they were a fallback, so `fallback` is only set on cache misses.
+    pub from_cache: bool,
+    /// Why the unwinder fell back to frame pointer unwinding, if it did.
+    pub fallback: Option<FallbackReason>,
+}
+
+impl FrameProvenance {
+    /// Provenance with the given source and nothing else known.
+    pub fn new(source: FrameSource) -> Self {
+        Self {
+            source,
+            module: None,
+            rule: None,
+            from_cache: false,
+            fallback: None,
+        }
+    }
+}
+
+/// Where a frame came from. See [`FrameProvenance`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameSource {
+    /// The first frame, from the initial registers.
+    Registers,
+    /// The module's `__unwind_info`, including the DWARF CFI in `__eh_frame` that it
+    /// refers to for some functions.
+    CompactUnwindInfo,
+    /// The module's `.eh_frame`, looked up with `.eh_frame_hdr`.
+    EhFrameHdr,
+    /// The module's `.eh_frame`.
+    EhFrame,
+    /// The module's `.debug_frame`.
+    DebugFrame,
+    /// The module's FPO data, see [`ModuleUnwindData::Fpo`](crate::ModuleUnwindData::Fpo).
+    Fpo,
+    /// A signal trampoline, whose rule restores the interrupted registers from the
+    /// signal frame.
+    SignalTrampoline,
+    /// Frame pointer unwinding, as a fallback or because the unwind information said so
+    /// for a cached rule. See [`FrameProvenance::fallback`].
+    FramePointer,
+    /// The frame record of a frame in a stack switch range, or of a frame whose caller
+    /// is on an auxiliary stack.
+    StackSwitch,
+    /// A PLT stub, whose rule follows from the known structure of PLT code. See
+    /// `add_plt_range` on [`UnwinderX86_64`](crate::x86_64::UnwinderX86_64) and
+    /// [`UnwinderAarch64`](crate::aarch64::UnwinderAarch64).
+    PltStub,
+    /// A lazy binding trampoline of the dynamic linker, whose rule follows from its
+    /// code. See `add_dl_trampoline_range` on
+    /// [`UnwinderX86_64`](crate::x86_64::UnwinderX86_64) and
+    /// [`UnwinderAarch64`](crate::aarch64::UnwinderAarch64).
+    DynamicLinkerTrampoline,
+    /// The handler that was set with
+    /// [`UnwindIterator::with_stack_switch_handler`](crate::UnwindIterator::with_stack_switch_handler).
+    StackSwitchHandler,
+    /// A logical frame of a suspended async task, from the handler that was set with
+    /// [`UnwindIterator::with_async_task_handler`](crate::UnwindIterator::with_async_task_handler).
+    AsyncTask,
+    /// The shadow stack that was supplied with
+    /// [`UnwindIterator::with_shadow_stack`](crate::UnwindIterator::with_shadow_stack).
+    ShadowStack,
+    /// The last branch records that were supplied with
+    /// [`UnwindIterator::with_branch_records`](crate::UnwindIterator::with_branch_records).
+    BranchRecords,
+}
+
+/// Why the unwinder fell back to frame pointer unwinding. See [`FrameProvenance`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FallbackReason {
+    /// The address isn't in any known module.
+    NoModule,
+    /// The module was added without unwind information.
+    NoUnwindData,
+    /// The module's unwind information could not be used for the address.
+    UnusableUnwindInfo(UnwinderError),
+}
+
+impl FallbackReason {
+    /// A stable numeric code for this reason, in the same code space as
+    /// [`Error::code`](crate::Error::code), so that the problems in each frame can be
+    /// reported the same way as the error that ends a walk.
+    pub fn code(&self) -> u32 {
+        match self {
+            FallbackReason::NoModule => 501,
+            FallbackReason::NoUnwindData => UnwinderError::NoModuleUnwindData.code(),
+            FallbackReason::UnusableUnwindInfo(err) => err.code(),
+        }
+    }
+}
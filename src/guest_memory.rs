@@ -0,0 +1,165 @@
+use std::ops::Range;
+
+use crate::process_snapshot::MemorySource;
+
+/// Translates guest addresses to host addresses, for unwinding programs that run under
+/// a user-mode emulator or binary translator, such as qemu-user, Rosetta-like
+/// translators or Wine on a different architecture. See [`GuestMemory`].
+pub trait AddressTranslation {
+    /// The host address for `guest_address`, or `None` if the guest address isn't
+    /// mapped.
+    fn guest_to_host(&self, guest_address: u64) -> Option<u64>;
+}
+
+/// Guest memory that is mapped at a constant offset in the host, like qemu-user's
+/// `guest_base`: guest address `a` is at host address `a + guest_base`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GuestBase(pub u64);
+
+impl AddressTranslation for GuestBase {
+    fn guest_to_host(&self, guest_address: u64) -> Option<u64> {
+        guest_address.checked_add(self.0)
+    }
+}
+
+/// Guest memory that is mapped in separate regions, each at its own host address.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GuestMappings {
+    /// Sorted by the start of the guest range.
+    mappings: Vec<(Range<u64>, u64)>,
+}
+
+impl GuestMappings {
+    /// No mappings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Map the guest addresses in `guest_range` to the host addresses starting at
+    /// `host_start`. The range must not overlap with the ranges of other mappings.
+    pub fn add_mapping(&mut self, guest_range: Range<u64>, host_start: u64) {
+        let index = self
+            .mappings
+            .partition_point(|(range, _)| range.start < guest_range.start);
+        self.mappings.insert(index, (guest_range, host_start));
+    }
+
+    /// Remove the mapping whose guest range starts at `guest_range_start`.
+    pub fn remove_mapping(&mut self, guest_range_start: u64) {
+        self.mappings
+            .retain(|(range, _)| range.start != guest_range_start);
+    }
+}
+
+impl AddressTranslation for GuestMappings {
+    fn guest_to_host(&self, guest_address: u64) -> Option<u64> {
+        let index = self
+            .mappings
+            .partition_point(|(range, _)| range.start <= guest_address);
+        let (guest_range, host_start) = self.mappings.get(index.checked_sub(1)?)?;
+        if guest_address >= guest_range.end {
+            return None;
+        }
+        host_start.checked_add(guest_address - guest_range.start)
+    }
+}
+
+/// Reads the stack of a guest program through the host's memory.
+///
+/// Guest programs are unwound in the guest's terms: add modules at their guest
+/// addresses, pass the guest's registers, and use the unwinder for the guest's
+/// architecture. Only stack reads need the host, and this type translates them. A
+/// read fails if its guest address isn't mapped, or if `read_host` fails for the host
+/// address.
+///
+/// ```
+/// use framehop::{GuestBase, GuestMemory};
+///
+/// let host_stack = [0u64, 0x401234];
+/// let mut guest_memory = GuestMemory::new(GuestBase(0x7f0000000000), |host_address| {
+///     let index = host_address.checked_sub(0x7f0000010000).ok_or(())? / 8;
+///     host_stack.get(index as usize).copied().ok_or(())
+/// });
+/// let mut read_stack = |guest_address| guest_memory.read_u64(guest_address).ok_or(());
+/// assert_eq!(read_stack(0x10008), Ok(0x401234));
+/// ```
+pub struct GuestMemory<T, F> {
+    translation: T,
+    read_host: F,
+}
+
+impl<T: AddressTranslation, F: FnMut(u64) -> Result<u64, ()>> GuestMemory<T, F> {
+    /// Read guest memory with `read_host`, which reads 8 bytes at a host address.
+    pub fn new(translation: T, read_host: F) -> Self {
+        Self {
+            translation,
+            read_host,
+        }
+    }
+
+    /// Read the 8 bytes at `guest_address`. Returns `None` if the guest address isn't
+    /// mapped or if `read_host` fails.
+    pub fn read_u64(&mut self, guest_address: u64) -> Option<u64> {
+        let host_address = self.translation.guest_to_host(guest_address)?;
+        (self.read_host)(host_address).ok()
+    }
+
+    /// The address translation.
+    pub fn translation(&self) -> &T {
+        &self.translation
+    }
+}
+
+impl<T: AddressTranslation, F: FnMut(u64) -> Result<u64, ()>> MemorySource for GuestMemory<T, F> {
+    fn read_u64(&mut self, address: u64) -> Option<u64> {
+        GuestMemory::read_u64(self, address)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::TestStack;
+    use crate::x86_64::{CacheX86_64, UnwindRegsX86_64, UnwinderX86_64};
+    use crate::{FrameAddress, Unwinder};
+
+    #[test]
+    fn test_guest_mappings() {
+        let mut mappings = GuestMappings::new();
+        mappings.add_mapping(0x20000..0x30000, 0x7f0000000000);
+        mappings.add_mapping(0x10000..0x11000, 0x5000);
+        assert_eq!(mappings.guest_to_host(0x10010), Some(0x5010));
+        assert_eq!(mappings.guest_to_host(0x2fff8), Some(0x7f000000fff8));
+        assert_eq!(mappings.guest_to_host(0x11000), None);
+        assert_eq!(mappings.guest_to_host(0x8000), None);
+        mappings.remove_mapping(0x10000);
+        assert_eq!(mappings.guest_to_host(0x10010), None);
+    }
+
+    #[test]
+    fn test_unwind_guest_stack() {
+        // The guest's stack is at 0x0..0x80, and the host has it at 0x7f0000000000.
+        let stack = TestStack::frame_chain();
+        let mut guest_memory = GuestMemory::new(GuestBase(0x7f0000000000), |host_address| {
+            let offset = host_address.checked_sub(0x7f0000000000).ok_or(())?;
+            stack.read(offset)
+        });
+        let mut read_stack = |guest_address| guest_memory.read_u64(guest_address).ok_or(());
+        let unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        let mut cache = CacheX86_64::new();
+        let regs = UnwindRegsX86_64::new(0x100300, 0x10, 0x20);
+        let mut iter = unwinder.iter_frames(0x100300, regs, &mut cache, &mut read_stack);
+        let mut frames = Vec::new();
+        while let Ok(Some(frame)) = iter.next() {
+            frames.push(frame);
+        }
+        assert_eq!(
+            frames,
+            vec![
+                FrameAddress::from_instruction_pointer(0x100300),
+                FrameAddress::from_return_address(0x100200).unwrap(),
+                FrameAddress::from_return_address(0x100100).unwrap(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::VecDeque;
+
+use fallible_iterator::FallibleIterator;
+
+use crate::error::Error;
+use crate::unwind_iterator::UnwindIterator;
+use crate::unwinder::Unwinder;
+use crate::FrameAddress;
+
+/// Expands a frame into the logical frames of the functions that were inlined at its
+/// address, with the caller's debug information, for example from `addr2line`. See
+/// [`UnwindIterator::with_inline_frames`].
+pub trait InlineFrameExpander {
+    /// The caller's type for a logical frame, for example a function name and a source
+    /// location.
+    type Frame;
+
+    /// Append the logical frames for `address` to `frames`, innermost first, ending with
+    /// the frame of the function that contains the address.
+    ///
+    /// `call_site_address` is the address to look up in the debug information: for
+    /// return addresses, it is the address of the call instruction (see
+    /// [`Unwinder::call_site_address`]). If nothing is appended, the frame is left out
+    /// of the stack.
+    fn expand(
+        &mut self,
+        address: FrameAddress,
+        call_site_address: u64,
+        frames: &mut Vec<Self::Frame>,
+    );
+}
+
+/// A logical frame yielded by [`InlineFrameIterator`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InlineFrame<T> {
+    /// The address of the frame that this logical frame was expanded from.
+    pub address: FrameAddress,
+    /// The frame from the [`InlineFrameExpander`].
+    pub frame: T,
+    /// Whether this logical frame is inlined into the next one. This is false for the
+    /// last logical frame of each frame.
+    pub is_inlined: bool,
+}
+
+/// Wraps an [`UnwindIterator`] and yields the logical frames of every frame, as expanded
+/// by an [`InlineFrameExpander`]. Created with [`UnwindIterator::with_inline_frames`].
+pub struct InlineFrameIterator<'u, 'c, 'r, U, F, E>
+where
+    U: Unwinder + ?Sized,
+    F: FnMut(u64) -> Result<u64, ()>,
+    E: InlineFrameExpander,
+{
+    iter: UnwindIterator<'u, 'c, 'r, U, F>,
+    expander: E,
+    expanded: Vec<E::Frame>,
+    pending: VecDeque<InlineFrame<E::Frame>>,
+}
+
+impl<'u, 'c, 'r, U, F, E> InlineFrameIterator<'u, 'c, 'r, U, F, E>
+where
+    U: Unwinder + ?Sized,
+    F: FnMut(u64) -> Result<u64, ()>,
+    E: InlineFrameExpander,
+{
+    pub(crate) fn new(iter: UnwindIterator<'u, 'c, 'r, U, F>, expander: E) -> Self {
+        Self {
+            iter,
+            expander,
+            expanded: Vec::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// The wrapped iterator, for example to get the
+    /// [`end_reason`](UnwindIterator::end_reason) of the walk.
+    pub fn unwind_iterator(&self) -> &UnwindIterator<'u, 'c, 'r, U, F> {
+        &self.iter
+    }
+
+    /// Yield the next logical frame. The walk ends like the wrapped
+    /// [`UnwindIterator::next`].
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<InlineFrame<E::Frame>>, Error> {
+        while self.pending.is_empty() {
+            let address = match self.iter.next()? {
+                Some(address) => address,
+                None => return Ok(None),
+            };
+            let call_site_address = self.iter.call_site_address(address);
+            self.expander
+                .expand(address, call_site_address, &mut self.expanded);
+            let count = self.expanded.len();
+            self.pending
+                .extend(
+                    self.expanded
+                        .drain(..)
+                        .enumerate()
+                        .map(|(index, frame)| InlineFrame {
+                            address,
+                            frame,
+                            is_inlined: index + 1 < count,
+                        }),
+                );
+        }
+        Ok(self.pending.pop_front())
+    }
+}
+
+impl<'u, 'c, 'r, U, F, E> FallibleIterator for InlineFrameIterator<'u, 'c, 'r, U, F, E>
+where
+    U: Unwinder + ?Sized,
+    F: FnMut(u64) -> Result<u64, ()>,
+    E: InlineFrameExpander,
+{
+    type Item = InlineFrame<E::Frame>;
+    type Error = Error;
+
+    fn next(&mut self) -> Result<Option<InlineFrame<E::Frame>>, Error> {
+        self.next()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::{test_module, TestStack};
+    use crate::x86_64::{CacheX86_64, UnwindRegsX86_64, UnwinderX86_64};
+
+    /// Pretends that a function is inlined at 0x100200 and that 0x100100 has no debug
+    /// information.
+    struct Expander;
+
+    impl InlineFrameExpander for Expander {
+        type Frame = &'static str;
+
+        fn expand(
+            &mut self,
+            _address: FrameAddress,
+            call_site_address: u64,
+            frames: &mut Vec<&'static str>,
+        ) {
+            match call_site_address {
+                0x100300 => frames.push("leaf"),
+                0x1001ff => frames.extend(["inlined", "caller"]),
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn test_inline_frames() {
+        let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        unwinder.add_module(test_module(0x100000..0x100400, None));
+        let stack = TestStack::frame_chain();
+        let mut read_stack = |addr| stack.read(addr);
+        let mut cache = CacheX86_64::new();
+        let regs = UnwindRegsX86_64::new(0x100300, 0x10, 0x20);
+        let mut iter = unwinder
+            .iter_frames(0x100300, regs, &mut cache, &mut read_stack)
+            .with_inline_frames(Expander);
+        let mut frames = Vec::new();
+        while let Ok(Some(frame)) = iter.next() {
+            frames.push((frame.address.address(), frame.frame, frame.is_inlined));
+        }
+        assert_eq!(
+            frames,
+            vec![
+                (0x100300, "leaf", false),
+                (0x100200, "inlined", true),
+                (0x100200, "caller", false),
+            ]
+        );
+    }
+}
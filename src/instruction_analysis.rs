@@ -9,6 +9,45 @@ pub trait InstructionAnalysis: Arch {
     fn rule_from_epilogue_analysis(text_bytes: &[u8], pc_offset: usize)
         -> Option<Self::UnwindRule>;
 
+    /// Returns the length of the call instruction that ends at return_address_offset,
+    /// if there is one.
+    /// Caller guarantees return_address_offset <= text_bytes.len()
+    fn call_instruction_len(text_bytes: &[u8], return_address_offset: usize) -> Option<usize>;
+
+    /// Detects the Linux sigreturn trampoline, which signal handlers return to.
+    /// Caller guarantees pc_offset <= text_bytes.len()
+    fn rule_from_sigreturn_trampoline_analysis(
+        text_bytes: &[u8],
+        pc_offset: usize,
+    ) -> Option<Self::UnwindRule>;
+
+    /// Returns the rule for pc_offset in a PLT section, which starts at the start of
+    /// plt_bytes, based on the known structure of PLT stubs. Returns None if the bytes
+    /// aren't PLT code.
+    /// Caller guarantees pc_offset <= plt_bytes.len()
+    fn rule_from_plt_analysis(plt_bytes: &[u8], pc_offset: usize) -> Option<Self::UnwindRule>;
+
+    /// Returns the rule for pc_offset in a lazy binding trampoline of the dynamic
+    /// linker, such as `_dl_runtime_resolve`, which starts at the start of
+    /// function_bytes. Returns None if the stack offset at pc_offset can't be derived
+    /// from the code.
+    /// Caller guarantees pc_offset <= function_bytes.len()
+    fn rule_from_dl_trampoline_analysis(
+        function_bytes: &[u8],
+        pc_offset: usize,
+    ) -> Option<Self::UnwindRule>;
+
+    /// Decodes the instructions from the start of the function up to pc_offset, and
+    /// returns a rule if they are all prologue instructions. Returns None if pc_offset
+    /// is past the end of the prologue.
+    /// Caller guarantees pc_offset <= function_bytes.len()
+    fn rule_from_function_start_analysis(
+        _function_bytes: &[u8],
+        _pc_offset: usize,
+    ) -> Option<Self::UnwindRule> {
+        None
+    }
+
     /// Caller guarantees pc_offset <= text_bytes.len()
     fn rule_from_instruction_analysis(
         text_bytes: &[u8],
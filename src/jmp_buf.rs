@@ -0,0 +1,105 @@
+use crate::aarch64::UnwindRegsAarch64;
+use crate::x86_64::UnwindRegsX86_64;
+
+/// How the C library protected the pointers in a `jmp_buf`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PointerMangling {
+    /// The pointers are stored as they are, as in musl.
+    None,
+    /// glibc's `PTR_MANGLE`, with the pointer guard of the process. The guard is at
+    /// `fs:0x30` on x86_64, in the `pointer_guard` field of the thread control block,
+    /// and in `__pointer_chk_guard` on aarch64. On x86_64, the mangled pointer is
+    /// `rol(ptr ^ guard, 0x11)`; on aarch64, it is `ptr ^ guard`.
+    Glibc { pointer_guard: u64 },
+}
+
+/// The offsets in glibc's and musl's x86_64 `__jmp_buf`, in units of 8 bytes.
+const X86_64_JB_RBP: usize = 1;
+const X86_64_JB_RSP: usize = 6;
+const X86_64_JB_PC: usize = 7;
+
+/// The offsets in glibc's and musl's aarch64 `__jmp_buf`, in units of 8 bytes.
+const AARCH64_JB_X29: usize = 10;
+const AARCH64_JB_LR: usize = 11;
+const AARCH64_JB_SP: usize = 13;
+
+/// The code address and the registers at which `longjmp` with this `jmp_buf` would
+/// resume, from the 8 register words at the start of a glibc or musl x86_64
+/// `jmp_buf`. Returns `None` if `jmp_buf` is too short.
+///
+/// The code address is the return address of the `setjmp` call, so start the
+/// unwind with [`FrameAddressKind::ReturnAddress`](crate::FrameAddressKind::ReturnAddress),
+/// see [`UnwindIterator::with_first_frame_kind`](crate::UnwindIterator::with_first_frame_kind).
+pub fn unwind_regs_from_jmp_buf_x86_64(
+    jmp_buf: &[u64],
+    mangling: PointerMangling,
+) -> Option<(u64, UnwindRegsX86_64)> {
+    let demangle = |ptr: u64| match mangling {
+        PointerMangling::None => ptr,
+        PointerMangling::Glibc { pointer_guard } => ptr.rotate_right(0x11) ^ pointer_guard,
+    };
+    let pc = demangle(*jmp_buf.get(X86_64_JB_PC)?);
+    let sp = demangle(*jmp_buf.get(X86_64_JB_RSP)?);
+    let bp = demangle(*jmp_buf.get(X86_64_JB_RBP)?);
+    Some((pc, UnwindRegsX86_64::new(pc, sp, bp)))
+}
+
+/// The code address and the registers at which `longjmp` with this `jmp_buf` would
+/// resume, from the 14 register words at the start of a glibc or musl aarch64
+/// `jmp_buf`. Returns `None` if `jmp_buf` is too short. glibc only mangles lr and sp.
+///
+/// As on x86_64, the code address is the return address of the `setjmp` call.
+pub fn unwind_regs_from_jmp_buf_aarch64(
+    jmp_buf: &[u64],
+    mangling: PointerMangling,
+) -> Option<(u64, UnwindRegsAarch64)> {
+    let demangle = |ptr: u64| match mangling {
+        PointerMangling::None => ptr,
+        PointerMangling::Glibc { pointer_guard } => ptr ^ pointer_guard,
+    };
+    let lr = demangle(*jmp_buf.get(AARCH64_JB_LR)?);
+    let sp = demangle(*jmp_buf.get(AARCH64_JB_SP)?);
+    let fp = *jmp_buf.get(AARCH64_JB_X29)?;
+    Some((lr, UnwindRegsAarch64::new(lr, sp, fp)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_glibc_x86_64() {
+        let pointer_guard = 0x1234_5678_9abc_def0;
+        let mangle = |ptr: u64| (ptr ^ pointer_guard).rotate_left(0x11);
+        let jmp_buf = [
+            1,
+            mangle(0x7ff0),
+            3,
+            4,
+            5,
+            6,
+            mangle(0x7fc8),
+            mangle(0x401234),
+        ];
+        let (pc, regs) =
+            unwind_regs_from_jmp_buf_x86_64(&jmp_buf, PointerMangling::Glibc { pointer_guard })
+                .unwrap();
+        assert_eq!(pc, 0x401234);
+        assert_eq!(regs, UnwindRegsX86_64::new(0x401234, 0x7fc8, 0x7ff0));
+        assert_eq!(
+            unwind_regs_from_jmp_buf_x86_64(&jmp_buf[..7], PointerMangling::None),
+            None
+        );
+    }
+
+    #[test]
+    fn test_aarch64() {
+        let mut jmp_buf = [0; 22];
+        jmp_buf[AARCH64_JB_X29] = 0x7ff0;
+        jmp_buf[AARCH64_JB_LR] = 0x401234;
+        jmp_buf[AARCH64_JB_SP] = 0x7fc0;
+        let (pc, regs) = unwind_regs_from_jmp_buf_aarch64(&jmp_buf, PointerMangling::None).unwrap();
+        assert_eq!(pc, 0x401234);
+        assert_eq!(regs, UnwindRegsAarch64::new(0x401234, 0x7fc0, 0x7ff0));
+    }
+}
@@ -2,7 +2,7 @@
 //!
 //! Framehop is a stack frame unwinder written in 100% Rust. It produces high quality stacks at high speed, on multiple platforms and architectures, without an expensive pre-processing step for unwind information. This makes it suitable for sampling profilers.
 //!
-//! It currently supports unwinding x86_64 and aarch64, with unwind information formats commonly used on macOS, Linux and Android.
+//! It currently supports unwinding x86_64, aarch64 and 32-bit x86, with unwind information formats commonly used on macOS, Linux, Android and 32-bit Windows.
 //!
 //! You give framehop register values, stack memory and unwind data, and framehop produces a list of return addresses.
 //!
@@ -26,13 +26,14 @@
 //!    - Apple's Compact Unwinding Format, in `__unwind_info` (macOS)
 //!    - DWARF CFI in `.eh_frame` (using `.eh_frame_hdr` as an index, if available)
 //!    - DWARF CFI in `.debug_frame`
+//!    - FPO data from the PDB or the debug directory of 32-bit Windows binaries
 //!  - It supports correct unwinding even when the program is interrupted inside a function prologue or epilogue. On macOS, it has to analyze assembly instructions in order to do this.
-//!  - On x86_64 and aarch64, it falls back to frame pointer unwinding if it cannot find unwind information for an address.
+//!  - On x86_64, aarch64 and x86, it falls back to frame pointer unwinding if it cannot find unwind information for an address.
 //!  - It caches the unwind rule for each address in a fixed-size cache, so that repeated unwinding from the same address is even faster.
 //!  - It generates binary search indexes for unwind information formats which don't have them. Specifically, for `.debug_frame` and for `.eh_frame` without `.eh_frame_hdr`.
 //!  - It does a reasonable job of detecting the end of the stack, so that you can differentiate between properly terminated stacks and prematurely truncated stacks.
 //!
-//! Framehop is not suitable for debuggers or to implement exception handling. Debuggers usually need to recover all register values for every frame whereas framehop only cares about return addresses. And exception handling needs the ability to call destructors, which is also a non-goal for framehop.
+//! Framehop has limited support for debuggers and for exception handling. [`Unwinder::step_out`] unwinds a single frame into the caller's registers, which is enough for stepping out of a function. But debuggers usually need to recover all register values for every frame whereas framehop only cares about return addresses, so it only recovers the stack pointer, the frame pointer and the instruction pointer or link register. For exception handling, `exception_handling_info` on the x86_64 and aarch64 unwinders finds the personality routine and the LSDA of a function in its DWARF FDE, see [`ExceptionHandlingInfo`]. But calling the personality routine and the destructors is up to the exception handling runtime; this is a non-goal for framehop.
 //!
 //! ## Speed
 //!
@@ -62,12 +63,11 @@
 //!     ModuleSvmaInfo {
 //!         base_svma: 0x100000000,
 //!         text: Some(0x100000b64..0x1001d2d18),
-//!         text_env: None,
 //!         stubs: Some(0x1001d2d18..0x1001d309c),
 //!         stub_helper: Some(0x1001d309c..0x1001d3438),
 //!         eh_frame: Some(0x100237f80..0x100237ffc),
-//!         eh_frame_hdr: None,
 //!         got: Some(0x100238000..0x100238010),
+//!         ..Default::default()
 //!     },
 //!     ModuleUnwindData::CompactUnwindInfoAndEhFrame(vec![/* __unwind_info */], None),
 //!     Some(TextByteData::new(
@@ -115,30 +115,160 @@
 mod add_signed;
 mod arcdata;
 mod arch;
+mod branch_record;
 mod cache;
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+mod capture;
 mod code_address;
 mod display_utils;
 mod dwarf;
+mod dyn_unwinder;
 mod error;
+mod exception_handling;
+mod folded_stacks;
+mod fpo;
+mod frame_confidence;
+mod frame_divergence;
+mod frame_encoding;
+mod frame_filter;
+mod frame_pointer_chain;
+mod frame_provenance;
+mod guest_memory;
+mod inline_frames;
 mod instruction_analysis;
 mod macho;
+mod module_event;
+#[cfg(feature = "from-file")]
+mod module_file;
+mod process_snapshot;
+mod range_map;
 mod rule_cache;
+mod shadow_stack;
+mod stack_copy;
+mod stack_hash;
+mod stack_slice;
+mod stub_rules;
+mod sync_unwinder;
+#[cfg(test)]
+mod test_utils;
+mod trace;
+mod unwind_end_reason;
+mod unwind_iterator;
+mod unwind_limits;
+mod unwind_mode;
+mod unwind_regs;
 mod unwind_result;
 mod unwind_rule;
 mod unwinder;
 
 /// Types for unwinding on the aarch64 CPU architecture.
 pub mod aarch64;
+/// Types for unwinding on the 32-bit x86 CPU architecture.
+pub mod x86;
 /// Types for unwinding on the x86_64 CPU architecture.
 pub mod x86_64;
 
+/// Adapters for unwinding the threads in a minidump.
+#[cfg(feature = "minidump")]
+pub mod minidump;
+
+/// Helpers for unwinding samples from the Linux perf subsystem.
+pub mod perf;
+
+/// A timestamped module table built from Windows ETW image load and unload events.
+pub mod etw;
+
+/// Unwind registers from the `jmp_buf` of `setjmp`, for the stack at which `longjmp`
+/// would resume.
+pub mod jmp_buf;
+
+/// An adapter with the frame type and the tracing flow of the `backtrace` crate.
+#[cfg(feature = "backtrace")]
+pub mod backtrace_compat;
+
+/// A serde fixture format for recording stack walks and replaying them.
+#[cfg(feature = "serde")]
+pub mod fixture;
+
+/// Register capture for threads which are stopped under `ptrace`.
+#[cfg(all(
+    feature = "ptrace",
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+pub mod ptrace;
+
+/// Thread sampling with `SIGPROF`, for sampling the threads of the current process.
+///
+/// This is only available on Linux: threads are signaled by their kernel thread ID
+/// with `tgkill`, and their stacks are copied with `process_vm_readv`. macOS has
+/// neither, and is not supported.
+#[cfg(all(
+    feature = "signal-sampling",
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+pub mod signal_sampling;
+
+/// Register capture for suspended threads on macOS, using `thread_get_state`.
+#[cfg(target_os = "macos")]
+pub mod mach;
+
+/// Conversions from the signal handler context (`ucontext_t`) to unwind registers.
+#[cfg(all(
+    feature = "ucontext",
+    any(target_os = "linux", target_os = "macos"),
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+pub mod ucontext;
+
+/// Register capture for suspended threads on Windows, using `GetThreadContext`, and
+/// thread sampling with the `windows-sampling` feature.
+#[cfg(all(
+    feature = "windows-threads",
+    windows,
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+pub mod windows;
+
+pub use branch_record::{BranchKind, BranchRecord};
 pub use cache::{AllocationPolicy, MayAllocateDuringUnwind, MustNotAllocateDuringUnwind};
-pub use code_address::FrameAddress;
-pub use error::Error;
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+pub use capture::capture_regs;
+pub use code_address::{FrameAddress, FrameAddressKind};
+pub use dwarf::DwarfUnwinderError;
+pub use dyn_unwinder::{DynUnwindRegs, DynUnwinder, DynUnwinderError, ErasedUnwinder};
+pub use error::{Error, ModuleError, UnwinderError};
+pub use exception_handling::{EhPointer, ExceptionHandlingInfo};
+pub use folded_stacks::{fold_stack, FoldedStacks};
+pub use fpo::{FpoData, FpoFrameType, FpoUnwinderError};
+pub use frame_confidence::FrameConfidence;
+pub use frame_divergence::FrameDivergence;
+pub use frame_encoding::{decode_frames, encode_frames, FrameDecodeError, FrameRecord};
+pub use frame_filter::FrameFilterAction;
+pub use frame_pointer_chain::{FramePointerChain, FramePointerChainEnd};
+pub use frame_provenance::{FallbackReason, FrameProvenance, FrameSource};
+pub use guest_memory::{AddressTranslation, GuestBase, GuestMappings, GuestMemory};
+pub use inline_frames::{InlineFrame, InlineFrameExpander, InlineFrameIterator};
+pub use macho::CompactUnwindInfoUnwinderError;
+pub use module_event::{ModuleEvent, ModuleEventSubscription};
+#[cfg(feature = "from-file")]
+pub use module_file::{MmapSection, ModuleFromFileError};
+pub use process_snapshot::{MemorySource, ProcessSnapshot, ThreadBacktrace, ThreadSnapshot};
 pub use rule_cache::CacheStats;
-pub use unwinder::{
-    Module, ModuleSvmaInfo, ModuleUnwindData, TextByteData, UnwindIterator, Unwinder,
-};
+pub use shadow_stack::ShadowStackMismatch;
+#[cfg(all(feature = "local-stack-copy", any(target_os = "linux", windows)))]
+pub use stack_copy::copy_local_stack;
+pub use stack_copy::copy_stack;
+pub use stack_slice::StackSlice;
+pub use stub_rules::StubRules;
+pub use sync_unwinder::SyncUnwinder;
+pub use unwind_end_reason::UnwindEndReason;
+pub use unwind_iterator::UnwindIterator;
+pub use unwind_limits::UnwindLimits;
+pub use unwind_mode::UnwindMode;
+pub use unwind_regs::UnwindRegs;
+pub use unwinder::{Module, ModuleSvmaInfo, ModuleUnwindData, TextByteData, Unwinder};
 
 /// The unwinder cache for the native CPU architecture.
 #[cfg(target_arch = "aarch64")]
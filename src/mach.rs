@@ -0,0 +1,177 @@
+use crate::aarch64::{PtrAuthMask, UnwindRegsAarch64};
+use crate::x86_64::UnwindRegsX86_64;
+
+/// The `x86_THREAD_STATE64` flavor for `thread_get_state`.
+pub const X86_THREAD_STATE64: i32 = 4;
+/// The `ARM_THREAD_STATE64` flavor for `thread_get_state`.
+pub const ARM_THREAD_STATE64: i32 = 6;
+
+/// The layout of `x86_thread_state64_t`, the thread state for the
+/// [`X86_THREAD_STATE64`] flavor.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct X86ThreadState64 {
+    pub rax: u64,
+    pub rbx: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rbp: u64,
+    pub rsp: u64,
+    pub r8: u64,
+    pub r9: u64,
+    pub r10: u64,
+    pub r11: u64,
+    pub r12: u64,
+    pub r13: u64,
+    pub r14: u64,
+    pub r15: u64,
+    pub rip: u64,
+    pub rflags: u64,
+    pub cs: u64,
+    pub fs: u64,
+    pub gs: u64,
+}
+
+/// The layout of `arm_thread_state64_t`, the thread state for the
+/// [`ARM_THREAD_STATE64`] flavor.
+///
+/// For arm64e processes, `fp`, `lr`, `sp` and `pc` can carry pointer authentication
+/// bits.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ArmThreadState64 {
+    pub x: [u64; 29],
+    pub fp: u64,
+    pub lr: u64,
+    pub sp: u64,
+    pub pc: u64,
+    pub cpsr: u32,
+    pub flags: u32,
+}
+
+/// The error type for the thread state functions in this module.
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadStateError {
+    #[error("thread_get_state failed with kern_return_t {0}")]
+    ThreadGetStateFailed(i32),
+
+    #[error("thread_get_state returned {0} words of state, which is less than expected")]
+    StateTooShort(u32),
+}
+
+extern "C" {
+    fn thread_get_state(
+        target_act: u32,
+        flavor: i32,
+        old_state: *mut u32,
+        old_state_count: *mut u32,
+    ) -> i32;
+}
+
+/// Call `thread_get_state` with the given flavor and fill `state`.
+///
+/// # Safety
+///
+/// `T` must be the state struct which matches `flavor`.
+unsafe fn get_state<T: Default>(thread_act: u32, flavor: i32) -> Result<T, ThreadStateError> {
+    let mut state = T::default();
+    let expected_count = (std::mem::size_of::<T>() / std::mem::size_of::<u32>()) as u32;
+    let mut count = expected_count;
+    let kr = thread_get_state(
+        thread_act,
+        flavor,
+        &mut state as *mut T as *mut u32,
+        &mut count,
+    );
+    if kr != 0 {
+        return Err(ThreadStateError::ThreadGetStateFailed(kr));
+    }
+    if count < expected_count {
+        return Err(ThreadStateError::StateTooShort(count));
+    }
+    Ok(state)
+}
+
+/// Query the `x86_THREAD_STATE64` of the given thread port and convert it into the
+/// instruction pointer and the unwind registers.
+///
+/// The thread should be suspended with `thread_suspend` while the registers are read
+/// and for as long as its stack is being read.
+pub fn get_thread_regs_x86_64(
+    thread_act: u32,
+) -> Result<(u64, UnwindRegsX86_64), ThreadStateError> {
+    // Safety: X86ThreadState64 is the state struct for X86_THREAD_STATE64.
+    let state: X86ThreadState64 = unsafe { get_state(thread_act, X86_THREAD_STATE64)? };
+    Ok(state.to_unwind_regs())
+}
+
+/// Query the `ARM_THREAD_STATE64` of the given thread port and convert it into the
+/// instruction pointer and the unwind registers.
+///
+/// Pointer authentication bits are stripped with `ptr_auth_mask`; on macOS,
+/// [`PtrAuthMask::new_24_40`] is usually the right choice.
+///
+/// The thread should be suspended with `thread_suspend` while the registers are read
+/// and for as long as its stack is being read.
+pub fn get_thread_regs_aarch64(
+    thread_act: u32,
+    ptr_auth_mask: PtrAuthMask,
+) -> Result<(u64, UnwindRegsAarch64), ThreadStateError> {
+    // Safety: ArmThreadState64 is the state struct for ARM_THREAD_STATE64.
+    let state: ArmThreadState64 = unsafe { get_state(thread_act, ARM_THREAD_STATE64)? };
+    Ok(state.to_unwind_regs(ptr_auth_mask))
+}
+
+impl X86ThreadState64 {
+    /// Returns the instruction pointer and the unwind registers.
+    pub fn to_unwind_regs(&self) -> (u64, UnwindRegsX86_64) {
+        (
+            self.rip,
+            UnwindRegsX86_64::new(self.rip, self.rsp, self.rbp),
+        )
+    }
+}
+
+impl ArmThreadState64 {
+    /// Returns the instruction pointer and the unwind registers, with pointer
+    /// authentication bits stripped using `ptr_auth_mask`.
+    pub fn to_unwind_regs(&self, ptr_auth_mask: PtrAuthMask) -> (u64, UnwindRegsAarch64) {
+        let pc = ptr_auth_mask.strip_ptr_auth(self.pc);
+        let regs = UnwindRegsAarch64::new_with_ptr_auth_mask(
+            ptr_auth_mask,
+            self.lr,
+            ptr_auth_mask.strip_ptr_auth(self.sp),
+            ptr_auth_mask.strip_ptr_auth(self.fp),
+        );
+        (pc, regs)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_state_sizes() {
+        assert_eq!(std::mem::size_of::<X86ThreadState64>() / 4, 42);
+        assert_eq!(std::mem::size_of::<ArmThreadState64>() / 4, 68);
+    }
+
+    #[test]
+    fn test_arm64e_stripping() {
+        let state = ArmThreadState64 {
+            fp: 0x16fdff2a0,
+            lr: 0x2a6e80018a2c5d0c,
+            sp: 0x16fdff290,
+            pc: 0x5b0e00018a2c5e40,
+            ..Default::default()
+        };
+        let (pc, regs) = state.to_unwind_regs(PtrAuthMask::new_24_40());
+        assert_eq!(pc, 0x18a2c5e40);
+        assert_eq!(regs.lr(), 0x18a2c5d0c);
+        assert_eq!(regs.sp(), 0x16fdff290);
+        assert_eq!(regs.fp(), 0x16fdff2a0);
+    }
+}
@@ -1,10 +1,13 @@
 use std::marker::PhantomData;
 
 use crate::dwarf::DwarfUnwinderError;
+use crate::stub_rules::StubRules;
 use crate::{arch::Arch, unwind_rule::UnwindRule};
 use macho_unwind_info::UnwindInfo;
 
+/// Why unwinding with `__unwind_info` failed for an address.
 #[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum CompactUnwindInfoUnwinderError {
     #[error("Bad __unwind_info format: {0}")]
     BadFormat(#[from] macho_unwind_info::Error),
@@ -46,6 +49,28 @@ pub enum CompactUnwindInfoUnwinderError {
     InvalidFrameless,
 }
 
+impl CompactUnwindInfoUnwinderError {
+    /// A stable numeric code for this error, see [`Error::code`](crate::Error::code).
+    /// [`BadDwarfUnwinding`](Self::BadDwarfUnwinding) has the code of the DWARF error.
+    pub fn code(&self) -> u32 {
+        match self {
+            CompactUnwindInfoUnwinderError::BadFormat(_) => 401,
+            CompactUnwindInfoUnwinderError::AddressOutsideRange(_) => 402,
+            CompactUnwindInfoUnwinderError::CallerCannotBeFrameless => 403,
+            CompactUnwindInfoUnwinderError::FunctionHasNoInfo => 404,
+            CompactUnwindInfoUnwinderError::BpOffsetDoesNotFit => 405,
+            CompactUnwindInfoUnwinderError::BadOpcodeKind(_) => 406,
+            CompactUnwindInfoUnwinderError::BadDwarfUnwinding(err) => err.code(),
+            CompactUnwindInfoUnwinderError::NoTextBytesToLookUpIndirectStackOffset => 407,
+            CompactUnwindInfoUnwinderError::IndirectStackOffsetOutOfBounds => 408,
+            CompactUnwindInfoUnwinderError::StackAdjustOverflow => 409,
+            CompactUnwindInfoUnwinderError::StackSizeDoesNotFit => 410,
+            CompactUnwindInfoUnwinderError::StubFunctionCannotBeCaller => 411,
+            CompactUnwindInfoUnwinderError::InvalidFrameless => 412,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum CuiUnwindResult<R: UnwindRule> {
     ExecRule(R),
@@ -63,6 +88,10 @@ pub trait CompactUnwindInfoUnwinding: Arch {
     fn rule_for_stub_helper(
         offset: u32,
     ) -> Result<CuiUnwindResult<Self::UnwindRule>, CompactUnwindInfoUnwinderError>;
+
+    /// A description of `opcode`, and the offset of the DWARF FDE that it refers to,
+    /// if any. For dumping unwind information.
+    fn describe_opcode(opcode: u32) -> (String, Option<u32>);
 }
 
 #[derive(Clone, Copy)]
@@ -78,6 +107,14 @@ impl<'a> TextBytes<'a> {
             bytes,
         }
     }
+
+    /// Returns the bytes for the given range of addresses relative to the base address,
+    /// if they are all available.
+    pub fn bytes_for_relative_range(&self, start: u32, end: u32) -> Option<&'a [u8]> {
+        let start = start.checked_sub(self.offset_from_base_address)? as usize;
+        let end = end.checked_sub(self.offset_from_base_address)? as usize;
+        self.bytes.get(start..end)
+    }
 }
 
 pub struct CompactUnwindInfoUnwinder<'a, A: CompactUnwindInfoUnwinding> {
@@ -85,6 +122,7 @@ pub struct CompactUnwindInfoUnwinder<'a, A: CompactUnwindInfoUnwinding> {
     text_bytes: Option<TextBytes<'a>>,
     stubs_range: (u32, u32),
     stub_helper_range: (u32, u32),
+    stub_rules: StubRules<A::UnwindRule>,
     _arch: PhantomData<A>,
 }
 
@@ -100,10 +138,15 @@ impl<'a, A: CompactUnwindInfoUnwinding> CompactUnwindInfoUnwinder<'a, A> {
             text_bytes,
             stubs_range,
             stub_helper_range,
+            stub_rules: StubRules::arch_default(),
             _arch: PhantomData,
         }
     }
 
+    pub fn set_stub_rules(&mut self, stub_rules: StubRules<A::UnwindRule>) {
+        self.stub_rules = stub_rules;
+    }
+
     pub fn function_for_address(
         &self,
         address: u32,
@@ -130,9 +173,7 @@ impl<'a, A: CompactUnwindInfoUnwinding> CompactUnwindInfoUnwinder<'a, A> {
                 return Err(CompactUnwindInfoUnwinderError::StubFunctionCannotBeCaller);
             }
             // All stub functions are frameless.
-            return Ok(CuiUnwindResult::ExecRule(
-                A::UnwindRule::rule_for_stub_functions(),
-            ));
+            return Ok(CuiUnwindResult::ExecRule(self.stub_rules.stub_functions));
         }
         if self.stub_helper_range.0 <= rel_lookup_address
             && rel_lookup_address < self.stub_helper_range.1
@@ -152,31 +193,27 @@ impl<'a, A: CompactUnwindInfoUnwinding> CompactUnwindInfoUnwinder<'a, A> {
                 // This could mean that we're inside a stub function, in the __stubs section.
                 // All stub functions are frameless.
                 // TODO: Obtain the actual __stubs address range and do better checking here.
-                return Ok(CuiUnwindResult::ExecRule(
-                    A::UnwindRule::rule_for_stub_functions(),
-                ));
+                return Ok(CuiUnwindResult::ExecRule(self.stub_rules.stub_functions));
             }
             Err(err) => return Err(err),
         };
         if is_first_frame && rel_lookup_address == function.start_address {
-            return Ok(CuiUnwindResult::ExecRule(
-                A::UnwindRule::rule_for_function_start(),
-            ));
+            return Ok(CuiUnwindResult::ExecRule(self.stub_rules.function_start));
         }
-        let address_offset_within_function =
-            usize::try_from(rel_lookup_address - function.start_address).unwrap();
+        let address_offset_within_function = rel_lookup_address
+            .checked_sub(function.start_address)
+            .ok_or(CompactUnwindInfoUnwinderError::AddressOutsideRange(
+                rel_lookup_address,
+            ))? as usize;
         let function_bytes = self.text_bytes.and_then(|text_bytes| {
-            let TextBytes {
-                offset_from_base_address,
-                bytes,
-            } = text_bytes;
-            let function_start_relative_to_text = function
-                .start_address
-                .checked_sub(offset_from_base_address)?
-                as usize;
-            let function_end_relative_to_text =
-                function.end_address.checked_sub(offset_from_base_address)? as usize;
-            bytes.get(function_start_relative_to_text..function_end_relative_to_text)
+            let function_bytes = text_bytes
+                .bytes_for_relative_range(function.start_address, function.end_address)?;
+            // Instruction analysis requires the address to be inside the function bytes.
+            // Malformed unwind info can violate this.
+            if address_offset_within_function > function_bytes.len() {
+                return None;
+            }
+            Some(function_bytes)
         });
         <A as CompactUnwindInfoUnwinding>::unwind_frame(
             function,
@@ -0,0 +1,122 @@
+use std::ops::Range;
+
+use ::minidump::format::{CONTEXT_AMD64, CONTEXT_ARM64, CONTEXT_X86};
+use ::minidump::{
+    MinidumpContext, MinidumpMemoryList, MinidumpModuleList, MinidumpRawContext,
+    Module as MinidumpModuleTrait,
+};
+
+use crate::aarch64::UnwindRegsAarch64;
+use crate::process_snapshot::MemorySource;
+use crate::x86::UnwindRegsX86;
+use crate::x86_64::UnwindRegsX86_64;
+
+/// Information about a module listed in a minidump's module list.
+///
+/// Minidumps don't contain the unwind information of the modules, so you will need
+/// to locate the binaries (for example with the help of the debug and code
+/// identifiers) and then create a [`Module`](crate::Module) using
+/// [`MinidumpModuleInfo::avma_range`] and the unwind sections from the binary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MinidumpModuleInfo {
+    /// The code file path of the module, as recorded in the minidump.
+    pub name: String,
+    /// The address range where this module was mapped into the process.
+    pub avma_range: Range<u64>,
+    /// The debug identifier from the CodeView record, in breakpad format, e.g. the
+    /// PDB GUID and age, or the ELF build ID converted into a debug ID.
+    pub debug_id: Option<String>,
+    /// The code identifier, e.g. the PE timestamp and image size, or the ELF build ID.
+    pub code_id: Option<String>,
+}
+
+/// Return information about all modules in the minidump's module list, sorted by
+/// start address.
+pub fn module_infos(module_list: &MinidumpModuleList) -> Vec<MinidumpModuleInfo> {
+    let mut infos: Vec<MinidumpModuleInfo> = module_list
+        .iter()
+        .map(|module| {
+            let start = module.base_address();
+            MinidumpModuleInfo {
+                name: module.code_file().into_owned(),
+                avma_range: start..start.saturating_add(module.size()),
+                debug_id: module
+                    .debug_identifier()
+                    .map(|debug_id| debug_id.breakpad().to_string()),
+                code_id: module.code_identifier().map(|code_id| code_id.to_string()),
+            }
+        })
+        .collect();
+    infos.sort_by_key(|info| info.avma_range.start);
+    infos
+}
+
+/// Reads stack memory from the memory regions which were captured in a minidump.
+///
+/// This can be used as the [`MemorySource`] of a
+/// [`ProcessSnapshot`](crate::ProcessSnapshot), or to create a `read_stack` callback:
+///
+/// ```ignore
+/// let reader = MinidumpStackReader::new(&memory_list);
+/// let mut read_stack = |addr| reader.read_u64(addr).ok_or(());
+/// ```
+pub struct MinidumpStackReader<'a, 'mdmp> {
+    memory_list: &'a MinidumpMemoryList<'mdmp>,
+}
+
+impl<'a, 'mdmp> MinidumpStackReader<'a, 'mdmp> {
+    /// Create a reader for the given memory list.
+    pub fn new(memory_list: &'a MinidumpMemoryList<'mdmp>) -> Self {
+        Self { memory_list }
+    }
+
+    /// Read the 8 bytes at `address`. Returns `None` if the address is not covered by
+    /// any of the captured memory regions.
+    pub fn read_u64(&self, address: u64) -> Option<u64> {
+        let memory = self.memory_list.memory_at_address(address)?;
+        memory.get_memory_at_address::<u64>(address)
+    }
+}
+
+impl<'a, 'mdmp> MemorySource for MinidumpStackReader<'a, 'mdmp> {
+    fn read_u64(&mut self, address: u64) -> Option<u64> {
+        MinidumpStackReader::read_u64(self, address)
+    }
+}
+
+/// The unwind registers of a minidump thread, for the CPU architecture of the dump.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MinidumpThreadRegs {
+    /// The instruction pointer and the registers of an x86_64 thread.
+    X86_64(u64, UnwindRegsX86_64),
+    /// The instruction pointer and the registers of an aarch64 thread.
+    Aarch64(u64, UnwindRegsAarch64),
+    /// The instruction pointer and the registers of a 32-bit x86 thread.
+    X86(u64, UnwindRegsX86),
+}
+
+/// Convert a thread context from a minidump into the instruction pointer and the
+/// unwind registers. Returns `None` if the context is for a CPU architecture which
+/// framehop doesn't support.
+pub fn thread_regs_from_context(context: &MinidumpContext) -> Option<MinidumpThreadRegs> {
+    match &context.raw {
+        MinidumpRawContext::Amd64(ctx) => Some(thread_regs_from_amd64_context(ctx)),
+        MinidumpRawContext::Arm64(ctx) => Some(thread_regs_from_arm64_context(ctx)),
+        MinidumpRawContext::X86(ctx) => Some(thread_regs_from_x86_context(ctx)),
+        _ => None,
+    }
+}
+
+fn thread_regs_from_amd64_context(ctx: &CONTEXT_AMD64) -> MinidumpThreadRegs {
+    MinidumpThreadRegs::X86_64(ctx.rip, UnwindRegsX86_64::new(ctx.rip, ctx.rsp, ctx.rbp))
+}
+
+fn thread_regs_from_arm64_context(ctx: &CONTEXT_ARM64) -> MinidumpThreadRegs {
+    let regs = UnwindRegsAarch64::new(ctx.iregs[30], ctx.sp, ctx.iregs[29]);
+    MinidumpThreadRegs::Aarch64(ctx.pc, regs)
+}
+
+fn thread_regs_from_x86_context(ctx: &CONTEXT_X86) -> MinidumpThreadRegs {
+    let (eip, esp, ebp) = (u64::from(ctx.eip), u64::from(ctx.esp), u64::from(ctx.ebp));
+    MinidumpThreadRegs::X86(eip, UnwindRegsX86::new(eip, esp, ebp))
+}
@@ -0,0 +1,37 @@
+use std::ops::Range;
+
+/// A change to the module table of an unwinder, passed to the subscribers that were
+/// added with the concrete unwinder's `subscribe_module_events` method. This lets
+/// components which hold data derived from the modules, for example external caches
+/// or tables generated for an eBPF unwinder, invalidate only the affected ranges.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ModuleEvent {
+    /// A module was added.
+    Added {
+        name: String,
+        avma_range: Range<u64>,
+    },
+    /// A module was removed. Its aliases were removed with it.
+    Removed { avma_range: Range<u64> },
+    /// A module was moved to a different address, with its aliases pointing to the new
+    /// range.
+    Rebased {
+        old_avma_range: Range<u64>,
+        new_avma_range: Range<u64>,
+    },
+    /// The text bytes or the stub rules of a module were replaced, so rules which were
+    /// derived from its code may have changed.
+    CodeChanged { avma_range: Range<u64> },
+    /// An alias range for a module's code was added.
+    AliasAdded {
+        alias_avma_range: Range<u64>,
+        module_avma_range_start: u64,
+    },
+    /// An alias range was removed.
+    AliasRemoved { alias_avma_range: Range<u64> },
+}
+
+/// Identifies a subscription to [`ModuleEvent`]s, for the concrete unwinder's
+/// `unsubscribe_module_events` method.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ModuleEventSubscription(pub(crate) u64);
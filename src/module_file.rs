@@ -0,0 +1,163 @@
+use std::fs::File;
+use std::ops::{Deref, Range};
+use std::path::Path;
+use std::sync::Arc;
+
+use memmap2::Mmap;
+use object::{CompressionFormat, Object, ObjectSection, ObjectSegment};
+
+use crate::unwinder::{Module, ModuleSvmaInfo, ModuleUnwindData, TextByteData};
+
+/// A section of a memory-mapped binary, for modules created with
+/// [`Module::from_file`]. All sections of a module share one mapping, which stays
+/// alive for as long as any of them does.
+#[derive(Clone)]
+pub struct MmapSection {
+    mmap: Arc<Mmap>,
+    range: Range<usize>,
+}
+
+impl Deref for MmapSection {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.mmap[self.range.clone()]
+    }
+}
+
+/// Why [`Module::from_file`] failed.
+#[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
+pub enum ModuleFromFileError {
+    #[error("Could not open or map the file: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Could not parse the object file: {0}")]
+    Object(#[from] object::read::Error),
+}
+
+impl Module<MmapSection> {
+    /// Open the ELF, mach-O or PE binary at `path`, memory-map it, and create a module
+    /// for it. `base_avma` is the address where the binary's base address was mapped
+    /// into the process, see [`Module::new`]; the module's address range is derived
+    /// from the binary's segments.
+    ///
+    /// The unwind sections and the text section aren't copied, they point into the
+    /// mapping. Compressed sections, such as a compressed `.debug_frame`, can't be used
+    /// without a copy, and are ignored.
+    ///
+    /// The binary must not be modified while the module exists, because the module
+    /// reads the mapped file without taking a copy.
+    pub fn from_file(path: impl AsRef<Path>, base_avma: u64) -> Result<Self, ModuleFromFileError> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        // Safety: The mapping is read-only, and the caller guarantees that the file is
+        // not modified while it is mapped.
+        let mmap = Arc::new(unsafe { Mmap::map(&file)? });
+        let object = object::File::parse(&mmap[..])?;
+
+        // For mach-O, relative addresses are based on the vmaddr of the __TEXT segment.
+        let base_svma = match object
+            .segments()
+            .find(|segment| segment.name() == Ok(Some("__TEXT")))
+        {
+            Some(text_segment) => text_segment.address(),
+            None => object.relative_address_base(),
+        };
+        let to_avma = |svma: u64| base_avma.wrapping_add(svma.wrapping_sub(base_svma));
+
+        // Segments without file data, such as __PAGEZERO, aren't part of the image.
+        let image_svma_range = object
+            .segments()
+            .filter(|segment| segment.file_range().1 != 0)
+            .map(|segment| segment.address()..segment.address() + segment.size())
+            .reduce(|a, b| a.start.min(b.start)..a.end.max(b.end));
+        let avma_range = match image_svma_range {
+            Some(svma_range) => to_avma(svma_range.start)..to_avma(svma_range.end),
+            None => base_avma..base_avma + mmap.len() as u64,
+        };
+
+        let text = object.section_by_name(".text");
+        let stubs = object.section_by_name("__stubs");
+        let stub_helper = object.section_by_name("__stub_helper");
+        let text_env = object.section_by_name("__text_env");
+        let unwind_info = object.section_by_name("__unwind_info");
+        let eh_frame = object.section_by_name(".eh_frame");
+        let eh_frame_hdr = object.section_by_name(".eh_frame_hdr");
+        let debug_frame = object.section_by_name(".debug_frame");
+        let got = object.section_by_name(".got");
+
+        let data = |section: &Option<object::Section>| match section {
+            Some(section) => section_data(&mmap, section),
+            None => None,
+        };
+        let unwind_data = match (
+            data(&unwind_info),
+            data(&eh_frame),
+            data(&eh_frame_hdr),
+            data(&debug_frame),
+        ) {
+            (Some(unwind_info), eh_frame, _, _) => {
+                ModuleUnwindData::CompactUnwindInfoAndEhFrame(unwind_info, eh_frame)
+            }
+            (None, Some(eh_frame), Some(eh_frame_hdr), _) => {
+                ModuleUnwindData::EhFrameHdrAndEhFrame(eh_frame_hdr, eh_frame)
+            }
+            (None, Some(eh_frame), None, _) => ModuleUnwindData::EhFrame(eh_frame),
+            (None, None, _, Some(debug_frame)) => ModuleUnwindData::DebugFrame(debug_frame),
+            (None, None, _, None) => ModuleUnwindData::None,
+        };
+        let text_data = match (&text, data(&text)) {
+            (Some(text), Some(bytes)) => {
+                let start = to_avma(text.address());
+                Some(TextByteData::new(bytes, start..start + text.size()))
+            }
+            _ => None,
+        };
+
+        let svma_range = |section: &Option<object::Section>| {
+            section
+                .as_ref()
+                .map(|section| section.address()..section.address() + section.size())
+        };
+        let svma_info = ModuleSvmaInfo {
+            base_svma,
+            text: svma_range(&text),
+            text_env: svma_range(&text_env),
+            stubs: svma_range(&stubs),
+            stub_helper: svma_range(&stub_helper),
+            eh_frame: svma_range(&eh_frame),
+            eh_frame_hdr: svma_range(&eh_frame_hdr),
+            got: svma_range(&got),
+            address_size: if object.is_64() { 8 } else { 4 },
+        };
+        Ok(Module::new(
+            path.to_string_lossy().into_owned(),
+            avma_range,
+            base_avma,
+            svma_info,
+            unwind_data,
+            text_data,
+        ))
+    }
+}
+
+/// The bytes of an uncompressed section, as a range of the mapping.
+fn section_data<'data>(
+    mmap: &Arc<Mmap>,
+    section: &impl ObjectSection<'data>,
+) -> Option<MmapSection> {
+    let file_range = section.compressed_file_range().ok()?;
+    if file_range.format != CompressionFormat::None {
+        return None;
+    }
+    let start = usize::try_from(file_range.offset).ok()?;
+    let end = start.checked_add(usize::try_from(file_range.uncompressed_size).ok()?)?;
+    if end > mmap.len() {
+        return None;
+    }
+    Some(MmapSection {
+        mmap: mmap.clone(),
+        range: start..end,
+    })
+}
@@ -0,0 +1,123 @@
+use crate::FrameAddress;
+
+/// Marks the start of hypervisor frames in a `PERF_SAMPLE_CALLCHAIN` list.
+pub const PERF_CONTEXT_HV: u64 = -32i64 as u64;
+/// Marks the start of kernel frames in a `PERF_SAMPLE_CALLCHAIN` list.
+pub const PERF_CONTEXT_KERNEL: u64 = -128i64 as u64;
+/// Marks the start of user-space frames in a `PERF_SAMPLE_CALLCHAIN` list.
+pub const PERF_CONTEXT_USER: u64 = -512i64 as u64;
+/// Marks the start of guest frames in a `PERF_SAMPLE_CALLCHAIN` list.
+pub const PERF_CONTEXT_GUEST: u64 = -2048i64 as u64;
+/// Marks the start of guest kernel frames in a `PERF_SAMPLE_CALLCHAIN` list.
+pub const PERF_CONTEXT_GUEST_KERNEL: u64 = -2176i64 as u64;
+/// Marks the start of guest user-space frames in a `PERF_SAMPLE_CALLCHAIN` list.
+pub const PERF_CONTEXT_GUEST_USER: u64 = -2560i64 as u64;
+/// All values at or above this value are context markers, not addresses.
+pub const PERF_CONTEXT_MAX: u64 = -4095i64 as u64;
+
+/// A frame in a stack that was spliced together from kernel and user frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplicedFrame {
+    /// A kernel frame from the sample's `PERF_SAMPLE_CALLCHAIN`.
+    Kernel(FrameAddress),
+    /// A user-space frame, as produced by framehop.
+    User(FrameAddress),
+}
+
+impl SplicedFrame {
+    /// The frame's address.
+    pub fn address(&self) -> FrameAddress {
+        match self {
+            SplicedFrame::Kernel(address) | SplicedFrame::User(address) => *address,
+        }
+    }
+}
+
+/// Extract the kernel frames from a `PERF_SAMPLE_CALLCHAIN` list.
+///
+/// The callchain is ordered from the innermost frame to the outermost frame, and is
+/// divided into sections by `PERF_CONTEXT_*` marker values. This returns the entries
+/// of the `PERF_CONTEXT_KERNEL` section which precedes the `PERF_CONTEXT_USER`
+/// marker. The first kernel entry is the kernel instruction pointer, the other
+/// entries are return addresses.
+pub fn kernel_frames_from_callchain(callchain: &[u64]) -> Vec<FrameAddress> {
+    let mut frames = Vec::new();
+    let mut in_kernel_context = false;
+    for &entry in callchain {
+        if entry >= PERF_CONTEXT_MAX {
+            match entry {
+                PERF_CONTEXT_KERNEL => in_kernel_context = true,
+                PERF_CONTEXT_USER => break,
+                _ => in_kernel_context = false,
+            }
+            continue;
+        }
+        if !in_kernel_context {
+            continue;
+        }
+        let frame = if frames.is_empty() {
+            Some(FrameAddress::from_instruction_pointer(entry))
+        } else {
+            FrameAddress::from_return_address(entry)
+        };
+        frames.extend(frame);
+    }
+    frames
+}
+
+/// Produce one stack for a perf sample, by putting the kernel frames from the
+/// sample's `PERF_SAMPLE_CALLCHAIN` in front of the user-space frames that were
+/// unwound from the sample's `PERF_SAMPLE_REGS_USER` and `PERF_SAMPLE_STACK_USER`.
+///
+/// Any user-space entries in the callchain (which the kernel computes with frame
+/// pointer unwinding) are ignored in favor of `user_frames`.
+///
+/// The returned stack is ordered from the innermost frame to the outermost frame.
+pub fn splice_kernel_and_user_frames(
+    callchain: &[u64],
+    user_frames: impl IntoIterator<Item = FrameAddress>,
+) -> Vec<SplicedFrame> {
+    kernel_frames_from_callchain(callchain)
+        .into_iter()
+        .map(SplicedFrame::Kernel)
+        .chain(user_frames.into_iter().map(SplicedFrame::User))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_splice() {
+        let callchain = [
+            PERF_CONTEXT_KERNEL,
+            0xffffffff81001000,
+            0xffffffff81002000,
+            PERF_CONTEXT_USER,
+            0x55550000a000,
+            0x55550000b000,
+        ];
+        let user_frames = [
+            FrameAddress::from_instruction_pointer(0x55550000a000),
+            FrameAddress::from_return_address(0x55550000c000).unwrap(),
+        ];
+        assert_eq!(
+            splice_kernel_and_user_frames(&callchain, user_frames),
+            vec![
+                SplicedFrame::Kernel(FrameAddress::from_instruction_pointer(0xffffffff81001000)),
+                SplicedFrame::Kernel(
+                    FrameAddress::from_return_address(0xffffffff81002000).unwrap()
+                ),
+                SplicedFrame::User(FrameAddress::from_instruction_pointer(0x55550000a000)),
+                SplicedFrame::User(FrameAddress::from_return_address(0x55550000c000).unwrap()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_user_only_callchain() {
+        let callchain = [PERF_CONTEXT_USER, 0x55550000a000];
+        assert_eq!(kernel_frames_from_callchain(&callchain), vec![]);
+    }
+}
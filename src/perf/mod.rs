@@ -0,0 +1,7 @@
+mod callchain;
+#[cfg(feature = "linux-perf")]
+mod sample;
+
+pub use callchain::*;
+#[cfg(feature = "linux-perf")]
+pub use sample::*;
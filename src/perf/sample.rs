@@ -0,0 +1,198 @@
+use crate::aarch64::UnwindRegsAarch64;
+use crate::x86_64::UnwindRegsX86_64;
+use crate::StackSlice;
+
+/// The `abi` value of `PERF_SAMPLE_REGS_USER` when no registers were captured, for
+/// example because the sample was taken in a kernel thread.
+pub const PERF_SAMPLE_REGS_ABI_NONE: u64 = 0;
+/// The `abi` value of `PERF_SAMPLE_REGS_USER` for 32 bit processes.
+pub const PERF_SAMPLE_REGS_ABI_32: u64 = 1;
+/// The `abi` value of `PERF_SAMPLE_REGS_USER` for 64 bit processes.
+pub const PERF_SAMPLE_REGS_ABI_64: u64 = 2;
+
+/// The perf register indexes on x86_64, from `arch/x86/include/uapi/asm/perf_regs.h`.
+pub mod perf_regs_x86_64 {
+    pub const BP: u32 = 6;
+    pub const SP: u32 = 7;
+    pub const IP: u32 = 8;
+}
+
+/// The perf register indexes on aarch64, from `arch/arm64/include/uapi/asm/perf_regs.h`.
+pub mod perf_regs_aarch64 {
+    pub const X29: u32 = 29;
+    pub const LR: u32 = 30;
+    pub const SP: u32 = 31;
+    pub const PC: u32 = 32;
+}
+
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PerfSampleError {
+    #[error("The sample data ended unexpectedly")]
+    UnexpectedEnd,
+
+    #[error("The sample has no user registers")]
+    NoUserRegs,
+
+    #[error("The register {0} required for unwinding is missing from the sample_regs_user mask")]
+    MissingRegister(u32),
+}
+
+/// The decoded `PERF_SAMPLE_REGS_USER` part of a sample record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PerfUserRegs<'a> {
+    abi: u64,
+    regs_mask: u64,
+    regs_data: &'a [u8],
+}
+
+impl<'a> PerfUserRegs<'a> {
+    /// Parse the `PERF_SAMPLE_REGS_USER` part of a sample record:
+    ///
+    /// ```c
+    /// { u64 abi; u64 regs[weight(mask)]; }
+    /// ```
+    ///
+    /// `regs_mask` is the `sample_regs_user` value from the `perf_event_attr`. Returns
+    /// the parsed registers and the remaining data after them.
+    pub fn parse(data: &'a [u8], regs_mask: u64) -> Result<(Self, &'a [u8]), PerfSampleError> {
+        let (abi, rest) = read_u64(data)?;
+        let regs_len = if abi == PERF_SAMPLE_REGS_ABI_NONE {
+            0
+        } else {
+            regs_mask.count_ones() as usize * 8
+        };
+        if rest.len() < regs_len {
+            return Err(PerfSampleError::UnexpectedEnd);
+        }
+        let (regs_data, rest) = rest.split_at(regs_len);
+        let regs = Self {
+            abi,
+            regs_mask,
+            regs_data,
+        };
+        Ok((regs, rest))
+    }
+
+    /// The ABI of the sampled process, one of the `PERF_SAMPLE_REGS_ABI_*` values.
+    pub fn abi(&self) -> u64 {
+        self.abi
+    }
+
+    /// Get the value of the register with the given perf register index. Returns `None`
+    /// if the register was not captured.
+    pub fn get(&self, reg_index: u32) -> Option<u64> {
+        if self.abi == PERF_SAMPLE_REGS_ABI_NONE || reg_index >= 64 {
+            return None;
+        }
+        if self.regs_mask & (1 << reg_index) == 0 {
+            return None;
+        }
+        let position = (self.regs_mask & ((1 << reg_index) - 1)).count_ones() as usize;
+        let bytes = self.regs_data.get(position * 8..position * 8 + 8)?;
+        Some(u64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn require(&self, reg_index: u32) -> Result<u64, PerfSampleError> {
+        if self.abi == PERF_SAMPLE_REGS_ABI_NONE {
+            return Err(PerfSampleError::NoUserRegs);
+        }
+        self.get(reg_index)
+            .ok_or(PerfSampleError::MissingRegister(reg_index))
+    }
+
+    /// Convert the registers into the instruction pointer and the x86_64 unwind
+    /// registers. The mask needs to include `IP`, `SP` and `BP`.
+    pub fn unwind_regs_x86_64(&self) -> Result<(u64, UnwindRegsX86_64), PerfSampleError> {
+        let ip = self.require(perf_regs_x86_64::IP)?;
+        let sp = self.require(perf_regs_x86_64::SP)?;
+        let bp = self.require(perf_regs_x86_64::BP)?;
+        Ok((ip, UnwindRegsX86_64::new(ip, sp, bp)))
+    }
+
+    /// Convert the registers into the instruction pointer and the aarch64 unwind
+    /// registers. The mask needs to include `PC`, `SP`, `LR` and `X29`.
+    pub fn unwind_regs_aarch64(&self) -> Result<(u64, UnwindRegsAarch64), PerfSampleError> {
+        let pc = self.require(perf_regs_aarch64::PC)?;
+        let sp = self.require(perf_regs_aarch64::SP)?;
+        let lr = self.require(perf_regs_aarch64::LR)?;
+        let fp = self.require(perf_regs_aarch64::X29)?;
+        Ok((pc, UnwindRegsAarch64::new(lr, sp, fp)))
+    }
+}
+
+/// Parse the `PERF_SAMPLE_STACK_USER` part of a sample record:
+///
+/// ```c
+/// { u64 size; char data[size]; u64 dyn_size; }
+/// ```
+///
+/// `dyn_size` is only present if `size` is non-zero. The stack bytes are copied by the
+/// kernel starting at the user stack pointer, so `sp` needs to be the stack pointer
+/// from the sample's user registers.
+///
+/// Returns a [`StackSlice`] which only covers the `dyn_size` bytes which were actually
+/// captured, and the remaining data after the stack part.
+pub fn parse_stack_user(
+    data: &[u8],
+    sp: u64,
+) -> Result<(StackSlice<&[u8]>, &[u8]), PerfSampleError> {
+    let (size, rest) = read_u64(data)?;
+    if size == 0 {
+        return Ok((StackSlice::new(sp, &[][..]), rest));
+    }
+    let size = usize::try_from(size).map_err(|_| PerfSampleError::UnexpectedEnd)?;
+    if rest.len() < size {
+        return Err(PerfSampleError::UnexpectedEnd);
+    }
+    let (stack_bytes, rest) = rest.split_at(size);
+    let (dyn_size, rest) = read_u64(rest)?;
+    let dyn_size = usize::try_from(dyn_size).map_or(size, |dyn_size| dyn_size.min(size));
+    Ok((StackSlice::new(sp, &stack_bytes[..dyn_size]), rest))
+}
+
+fn read_u64(data: &[u8]) -> Result<(u64, &[u8]), PerfSampleError> {
+    if data.len() < 8 {
+        return Err(PerfSampleError::UnexpectedEnd);
+    }
+    let (bytes, rest) = data.split_at(8);
+    Ok((u64::from_le_bytes(bytes.try_into().unwrap()), rest))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_regs_and_stack() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&PERF_SAMPLE_REGS_ABI_64.to_le_bytes());
+        // Mask: BP, SP, IP
+        data.extend_from_slice(&0x7ffc0040u64.to_le_bytes());
+        data.extend_from_slice(&0x7ffc0000u64.to_le_bytes());
+        data.extend_from_slice(&0x401234u64.to_le_bytes());
+        data.extend_from_slice(&16u64.to_le_bytes());
+        data.extend_from_slice(&0x1111u64.to_le_bytes());
+        data.extend_from_slice(&0x2222u64.to_le_bytes());
+        data.extend_from_slice(&8u64.to_le_bytes());
+
+        let mask =
+            (1 << perf_regs_x86_64::BP) | (1 << perf_regs_x86_64::SP) | (1 << perf_regs_x86_64::IP);
+        let (regs, rest) = PerfUserRegs::parse(&data, mask).unwrap();
+        let (ip, unwind_regs) = regs.unwind_regs_x86_64().unwrap();
+        assert_eq!(ip, 0x401234);
+        assert_eq!(
+            unwind_regs,
+            UnwindRegsX86_64::new(0x401234, 0x7ffc0000, 0x7ffc0040)
+        );
+        assert_eq!(
+            regs.unwind_regs_aarch64(),
+            Err(PerfSampleError::MissingRegister(perf_regs_aarch64::PC))
+        );
+
+        let (stack, rest) = parse_stack_user(rest, unwind_regs.sp()).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(stack.address_range(), 0x7ffc0000..0x7ffc0008);
+        assert_eq!(stack.read_u64(0x7ffc0000), Some(0x1111));
+        assert_eq!(stack.read_u64(0x7ffc0008), None);
+    }
+}
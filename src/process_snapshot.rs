@@ -0,0 +1,172 @@
+use crate::error::Error;
+use crate::unwind_end_reason::UnwindEndReason;
+use crate::unwinder::Unwinder;
+use crate::FrameAddress;
+
+/// A source of stack memory for a [`ProcessSnapshot`].
+///
+/// This is implemented for all `FnMut(u64) -> Result<u64, ()>` closures, i.e. for the
+/// same kind of callback that the [`Unwinder`] methods accept as `read_stack`.
+pub trait MemorySource {
+    /// Read the 8 bytes at `address`. Returns `None` if they can't be read.
+    fn read_u64(&mut self, address: u64) -> Option<u64>;
+}
+
+impl<F: FnMut(u64) -> Result<u64, ()>> MemorySource for F {
+    fn read_u64(&mut self, address: u64) -> Option<u64> {
+        self(address).ok()
+    }
+}
+
+/// The initial register state of one thread in a [`ProcessSnapshot`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThreadSnapshot<R> {
+    /// The OS thread ID.
+    pub thread_id: u64,
+    /// The instruction pointer of the thread.
+    pub pc: u64,
+    /// The unwind registers of the thread.
+    pub regs: R,
+}
+
+/// The result of unwinding one thread of a [`ProcessSnapshot`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThreadBacktrace {
+    /// The OS thread ID.
+    pub thread_id: u64,
+    /// The frames, starting with the instruction pointer.
+    pub frames: Vec<FrameAddress>,
+    /// `None` if unwinding ended without an error, otherwise the error which stopped
+    /// the unwinding. The frames up to the error are still valid.
+    pub error: Option<Error>,
+    /// Why the unwinding ended.
+    pub end_reason: UnwindEndReason,
+}
+
+/// A snapshot of a process: its modules (held by the unwinder), a source for its stack
+/// memory, and the register state of each of its threads.
+///
+/// This is the common ground for unwinding all threads of a process, regardless of
+/// whether the data comes from a minidump, a core dump, or a suspended live process.
+///
+/// Type arguments:
+///
+///  - `U`: The [`Unwinder`] for the process's CPU architecture.
+///  - `M`: The [`MemorySource`] for reading stack memory.
+pub struct ProcessSnapshot<U: Unwinder, M: MemorySource> {
+    unwinder: U,
+    memory: M,
+    threads: Vec<ThreadSnapshot<U::UnwindRegs>>,
+}
+
+impl<U: Unwinder, M: MemorySource> ProcessSnapshot<U, M> {
+    /// Create a snapshot without any threads. Modules can be added to the unwinder
+    /// before or after creating the snapshot.
+    pub fn new(unwinder: U, memory: M) -> Self {
+        Self {
+            unwinder,
+            memory,
+            threads: Vec::new(),
+        }
+    }
+
+    /// Add a module that was loaded in the process.
+    pub fn add_module(&mut self, module: U::Module) {
+        self.unwinder.add_module(module);
+    }
+
+    /// Add a thread with its initial register state.
+    pub fn add_thread(&mut self, thread_id: u64, pc: u64, regs: U::UnwindRegs) {
+        self.threads.push(ThreadSnapshot {
+            thread_id,
+            pc,
+            regs,
+        });
+    }
+
+    /// The unwinder which holds the process's modules.
+    pub fn unwinder(&self) -> &U {
+        &self.unwinder
+    }
+
+    /// The threads which have been added to this snapshot.
+    pub fn threads(&self) -> &[ThreadSnapshot<U::UnwindRegs>] {
+        &self.threads
+    }
+
+    /// Unwind every thread and return one backtrace per thread, in the order in which
+    /// the threads were added.
+    pub fn backtraces(&mut self, cache: &mut U::Cache) -> Vec<ThreadBacktrace> {
+        let mut backtraces = Vec::with_capacity(self.threads.len());
+        for thread in &self.threads {
+            let memory = &mut self.memory;
+            let mut read_stack = |address| memory.read_u64(address).ok_or(());
+            let mut iter =
+                self.unwinder
+                    .iter_frames(thread.pc, thread.regs, cache, &mut read_stack);
+            let mut frames = Vec::new();
+            let (error, end_reason) = loop {
+                match iter.next() {
+                    Ok(Some(frame)) => frames.push(frame),
+                    Ok(None) => {
+                        let end_reason = iter.end_reason();
+                        break (
+                            None,
+                            end_reason.unwrap_or(UnwindEndReason::NullReturnAddress),
+                        );
+                    }
+                    Err(err) => {
+                        let end_reason = iter.end_reason();
+                        break (Some(err), end_reason.unwrap_or(UnwindEndReason::Error(err)));
+                    }
+                }
+            };
+            backtraces.push(ThreadBacktrace {
+                thread_id: thread.thread_id,
+                frames,
+                error,
+                end_reason,
+            });
+        }
+        backtraces
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::TestStack;
+    use crate::x86_64::{CacheX86_64, UnwindRegsX86_64, UnwinderX86_64};
+
+    #[test]
+    fn test_backtraces() {
+        let stack = TestStack::frame_chain();
+        let read_stack = move |addr| stack.read(addr);
+        let mut snapshot = ProcessSnapshot::new(UnwinderX86_64::<Vec<u8>>::new(), read_stack);
+        snapshot.add_thread(1, 0x100400, UnwindRegsX86_64::new(0x100400, 0x10, 0x20));
+        snapshot.add_thread(2, 0x100500, UnwindRegsX86_64::new(0x100500, 0x10, 0x400));
+        let mut cache = CacheX86_64::new();
+        let backtraces = snapshot.backtraces(&mut cache);
+        assert_eq!(
+            backtraces,
+            vec![
+                ThreadBacktrace {
+                    thread_id: 1,
+                    frames: vec![
+                        FrameAddress::from_instruction_pointer(0x100400),
+                        FrameAddress::from_return_address(0x100200).unwrap(),
+                        FrameAddress::from_return_address(0x100100).unwrap(),
+                    ],
+                    error: None,
+                    end_reason: UnwindEndReason::NullReturnAddress,
+                },
+                ThreadBacktrace {
+                    thread_id: 2,
+                    frames: vec![FrameAddress::from_instruction_pointer(0x100500)],
+                    error: Some(Error::CouldNotReadStack(0x400)),
+                    end_reason: UnwindEndReason::UnreadableStack(Error::CouldNotReadStack(0x400)),
+                },
+            ]
+        );
+    }
+}
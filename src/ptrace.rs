@@ -0,0 +1,104 @@
+use crate::UnwindRegsNative;
+
+/// Read the registers of the stopped thread `tid` using `PTRACE_GETREGSET` with
+/// `NT_PRSTATUS`, and convert them into unwind registers.
+///
+/// Returns the instruction pointer together with the unwind registers, i.e. the two
+/// values you need for [`Unwinder::iter_frames`](crate::Unwinder::iter_frames).
+///
+/// The thread must be in a ptrace-stop for the calling thread, for example after
+/// `PTRACE_ATTACH` (or `PTRACE_SEIZE` + `PTRACE_INTERRUPT`) followed by a successful
+/// `waitpid`. Only threads of the native CPU architecture are supported.
+pub fn get_thread_regs(tid: libc::pid_t) -> std::io::Result<(u64, UnwindRegsNative)> {
+    // Safety: user_regs_struct is a plain C struct of integers; all-zero is a valid value.
+    let mut user_regs: libc::user_regs_struct = unsafe { std::mem::zeroed() };
+    let mut iov = libc::iovec {
+        iov_base: &mut user_regs as *mut libc::user_regs_struct as *mut libc::c_void,
+        iov_len: std::mem::size_of::<libc::user_regs_struct>(),
+    };
+    // Safety: iov points to a buffer which is large enough for NT_PRSTATUS.
+    let ret = unsafe {
+        libc::ptrace(
+            libc::PTRACE_GETREGSET,
+            tid,
+            libc::NT_PRSTATUS as usize as *mut libc::c_void,
+            &mut iov as *mut libc::iovec as *mut libc::c_void,
+        )
+    };
+    if ret == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+    if iov.iov_len < std::mem::size_of::<libc::user_regs_struct>() {
+        // The kernel gave us a different register set than expected, for example
+        // because the tracee is a 32 bit process.
+        return Err(std::io::Error::from(std::io::ErrorKind::InvalidData));
+    }
+    Ok(unwind_regs_from_user_regs(&user_regs))
+}
+
+/// Convert a `user_regs_struct`, as returned by `PTRACE_GETREGS` / `PTRACE_GETREGSET`,
+/// into the instruction pointer and the unwind registers.
+#[cfg(target_arch = "x86_64")]
+pub fn unwind_regs_from_user_regs(user_regs: &libc::user_regs_struct) -> (u64, UnwindRegsNative) {
+    let regs = UnwindRegsNative::new(user_regs.rip, user_regs.rsp, user_regs.rbp);
+    (user_regs.rip, regs)
+}
+
+/// Convert a `user_regs_struct`, as returned by `PTRACE_GETREGSET`, into the
+/// instruction pointer and the unwind registers.
+///
+/// No pointer authentication stripping is applied to `lr`. If the tracee uses pointer
+/// authentication, use [`UnwindRegsAarch64::new_with_ptr_auth_mask`](crate::aarch64::UnwindRegsAarch64::new_with_ptr_auth_mask)
+/// with the register values from the returned regs.
+#[cfg(target_arch = "aarch64")]
+pub fn unwind_regs_from_user_regs(user_regs: &libc::user_regs_struct) -> (u64, UnwindRegsNative) {
+    let regs = UnwindRegsNative::new(user_regs.regs[30], user_regs.sp, user_regs.regs[29]);
+    (user_regs.pc, regs)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_untraced_thread() {
+        // The calling thread is not traced by itself, so the kernel refuses the request.
+        // Safety: gettid has no preconditions.
+        let tid = unsafe { libc::syscall(libc::SYS_gettid) } as libc::pid_t;
+        let err = get_thread_regs(tid).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ESRCH));
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_unwind_regs_from_user_regs() {
+        // Safety: user_regs_struct is a plain C struct of integers.
+        let mut user_regs: libc::user_regs_struct = unsafe { std::mem::zeroed() };
+        user_regs.rip = 0x100200;
+        user_regs.rsp = 0x7ff0;
+        user_regs.rbp = 0x8010;
+        user_regs.rax = 0x1234;
+        let (pc, regs) = unwind_regs_from_user_regs(&user_regs);
+        assert_eq!(pc, 0x100200);
+        assert_eq!(regs.ip(), 0x100200);
+        assert_eq!(regs.sp(), 0x7ff0);
+        assert_eq!(regs.bp(), 0x8010);
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    #[test]
+    fn test_unwind_regs_from_user_regs() {
+        // Safety: user_regs_struct is a plain C struct of integers.
+        let mut user_regs: libc::user_regs_struct = unsafe { std::mem::zeroed() };
+        user_regs.pc = 0x100200;
+        user_regs.sp = 0x7ff0;
+        user_regs.regs[29] = 0x8010;
+        user_regs.regs[30] = 0x100104;
+        user_regs.regs[0] = 0x1234;
+        let (pc, regs) = unwind_regs_from_user_regs(&user_regs);
+        assert_eq!(pc, 0x100200);
+        assert_eq!(regs.lr(), 0x100104);
+        assert_eq!(regs.sp(), 0x7ff0);
+        assert_eq!(regs.fp(), 0x8010);
+    }
+}
@@ -0,0 +1,90 @@
+use std::ops::Range;
+
+/// Address ranges with a value each, sorted by the start of the range, so that the
+/// range which contains an address is found with a binary search. This is for the
+/// ranges which are checked for every frame, such as PLT ranges.
+///
+/// The ranges are expected not to overlap. If they do, an address is only looked up
+/// in the range with the highest start address at or below it.
+pub(crate) struct RangeMap<V> {
+    /// sorted by range.start
+    entries: Vec<(Range<u64>, V)>,
+}
+
+impl<V> RangeMap<V> {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn insert(&mut self, range: Range<u64>, value: V) {
+        let index = self
+            .entries
+            .partition_point(|(entry_range, _)| entry_range.start <= range.start);
+        self.entries.insert(index, (range, value));
+    }
+
+    /// Remove the range which starts at `start`, and return it with its value.
+    pub fn remove(&mut self, start: u64) -> Option<(Range<u64>, V)> {
+        let index = self
+            .entries
+            .binary_search_by_key(&start, |(range, _)| range.start)
+            .ok()?;
+        Some(self.entries.remove(index))
+    }
+
+    /// The range which contains `address`, with its value.
+    pub fn get(&self, address: u64) -> Option<(&Range<u64>, &V)> {
+        let index = self
+            .entries
+            .partition_point(|(range, _)| range.start <= address)
+            .checked_sub(1)?;
+        let (range, value) = &self.entries[index];
+        range.contains(&address).then_some((range, value))
+    }
+
+    pub fn contains(&self, address: u64) -> bool {
+        self.get(address).is_some()
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut V> {
+        self.entries.iter_mut().map(|(_, value)| value)
+    }
+
+    pub fn retain(&mut self, mut f: impl FnMut(&Range<u64>, &V) -> bool) {
+        self.entries.retain(|(range, value)| f(range, value));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        let mut map = RangeMap::new();
+        map.insert(0x300..0x400, 3);
+        map.insert(0x100..0x180, 1);
+        map.insert(0x200..0x280, 2);
+        assert_eq!(map.get(0xff), None);
+        assert_eq!(map.get(0x100), Some((&(0x100..0x180), &1)));
+        assert_eq!(map.get(0x17f), Some((&(0x100..0x180), &1)));
+        assert_eq!(map.get(0x180), None);
+        assert_eq!(map.get(0x250), Some((&(0x200..0x280), &2)));
+        assert_eq!(map.get(0x3ff), Some((&(0x300..0x400), &3)));
+        assert!(!map.contains(0x400));
+        assert!(!map.contains(u64::MAX));
+
+        assert_eq!(map.remove(0x200), Some((0x200..0x280, 2)));
+        assert_eq!(map.remove(0x200), None);
+        assert!(!map.contains(0x250));
+        assert!(map.contains(0x300));
+
+        map.values_mut().for_each(|value| *value *= 10);
+        assert_eq!(map.get(0x100), Some((&(0x100..0x180), &10)));
+        map.retain(|_, value| *value != 10);
+        assert!(!map.contains(0x100));
+        assert_eq!(map.get(0x300), Some((&(0x300..0x400), &30)));
+    }
+}
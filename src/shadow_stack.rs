@@ -0,0 +1,17 @@
+/// A frame whose unwound return address differs from the one on the shadow stack.
+///
+/// Found by [`UnwindIterator::with_shadow_stack`](crate::UnwindIterator::with_shadow_stack)
+/// and returned by
+/// [`UnwindIterator::shadow_stack_mismatch`](crate::UnwindIterator::shadow_stack_mismatch).
+/// The shadow stack is maintained by the CPU, so a mismatch means that the unwind
+/// went wrong at or below this frame, for example because of wrong unwind information
+/// or a frame pointer that was used as a general purpose register.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ShadowStackMismatch {
+    /// The index of the frame in the stack, where the first frame has index 0.
+    pub frame_index: usize,
+    /// The return address that the unwinder found.
+    pub unwound_return_address: u64,
+    /// The return address on the shadow stack, which the iterator yielded instead.
+    pub shadow_return_address: u64,
+}
@@ -0,0 +1,232 @@
+use std::cell::UnsafeCell;
+use std::ffi::{c_int, c_void};
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicPtr, AtomicU32, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::stack_copy::copy_local_memory;
+use crate::ucontext::unwind_regs_from_ucontext;
+use crate::{StackSlice, UnwindRegsNative};
+
+const IDLE: u32 = 0;
+const REQUESTED: u32 = 1;
+const WRITING: u32 = 2;
+const DONE: u32 = 3;
+
+/// The registers and the stack bytes of a thread, captured by
+/// [`SignalSampler::sample_thread`].
+pub struct ThreadSample {
+    /// The instruction pointer.
+    pub pc: u64,
+    /// The unwind registers.
+    pub regs: UnwindRegsNative,
+    /// The stack bytes, starting at the stack pointer.
+    pub stack: StackSlice<Vec<u8>>,
+}
+
+/// Samples threads of the current process by sending them `SIGPROF`. The signal
+/// handler runs on the sampled thread, captures its registers, and copies its stack
+/// into a buffer which was allocated beforehand. It only makes system calls, so it is
+/// async-signal-safe: the sampled thread can be interrupted anywhere, even while it
+/// holds the heap lock.
+///
+/// The sampler takes over `SIGPROF` for as long as it is alive, and restores the
+/// previous handler when it is dropped. If the previous action was the default one,
+/// which terminates the process, `SIGPROF` is ignored instead: the signal of a
+/// request that timed out can still be pending for a thread which blocks it. Only one
+/// sampler can exist at a time.
+///
+/// This is only available on Linux, on x86_64 and aarch64.
+///
+/// Unwind the samples with [`UnwinderNative`](crate::UnwinderNative) and modules for
+/// the images of the current process, reading the stack with
+/// [`StackSlice::read_u64`].
+pub struct SignalSampler {
+    max_stack_bytes: usize,
+    previous_action: libc::sigaction,
+    /// Requests share one slot, so they are made one at a time.
+    lock: Mutex<()>,
+}
+
+/// The request and the result that are exchanged with the signal handler. `state`
+/// says who owns the other fields: `sample_thread` while it's `IDLE` or `DONE`, the
+/// signal handler while it's `WRITING`.
+struct Slot {
+    state: AtomicU32,
+    target_tid: AtomicI32,
+    buffer: AtomicPtr<u8>,
+    buffer_len: AtomicUsize,
+    sample: UnsafeCell<Option<(u64, UnwindRegsNative, usize)>>,
+}
+
+// Safety: Access to `sample` is synchronized through `state`.
+unsafe impl Sync for Slot {}
+
+static SLOT: Slot = Slot {
+    state: AtomicU32::new(IDLE),
+    target_tid: AtomicI32::new(0),
+    buffer: AtomicPtr::new(std::ptr::null_mut()),
+    buffer_len: AtomicUsize::new(0),
+    sample: UnsafeCell::new(None),
+};
+
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+impl SignalSampler {
+    /// Install the `SIGPROF` handler. Each sample copies up to `max_stack_bytes` of the
+    /// sampled thread's stack.
+    ///
+    /// Fails with [`std::io::ErrorKind::AlreadyExists`] if another sampler exists.
+    pub fn new(max_stack_bytes: usize) -> std::io::Result<Self> {
+        if INSTALLED
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                "a SignalSampler already exists",
+            ));
+        }
+        // Safety: The action is fully initialized before it is installed, and the
+        // handler has the signature that SA_SIGINFO requires.
+        let previous_action = unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handle_sigprof as *const () as usize;
+            action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            let mut previous_action: libc::sigaction = std::mem::zeroed();
+            if libc::sigaction(libc::SIGPROF, &action, &mut previous_action) != 0 {
+                let err = std::io::Error::last_os_error();
+                INSTALLED.store(false, Ordering::Release);
+                return Err(err);
+            }
+            previous_action
+        };
+        Ok(Self {
+            max_stack_bytes,
+            previous_action,
+            lock: Mutex::new(()),
+        })
+    }
+
+    /// Capture the registers and the stack of the thread with the thread ID `tid`,
+    /// which must belong to the current process. The current thread can sample
+    /// itself. See [`current_thread_id`].
+    ///
+    /// Fails with [`std::io::ErrorKind::TimedOut`] if the thread doesn't handle the
+    /// signal within `timeout`, for example because it blocks `SIGPROF`.
+    pub fn sample_thread(
+        &self,
+        tid: libc::pid_t,
+        timeout: Duration,
+    ) -> std::io::Result<ThreadSample> {
+        let _guard = self
+            .lock
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut buffer = vec![0; self.max_stack_bytes];
+        SLOT.target_tid.store(tid, Ordering::Relaxed);
+        SLOT.buffer.store(buffer.as_mut_ptr(), Ordering::Relaxed);
+        SLOT.buffer_len.store(buffer.len(), Ordering::Relaxed);
+        SLOT.state.store(REQUESTED, Ordering::Release);
+
+        // Safety: tgkill has no memory safety requirements.
+        let result = unsafe { libc::syscall(libc::SYS_tgkill, libc::getpid(), tid, libc::SIGPROF) };
+        if result != 0 {
+            SLOT.state.store(IDLE, Ordering::Release);
+            return Err(std::io::Error::last_os_error());
+        }
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            match SLOT.state.load(Ordering::Acquire) {
+                DONE => break,
+                REQUESTED if Instant::now() >= deadline => {
+                    // If the handler starts now, it finds the slot idle and returns.
+                    if SLOT
+                        .state
+                        .compare_exchange(REQUESTED, IDLE, Ordering::AcqRel, Ordering::Acquire)
+                        .is_ok()
+                    {
+                        return Err(std::io::Error::new(
+                            std::io::ErrorKind::TimedOut,
+                            "the thread did not handle SIGPROF",
+                        ));
+                    }
+                }
+                _ => std::thread::yield_now(),
+            }
+        }
+
+        // Safety: The handler is done with the slot, and it stays ours until the
+        // state is reset below.
+        let sample = unsafe { (*SLOT.sample.get()).take() };
+        SLOT.state.store(IDLE, Ordering::Release);
+        let (pc, regs, stack_len) = match sample {
+            Some(sample) => sample,
+            None => return Err(std::io::Error::from(std::io::ErrorKind::Other)),
+        };
+        buffer.truncate(stack_len);
+        Ok(ThreadSample {
+            pc,
+            regs,
+            stack: StackSlice::new(regs.sp(), buffer),
+        })
+    }
+}
+
+impl Drop for SignalSampler {
+    fn drop(&mut self) {
+        let mut action = self.previous_action;
+        if action.sa_sigaction == libc::SIG_DFL {
+            // A pending signal from a timed-out request must not kill the process.
+            action.sa_sigaction = libc::SIG_IGN;
+        }
+        // Safety: action was returned by sigaction, or is SIG_IGN with its mask and
+        // flags.
+        unsafe {
+            libc::sigaction(libc::SIGPROF, &action, std::ptr::null_mut());
+        }
+        INSTALLED.store(false, Ordering::Release);
+    }
+}
+
+/// The thread ID of the current thread, for [`SignalSampler::sample_thread`].
+pub fn current_thread_id() -> libc::pid_t {
+    // Safety: gettid has no memory safety requirements.
+    unsafe { libc::syscall(libc::SYS_gettid) as libc::pid_t }
+}
+
+extern "C" fn handle_sigprof(_signal: c_int, _info: *mut libc::siginfo_t, context: *mut c_void) {
+    if current_thread_id() != SLOT.target_tid.load(Ordering::Relaxed) {
+        return;
+    }
+    if SLOT
+        .state
+        .compare_exchange(REQUESTED, WRITING, Ordering::AcqRel, Ordering::Acquire)
+        .is_err()
+    {
+        return;
+    }
+    // The check above can see the target of an earlier request, which timed out, if
+    // this is the late signal of that request. Now that we own the slot, the target
+    // is the one of the current request.
+    if current_thread_id() != SLOT.target_tid.load(Ordering::Relaxed) {
+        SLOT.state.store(REQUESTED, Ordering::Release);
+        return;
+    }
+    // The interrupted code may be looking at errno.
+    // Safety: __errno_location returns a pointer to this thread's errno.
+    let errno = unsafe { *libc::__errno_location() };
+    // Safety: The kernel passes a valid ucontext_t to SA_SIGINFO handlers.
+    let (pc, regs) = unsafe { unwind_regs_from_ucontext(&*(context as *const libc::ucontext_t)) };
+    let buffer = SLOT.buffer.load(Ordering::Relaxed);
+    let buffer_len = SLOT.buffer_len.load(Ordering::Relaxed);
+    let stack_len = copy_local_memory(regs.sp(), buffer, buffer_len);
+    // Safety: We own the slot while its state is WRITING.
+    unsafe {
+        *SLOT.sample.get() = Some((pc, regs, stack_len));
+        *libc::__errno_location() = errno;
+    }
+    SLOT.state.store(DONE, Ordering::Release);
+}
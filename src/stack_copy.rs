@@ -0,0 +1,154 @@
+use crate::{MemorySource, StackSlice};
+
+#[cfg(all(feature = "local-stack-copy", any(target_os = "linux", windows)))]
+const PAGE_SIZE: u64 = 0x1000;
+
+/// Copy up to `max_stack_bytes` of stack starting at `sp`, 8 bytes at a time, with
+/// `memory`, for example a reader for the memory of another process. The copy stops
+/// at the first word which can't be read, so the returned stack can be shorter than
+/// `max_stack_bytes`; this is normal when the copy reaches the end of the stack.
+pub fn copy_stack<M: MemorySource>(
+    memory: &mut M,
+    sp: u64,
+    max_stack_bytes: usize,
+) -> StackSlice<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(max_stack_bytes);
+    let mut address = sp;
+    while bytes.len() + 8 <= max_stack_bytes {
+        match memory.read_u64(address) {
+            Some(value) => bytes.extend_from_slice(&value.to_le_bytes()),
+            None => break,
+        }
+        address = match address.checked_add(8) {
+            Some(address) => address,
+            None => break,
+        };
+    }
+    StackSlice::new(sp, bytes)
+}
+
+/// Copy up to `max_stack_bytes` of stack of the current process starting at `sp`,
+/// for example of a thread which was suspended or interrupted by a signal. The copy
+/// goes through the kernel, so it fails instead of faulting at the guard page at the
+/// end of the stack, and it stops early at the first unreadable page.
+///
+/// This allocates, so it can't be used in a signal handler; the `signal_sampling`
+/// module copies the stack of an interrupted thread without allocating.
+#[cfg(all(feature = "local-stack-copy", any(target_os = "linux", windows)))]
+pub fn copy_local_stack(sp: u64, max_stack_bytes: usize) -> StackSlice<Vec<u8>> {
+    let mut buffer = vec![0; max_stack_bytes];
+    #[cfg(target_os = "linux")]
+    let len = copy_local_memory(sp, buffer.as_mut_ptr(), buffer.len());
+    #[cfg(windows)]
+    // Safety: GetCurrentProcess has no memory safety requirements.
+    let len = copy_process_memory(
+        unsafe { windows_sys::Win32::System::Threading::GetCurrentProcess() },
+        sp,
+        &mut buffer,
+    );
+    buffer.truncate(len);
+    StackSlice::new(sp, buffer)
+}
+
+/// Copy memory of the current process starting at `address` into the buffer, page by
+/// page, until the buffer is full or a page cannot be read. Returns the number of
+/// copied bytes.
+///
+/// The bytes are copied with `process_vm_readv` on the current process, which fails
+/// instead of faulting when it reaches the guard page at the end of the stack. This
+/// only makes system calls, so it is async-signal-safe.
+#[cfg(all(feature = "local-stack-copy", target_os = "linux"))]
+pub(crate) fn copy_local_memory(address: u64, buffer: *mut u8, buffer_len: usize) -> usize {
+    use std::ffi::c_void;
+
+    let mut copied = 0;
+    while copied < buffer_len {
+        let address = address + copied as u64;
+        let page_end = (address / PAGE_SIZE + 1) * PAGE_SIZE;
+        let chunk_len = ((page_end - address) as usize).min(buffer_len - copied);
+        let local = libc::iovec {
+            // Safety: copied < buffer_len, so the pointer is inside the buffer.
+            iov_base: unsafe { buffer.add(copied) } as *mut c_void,
+            iov_len: chunk_len,
+        };
+        let remote = libc::iovec {
+            iov_base: address as *mut c_void,
+            iov_len: chunk_len,
+        };
+        // Safety: The local range is inside the buffer.
+        let bytes_read =
+            unsafe { libc::process_vm_readv(libc::getpid(), &local, 1, &remote, 1, 0) };
+        if bytes_read <= 0 {
+            break;
+        }
+        copied += (bytes_read as usize).min(chunk_len);
+        if (bytes_read as usize) < chunk_len {
+            break;
+        }
+    }
+    copied
+}
+
+/// Copy memory of `process` starting at `address` into `buffer`, page by page, until
+/// the buffer is full or a page cannot be read. Returns the number of copied bytes.
+#[cfg(all(feature = "local-stack-copy", windows))]
+pub(crate) fn copy_process_memory(
+    process: windows_sys::Win32::Foundation::HANDLE,
+    address: u64,
+    buffer: &mut [u8],
+) -> usize {
+    use std::ffi::c_void;
+    use windows_sys::Win32::System::Diagnostics::Debug::ReadProcessMemory;
+
+    let mut copied = 0;
+    while copied < buffer.len() {
+        let address = address + copied as u64;
+        let page_end = (address / PAGE_SIZE + 1) * PAGE_SIZE;
+        let chunk_len = ((page_end - address) as usize).min(buffer.len() - copied);
+        let mut bytes_read = 0;
+        // Safety: The destination range is inside buffer.
+        let success = unsafe {
+            ReadProcessMemory(
+                process,
+                address as *const c_void,
+                buffer[copied..].as_mut_ptr() as *mut c_void,
+                chunk_len,
+                &mut bytes_read,
+            )
+        };
+        copied += bytes_read.min(chunk_len);
+        if success == 0 || bytes_read < chunk_len {
+            break;
+        }
+    }
+    copied
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::TestStack;
+
+    #[test]
+    fn test_copy_stack() {
+        let stack = TestStack::from([1, 2, 3, 4]);
+        let mut read_stack = |addr| stack.read(addr);
+        let slice = copy_stack(&mut read_stack, 0x8, 0x100);
+        assert_eq!(slice.address_range(), 0x8..0x20);
+        assert_eq!(slice.read_u64(0x18), Some(4));
+        let slice = copy_stack(&mut read_stack, 0x0, 0x13);
+        assert_eq!(slice.address_range(), 0x0..0x10);
+    }
+
+    #[cfg(all(feature = "local-stack-copy", target_os = "linux"))]
+    #[test]
+    fn test_copy_local_stack() {
+        let values = [0x1234u64, 0x5678];
+        let sp = values.as_ptr() as u64;
+        let slice = copy_local_stack(sp, 16);
+        assert_eq!(slice.read_u64(sp), Some(0x1234));
+        assert_eq!(slice.read_u64(sp + 8), Some(0x5678));
+        // Unmapped memory is not copied.
+        assert!(copy_local_stack(0x8, 16).bytes().is_empty());
+    }
+}
@@ -0,0 +1,48 @@
+/// A streaming 64-bit hash over the frames of a stack, see
+/// [`UnwindIterator::with_stack_hash`](crate::UnwindIterator::with_stack_hash).
+///
+/// The hash function is fixed, and modules are identified by their
+/// [id](crate::Module::id), not by their load address. So hashes from different walks
+/// and different runs can be compared, as long as the modules have the same ids.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct StackHasher {
+    state: u64,
+}
+
+const SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+const MULTIPLIER: u64 = 0x517c_c1b7_2722_0a95;
+
+impl StackHasher {
+    pub fn new() -> Self {
+        Self { state: SEED }
+    }
+
+    /// Add a frame, identified by its module and its module-relative address.
+    pub fn add_frame(&mut self, module_id: u64, relative_address: u64) {
+        self.add(module_id);
+        self.add(relative_address);
+    }
+
+    fn add(&mut self, value: u64) {
+        self.state = (self.state.rotate_left(5) ^ value).wrapping_mul(MULTIPLIER);
+    }
+
+    /// The hash of the frames that were added so far. The final mixing step of
+    /// MurmurHash3 spreads every input bit over the whole hash.
+    pub fn finish(&self) -> u64 {
+        let mut hash = self.state;
+        hash ^= hash >> 33;
+        hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+        hash ^= hash >> 33;
+        hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+        hash ^= hash >> 33;
+        hash
+    }
+}
+
+/// The default [id](crate::Module::id) of a module: the 64-bit FNV-1a hash of its name.
+pub(crate) fn module_name_hash(name: &str) -> u64 {
+    name.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
@@ -0,0 +1,56 @@
+use std::ops::{Deref, Range};
+
+/// A copy of a contiguous piece of stack memory, for example the stack bytes that
+/// were captured together with a sample.
+///
+/// [`StackSlice::read_u64`] can be used for the `read_stack` callback:
+///
+/// ```
+/// use framehop::StackSlice;
+///
+/// let stack = StackSlice::new(0x7ffd0000, vec![0u8; 0x100]);
+/// let mut read_stack = |addr| stack.read_u64(addr).ok_or(());
+/// assert_eq!(read_stack(0x7ffd0008), Ok(0));
+/// assert_eq!(read_stack(0x7ffd0100), Err(()));
+/// ```
+///
+/// Type arguments:
+///
+///  - `D`: The type for the stack bytes. It just needs to provide a slice of bytes via
+///    its `Deref` implementation.
+#[derive(Clone)]
+pub struct StackSlice<D: Deref<Target = [u8]>> {
+    start_address: u64,
+    bytes: D,
+}
+
+impl<D: Deref<Target = [u8]>> StackSlice<D> {
+    /// Create a stack slice whose first byte is at `start_address`. This is usually the
+    /// stack pointer value at the time the stack was captured.
+    pub fn new(start_address: u64, bytes: D) -> Self {
+        Self {
+            start_address,
+            bytes,
+        }
+    }
+
+    /// Read the 8 bytes at `address`, in little endian byte order. Returns `None` if
+    /// any of the 8 bytes are outside the captured range.
+    pub fn read_u64(&self, address: u64) -> Option<u64> {
+        let offset = address.checked_sub(self.start_address)?;
+        let offset = usize::try_from(offset).ok()?;
+        let end = offset.checked_add(8)?;
+        let bytes = self.bytes.get(offset..end)?;
+        Some(u64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    /// The address range covered by the captured bytes.
+    pub fn address_range(&self) -> Range<u64> {
+        self.start_address..self.start_address + self.bytes.len() as u64
+    }
+
+    /// The captured bytes.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
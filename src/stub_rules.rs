@@ -0,0 +1,30 @@
+use crate::unwind_rule::UnwindRule;
+
+/// The rules that the unwinder uses for code whose unwind rule it infers from
+/// conventions instead of reading it from unwind information. Each architecture has
+/// defaults, which can be overridden per module with the concrete unwinder's
+/// `set_module_stub_rules` method, for example for a JIT region whose stubs push the
+/// return address before they jump.
+///
+/// `R` is the architecture's rule type, such as
+/// [`UnwindRuleX86_64`](crate::x86_64::UnwindRuleX86_64).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StubRules<R> {
+    /// The rule for stub functions: the mach-O `__stubs` section, addresses in the
+    /// first frame that the module's `__unwind_info` doesn't cover, and PLT ranges
+    /// whose code bytes couldn't be analyzed.
+    pub stub_functions: R,
+    /// The rule for the first instruction of a function in the first frame, before
+    /// the function's prologue has run. Used with `__unwind_info`.
+    pub function_start: R,
+}
+
+impl<R: UnwindRule> StubRules<R> {
+    /// The architecture's defaults.
+    pub(crate) fn arch_default() -> Self {
+        Self {
+            stub_functions: R::rule_for_stub_functions(),
+            function_start: R::rule_for_function_start(),
+        }
+    }
+}
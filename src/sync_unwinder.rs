@@ -0,0 +1,132 @@
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::unwinder::Unwinder;
+
+/// A wrapper which lets several threads unwind with the same [`Unwinder`] while
+/// modules are being added and removed.
+///
+/// Unwinding only needs shared access to the unwinder, so any number of threads can
+/// walk stacks at the same time: call [`SyncUnwinder::read`] and use the returned
+/// guard like the unwinder itself. [`SyncUnwinder::add_module`] and
+/// [`SyncUnwinder::remove_module`] wait until the walks that hold a guard are done,
+/// so every walk sees a consistent list of modules from start to finish. Keep the
+/// guard only for the duration of one walk, so that module updates aren't delayed.
+///
+/// Each thread needs its own cache, because unwinding mutates it. Caches notice when
+/// the modules change and don't return rules for the old modules, so they don't need
+/// to be cleared after [`SyncUnwinder::add_module`] or
+/// [`SyncUnwinder::remove_module`].
+///
+/// ```
+/// use framehop::x86_64::{CacheX86_64, UnwindRegsX86_64, UnwinderX86_64};
+/// use framehop::{SyncUnwinder, Unwinder};
+///
+/// let unwinder = SyncUnwinder::new(UnwinderX86_64::<Vec<u8>>::new());
+/// std::thread::scope(|s| {
+///     s.spawn(|| {
+///         let mut cache = CacheX86_64::<_>::new();
+///         let mut read_stack = |_| Err(());
+///         let regs = UnwindRegsX86_64::new(0x1000, 0x10, 0x20);
+///         let unwinder = unwinder.read();
+///         let mut iter = unwinder.iter_frames(0x1000, regs, &mut cache, &mut read_stack);
+///         while let Ok(Some(_frame)) = iter.next() {}
+///     });
+///     s.spawn(|| unwinder.remove_module(0x100000));
+/// });
+/// ```
+///
+/// A panic in another thread while it held the lock doesn't make the unwinder
+/// unusable: adding and removing modules leaves the module list consistent even if a
+/// caller panics, so the lock's poisoning is ignored.
+#[derive(Debug, Default)]
+pub struct SyncUnwinder<U: Unwinder> {
+    unwinder: RwLock<U>,
+}
+
+impl<U: Unwinder> SyncUnwinder<U> {
+    /// Wrap `unwinder`.
+    pub fn new(unwinder: U) -> Self {
+        Self {
+            unwinder: RwLock::new(unwinder),
+        }
+    }
+
+    /// Shared access to the unwinder, for walking stacks. Blocks while a module is
+    /// being added or removed.
+    pub fn read(&self) -> RwLockReadGuard<'_, U> {
+        self.unwinder
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Exclusive access to the unwinder, for configuration which isn't part of the
+    /// [`Unwinder`] trait, like root ranges. Blocks until all walks are done.
+    pub fn write(&self) -> RwLockWriteGuard<'_, U> {
+        self.unwinder
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Add a module, see [`Unwinder::add_module`]. Blocks until all walks are done.
+    pub fn add_module(&self, module: U::Module) {
+        self.write().add_module(module);
+    }
+
+    /// Remove a module, see [`Unwinder::remove_module`]. Blocks until all walks are
+    /// done.
+    pub fn remove_module(&self, module_avma_range_start: u64) {
+        self.write().remove_module(module_avma_range_start);
+    }
+
+    /// Unwrap the unwinder.
+    pub fn into_inner(self) -> U {
+        self.unwinder
+            .into_inner()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::{frame_addresses, test_module, TestStack};
+    use crate::x86_64::{CacheX86_64, UnwindRegsX86_64, UnwinderX86_64};
+
+    #[test]
+    fn test_concurrent_walks() {
+        let unwinder = SyncUnwinder::new(UnwinderX86_64::new());
+        unwinder.add_module(test_module(0x100000..0x100400, None));
+        let stack = TestStack::frame_chain();
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    let mut cache = CacheX86_64::<_>::new();
+                    let mut read_stack = |addr| stack.read(addr);
+                    for _ in 0..100 {
+                        let unwinder = unwinder.read();
+                        let mut iter = unwinder.iter_frames(
+                            0x100300,
+                            UnwindRegsX86_64::new(0x100300, 0x10, 0x20),
+                            &mut cache,
+                            &mut read_stack,
+                        );
+                        assert_eq!(
+                            frame_addresses(&mut iter),
+                            vec![0x100300, 0x100200, 0x100100]
+                        );
+                    }
+                });
+            }
+            s.spawn(|| {
+                for i in 0..100 {
+                    let start = 0x200000 + i * 0x1000;
+                    unwinder.add_module(test_module(start..start + 0x400, None));
+                }
+                for i in 0..100 {
+                    unwinder.remove_module(0x200000 + i * 0x1000);
+                }
+            });
+        });
+        assert!(!unwinder.into_inner().is_known_code_address(0x200000));
+    }
+}
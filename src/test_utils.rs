@@ -0,0 +1,163 @@
+//! Module and stack fixtures for the unit tests.
+
+use std::ops::Range;
+
+use crate::unwind_iterator::UnwindIterator;
+use crate::unwinder::{Module, ModuleSvmaInfo, ModuleUnwindData, TextByteData, Unwinder};
+
+/// The section information of a module whose base SVMA is 0, with only a text
+/// section.
+pub fn test_svma_info(text: Option<Range<u64>>) -> ModuleSvmaInfo {
+    ModuleSvmaInfo {
+        text,
+        ..Default::default()
+    }
+}
+
+/// A module called "lib" without unwind data, which covers `avma_range` and is based
+/// at its start. Its text section is the whole range, and `text` are the code bytes of
+/// the whole range, if the test needs them.
+pub fn test_module(avma_range: Range<u64>, text: Option<Vec<u8>>) -> Module<Vec<u8>> {
+    let base_avma = avma_range.start;
+    let text_svma = 0..avma_range.end - base_avma;
+    let text_data = text.map(|bytes| TextByteData::new(bytes, avma_range.clone()));
+    Module::new(
+        "lib".to_string(),
+        avma_range,
+        base_avma,
+        test_svma_info(Some(text_svma)),
+        ModuleUnwindData::None,
+        text_data,
+    )
+}
+
+/// An x86_64 .eh_frame with FDEs for the hot part of a function at 0x300..0x310,
+/// and for its cold part at 0x400..0x410, which starts with the 24-byte stack frame
+/// of the hot part.
+pub fn hot_and_cold_eh_frame() -> Vec<u8> {
+    #[rustfmt::skip]
+    let eh_frame = vec![
+        // CIE: augmentation "", code alignment 1, data alignment -8, return
+        // address in r16; DW_CFA_def_cfa rsp+8; DW_CFA_offset r16 at CFA-8.
+        0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01, 0x78, 0x10,
+        0x0c, 0x07, 0x08, 0x90, 0x01, 0x00, 0x00,
+        // FDE for the cold part 0x400..0x410: DW_CFA_def_cfa_offset 24.
+        0x18, 0x00, 0x00, 0x00, 0x18, 0x00, 0x00, 0x00,
+        0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x0e, 0x18, 0x00, 0x00,
+        // FDE for the hot part 0x300..0x310, without instructions.
+        0x14, 0x00, 0x00, 0x00, 0x34, 0x00, 0x00, 0x00,
+        0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+    eh_frame
+}
+
+/// A module at 0x100000..0x101000 whose unwind information is `eh_frame`, which
+/// follows its text section.
+pub fn eh_frame_module(eh_frame: Vec<u8>, text: Option<Vec<u8>>) -> Module<Vec<u8>> {
+    let eh_frame_len = eh_frame.len() as u64;
+    Module::new(
+        "lib".to_string(),
+        0x100000..0x101000,
+        0x100000,
+        ModuleSvmaInfo {
+            text: Some(0..0x1000),
+            eh_frame: Some(0x1000..0x1000 + eh_frame_len),
+            ..Default::default()
+        },
+        ModuleUnwindData::EhFrame(eh_frame),
+        text.map(|bytes| TextByteData::new(bytes, 0x100000..0x101000)),
+    )
+}
+
+/// Stack memory made of 8-byte slots, starting at address 0.
+#[derive(Clone, Debug, Default)]
+pub struct TestStack {
+    slots: Vec<u64>,
+}
+
+impl TestStack {
+    /// A stack with `len` zeroed slots.
+    pub fn zeroed(len: usize) -> Self {
+        Self {
+            slots: vec![0; len],
+        }
+    }
+
+    /// The stack that most tests walk, for sp = 0x10 and a frame pointer of 0x20.
+    /// The frame records at 0x20 and 0x40 return to 0x100200 and 0x100100, and the
+    /// one at 0x70 ends the chain. The slot at sp holds 0x100300, which is found
+    /// by rules that read the return address from sp.
+    pub fn frame_chain() -> Self {
+        Self {
+            slots: vec![
+                1, 2, 0x100300, 4, 0x40, 0x100200, 5, 6, 0x70, 0x100100, 7, 8, 9, 10, 0x0, 0x0,
+            ],
+        }
+    }
+
+    /// Store `value` at `address`, growing the stack if needed.
+    pub fn set(&mut self, address: u64, value: u64) {
+        let index = (address / 8) as usize;
+        if index >= self.slots.len() {
+            self.slots.resize(index + 1, 0);
+        }
+        self.slots[index] = value;
+    }
+
+    /// Store `value` at `address`.
+    pub fn with(mut self, address: u64, value: u64) -> Self {
+        self.set(address, value);
+        self
+    }
+
+    /// Store a frame record at `address`: the caller's frame pointer, followed by the
+    /// return address.
+    pub fn with_frame_record(self, address: u64, fp: u64, return_address: u64) -> Self {
+        self.with(address, fp).with(address + 8, return_address)
+    }
+
+    /// Read the slot at `address`, for use as the `read_stack` callback.
+    pub fn read(&self, address: u64) -> Result<u64, ()> {
+        self.slots.get((address / 8) as usize).copied().ok_or(())
+    }
+}
+
+impl<const N: usize> From<[u64; N]> for TestStack {
+    fn from(slots: [u64; N]) -> Self {
+        Self {
+            slots: slots.to_vec(),
+        }
+    }
+}
+
+/// Walk the rest of the stack and return the addresses of the frames, until the walk
+/// ends or fails.
+pub fn frame_addresses<U, F>(iter: &mut UnwindIterator<'_, '_, '_, U, F>) -> Vec<u64>
+where
+    U: Unwinder + ?Sized,
+    F: FnMut(u64) -> Result<u64, ()>,
+{
+    let mut frames = Vec::new();
+    while let Ok(Some(frame)) = iter.next() {
+        frames.push(frame.address());
+    }
+    frames
+}
+
+/// Run `f` with the handle of another thread, which stays blocked until `f` returns.
+#[cfg(all(feature = "windows-threads", windows))]
+pub fn with_blocked_thread<R>(f: impl FnOnce(windows_sys::Win32::Foundation::HANDLE) -> R) -> R {
+    use std::os::windows::io::AsRawHandle;
+
+    let (sender, receiver) = std::sync::mpsc::channel::<()>();
+    let thread = std::thread::spawn(move || {
+        let _ = receiver.recv();
+    });
+    let result = f(thread.as_raw_handle() as _);
+    drop(sender);
+    thread.join().unwrap();
+    result
+}
@@ -0,0 +1,102 @@
+//! Wrappers around the `tracing` macros which expand to nothing when the `tracing`
+//! feature is disabled, so that call sites don't need their own `#[cfg]`.
+
+macro_rules! trace_event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::trace!($($arg)*);
+    };
+}
+
+macro_rules! debug_event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($arg)*);
+    };
+}
+
+pub(crate) use debug_event;
+pub(crate) use trace_event;
+
+#[cfg(all(test, feature = "tracing"))]
+mod test {
+    use std::fmt::Debug;
+    use std::sync::{Arc, Mutex};
+
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    use crate::test_utils::{test_module, TestStack};
+    use crate::x86_64::{CacheX86_64, UnwindRegsX86_64, UnwinderX86_64};
+    use crate::{FrameAddress, Unwinder};
+
+    /// A subscriber which records the messages of all events.
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, _span: &Attributes<'_>) -> Id {
+            Id::from_u64(1)
+        }
+        fn record(&self, _span: &Id, _values: &Record<'_>) {}
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+        fn event(&self, event: &Event<'_>) {
+            struct Message<'a>(&'a mut Vec<String>);
+            impl Visit for Message<'_> {
+                fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+                    if field.name() == "message" {
+                        self.0.push(format!("{value:?}"));
+                    }
+                }
+            }
+            event.record(&mut Message(&mut self.0.lock().unwrap()));
+        }
+        fn enter(&self, _span: &Id) {}
+        fn exit(&self, _span: &Id) {}
+    }
+
+    #[test]
+    fn test_events() {
+        let stack = TestStack::frame_chain();
+        let mut read_stack = |addr| stack.read(addr);
+        let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        unwinder.add_module(test_module(0x100000..0x100400, None));
+        let mut cache = CacheX86_64::new();
+        let mut unwind = |address| {
+            let mut regs = UnwindRegsX86_64::new(address, 0x10, 0x20);
+            unwinder
+                .unwind_frame(
+                    FrameAddress::from_return_address(address).unwrap(),
+                    &mut regs,
+                    &mut cache,
+                    &mut read_stack,
+                )
+                .unwrap();
+        };
+
+        let recorder = Recorder::default();
+        tracing::subscriber::with_default(recorder.clone(), || {
+            unwind(0x500000);
+            unwind(0x100300);
+            unwind(0x100300);
+        });
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            vec![
+                "rule cache miss",
+                "no module for address, using fallback rule",
+                "caching rule",
+                "rule cache miss",
+                "found module",
+                "selected unwinder",
+                "module has no unwind data, using fallback rule",
+                "caching rule",
+                "rule cache hit",
+            ]
+        );
+    }
+}
@@ -0,0 +1,159 @@
+#[cfg(target_arch = "aarch64")]
+use crate::aarch64::UnwindRegsAarch64;
+#[cfg(target_arch = "x86_64")]
+use crate::x86_64::UnwindRegsX86_64;
+use crate::UnwindRegsNative;
+
+/// Convert the `ucontext_t` which was passed to a signal handler (installed with
+/// `SA_SIGINFO`) into the instruction pointer and the unwind registers of the
+/// interrupted code.
+///
+/// On Linux, the same conversion is available as `From<&libc::ucontext_t>`, because
+/// the mcontext is stored inline. On macOS, `uc_mcontext` is a raw pointer, so there
+/// is no safe `From` impl for `ucontext_t`; only `From<&libc::__darwin_mcontext64>`
+/// is provided, for callers who already have a reference to the mcontext.
+///
+/// # Safety
+///
+/// On macOS, `uc_mcontext` is a pointer, which is dereferenced. It must point to a
+/// valid mcontext, which is the case for the `ucontext_t` passed to a signal handler.
+/// On Linux, this function has no safety requirements.
+pub unsafe fn unwind_regs_from_ucontext(ucontext: &libc::ucontext_t) -> (u64, UnwindRegsNative) {
+    #[cfg(target_os = "linux")]
+    let mcontext = &ucontext.uc_mcontext;
+    #[cfg(target_os = "macos")]
+    let mcontext = &*ucontext.uc_mcontext;
+    unwind_regs_from_mcontext(mcontext)
+}
+
+#[cfg(target_os = "linux")]
+type RawMcontext = libc::mcontext_t;
+#[cfg(target_os = "macos")]
+type RawMcontext = libc::__darwin_mcontext64;
+
+#[cfg(target_arch = "x86_64")]
+fn unwind_regs_from_mcontext(mcontext: &RawMcontext) -> (u64, UnwindRegsX86_64) {
+    let regs = UnwindRegsX86_64::from(mcontext);
+    (regs.ip(), regs)
+}
+
+#[cfg(target_arch = "aarch64")]
+fn unwind_regs_from_mcontext(mcontext: &RawMcontext) -> (u64, UnwindRegsAarch64) {
+    #[cfg(target_os = "linux")]
+    let pc = mcontext.pc;
+    #[cfg(target_os = "macos")]
+    let pc = crate::aarch64::PtrAuthMask::new_24_40().strip_ptr_auth(mcontext.__ss.__pc);
+    (pc, UnwindRegsAarch64::from(mcontext))
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+impl From<&libc::mcontext_t> for UnwindRegsX86_64 {
+    fn from(mcontext: &libc::mcontext_t) -> Self {
+        let gregs = &mcontext.gregs;
+        UnwindRegsX86_64::new(
+            gregs[libc::REG_RIP as usize] as u64,
+            gregs[libc::REG_RSP as usize] as u64,
+            gregs[libc::REG_RBP as usize] as u64,
+        )
+    }
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+impl From<&libc::ucontext_t> for UnwindRegsX86_64 {
+    fn from(ucontext: &libc::ucontext_t) -> Self {
+        Self::from(&ucontext.uc_mcontext)
+    }
+}
+
+#[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+impl From<&libc::mcontext_t> for UnwindRegsAarch64 {
+    fn from(mcontext: &libc::mcontext_t) -> Self {
+        UnwindRegsAarch64::new(mcontext.regs[30], mcontext.sp, mcontext.regs[29])
+    }
+}
+
+#[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+impl From<&libc::ucontext_t> for UnwindRegsAarch64 {
+    fn from(ucontext: &libc::ucontext_t) -> Self {
+        Self::from(&ucontext.uc_mcontext)
+    }
+}
+
+#[cfg(all(target_os = "macos", target_arch = "x86_64"))]
+impl From<&libc::__darwin_mcontext64> for UnwindRegsX86_64 {
+    fn from(mcontext: &libc::__darwin_mcontext64) -> Self {
+        let ss = &mcontext.__ss;
+        UnwindRegsX86_64::new(ss.__rip, ss.__rsp, ss.__rbp)
+    }
+}
+
+/// On macOS arm64, the registers are stripped of pointer authentication bits with
+/// [`PtrAuthMask::new_24_40`](crate::aarch64::PtrAuthMask::new_24_40), which is
+/// harmless for non-arm64e processes.
+#[cfg(all(target_os = "macos", target_arch = "aarch64"))]
+impl From<&libc::__darwin_mcontext64> for UnwindRegsAarch64 {
+    fn from(mcontext: &libc::__darwin_mcontext64) -> Self {
+        let mask = crate::aarch64::PtrAuthMask::new_24_40();
+        let ss = &mcontext.__ss;
+        UnwindRegsAarch64::new_with_ptr_auth_mask(
+            mask,
+            ss.__lr,
+            mask.strip_ptr_auth(ss.__sp),
+            mask.strip_ptr_auth(ss.__fp),
+        )
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod test {
+    use super::*;
+
+    fn zeroed_ucontext() -> libc::ucontext_t {
+        // Safety: ucontext_t is a plain C struct; all-zero is a valid value.
+        unsafe { std::mem::zeroed() }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_unwind_regs_from_ucontext() {
+        let mut ucontext = zeroed_ucontext();
+        let gregs = &mut ucontext.uc_mcontext.gregs;
+        gregs[libc::REG_RIP as usize] = 0x100200;
+        gregs[libc::REG_RSP as usize] = 0x7ff0;
+        gregs[libc::REG_RBP as usize] = 0x8010;
+        gregs[libc::REG_RAX as usize] = 0x1234;
+
+        let regs = UnwindRegsX86_64::from(&ucontext);
+        assert_eq!(regs.ip(), 0x100200);
+        assert_eq!(regs.sp(), 0x7ff0);
+        assert_eq!(regs.bp(), 0x8010);
+
+        // Safety: On Linux, the mcontext is stored inline.
+        let (pc, regs) = unsafe { unwind_regs_from_ucontext(&ucontext) };
+        assert_eq!(pc, 0x100200);
+        assert_eq!(regs.sp(), 0x7ff0);
+        assert_eq!(regs.bp(), 0x8010);
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    #[test]
+    fn test_unwind_regs_from_ucontext() {
+        let mut ucontext = zeroed_ucontext();
+        let mcontext = &mut ucontext.uc_mcontext;
+        mcontext.pc = 0x100200;
+        mcontext.sp = 0x7ff0;
+        mcontext.regs[29] = 0x8010;
+        mcontext.regs[30] = 0x100104;
+        mcontext.regs[0] = 0x1234;
+
+        let regs = UnwindRegsAarch64::from(&ucontext);
+        assert_eq!(regs.lr(), 0x100104);
+        assert_eq!(regs.sp(), 0x7ff0);
+        assert_eq!(regs.fp(), 0x8010);
+
+        // Safety: On Linux, the mcontext is stored inline.
+        let (pc, regs) = unsafe { unwind_regs_from_ucontext(&ucontext) };
+        assert_eq!(pc, 0x100200);
+        assert_eq!(regs.lr(), 0x100104);
+    }
+}
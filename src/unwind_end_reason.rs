@@ -0,0 +1,41 @@
+use crate::error::Error;
+
+/// Why a stack walk ended. Returned by [`UnwindIterator::end_reason`](crate::UnwindIterator::end_reason).
+///
+/// Profilers can use this to tell complete stacks from broken ones when aggregating
+/// samples: only [`ReachedRoot`](UnwindEndReason::ReachedRoot) and
+/// [`NullReturnAddress`](UnwindEndReason::NullReturnAddress) mean that the stack was
+/// walked all the way to its end.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnwindEndReason {
+    /// The last frame is in one of the unwinder's root address ranges, see
+    /// [`Unwinder::is_root_address`](crate::Unwinder::is_root_address).
+    ReachedRoot,
+    /// The unwind information or the frame pointer chain marked the end of the stack,
+    /// usually with a null return address or a null frame pointer.
+    NullReturnAddress,
+    /// The walk failed in a frame whose address isn't covered by any known module, so
+    /// the unwinder could only guess using frame pointers.
+    MissingModule(u64),
+    /// The walk failed because stack memory could not be read. The error is one of
+    /// [`Error::CouldNotReadStack`], [`Error::StackTruncated`] or
+    /// [`Error::OutOfStackBounds`].
+    UnreadableStack(Error),
+    /// The walk stopped at the depth that was set with
+    /// [`UnwindIterator::with_max_depth`](crate::UnwindIterator::with_max_depth).
+    MaxDepth,
+    /// The walk stopped because it used up the stack read budget that was set with
+    /// [`UnwindIterator::with_stack_read_budget`](crate::UnwindIterator::with_stack_read_budget).
+    BudgetExhausted,
+    /// The unwind failed or disagreed with the last branch records that were supplied
+    /// with [`UnwindIterator::with_branch_records`](crate::UnwindIterator::with_branch_records),
+    /// so the walk continued with the calls from the branch records, and ended when
+    /// there were no more of them.
+    EndOfBranchRecords,
+    /// The filter that was set with
+    /// [`UnwindIterator::with_frame_filter`](crate::UnwindIterator::with_frame_filter)
+    /// ended the walk.
+    StoppedByFilter,
+    /// The walk failed with any other error.
+    Error(Error),
+}
@@ -0,0 +1,2151 @@
+use fallible_iterator::FallibleIterator;
+
+use crate::branch_record::{pending_call_sites, BranchRecord};
+use crate::error::Error;
+use crate::frame_confidence::FrameConfidence;
+use crate::frame_divergence::FrameDivergence;
+use crate::frame_filter::FrameFilterAction;
+use crate::frame_provenance::{FrameProvenance, FrameSource};
+use crate::inline_frames::{InlineFrameExpander, InlineFrameIterator};
+use crate::shadow_stack::ShadowStackMismatch;
+use crate::stack_hash::StackHasher;
+use crate::unwind_end_reason::UnwindEndReason;
+use crate::unwind_regs::UnwindRegs;
+use crate::unwinder::Unwinder;
+use crate::{FrameAddress, FrameAddressKind};
+
+use std::ops::Range;
+
+/// An iterator for unwinding the entire stack, starting from the initial register values.
+///
+/// The first yielded frame is the instruction pointer. Subsequent addresses are return
+/// addresses.
+///
+/// This iterator attempts to detect if stack unwinding completed successfully, or if the
+/// stack was truncated prematurely. If it thinks that it successfully found the root
+/// function, it will complete with `Ok(None)`, otherwise it will complete with `Err(...)`.
+/// However, the detection does not work in all cases, so you should expect `Err(...)` to
+/// be returned even during normal operation. As a result, it is not recommended to use
+/// this iterator as a `FallibleIterator`, because you might lose the entire stack if the
+/// last iteration returns `Err(...)`.
+///
+/// If you know the address range of the thread's stack, supply it with
+/// [`UnwindIterator::with_stack_bounds`]. This stops the iteration with
+/// [`Error::OutOfStackBounds`] instead of walking into unrelated memory.
+///
+/// The iterator remembers the stack pointer and return address of the last few
+/// frames. If a corrupted frame pointer chain leads back to one of them, the
+/// iteration stops with [`Error::UnwindingCycle`] instead of looping forever.
+///
+/// If the stack bytes are a partial copy of the real stack, supply the copied range
+/// with [`UnwindIterator::with_captured_stack`], so that running off the end of the
+/// copy is reported as [`Error::StackTruncated`].
+///
+/// Use [`UnwindIterator::next_with_confidence`] to find out how each frame was
+/// recovered, and [`UnwindIterator::with_return_address_validation`] to flag frames
+/// whose addresses don't belong to any known module.
+/// [`UnwindIterator::with_call_site_verification`] flags return addresses which don't
+/// follow a call instruction.
+///
+/// [`UnwindIterator::with_divergence_validation`] additionally compares every step
+/// against frame pointer unwinding, to find places where the unwind information is
+/// wrong, and [`UnwindIterator::with_provenance`] records how each frame was found.
+///
+/// Once the iterator has yielded a frame in one of the unwinder's root address ranges
+/// (see [`Unwinder::is_root_address`]), it completes with `Ok(None)` without trying
+/// to unwind any further. Unwinding past thread entry points often produces a junk
+/// frame from uninitialized stack memory.
+///
+/// Lifetimes:
+///
+///  - `'u`: The lifetime of the [`Unwinder`].
+///  - `'c`: The lifetime of the unwinder cache.
+///  - `'r`: The lifetime of the exclusive access to the `read_stack` callback.
+pub struct UnwindIterator<'u, 'c, 'r, U: Unwinder + ?Sized, F: FnMut(u64) -> Result<u64, ()>> {
+    unwinder: &'u U,
+    options: UnwindOptions<'r, U::UnwindRegs>,
+    state: UnwindIteratorState,
+    regs: U::UnwindRegs,
+    cache: &'c mut U::Cache,
+    read_stack: &'r mut F,
+    recent_frames: [(u64, u64); RECENT_FRAME_COUNT],
+    recent_frame_index: usize,
+    divergences: Option<Vec<FrameDivergence>>,
+    provenance: Option<Vec<FrameProvenance>>,
+    /// How the unwinder found the last unwound frame, if provenance is recorded.
+    last_provenance: FrameProvenance,
+    stack_hasher: Option<StackHasher>,
+    snapshots: Option<Vec<FrameSnapshot<U::UnwindRegs>>>,
+    frame_count: usize,
+    stack_read_budget: Option<usize>,
+    end_reason: Option<UnwindEndReason>,
+    /// The logical frames from the async task handler which are yet to be yielded,
+    /// from `async_task_frame_index` on.
+    async_task_frames: Vec<FrameAddress>,
+    async_task_frame_index: usize,
+    shadow_stack_index: usize,
+    shadow_stack_mismatch: Option<ShadowStackMismatch>,
+    branch_call_sites: Vec<u64>,
+    branch_call_site_index: usize,
+    uses_branch_records: bool,
+    following_branch_records: bool,
+    /// The registers of a second walk with different placeholders for the unknown
+    /// registers, while any registers are still unknown.
+    placeholder_regs: Option<U::UnwindRegs>,
+}
+
+/// The options of an [`UnwindIterator`], as set by its `with_` methods. The walk only
+/// changes them to drop options which no longer apply, such as the stack bounds after
+/// a stack switch.
+struct UnwindOptions<'r, R> {
+    first_frame_kind: FrameAddressKind,
+    stack_bounds: Option<Range<u64>>,
+    auxiliary_stacks: Vec<Range<u64>>,
+    captured_stack: Option<Range<u64>>,
+    validate_return_addresses: bool,
+    verify_call_sites: bool,
+    max_depth: Option<usize>,
+    stack_switch_handler: Option<&'r mut StackSwitchHandler<'r, R>>,
+    frame_filter: Option<&'r mut FrameFilter<'r>>,
+    async_task_handler: Option<&'r mut AsyncTaskHandler<'r, R>>,
+    shadow_stack: Option<&'r [u64]>,
+}
+
+impl<R> Default for UnwindOptions<'_, R> {
+    fn default() -> Self {
+        Self {
+            first_frame_kind: FrameAddressKind::Interrupted,
+            stack_bounds: None,
+            auxiliary_stacks: Vec::new(),
+            captured_stack: None,
+            validate_return_addresses: false,
+            verify_call_sites: false,
+            max_depth: None,
+            stack_switch_handler: None,
+            frame_filter: None,
+            async_task_handler: None,
+            shadow_stack: None,
+        }
+    }
+}
+
+/// See [`UnwindIterator::with_stack_switch_handler`].
+type StackSwitchHandler<'r, R> = dyn FnMut(FrameAddress, &R) -> Option<(FrameAddress, R)> + 'r;
+
+/// The state of an [`UnwindIterator`] after yielding a frame, see
+/// [`UnwindIterator::with_register_snapshots`]. [`UnwindIterator::resume_from`]
+/// restores all of it, so that the resumed walk continues exactly like the original
+/// one did after the frame.
+#[derive(Clone)]
+struct FrameSnapshot<R> {
+    address: FrameAddress,
+    regs: R,
+    placeholder_regs: Option<R>,
+    stack_hasher: Option<StackHasher>,
+    state: UnwindIteratorState,
+    frame_count: usize,
+    stack_bounds: Option<Range<u64>>,
+    captured_stack: Option<Range<u64>>,
+    stack_read_budget: Option<usize>,
+    recent_frames: [(u64, u64); RECENT_FRAME_COUNT],
+    recent_frame_index: usize,
+    /// The logical frames from the async task handler which were yet to be yielded.
+    async_task_frames: Vec<FrameAddress>,
+    divergence_count: usize,
+    provenance_count: usize,
+}
+
+/// See [`UnwindIterator::with_frame_filter`].
+type FrameFilter<'r> = dyn FnMut(FrameAddress) -> FrameFilterAction + 'r;
+
+/// See [`UnwindIterator::with_async_task_handler`].
+type AsyncTaskHandler<'r, R> = dyn FnMut(FrameAddress, &R) -> Vec<FrameAddress> + 'r;
+
+/// The number of (sp, return address) pairs that [`UnwindIterator`] remembers
+/// for cycle detection.
+const RECENT_FRAME_COUNT: usize = 16;
+
+/// The longest call instruction, on x86_64. On aarch64, calls are 4 bytes long.
+const MAX_CALL_INSTRUCTION_LEN: u64 = 15;
+
+/// How [`UnwindIterator::unwind_frame`] reads the stack.
+enum StackReads<'a> {
+    /// Read the stack through the stack bounds and the stack read budget.
+    Direct,
+    /// Like `Direct`, and also record the successful reads.
+    Record(&'a mut Vec<(u64, u64)>),
+    /// Only answer the reads which were recorded, without reading the stack again.
+    Replay(&'a [(u64, u64)]),
+}
+
+#[derive(Clone, Copy)]
+enum UnwindIteratorState {
+    Initial(u64),
+    Unwinding(FrameAddress),
+    Done(UnwindEndReason),
+}
+
+impl<'u, 'c, 'r, U: Unwinder + ?Sized, F: FnMut(u64) -> Result<u64, ()>>
+    UnwindIterator<'u, 'c, 'r, U, F>
+{
+    /// Create a new iterator. You'd usually use [`Unwinder::iter_frames`] instead.
+    pub fn new(
+        unwinder: &'u U,
+        pc: u64,
+        regs: U::UnwindRegs,
+        cache: &'c mut U::Cache,
+        read_stack: &'r mut F,
+    ) -> Self {
+        Self {
+            unwinder,
+            options: UnwindOptions::default(),
+            state: UnwindIteratorState::Initial(pc),
+            regs,
+            cache,
+            read_stack,
+            recent_frames: [(0, 0); RECENT_FRAME_COUNT],
+            recent_frame_index: 0,
+            divergences: None,
+            provenance: None,
+            last_provenance: FrameProvenance::new(FrameSource::Registers),
+            stack_hasher: None,
+            snapshots: None,
+            frame_count: 0,
+            stack_read_budget: None,
+            end_reason: None,
+            async_task_frames: Vec::new(),
+            async_task_frame_index: 0,
+            shadow_stack_index: 0,
+            shadow_stack_mismatch: None,
+            branch_call_sites: Vec::new(),
+            branch_call_site_index: 0,
+            uses_branch_records: false,
+            following_branch_records: false,
+            placeholder_regs: None,
+        }
+    }
+
+    /// Set how the `pc` of the first frame was obtained. The default is
+    /// [`FrameAddressKind::Interrupted`].
+    ///
+    /// With [`FrameAddressKind::ReturnAddress`], the first frame is yielded as a
+    /// [`FrameAddress::ReturnAddress`], and unwind information is looked up for the
+    /// call instruction rather than for the instruction after it.
+    pub fn with_first_frame_kind(mut self, first_frame_kind: FrameAddressKind) -> Self {
+        self.options.first_frame_kind = first_frame_kind;
+        self
+    }
+
+    /// Set the address range of the stack that is being unwound, for example from
+    /// `pthread_attr_getstack`, from the thread information block on Windows, or from
+    /// the `[stack]` mapping in `/proc/<pid>/maps`.
+    ///
+    /// Stack reads outside of this range are refused, and unwinding stops with
+    /// [`Error::OutOfStackBounds`] if the stack pointer leaves this range, or if the
+    /// unwinder tries to read memory outside of it. Without stack bounds, corrupted
+    /// frame pointers can make the unwinder walk into unrelated memory and produce
+    /// plausible-looking garbage frames.
+    ///
+    /// The bounds are dropped once the walk reaches a frame in one of the unwinder's
+    /// stack switch ranges (see [`Unwinder::is_stack_switch_address`]), because the
+    /// walk then continues on a different stack.
+    pub fn with_stack_bounds(mut self, stack_bounds: Range<u64>) -> Self {
+        self.options.stack_bounds = Some(stack_bounds);
+        self
+    }
+
+    /// Add the address range of another stack that the thread may have switched from,
+    /// such as a fiber or green thread stack, or the alternate signal stack set up
+    /// with `sigaltstack`. Call this once for each stack.
+    ///
+    /// Stack reads and stack pointers in these ranges are accepted in addition to the
+    /// ones within [`UnwindIterator::with_stack_bounds`]. And if following a frame
+    /// record fails because it moves backwards on the current stack, but leads onto one
+    /// of the other known stacks, the walk continues on that stack instead of stopping.
+    /// Such frames are reported with [`FrameConfidence::FramePointer`].
+    pub fn with_auxiliary_stack(mut self, stack: Range<u64>) -> Self {
+        self.options.auxiliary_stacks.push(stack);
+        self
+    }
+
+    /// Set the address range of the stack bytes that were captured, if the stack
+    /// memory available to `read_stack` is only a copy of the top part of the stack.
+    /// This is the case for Linux perf samples, which contain a fixed number of bytes
+    /// starting at the stack pointer. For a [`StackSlice`](crate::StackSlice), pass
+    /// its [`address_range`](crate::StackSlice::address_range).
+    ///
+    /// Failed reads past the end of this range end the iteration with
+    /// [`Error::StackTruncated`] instead of [`Error::CouldNotReadStack`], so that
+    /// callers can tell a partially captured stack from a genuine unwinding failure.
+    pub fn with_captured_stack(mut self, captured_stack: Range<u64>) -> Self {
+        self.options.captured_stack = Some(captured_stack);
+        self
+    }
+
+    /// Check every frame address against the address ranges of the modules known to
+    /// the unwinder. Frames with addresses outside of all modules are reported with
+    /// [`FrameConfidence::Implausible`] by [`UnwindIterator::next_with_confidence`].
+    ///
+    /// This only makes sense if all modules of the process have been added to the
+    /// unwinder, including the ones without unwind information.
+    pub fn with_return_address_validation(mut self) -> Self {
+        self.options.validate_return_addresses = true;
+        self
+    }
+
+    /// Check that every return address follows a call instruction, see
+    /// [`Unwinder::is_preceded_by_call`]. Frames whose return address doesn't are
+    /// reported with [`FrameConfidence::Implausible`] by
+    /// [`UnwindIterator::next_with_confidence`]. Frames in modules without code bytes
+    /// are not checked.
+    pub fn with_call_site_verification(mut self) -> Self {
+        self.options.verify_call_sites = true;
+        self
+    }
+
+    /// For every frame, also unwind with frame pointer unwinding and record where the
+    /// result differs from the one given by the unwind information. The recorded
+    /// divergences can be retrieved with [`UnwindIterator::divergences`].
+    ///
+    /// See [`Unwinder::check_frame_divergence`] for details.
+    pub fn with_divergence_validation(mut self) -> Self {
+        self.divergences = Some(Vec::new());
+        self
+    }
+
+    /// The divergences that were found so far, if divergence validation was enabled
+    /// with [`UnwindIterator::with_divergence_validation`]. Empty otherwise.
+    pub fn divergences(&self) -> &[FrameDivergence] {
+        self.divergences.as_deref().unwrap_or(&[])
+    }
+
+    /// Record how every frame was found: which unwind information was used, which
+    /// rule was executed, whether it came from the cache, and why the unwinder fell
+    /// back to frame pointers, if it did. The records can be retrieved with
+    /// [`UnwindIterator::provenance`].
+    ///
+    /// This formats the rule of every frame, so it is meant for tools that audit the
+    /// quality of stack walks rather than for profiling.
+    pub fn with_provenance(mut self) -> Self {
+        self.provenance = Some(Vec::new());
+        self
+    }
+
+    /// The provenance of the frames that were yielded so far, one entry per frame, if
+    /// it is recorded with [`UnwindIterator::with_provenance`]. Empty otherwise.
+    pub fn provenance(&self) -> &[FrameProvenance] {
+        self.provenance.as_deref().unwrap_or(&[])
+    }
+
+    /// Compute a 64-bit hash over the frames while they are yielded, which can be
+    /// retrieved with [`UnwindIterator::stack_hash`]. Samplers can use it to aggregate
+    /// identical stacks without collecting and comparing the frames.
+    ///
+    /// Frames are hashed by the [id](crate::Module::id) of their module and their
+    /// module-relative address, see [`Unwinder::module_id_and_relative_address`], so
+    /// the hash of a stack doesn't depend on where its modules were loaded. Frames
+    /// outside of known modules are hashed by their address.
+    pub fn with_stack_hash(mut self) -> Self {
+        self.stack_hasher = Some(StackHasher::new());
+        self
+    }
+
+    /// The hash of the frames that were yielded so far, if it is computed with
+    /// [`UnwindIterator::with_stack_hash`].
+    pub fn stack_hash(&self) -> Option<u64> {
+        self.stack_hasher.as_ref().map(StackHasher::finish)
+    }
+
+    /// Keep the registers of every yielded frame, so that they can be retrieved with
+    /// [`UnwindIterator::frame_registers`], and so that the walk can be resumed from
+    /// any yielded frame with [`UnwindIterator::resume_from`].
+    pub fn with_register_snapshots(mut self) -> Self {
+        self.snapshots = Some(Vec::new());
+        self
+    }
+
+    /// The address and the registers of the frame at `index`, as yielded by this
+    /// iterator, if register snapshots are kept with
+    /// [`UnwindIterator::with_register_snapshots`]. These are the registers that the
+    /// frame's caller is unwound from.
+    ///
+    /// To walk the rest of the stack with different options, start a new walk with
+    /// these registers, using [`FrameAddressKind::ReturnAddress`] for return addresses.
+    pub fn frame_registers(&self, index: usize) -> Option<(FrameAddress, U::UnwindRegs)> {
+        let snapshot = self.snapshots.as_ref()?.get(index)?;
+        Some((snapshot.address, snapshot.regs))
+    }
+
+    /// Go back to the frame at `index`, so that the next call to
+    /// [`UnwindIterator::next`] unwinds its caller again. The frames after it are
+    /// forgotten, including their provenance and divergences, and the rest of the
+    /// iterator's state, such as the stack hash, the frame count for the maximum depth,
+    /// the stack bounds and the remaining stack read budget, is restored to what it was
+    /// after that frame.
+    ///
+    /// Returns `false` and does nothing if there is no snapshot for `index`, or if a
+    /// shadow stack or branch records were supplied, whose progress can't be rewound.
+    pub fn resume_from(&mut self, index: usize) -> bool {
+        if self.options.shadow_stack.is_some() || self.uses_branch_records {
+            return false;
+        }
+        let snapshot = match self.snapshots.as_mut() {
+            Some(snapshots) if index < snapshots.len() => {
+                snapshots.truncate(index + 1);
+                snapshots[index].clone()
+            }
+            _ => return false,
+        };
+        if let Some(provenance) = &mut self.provenance {
+            provenance.truncate(snapshot.provenance_count);
+        }
+        if let Some(divergences) = &mut self.divergences {
+            divergences.truncate(snapshot.divergence_count);
+        }
+        self.regs = snapshot.regs;
+        self.placeholder_regs = snapshot.placeholder_regs;
+        self.stack_hasher = snapshot.stack_hasher;
+        self.state = snapshot.state;
+        self.frame_count = snapshot.frame_count;
+        self.options.stack_bounds = snapshot.stack_bounds;
+        self.options.captured_stack = snapshot.captured_stack;
+        self.stack_read_budget = snapshot.stack_read_budget;
+        self.recent_frames = snapshot.recent_frames;
+        self.recent_frame_index = snapshot.recent_frame_index;
+        self.async_task_frames = snapshot.async_task_frames;
+        self.async_task_frame_index = 0;
+        self.end_reason = None;
+        true
+    }
+
+    /// Stop the walk with `Ok(None)` after `max_depth` frames have been yielded,
+    /// including the first frame. [`UnwindIterator::end_reason`] then returns
+    /// [`UnwindEndReason::MaxDepth`].
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.options.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Allow at most `max_reads` calls to `read_stack` for the whole walk. Once the
+    /// budget is used up, further reads are refused, and the walk stops with
+    /// `Ok(None)` as soon as a frame can't be unwound without them.
+    /// [`UnwindIterator::end_reason`] then returns [`UnwindEndReason::BudgetExhausted`].
+    ///
+    /// This bounds the time spent on a single stack, for example when unwinding
+    /// inside a signal handler.
+    pub fn with_stack_read_budget(mut self, max_reads: usize) -> Self {
+        self.stack_read_budget = Some(max_reads);
+        self
+    }
+
+    /// Expand every frame into the logical frames of the functions that were inlined
+    /// at its address, using the caller's debug information. The returned iterator
+    /// yields the logical frames, so that the caller gets an inline-aware stack in one
+    /// pass.
+    pub fn with_inline_frames<E: InlineFrameExpander>(
+        self,
+        expander: E,
+    ) -> InlineFrameIterator<'u, 'c, 'r, U, F, E> {
+        InlineFrameIterator::new(self, expander)
+    }
+
+    /// Decide for every frame whether to yield it, to skip it, or to end the walk,
+    /// for example to stop at `main` or to hide a runtime's internal frames. The
+    /// filter is usually implemented with the caller's symbol lookup.
+    ///
+    /// Skipped frames are still unwound, because the walk continues from them, and
+    /// they count towards the maximum depth that was set with
+    /// [`UnwindIterator::with_max_depth`]. They don't get
+    /// [provenance](UnwindIterator::with_provenance) records and they aren't part of
+    /// the [stack hash](UnwindIterator::with_stack_hash). When the filter ends the
+    /// walk, [`UnwindIterator::end_reason`] returns
+    /// [`UnwindEndReason::StoppedByFilter`].
+    pub fn with_frame_filter(
+        mut self,
+        filter: &'r mut dyn FnMut(FrameAddress) -> FrameFilterAction,
+    ) -> Self {
+        self.options.frame_filter = Some(filter);
+        self
+    }
+
+    /// Supply the register state of the previous stack when the walk reaches a frame
+    /// in one of the unwinder's stack switch ranges (see
+    /// [`Unwinder::is_stack_switch_address`]). This lets logical stacks that span
+    /// several machine stacks be walked, for example for coroutines, green threads
+    /// or `swapcontext`, where the scheduler knows where the previous context was
+    /// suspended.
+    ///
+    /// The handler is called with the address of the stack switch frame and the
+    /// registers for that frame. If it returns the address and the registers of the
+    /// frame that continues the logical stack, the walk yields that address and
+    /// continues from there. If it returns `None`, the frame is unwound with its frame
+    /// record, as without a handler.
+    ///
+    /// Frames which are both in a root range and in a stack switch range, such as the
+    /// start function of a fiber, end the walk unless the handler returns a frame.
+    pub fn with_stack_switch_handler(
+        mut self,
+        handler: &'r mut StackSwitchHandler<'r, U::UnwindRegs>,
+    ) -> Self {
+        self.options.stack_switch_handler = Some(handler);
+        self
+    }
+
+    /// Splice the logical frames of suspended async tasks into the walk, for "async
+    /// backtraces". When the walk yields a frame in one of the unwinder's async
+    /// boundary ranges (see [`Unwinder::is_async_boundary_address`]), for example in
+    /// the poll function of an async runtime, the handler is called with the address
+    /// and the registers of that frame. It returns the logical frames of the task
+    /// chain which is being polled, usually found through a task pointer that it reads
+    /// from the registers or the stack, ordered from the innermost await point
+    /// outwards. The walk yields these frames right after the boundary frame, and then
+    /// continues with the native callers of the boundary frame.
+    ///
+    /// The logical frames count towards the maximum depth, and they go through the
+    /// frame filter like native frames. Their provenance is [`FrameSource::AsyncTask`].
+    pub fn with_async_task_handler(
+        mut self,
+        handler: &'r mut dyn FnMut(FrameAddress, &U::UnwindRegs) -> Vec<FrameAddress>,
+    ) -> Self {
+        self.options.async_task_handler = Some(handler);
+        self
+    }
+
+    /// Cross-check the unwound return addresses against the thread's shadow stack, for
+    /// example the Intel CET shadow stack, read from the shadow stack pointer upwards.
+    /// `return_addresses` starts with the return address of the first frame's function.
+    ///
+    /// Frames whose return address matches the shadow stack are reported with
+    /// [`FrameConfidence::Exact`]. At the first mismatch, the iterator yields the
+    /// shadow stack's return address instead, records the mismatch (see
+    /// [`UnwindIterator::shadow_stack_mismatch`]), and yields the rest of the shadow
+    /// stack from then on, because the registers from the normal unwind can no longer
+    /// be trusted.
+    ///
+    /// The checks stop after a signal frame, because the kernel stores a token rather
+    /// than a return address on the shadow stack when it delivers a signal.
+    pub fn with_shadow_stack(mut self, return_addresses: &'r [u64]) -> Self {
+        self.options.shadow_stack = Some(return_addresses);
+        self
+    }
+
+    /// Start the walk with only the instruction pointer and the stack pointer known,
+    /// for sampling sources which don't provide the other registers. The values of the
+    /// other registers in the initial registers are ignored.
+    ///
+    /// Frames are only unwound if their caller doesn't depend on the unknown
+    /// registers, which rules out frame pointer unwinding until a frame restores the
+    /// frame pointer from the stack, for example from a signal frame. The walk stops
+    /// with [`Error::NeedsUnknownRegister`] at the first frame which would have needed
+    /// an unknown register.
+    ///
+    /// To find out which registers a frame depends on, every frame is unwound twice
+    /// with different placeholder values, until all registers are known.
+    pub fn with_unknown_registers(mut self) -> Self {
+        self.placeholder_regs = Some(self.regs.with_unknown_registers(1));
+        self.regs = self.regs.with_unknown_registers(0);
+        self
+    }
+
+    /// Use the CPU's last branch records, taken together with the sample, to check and
+    /// repair the top frames of the stack. `records` is ordered from the most recent
+    /// branch to the oldest one. The calls which haven't returned yet are the call
+    /// sites of the innermost callers.
+    ///
+    /// Frames whose return address follows the matching call site are reported with
+    /// [`FrameConfidence::Exact`]. If a frame doesn't match, for example because the
+    /// first frame's function has no unwind information and the frame pointer skipped
+    /// its caller, or if unwinding fails, for example because the copied stack is
+    /// truncated, the iterator yields the remaining callers from the branch records
+    /// and then ends with [`UnwindEndReason::EndOfBranchRecords`].
+    ///
+    /// Repairing needs the return address after each call site, so it only works
+    /// for modules with code bytes, see [`Unwinder::call_site_address`].
+    pub fn with_branch_records(mut self, records: &[BranchRecord]) -> Self {
+        self.branch_call_sites = pending_call_sites(records);
+        self.uses_branch_records = true;
+        self
+    }
+
+    /// The first frame whose unwound return address didn't match the shadow stack, if
+    /// a shadow stack was supplied with [`UnwindIterator::with_shadow_stack`].
+    pub fn shadow_stack_mismatch(&self) -> Option<ShadowStackMismatch> {
+        self.shadow_stack_mismatch
+    }
+
+    /// Why the walk ended, or `None` if it hasn't ended yet.
+    ///
+    /// This is set once [`UnwindIterator::next`] has returned `Ok(None)` or an error.
+    pub fn end_reason(&self) -> Option<UnwindEndReason> {
+        self.end_reason
+    }
+}
+
+impl<'u, 'c, 'r, U: Unwinder + ?Sized, F: FnMut(u64) -> Result<u64, ()>>
+    UnwindIterator<'u, 'c, 'r, U, F>
+{
+    /// Yield the next frame in the stack.
+    ///
+    /// The first frame is `Ok(Some(FrameAddress::InstructionPointer(...)))`, unless
+    /// a different kind was set with [`UnwindIterator::with_first_frame_kind`].
+    /// Subsequent frames are `Ok(Some(FrameAddress::ReturnAddress(...)))`.
+    ///
+    /// If a root function has been reached, this iterator completes with `Ok(None)`.
+    /// Otherwise it completes with `Err(...)`, usually indicating that a certain stack
+    /// address could not be read. In both cases, [`UnwindIterator::end_reason`] says
+    /// why the walk ended.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<FrameAddress>, Error> {
+        let next = self.next_with_confidence()?;
+        Ok(next.map(|(address, _confidence)| address))
+    }
+
+    /// Like [`UnwindIterator::next`], but also returns how much the frame can be
+    /// trusted.
+    pub fn next_with_confidence(
+        &mut self,
+    ) -> Result<Option<(FrameAddress, FrameConfidence)>, Error> {
+        loop {
+            let (address, confidence) = match self.next_frame()? {
+                Some(next) => next,
+                None => return Ok(None),
+            };
+            let action = match &mut self.options.frame_filter {
+                Some(filter) => filter(address),
+                None => FrameFilterAction::Yield,
+            };
+            match action {
+                FrameFilterAction::Yield => {}
+                FrameFilterAction::YieldAndStop => {
+                    self.state = UnwindIteratorState::Done(UnwindEndReason::StoppedByFilter);
+                }
+                FrameFilterAction::Skip => {
+                    self.discard_provenance();
+                    continue;
+                }
+                FrameFilterAction::Stop => {
+                    self.discard_provenance();
+                    self.state = UnwindIteratorState::Done(UnwindEndReason::StoppedByFilter);
+                    self.end_reason = Some(UnwindEndReason::StoppedByFilter);
+                    return Ok(None);
+                }
+            }
+            if let Some(hasher) = &mut self.stack_hasher {
+                let address = address.address();
+                match self.unwinder.module_id_and_relative_address(address) {
+                    Some((module, relative_address)) => {
+                        hasher.add_frame(module, u64::from(relative_address))
+                    }
+                    None => hasher.add_frame(0, address),
+                }
+            }
+            if let Some(mut snapshots) = self.snapshots.take() {
+                snapshots.push(self.snapshot(address));
+                self.snapshots = Some(snapshots);
+            }
+            return Ok(Some((address, confidence)));
+        }
+    }
+
+    /// The state after yielding the frame at `address`, see [`FrameSnapshot`].
+    fn snapshot(&self, address: FrameAddress) -> FrameSnapshot<U::UnwindRegs> {
+        FrameSnapshot {
+            address,
+            regs: self.regs,
+            placeholder_regs: self.placeholder_regs,
+            stack_hasher: self.stack_hasher,
+            state: self.state,
+            frame_count: self.frame_count,
+            stack_bounds: self.options.stack_bounds.clone(),
+            captured_stack: self.options.captured_stack.clone(),
+            stack_read_budget: self.stack_read_budget,
+            recent_frames: self.recent_frames,
+            recent_frame_index: self.recent_frame_index,
+            async_task_frames: self.async_task_frames[self.async_task_frame_index..].to_vec(),
+            divergence_count: self.divergences().len(),
+            provenance_count: self.provenance().len(),
+        }
+    }
+
+    fn next_frame(&mut self) -> Result<Option<(FrameAddress, FrameConfidence)>, Error> {
+        if let Some(next) = self.next_async_task_frame() {
+            return Ok(Some(next));
+        }
+        let next = match self.state {
+            UnwindIteratorState::Initial(pc) => {
+                let address =
+                    match FrameAddress::from_first_frame_address(pc, self.options.first_frame_kind)
+                    {
+                        Some(address) => address,
+                        None => {
+                            self.end_reason = Some(UnwindEndReason::NullReturnAddress);
+                            return Err(Error::ReturnAddressIsNull);
+                        }
+                    };
+                self.state = self.state_after(address);
+                self.record_provenance(FrameProvenance::new(FrameSource::Registers));
+                return Ok(Some((
+                    address,
+                    self.validate(address, FrameConfidence::Exact),
+                )));
+            }
+            UnwindIteratorState::Unwinding(_) if self.shadow_stack_mismatch.is_some() => {
+                return Ok(self.next_from_shadow_stack());
+            }
+            UnwindIteratorState::Unwinding(_) if self.following_branch_records => {
+                return Ok(self.next_from_branch_records());
+            }
+            UnwindIteratorState::Unwinding(address) => {
+                if let Some(divergences) = &mut self.divergences {
+                    divergences.extend(self.unwinder.check_frame_divergence(
+                        address,
+                        &self.regs,
+                        self.cache,
+                        self.read_stack,
+                    ));
+                }
+                if self
+                    .unwinder
+                    .is_stack_switch_address(address.address_for_lookup())
+                {
+                    // The stack bounds and the captured stack only describe the
+                    // current stack, not the one that the walk continues on.
+                    self.options.stack_bounds = None;
+                    self.options.captured_stack = None;
+                    let switched = match &mut self.options.stack_switch_handler {
+                        Some(handler) => handler(address, &self.regs),
+                        None => None,
+                    };
+                    if let Some((next_address, regs)) = switched {
+                        self.regs = regs;
+                        self.last_provenance =
+                            FrameProvenance::new(FrameSource::StackSwitchHandler);
+                        return self.yield_frame(next_address, FrameConfidence::Exact);
+                    }
+                    if self.unwinder.is_root_address(address.address_for_lookup()) {
+                        self.state = UnwindIteratorState::Done(UnwindEndReason::ReachedRoot);
+                        self.end_reason = Some(UnwindEndReason::ReachedRoot);
+                        return Ok(None);
+                    }
+                }
+                let mut budget_exhausted = false;
+                let next = match self.unwind_frame_with_placeholders(address, &mut budget_exhausted)
+                {
+                    Err(
+                        err @ (Error::FramePointerBelowStackPointer(_)
+                        | Error::FramepointerUnwindingMovedBackwards),
+                    ) if !self.options.auxiliary_stacks.is_empty() => {
+                        match self.unwind_frame_onto_auxiliary_stack(address, &mut budget_exhausted)
+                        {
+                            Some(next) => Ok(Some(next)),
+                            None => Err(err),
+                        }
+                    }
+                    next => next,
+                };
+                let next = match next {
+                    Err(_) if budget_exhausted => {
+                        self.state = UnwindIteratorState::Done(UnwindEndReason::BudgetExhausted);
+                        self.end_reason = Some(UnwindEndReason::BudgetExhausted);
+                        return Ok(None);
+                    }
+                    Err(Error::CouldNotReadStack(addr)) if self.is_past_captured_stack(addr) => {
+                        Err(Error::StackTruncated(addr))
+                    }
+                    next => next,
+                };
+                match next {
+                    Ok(next) => next,
+                    Err(_) if self.next_branch_record_frame().is_some() => {
+                        self.following_branch_records = true;
+                        return Ok(self.next_from_branch_records());
+                    }
+                    Err(err) => {
+                        self.end_reason = Some(self.end_reason_for_error(address, err));
+                        return Err(err);
+                    }
+                }
+            }
+            UnwindIteratorState::Done(end_reason) => {
+                self.end_reason = Some(end_reason);
+                return Ok(None);
+            }
+        };
+        match next {
+            Some((return_address, confidence)) => {
+                let (return_address, confidence) =
+                    self.check_shadow_stack(return_address, confidence);
+                let (return_address, confidence) =
+                    self.check_branch_records(return_address, confidence);
+                if self.shadow_stack_mismatch.is_some() {
+                    self.last_provenance = FrameProvenance::new(FrameSource::ShadowStack);
+                } else if self.following_branch_records {
+                    self.last_provenance = FrameProvenance::new(FrameSource::BranchRecords);
+                }
+                self.yield_frame(return_address, confidence)
+            }
+            None => {
+                self.state = UnwindIteratorState::Done(UnwindEndReason::NullReturnAddress);
+                self.end_reason = Some(UnwindEndReason::NullReturnAddress);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Compare an unwound frame with the shadow stack. Returns the frame to yield.
+    fn check_shadow_stack(
+        &mut self,
+        address: FrameAddress,
+        confidence: FrameConfidence,
+    ) -> (FrameAddress, FrameConfidence) {
+        let shadow_stack = match self.options.shadow_stack {
+            Some(shadow_stack) => shadow_stack,
+            None => return (address, confidence),
+        };
+        if !address.is_return_address() {
+            // A signal frame. The shadow stack has a token here, stop checking.
+            self.options.shadow_stack = None;
+            return (address, confidence);
+        }
+        let shadow_return_address = match shadow_stack.get(self.shadow_stack_index) {
+            Some(&shadow_return_address) => shadow_return_address,
+            None => return (address, confidence),
+        };
+        self.shadow_stack_index += 1;
+        if shadow_return_address == address.address() {
+            return (address, FrameConfidence::Exact);
+        }
+        match FrameAddress::from_return_address(shadow_return_address) {
+            Some(shadow_address) => {
+                self.shadow_stack_mismatch = Some(ShadowStackMismatch {
+                    frame_index: self.frame_count,
+                    unwound_return_address: address.address(),
+                    shadow_return_address,
+                });
+                (shadow_address, FrameConfidence::Exact)
+            }
+            None => (address, confidence),
+        }
+    }
+
+    /// Yield the next return address from the shadow stack, after a mismatch.
+    fn next_from_shadow_stack(&mut self) -> Option<(FrameAddress, FrameConfidence)> {
+        let shadow_stack = self.options.shadow_stack.unwrap_or(&[]);
+        let address = shadow_stack
+            .get(self.shadow_stack_index)
+            .and_then(|&address| FrameAddress::from_return_address(address));
+        match address {
+            Some(address) => {
+                self.shadow_stack_index += 1;
+                self.state = self.state_after(address);
+                self.record_provenance(FrameProvenance::new(FrameSource::ShadowStack));
+                Some((address, self.validate(address, FrameConfidence::Exact)))
+            }
+            None => {
+                // The bottom of the shadow stack is the thread's entry point.
+                self.state = UnwindIteratorState::Done(UnwindEndReason::ReachedRoot);
+                self.end_reason = Some(UnwindEndReason::ReachedRoot);
+                None
+            }
+        }
+    }
+
+    /// Compare an unwound frame with the next call site from the branch records.
+    /// Returns the frame to yield.
+    fn check_branch_records(
+        &mut self,
+        address: FrameAddress,
+        confidence: FrameConfidence,
+    ) -> (FrameAddress, FrameConfidence) {
+        if self.shadow_stack_mismatch.is_some() {
+            // The shadow stack is more reliable.
+            return (address, confidence);
+        }
+        if !address.is_return_address() {
+            // A signal frame, which wasn't entered with a call.
+            self.branch_call_sites.clear();
+            return (address, confidence);
+        }
+        let call_site = match self.branch_call_sites.get(self.branch_call_site_index) {
+            Some(&call_site) => call_site,
+            None => return (address, confidence),
+        };
+        let return_address = address.address();
+        if return_address > call_site && return_address - call_site <= MAX_CALL_INSTRUCTION_LEN {
+            self.branch_call_site_index += 1;
+            return (address, FrameConfidence::Exact);
+        }
+        match self.next_branch_record_frame() {
+            Some(branch_address) => {
+                self.branch_call_site_index += 1;
+                self.following_branch_records = true;
+                (branch_address, FrameConfidence::Exact)
+            }
+            None => {
+                self.branch_call_sites.clear();
+                (address, confidence)
+            }
+        }
+    }
+
+    /// The return address after the next call site from the branch records. This is
+    /// the instruction length for which [`Unwinder::call_site_address`] leads back to
+    /// the call site; without code bytes, there is none.
+    fn next_branch_record_frame(&self) -> Option<FrameAddress> {
+        let call_site = *self.branch_call_sites.get(self.branch_call_site_index)?;
+        (2..=MAX_CALL_INSTRUCTION_LEN)
+            .filter_map(|len| call_site.checked_add(len))
+            .filter_map(FrameAddress::from_return_address)
+            .find(|&address| self.unwinder.call_site_address(address) == call_site)
+    }
+
+    /// Yield the next caller from the branch records, after the unwind failed or
+    /// disagreed with them.
+    fn next_from_branch_records(&mut self) -> Option<(FrameAddress, FrameConfidence)> {
+        match self.next_branch_record_frame() {
+            Some(address) => {
+                self.branch_call_site_index += 1;
+                self.state = self.state_after(address);
+                self.record_provenance(FrameProvenance::new(FrameSource::BranchRecords));
+                Some((address, self.validate(address, FrameConfidence::Exact)))
+            }
+            None => {
+                self.state = UnwindIteratorState::Done(UnwindEndReason::EndOfBranchRecords);
+                self.end_reason = Some(UnwindEndReason::EndOfBranchRecords);
+                None
+            }
+        }
+    }
+
+    /// Yield a frame that was found by unwinding, unless it closes a cycle.
+    fn yield_frame(
+        &mut self,
+        return_address: FrameAddress,
+        confidence: FrameConfidence,
+    ) -> Result<Option<(FrameAddress, FrameConfidence)>, Error> {
+        // The unwinder never returns null addresses, so the zero-initialized
+        // entries of recent_frames never match.
+        let frame = (self.regs.sp(), return_address.address());
+        if self.recent_frames.contains(&frame) {
+            let err = Error::UnwindingCycle(return_address.address());
+            self.end_reason = Some(UnwindEndReason::Error(err));
+            return Err(err);
+        }
+        self.recent_frames[self.recent_frame_index] = frame;
+        self.recent_frame_index = (self.recent_frame_index + 1) % RECENT_FRAME_COUNT;
+        self.state = self.state_after(return_address);
+        if let Some(provenance) = &mut self.provenance {
+            provenance.push(self.last_provenance.clone());
+        }
+        Ok(Some((
+            return_address,
+            self.validate(return_address, confidence),
+        )))
+    }
+
+    fn record_provenance(&mut self, provenance: FrameProvenance) {
+        if let Some(provenances) = &mut self.provenance {
+            provenances.push(provenance);
+        }
+    }
+
+    pub(crate) fn call_site_address(&self, address: FrameAddress) -> u64 {
+        self.unwinder.call_site_address(address)
+    }
+
+    #[cfg(feature = "backtrace")]
+    pub(crate) fn module_relative_address(&self, address: u64) -> Option<(u64, u32)> {
+        self.unwinder.module_relative_address(address)
+    }
+
+    /// Remove the provenance of a frame that the frame filter didn't let through.
+    fn discard_provenance(&mut self) {
+        if let Some(provenances) = &mut self.provenance {
+            provenances.pop();
+        }
+    }
+
+    /// The state after yielding `address`: stop if it is in a root function, or if
+    /// the maximum depth has been reached.
+    fn state_after(&mut self, address: FrameAddress) -> UnwindIteratorState {
+        self.frame_count += 1;
+        let lookup_address = address.address_for_lookup();
+        if self.unwinder.is_root_address(lookup_address)
+            && !(self.options.stack_switch_handler.is_some()
+                && self.unwinder.is_stack_switch_address(lookup_address))
+        {
+            UnwindIteratorState::Done(UnwindEndReason::ReachedRoot)
+        } else if matches!(self.options.max_depth, Some(max_depth) if self.frame_count >= max_depth)
+        {
+            UnwindIteratorState::Done(UnwindEndReason::MaxDepth)
+        } else {
+            if self.unwinder.is_async_boundary_address(lookup_address) {
+                if let Some(handler) = &mut self.options.async_task_handler {
+                    self.async_task_frames = handler(address, &self.regs);
+                    self.async_task_frame_index = 0;
+                }
+            }
+            UnwindIteratorState::Unwinding(address)
+        }
+    }
+
+    /// Yield the next logical frame from the async task handler, if any are left. The
+    /// state stays at the boundary frame, so that the walk continues with its native
+    /// callers afterwards.
+    fn next_async_task_frame(&mut self) -> Option<(FrameAddress, FrameConfidence)> {
+        if !matches!(self.state, UnwindIteratorState::Unwinding(_)) {
+            return None;
+        }
+        let address = *self.async_task_frames.get(self.async_task_frame_index)?;
+        self.async_task_frame_index += 1;
+        self.frame_count += 1;
+        if matches!(self.options.max_depth, Some(max_depth) if self.frame_count >= max_depth) {
+            self.state = UnwindIteratorState::Done(UnwindEndReason::MaxDepth);
+        }
+        self.record_provenance(FrameProvenance::new(FrameSource::AsyncTask));
+        Some((address, self.validate(address, FrameConfidence::Exact)))
+    }
+
+    /// Classify the error which stopped the walk while unwinding the frame at `address`.
+    fn end_reason_for_error(&self, address: FrameAddress, err: Error) -> UnwindEndReason {
+        match err {
+            Error::CouldNotReadStack(_) | Error::StackTruncated(_) | Error::OutOfStackBounds(_) => {
+                UnwindEndReason::UnreadableStack(err)
+            }
+            _ if !self
+                .unwinder
+                .is_known_code_address(address.address_for_lookup()) =>
+            {
+                UnwindEndReason::MissingModule(address.address())
+            }
+            _ => UnwindEndReason::Error(err),
+        }
+    }
+
+    /// Unwind one frame, applying the stack bounds and the stack read budget if there
+    /// are any. Sets `budget_exhausted` if a read was refused because of the budget.
+    /// With `across_stack_switch`, the frame is unwound with its frame record, see
+    /// [`Unwinder::unwind_frame_across_stack_switch`].
+    fn unwind_frame(
+        &mut self,
+        address: FrameAddress,
+        budget_exhausted: &mut bool,
+        across_stack_switch: bool,
+        mut reads: StackReads,
+    ) -> Result<Option<(FrameAddress, FrameConfidence)>, Error> {
+        let stack_bounds = self.options.stack_bounds.as_ref();
+        let auxiliary_stacks = &self.options.auxiliary_stacks[..];
+        let stack_read_budget = &mut self.stack_read_budget;
+        let read_stack = &mut self.read_stack;
+        let mut read_stack_limited = |addr| {
+            if let StackReads::Replay(recorded) = &reads {
+                return match recorded.iter().find(|(read_addr, _)| *read_addr == addr) {
+                    Some((_, value)) => Ok(*value),
+                    None => Err(()),
+                };
+            }
+            if let Some(stack_bounds) = stack_bounds {
+                if !is_in_stacks(stack_bounds, auxiliary_stacks, addr) {
+                    return Err(());
+                }
+            }
+            if let Some(remaining_reads) = stack_read_budget {
+                if *remaining_reads == 0 {
+                    *budget_exhausted = true;
+                    return Err(());
+                }
+                *remaining_reads -= 1;
+            }
+            let value = read_stack(addr)?;
+            if let StackReads::Record(recorded) = &mut reads {
+                recorded.push((addr, value));
+            }
+            Ok(value)
+        };
+        let next = if across_stack_switch {
+            self.last_provenance = FrameProvenance::new(FrameSource::StackSwitch);
+            self.unwinder.unwind_frame_across_stack_switch(
+                address,
+                &mut self.regs,
+                &mut read_stack_limited,
+            )
+        } else if self.provenance.is_some() {
+            self.unwinder.unwind_frame_with_provenance(
+                address,
+                &mut self.regs,
+                self.cache,
+                &mut read_stack_limited,
+                &mut self.last_provenance,
+            )
+        } else {
+            self.unwinder.unwind_frame_with_confidence(
+                address,
+                &mut self.regs,
+                self.cache,
+                &mut read_stack_limited,
+            )
+        };
+        let stack_bounds = match stack_bounds {
+            Some(stack_bounds) => stack_bounds,
+            None => return next,
+        };
+        let next = match next {
+            Err(Error::CouldNotReadStack(addr))
+                if !is_in_stacks(stack_bounds, auxiliary_stacks, addr) =>
+            {
+                return Err(Error::OutOfStackBounds(addr));
+            }
+            next => next?,
+        };
+        let sp = self.regs.sp();
+        if next.is_some()
+            && !std::iter::once(stack_bounds)
+                .chain(auxiliary_stacks)
+                .any(|stack| stack.start <= sp && sp <= stack.end)
+        {
+            return Err(Error::OutOfStackBounds(sp));
+        }
+        Ok(next)
+    }
+
+    /// Unwind one frame, and if some registers are still unknown, unwind it a second
+    /// time with different placeholders for them. Fails with
+    /// [`Error::NeedsUnknownRegister`] if the results differ. The second unwind only
+    /// sees the stack reads of the first one, so that it doesn't count against the
+    /// stack read budget; reading any other address makes the results differ.
+    fn unwind_frame_with_placeholders(
+        &mut self,
+        address: FrameAddress,
+        budget_exhausted: &mut bool,
+    ) -> Result<Option<(FrameAddress, FrameConfidence)>, Error> {
+        let placeholder_regs = match self.placeholder_regs {
+            Some(placeholder_regs) => placeholder_regs,
+            None => return self.unwind_frame(address, budget_exhausted, false, StackReads::Direct),
+        };
+        let mut reads = Vec::new();
+        let next = self.unwind_frame(
+            address,
+            budget_exhausted,
+            false,
+            StackReads::Record(&mut reads),
+        );
+        let regs = std::mem::replace(&mut self.regs, placeholder_regs);
+        let provenance = self.last_provenance.clone();
+        let placeholder_next =
+            self.unwind_frame(address, budget_exhausted, false, StackReads::Replay(&reads));
+        self.last_provenance = provenance;
+        let placeholder_regs = std::mem::replace(&mut self.regs, regs);
+        if next != placeholder_next || self.regs.sp() != placeholder_regs.sp() {
+            return Err(Error::NeedsUnknownRegister(address.address()));
+        }
+        self.placeholder_regs = if self.regs == placeholder_regs {
+            None
+        } else {
+            Some(placeholder_regs)
+        };
+        next
+    }
+
+    /// Retry a frame whose frame pointer unwinding failed because it moved backwards,
+    /// in case the frame record leads onto a different one of the known stacks. The
+    /// retry is only kept if the new stack pointer or frame pointer is on a known stack
+    /// which doesn't contain the old stack pointer.
+    fn unwind_frame_onto_auxiliary_stack(
+        &mut self,
+        address: FrameAddress,
+        budget_exhausted: &mut bool,
+    ) -> Option<(FrameAddress, FrameConfidence)> {
+        let regs = self.regs;
+        let next = match self.unwind_frame(address, budget_exhausted, true, StackReads::Direct) {
+            Ok(Some((next_address, _))) => next_address,
+            _ => {
+                self.regs = regs;
+                return None;
+            }
+        };
+        let stacks = || {
+            self.options
+                .stack_bounds
+                .iter()
+                .chain(&self.options.auxiliary_stacks)
+        };
+        let old_sp = regs.sp();
+        let is_on_other_stack = |address| {
+            stacks().any(|stack| {
+                is_in_stack_bounds(stack, address) && !is_in_stack_bounds(stack, old_sp)
+            })
+        };
+        if !is_on_other_stack(self.regs.sp()) && !is_on_other_stack(self.regs.fp()) {
+            self.regs = regs;
+            return None;
+        }
+        Some((next, FrameConfidence::FramePointer))
+    }
+
+    fn is_past_captured_stack(&self, address: u64) -> bool {
+        match &self.options.captured_stack {
+            Some(captured_stack) => {
+                captured_stack.start <= address && address.saturating_add(8) > captured_stack.end
+            }
+            None => false,
+        }
+    }
+
+    fn validate(&self, address: FrameAddress, confidence: FrameConfidence) -> FrameConfidence {
+        if self.options.validate_return_addresses
+            && !self
+                .unwinder
+                .is_known_code_address(address.address_for_lookup())
+        {
+            return FrameConfidence::Implausible;
+        }
+        if self.options.verify_call_sites
+            && self.unwinder.is_preceded_by_call(address) == Some(false)
+        {
+            return FrameConfidence::Implausible;
+        }
+        confidence
+    }
+}
+
+impl<'u, 'c, 'r, U: Unwinder + ?Sized, F: FnMut(u64) -> Result<u64, ()>> FallibleIterator
+    for UnwindIterator<'u, 'c, 'r, U, F>
+{
+    type Item = FrameAddress;
+    type Error = Error;
+
+    fn next(&mut self) -> Result<Option<FrameAddress>, Error> {
+        self.next()
+    }
+}
+
+/// Returns whether the 8 bytes at `address` are inside `stack_bounds`.
+fn is_in_stack_bounds(stack_bounds: &Range<u64>, address: u64) -> bool {
+    stack_bounds.start <= address
+        && address
+            .checked_add(8)
+            .is_some_and(|end| end <= stack_bounds.end)
+}
+
+fn is_in_stacks(stack_bounds: &Range<u64>, auxiliary_stacks: &[Range<u64>], address: u64) -> bool {
+    is_in_stack_bounds(stack_bounds, address)
+        || auxiliary_stacks
+            .iter()
+            .any(|stack| is_in_stack_bounds(stack, address))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::{
+        eh_frame_module, frame_addresses, hot_and_cold_eh_frame, test_module, TestStack,
+    };
+    use crate::x86_64::{CacheX86_64, UnwindRegsX86_64, UnwinderX86_64};
+    use crate::{BranchKind, FallbackReason, Module, StackSlice};
+
+    #[test]
+    fn test_stack_bounds() {
+        let stack = TestStack::frame_chain();
+        let mut read_stack = |addr| stack.read(addr);
+        let unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        let mut cache = CacheX86_64::new();
+        let mut iter = unwinder
+            .iter_frames(
+                0x100400,
+                UnwindRegsX86_64::new(0x100400, 0x10, 0x20),
+                &mut cache,
+                &mut read_stack,
+            )
+            .with_stack_bounds(0x0..0x40);
+        assert_eq!(
+            iter.next(),
+            Ok(Some(FrameAddress::from_instruction_pointer(0x100400)))
+        );
+        assert_eq!(
+            iter.next(),
+            Ok(Some(FrameAddress::from_return_address(0x100200).unwrap()))
+        );
+        assert_eq!(iter.next(), Err(Error::OutOfStackBounds(0x40)));
+    }
+
+    #[test]
+    fn test_auxiliary_stacks() {
+        let stack = TestStack::zeroed(48)
+            // The thread's stack at 0x0..0x80.
+            .with_frame_record(0x20, 0x40, 0x100200)
+            .with_frame_record(0x40, 0x0, 0x100100)
+            // The alternate signal stack at 0x100..0x180, whose outermost frame record
+            // points back to the thread's stack.
+            .with_frame_record(0x110, 0x20, 0x100300);
+        let mut read_stack = |addr| stack.read(addr);
+        let unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        let mut cache = CacheX86_64::new();
+        let regs = UnwindRegsX86_64::new(0x100400, 0x100, 0x110);
+
+        let mut iter = unwinder
+            .iter_frames(0x100400, regs, &mut cache, &mut read_stack)
+            .with_stack_bounds(0x0..0x80);
+        assert_eq!(iter.by_ref().count(), Err(Error::OutOfStackBounds(0x110)));
+
+        let iter = unwinder
+            .iter_frames(0x100400, regs, &mut cache, &mut read_stack)
+            .with_stack_bounds(0x0..0x80)
+            .with_auxiliary_stack(0x100..0x180);
+        assert_eq!(
+            iter.collect::<Vec<_>>(),
+            Ok(vec![
+                FrameAddress::from_instruction_pointer(0x100400),
+                FrameAddress::from_return_address(0x100300).unwrap(),
+                FrameAddress::from_return_address(0x100200).unwrap(),
+                FrameAddress::from_return_address(0x100100).unwrap(),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_shadow_stack() {
+        let stack = TestStack::frame_chain();
+        let mut read_stack = |addr| stack.read(addr);
+        let unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        let mut cache = CacheX86_64::new();
+        let shadow_stack = [0x100200, 0x100150, 0x100050];
+        let mut iter = unwinder
+            .iter_frames(
+                0x100400,
+                UnwindRegsX86_64::new(0x100400, 0x10, 0x20),
+                &mut cache,
+                &mut read_stack,
+            )
+            .with_shadow_stack(&shadow_stack);
+        assert_eq!(
+            iter.next_with_confidence(),
+            Ok(Some((
+                FrameAddress::from_instruction_pointer(0x100400),
+                FrameConfidence::Exact
+            )))
+        );
+        // Confirmed by the shadow stack.
+        assert_eq!(
+            iter.next_with_confidence(),
+            Ok(Some((
+                FrameAddress::from_return_address(0x100200).unwrap(),
+                FrameConfidence::Exact
+            )))
+        );
+        assert_eq!(iter.shadow_stack_mismatch(), None);
+        // Repaired from the shadow stack.
+        assert_eq!(
+            iter.next(),
+            Ok(Some(FrameAddress::from_return_address(0x100150).unwrap()))
+        );
+        assert_eq!(
+            iter.shadow_stack_mismatch(),
+            Some(ShadowStackMismatch {
+                frame_index: 2,
+                unwound_return_address: 0x100100,
+                shadow_return_address: 0x100150,
+            })
+        );
+        assert_eq!(
+            iter.next(),
+            Ok(Some(FrameAddress::from_return_address(0x100050).unwrap()))
+        );
+        assert_eq!(iter.next(), Ok(None));
+        assert_eq!(iter.end_reason(), Some(UnwindEndReason::ReachedRoot));
+    }
+
+    #[test]
+    fn test_branch_records() {
+        // Calls at 0x100100 and at 0x1001f0, each returning to 5 bytes later.
+        let mut text = vec![0x90; 0x400];
+        text[0x100..0x105].copy_from_slice(&[0xe8, 0x00, 0x01, 0x00, 0x00]);
+        text[0x1f0..0x1f5].copy_from_slice(&[0xe8, 0x00, 0xff, 0xff, 0xff]);
+        let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        unwinder.add_module(test_module(0x100000..0x100400, Some(text)));
+        let branch_records = [
+            BranchRecord {
+                from: 0x100100,
+                to: 0x100300,
+                kind: BranchKind::Call,
+            },
+            BranchRecord {
+                from: 0x100080,
+                to: 0x100105,
+                kind: BranchKind::Return,
+            },
+            BranchRecord {
+                from: 0x100100,
+                to: 0x100040,
+                kind: BranchKind::Call,
+            },
+            BranchRecord {
+                from: 0x1001f0,
+                to: 0x1000f0,
+                kind: BranchKind::Call,
+            },
+        ];
+        let mut cache = CacheX86_64::new();
+
+        // The copied stack ends after the first frame record.
+        let stack = TestStack::from([1, 2, 3, 4, 0x40, 0x100105]);
+        let mut read_stack = |addr| stack.read(addr);
+        let mut iter = unwinder
+            .iter_frames(
+                0x100300,
+                UnwindRegsX86_64::new(0x100300, 0x10, 0x20),
+                &mut cache,
+                &mut read_stack,
+            )
+            .with_captured_stack(0x0..0x30)
+            .with_branch_records(&branch_records);
+        assert_eq!(
+            iter.next_with_confidence(),
+            Ok(Some((
+                FrameAddress::from_instruction_pointer(0x100300),
+                FrameConfidence::Exact
+            )))
+        );
+        // Confirmed by the branch records.
+        assert_eq!(
+            iter.next_with_confidence(),
+            Ok(Some((
+                FrameAddress::from_return_address(0x100105).unwrap(),
+                FrameConfidence::Exact
+            )))
+        );
+        // Unwinding fails with Error::StackTruncated, continue with the branch records.
+        assert_eq!(
+            iter.next(),
+            Ok(Some(FrameAddress::from_return_address(0x1001f5).unwrap()))
+        );
+        assert_eq!(iter.next(), Ok(None));
+        assert_eq!(iter.end_reason(), Some(UnwindEndReason::EndOfBranchRecords));
+
+        // The frame pointer skips the caller of the first frame.
+        let stack = TestStack::from([1, 2, 3, 4, 0x40, 0x1001f5, 5, 6, 0x0, 0x0]);
+        let mut read_stack = |addr| stack.read(addr);
+        let mut iter = unwinder
+            .iter_frames(
+                0x100300,
+                UnwindRegsX86_64::new(0x100300, 0x10, 0x20),
+                &mut cache,
+                &mut read_stack,
+            )
+            .with_branch_records(&branch_records);
+        let frames: Vec<_> = iter.by_ref().collect().unwrap();
+        assert_eq!(
+            frames,
+            vec![
+                FrameAddress::from_instruction_pointer(0x100300),
+                FrameAddress::from_return_address(0x100105).unwrap(),
+                FrameAddress::from_return_address(0x1001f5).unwrap(),
+            ]
+        );
+        assert_eq!(iter.end_reason(), Some(UnwindEndReason::EndOfBranchRecords));
+    }
+
+    #[test]
+    fn test_stack_switch_handler() {
+        let stack = TestStack::zeroed(48)
+            // The scheduler's stack, where the previous context was suspended.
+            .with_frame_record(0x20, 0x0, 0x100100)
+            // The coroutine stack at 0x100, whose outermost frame record at 0x110 returns
+            // into the coroutine entry trampoline.
+            .with_frame_record(0x110, 0x0, 0x180500);
+        let mut read_stack = |addr| stack.read(addr);
+        let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        unwinder.add_stack_switch_range(0x180000..0x181000);
+        // Like a fiber start function, the trampoline is also a root.
+        unwinder.add_root_range(0x180000..0x181000);
+        let mut cache = CacheX86_64::new();
+        let regs = UnwindRegsX86_64::new(0x100400, 0x100, 0x110);
+
+        let mut iter = unwinder.iter_frames(0x100400, regs, &mut cache, &mut read_stack);
+        assert_eq!(iter.by_ref().count(), Ok(2));
+        assert_eq!(iter.end_reason(), Some(UnwindEndReason::ReachedRoot));
+
+        let mut handler = |address: FrameAddress, _regs: &UnwindRegsX86_64| {
+            assert_eq!(address.address(), 0x180500);
+            Some((
+                FrameAddress::from_return_address(0x100200).unwrap(),
+                UnwindRegsX86_64::new(0x100200, 0x10, 0x20),
+            ))
+        };
+        let iter = unwinder
+            .iter_frames(0x100400, regs, &mut cache, &mut read_stack)
+            .with_stack_switch_handler(&mut handler);
+        assert_eq!(
+            iter.collect::<Vec<_>>(),
+            Ok(vec![
+                FrameAddress::from_instruction_pointer(0x100400),
+                FrameAddress::from_return_address(0x180500).unwrap(),
+                FrameAddress::from_return_address(0x100200).unwrap(),
+                FrameAddress::from_return_address(0x100100).unwrap(),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_async_task_handler() {
+        let stack = TestStack::frame_chain();
+        let mut read_stack = |addr| stack.read(addr);
+        let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        // The executor's poll function.
+        unwinder.add_async_boundary_range(0x100180..0x100280);
+        let mut cache = CacheX86_64::new();
+        let regs = UnwindRegsX86_64::new(0x100400, 0x10, 0x20);
+
+        let mut handler = |address: FrameAddress, regs: &UnwindRegsX86_64| {
+            assert_eq!(address.address(), 0x100200);
+            assert_eq!(regs.bp(), 0x40);
+            vec![
+                FrameAddress::from_return_address(0x500010).unwrap(),
+                FrameAddress::from_return_address(0x500020).unwrap(),
+            ]
+        };
+        let mut iter = unwinder
+            .iter_frames(0x100400, regs, &mut cache, &mut read_stack)
+            .with_async_task_handler(&mut handler)
+            .with_provenance();
+        assert_eq!(
+            iter.by_ref().collect::<Vec<_>>(),
+            Ok(vec![
+                FrameAddress::from_instruction_pointer(0x100400),
+                FrameAddress::from_return_address(0x100200).unwrap(),
+                FrameAddress::from_return_address(0x500010).unwrap(),
+                FrameAddress::from_return_address(0x500020).unwrap(),
+                FrameAddress::from_return_address(0x100100).unwrap(),
+            ])
+        );
+        let sources: Vec<_> = iter
+            .provenance()
+            .iter()
+            .map(|provenance| provenance.source)
+            .collect();
+        assert_eq!(sources[2], FrameSource::AsyncTask);
+        assert_eq!(sources[3], FrameSource::AsyncTask);
+        assert_eq!(sources[4], FrameSource::FramePointer);
+
+        // The logical frames count towards the maximum depth.
+        let iter = unwinder
+            .iter_frames(0x100400, regs, &mut cache, &mut read_stack)
+            .with_async_task_handler(&mut handler)
+            .with_max_depth(3);
+        assert_eq!(iter.count(), Ok(3));
+    }
+
+    #[test]
+    fn test_end_reason() {
+        let stack = TestStack::frame_chain();
+        let mut read_stack = |addr| stack.read(addr);
+        let unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        let mut cache = CacheX86_64::new();
+        let regs = UnwindRegsX86_64::new(0x100400, 0x10, 0x20);
+
+        let mut iter = unwinder.iter_frames(0x100400, regs, &mut cache, &mut read_stack);
+        assert_eq!(iter.by_ref().count(), Ok(3));
+        assert_eq!(iter.end_reason(), Some(UnwindEndReason::NullReturnAddress));
+
+        let mut iter = unwinder
+            .iter_frames(0x100400, regs, &mut cache, &mut read_stack)
+            .with_max_depth(2);
+        assert_eq!(iter.end_reason(), None);
+        assert_eq!(iter.by_ref().count(), Ok(2));
+        assert_eq!(iter.end_reason(), Some(UnwindEndReason::MaxDepth));
+
+        // Every frame pointer step reads two values, so the budget runs out during
+        // the second step.
+        let mut iter = unwinder
+            .iter_frames(0x100400, regs, &mut cache, &mut read_stack)
+            .with_stack_read_budget(3);
+        assert_eq!(iter.by_ref().count(), Ok(2));
+        assert_eq!(iter.end_reason(), Some(UnwindEndReason::BudgetExhausted));
+
+        let mut iter = unwinder
+            .iter_frames(0x100400, regs, &mut cache, &mut read_stack)
+            .with_stack_bounds(0x0..0x40);
+        assert_eq!(iter.by_ref().count(), Err(Error::OutOfStackBounds(0x40)));
+        assert_eq!(
+            iter.end_reason(),
+            Some(UnwindEndReason::UnreadableStack(Error::OutOfStackBounds(
+                0x40
+            )))
+        );
+
+        let regs = UnwindRegsX86_64::new(0x100400, 0x10, 0x24);
+        let mut iter = unwinder.iter_frames(0x100400, regs, &mut cache, &mut read_stack);
+        assert_eq!(
+            iter.by_ref().count(),
+            Err(Error::MisalignedFramePointer(0x24))
+        );
+        assert_eq!(
+            iter.end_reason(),
+            Some(UnwindEndReason::MissingModule(0x100400))
+        );
+    }
+
+    #[test]
+    fn test_first_frame_kind() {
+        let stack = TestStack::frame_chain();
+        let mut read_stack = |addr| stack.read(addr);
+        let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        // The root range ends right at the first frame's address, so it only covers
+        // the first frame if the address is adjusted for lookup.
+        unwinder.add_root_range(0x100300..0x100400);
+        let mut cache = CacheX86_64::new();
+        let mut iter = unwinder
+            .iter_frames(
+                0x100400,
+                UnwindRegsX86_64::new(0x100400, 0x10, 0x20),
+                &mut cache,
+                &mut read_stack,
+            )
+            .with_first_frame_kind(FrameAddressKind::ReturnAddress);
+        assert_eq!(
+            iter.next(),
+            Ok(Some(FrameAddress::from_return_address(0x100400).unwrap()))
+        );
+        assert_eq!(iter.next(), Ok(None));
+    }
+
+    #[test]
+    fn test_divergence_validation() {
+        // At the start of the hot part, the return address is at sp, but bp still
+        // belongs to the caller, so frame pointer unwinding skips the caller.
+        let stack = TestStack::frame_chain().with(0x10, 0x100250);
+        let mut read_stack = |addr| stack.read(addr);
+        let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        unwinder.add_module(eh_frame_module(hot_and_cold_eh_frame(), None));
+        let mut cache = CacheX86_64::new();
+        let regs = UnwindRegsX86_64::new(0x100300, 0x10, 0x20);
+        let divergence = FrameDivergence {
+            address: FrameAddress::from_instruction_pointer(0x100300),
+            cfi_return_address: 0x100250,
+            cfi_sp: 0x18,
+            frame_pointer_return_address: 0x100200,
+            frame_pointer_sp: 0x30,
+        };
+        assert_eq!(
+            unwinder.check_frame_divergence(divergence.address, &regs, &mut cache, &mut read_stack),
+            Some(divergence)
+        );
+
+        // The callers aren't covered by the CFI, so they are unwound with the frame
+        // pointer and can't diverge.
+        let mut iter = unwinder
+            .iter_frames(0x100300, regs, &mut cache, &mut read_stack)
+            .with_divergence_validation();
+        assert_eq!(
+            frame_addresses(&mut iter),
+            vec![0x100300, 0x100250, 0x100200, 0x100100]
+        );
+        assert_eq!(iter.divergences(), &[divergence]);
+    }
+
+    #[test]
+    fn test_unknown_registers() {
+        // __restore_rt at 0x100100.
+        let mut text = vec![0x90; 0x400];
+        text[0x100..0x109].copy_from_slice(&[0x48, 0xc7, 0xc0, 0x0f, 0x00, 0x00, 0x00, 0x0f, 0x05]);
+        let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        unwinder.add_module(test_module(0x100000..0x100400, Some(text)));
+        let stack = TestStack::zeroed(32)
+            // The signal frame restores rbp, rsp and rip.
+            .with(0x78, 0xe0)
+            .with(0xa0, 0xd0)
+            .with(0xa8, 0x100300)
+            // The frame record of the interrupted function.
+            .with_frame_record(0xe0, 0x0, 0x100200);
+        let mut read_stack = |addr| stack.read(addr);
+        let mut cache = CacheX86_64::new();
+
+        // The frame pointer is needed right away.
+        let regs = UnwindRegsX86_64::new(0x100300, 0xd0, 0xe0);
+        let mut iter = unwinder
+            .iter_frames(0x100300, regs, &mut cache, &mut read_stack)
+            .with_unknown_registers();
+        assert_eq!(
+            iter.next(),
+            Ok(Some(FrameAddress::from_instruction_pointer(0x100300)))
+        );
+        assert_eq!(iter.next(), Err(Error::NeedsUnknownRegister(0x100300)));
+
+        // The signal frame restores the frame pointer.
+        let regs = UnwindRegsX86_64::new(0x100100, 0x0, 0x0);
+        let mut iter = unwinder
+            .iter_frames(0x100100, regs, &mut cache, &mut read_stack)
+            .with_unknown_registers();
+        assert_eq!(
+            iter.next(),
+            Ok(Some(FrameAddress::from_instruction_pointer(0x100100)))
+        );
+        assert_eq!(
+            iter.next(),
+            Ok(Some(FrameAddress::from_instruction_pointer(0x100300)))
+        );
+        assert_eq!(
+            iter.next(),
+            Ok(Some(FrameAddress::from_return_address(0x100200).unwrap()))
+        );
+
+        // The placeholder unwind doesn't read the stack again.
+        let reads = std::cell::Cell::new(0);
+        let mut read_stack = |addr: u64| {
+            reads.set(reads.get() + 1);
+            stack.read(addr)
+        };
+        let regs = UnwindRegsX86_64::new(0x100100, 0x0, 0x0);
+        let frames = unwinder
+            .iter_frames(0x100100, regs, &mut cache, &mut read_stack)
+            .with_unknown_registers()
+            .count();
+        assert_eq!(frames, Ok(3));
+        let reads_with_placeholders = reads.replace(0);
+        let frames = unwinder
+            .iter_frames(0x100100, regs, &mut cache, &mut read_stack)
+            .count();
+        assert_eq!(frames, Ok(3));
+        assert_eq!(reads_with_placeholders, reads.get());
+    }
+
+    #[test]
+    fn test_call_site_verification() {
+        // call 0x100100; nop
+        let mut text = vec![0x90; 0x400];
+        text[0x200..0x205].copy_from_slice(&[0xe8, 0xfb, 0xfe, 0xff, 0xff]);
+        let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        unwinder.add_module(test_module(0x100000..0x100400, Some(text)));
+        let stack = TestStack::from([
+            1, 2, 0x100300, 4, 0x40, 0x100205, 5, 6, 0x70, 0x100100, 7, 8, 9, 10, 0x0, 0x0,
+        ]);
+        let mut read_stack = |addr| stack.read(addr);
+        let mut cache = CacheX86_64::new();
+        let mut iter = unwinder
+            .iter_frames(
+                0x100300,
+                UnwindRegsX86_64::new(0x100300, 0x10, 0x20),
+                &mut cache,
+                &mut read_stack,
+            )
+            .with_call_site_verification();
+        assert_eq!(
+            iter.next_with_confidence(),
+            Ok(Some((
+                FrameAddress::from_instruction_pointer(0x100300),
+                FrameConfidence::Exact
+            )))
+        );
+        assert_eq!(
+            iter.next_with_confidence(),
+            Ok(Some((
+                FrameAddress::from_return_address(0x100205).unwrap(),
+                FrameConfidence::FramePointer
+            )))
+        );
+        // Preceded by nops.
+        assert_eq!(
+            iter.next_with_confidence(),
+            Ok(Some((
+                FrameAddress::from_return_address(0x100100).unwrap(),
+                FrameConfidence::Implausible
+            )))
+        );
+    }
+
+    #[test]
+    fn test_provenance() {
+        let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        unwinder.add_module(test_module(0x100000..0x100400, None));
+        let stack = TestStack::from([
+            1, 2, 0x100300, 4, 0x40, 0x100200, 5, 6, 0x70, 0x500000, 7, 8, 9, 10, 0x90, 0x100100,
+            11, 12, 0x0, 0x0,
+        ]);
+        let mut read_stack = |addr| stack.read(addr);
+        let mut cache = CacheX86_64::new();
+        let regs = UnwindRegsX86_64::new(0x100300, 0x10, 0x20);
+        let fallback = |fallback, module: Option<&str>| FrameProvenance {
+            source: FrameSource::FramePointer,
+            module: module.map(str::to_string),
+            rule: Some("sp' = bp + 0x10; bp' = *bp; ra = *(sp' - 8)".to_string()),
+            from_cache: false,
+            fallback: Some(fallback),
+        };
+
+        let mut iter = unwinder
+            .iter_frames(0x100300, regs, &mut cache, &mut read_stack)
+            .with_provenance();
+        while let Ok(Some(_)) = iter.next() {}
+        assert_eq!(
+            iter.provenance(),
+            &[
+                FrameProvenance::new(FrameSource::Registers),
+                fallback(FallbackReason::NoUnwindData, Some("lib")),
+                fallback(FallbackReason::NoUnwindData, Some("lib")),
+                fallback(FallbackReason::NoModule, None),
+            ]
+        );
+
+        // The second walk hits the cache.
+        let mut iter = unwinder
+            .iter_frames(0x100300, regs, &mut cache, &mut read_stack)
+            .with_provenance();
+        assert!(iter.next().is_ok());
+        assert!(iter.next().is_ok());
+        assert_eq!(
+            iter.provenance()[1],
+            FrameProvenance {
+                from_cache: true,
+                fallback: None,
+                ..fallback(FallbackReason::NoUnwindData, Some("lib"))
+            }
+        );
+    }
+
+    #[test]
+    fn test_stack_hash() {
+        let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        unwinder.add_module(test_module(0x100000..0x100400, None));
+        let mut cache = CacheX86_64::new();
+        let regs = UnwindRegsX86_64::new(0x100300, 0x10, 0x20);
+        let mut walk = |stack: &TestStack| {
+            let mut read_stack = |addr| stack.read(addr);
+            let mut iter = unwinder
+                .iter_frames(0x100300, regs, &mut cache, &mut read_stack)
+                .with_stack_hash();
+            let mut hashes = Vec::new();
+            while let Ok(Some(_)) = iter.next() {
+                hashes.extend(iter.stack_hash());
+            }
+            hashes
+        };
+
+        let stack = TestStack::from([
+            1, 2, 0x100300, 4, 0x40, 0x100200, 5, 6, 0x70, 0x500000, 7, 8, 9, 10, 0x0, 0x0,
+        ]);
+        let hashes = walk(&stack);
+        assert_eq!(hashes.len(), 3);
+        assert_ne!(hashes[0], hashes[1]);
+        assert_ne!(hashes[1], hashes[2]);
+        assert_eq!(walk(&stack), hashes);
+
+        // A different caller changes the hash from that frame on.
+        let other_stack = stack.clone().with(0x28, 0x100208);
+        let other_hashes = walk(&other_stack);
+        assert_eq!(other_hashes[0], hashes[0]);
+        assert_ne!(other_hashes[1], hashes[1]);
+        assert_ne!(other_hashes[2], hashes[2]);
+
+        // Without with_stack_hash, there is no hash.
+        let mut read_stack = |addr| stack.read(addr);
+        let mut iter = unwinder.iter_frames(0x100300, regs, &mut cache, &mut read_stack);
+        assert!(iter.next().is_ok());
+        assert_eq!(iter.stack_hash(), None);
+    }
+
+    #[test]
+    fn test_stack_hash_load_address() {
+        // The hash of the same stack, with the module "lib" loaded at `base`.
+        let hash = |base: u64, module: fn(Module<Vec<u8>>) -> Module<Vec<u8>>| {
+            let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+            unwinder.add_module(module(test_module(base..base + 0x400, None)));
+            let stack = TestStack::frame_chain()
+                .with(0x28, base + 0x200)
+                .with(0x48, base + 0x100);
+            let mut read_stack = |addr| stack.read(addr);
+            let mut cache = CacheX86_64::new();
+            let regs = UnwindRegsX86_64::new(base + 0x300, 0x10, 0x20);
+            let mut iter = unwinder
+                .iter_frames(base + 0x300, regs, &mut cache, &mut read_stack)
+                .with_stack_hash();
+            assert_eq!(iter.by_ref().count(), Ok(3));
+            iter.stack_hash().unwrap()
+        };
+        let hash_by_name = hash(0x100000, |module| module);
+        assert_eq!(hash(0x7f0000100000, |module| module), hash_by_name);
+
+        // Modules can be identified by their own ids instead of their names.
+        let hash_by_id = hash(0x100000, |module| module.with_id(1));
+        assert_ne!(hash_by_id, hash_by_name);
+        assert_eq!(hash(0x7f0000100000, |module| module.with_id(1)), hash_by_id);
+        assert_ne!(hash(0x100000, |module| module.with_id(2)), hash_by_id);
+    }
+
+    #[test]
+    fn test_frame_filter() {
+        let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        unwinder.add_module(test_module(0x100000..0x100400, None));
+        let stack = TestStack::from([
+            1, 2, 0x100300, 4, 0x40, 0x100200, 5, 6, 0x70, 0x100100, 7, 8, 9, 10, 0x90, 0x100050,
+            11, 12, 0x0, 0x0,
+        ]);
+        let mut read_stack = |addr| stack.read(addr);
+        let mut cache = CacheX86_64::new();
+        let regs = UnwindRegsX86_64::new(0x100300, 0x10, 0x20);
+
+        // Skip the frame at 0x100200 and stop at "main", which is at 0x100100.
+        let mut filter = |address: FrameAddress| match address.address() {
+            0x100200 => FrameFilterAction::Skip,
+            0x100100 => FrameFilterAction::YieldAndStop,
+            _ => FrameFilterAction::Yield,
+        };
+        let mut iter = unwinder
+            .iter_frames(0x100300, regs, &mut cache, &mut read_stack)
+            .with_frame_filter(&mut filter)
+            .with_provenance();
+        assert_eq!(frame_addresses(&mut iter), vec![0x100300, 0x100100]);
+        assert_eq!(iter.provenance().len(), 2);
+        assert_eq!(iter.end_reason(), Some(UnwindEndReason::StoppedByFilter));
+
+        // Stop before the frame at 0x100100.
+        let mut filter = |address: FrameAddress| match address.address() {
+            0x100100 => FrameFilterAction::Stop,
+            _ => FrameFilterAction::Yield,
+        };
+        let mut iter = unwinder
+            .iter_frames(0x100300, regs, &mut cache, &mut read_stack)
+            .with_frame_filter(&mut filter);
+        assert_eq!(frame_addresses(&mut iter), vec![0x100300, 0x100200]);
+        assert_eq!(iter.end_reason(), Some(UnwindEndReason::StoppedByFilter));
+    }
+
+    #[test]
+    fn test_resume_from() {
+        let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        unwinder.add_module(test_module(0x100000..0x100400, None));
+        let stack = TestStack::frame_chain();
+        let mut read_stack = |addr| stack.read(addr);
+        let mut cache = CacheX86_64::new();
+        let regs = UnwindRegsX86_64::new(0x100300, 0x10, 0x20);
+        let mut iter = unwinder
+            .iter_frames(0x100300, regs, &mut cache, &mut read_stack)
+            .with_register_snapshots()
+            .with_stack_hash();
+        assert_eq!(
+            frame_addresses(&mut iter),
+            vec![0x100300, 0x100200, 0x100100]
+        );
+        assert_eq!(iter.end_reason(), Some(UnwindEndReason::NullReturnAddress));
+        let hash = iter.stack_hash();
+
+        assert_eq!(
+            iter.frame_registers(0),
+            Some((FrameAddress::from_instruction_pointer(0x100300), regs))
+        );
+        let (address, regs_1) = iter.frame_registers(1).unwrap();
+        assert_eq!(address.address(), 0x100200);
+        assert_eq!((regs_1.sp(), regs_1.bp()), (0x30, 0x40));
+        assert_eq!(iter.frame_registers(3), None);
+
+        // Walk the rest of the stack again from the second frame.
+        assert!(iter.resume_from(1));
+        assert_eq!(iter.end_reason(), None);
+        assert_eq!(iter.frame_registers(2), None);
+        assert_eq!(
+            iter.next().map(|f| f.map(|f| f.address())),
+            Ok(Some(0x100100))
+        );
+        assert_eq!(iter.next(), Ok(None));
+        assert_eq!(iter.stack_hash(), hash);
+        assert!(!iter.resume_from(5));
+    }
+
+    #[test]
+    fn test_resume_from_after_filtered_frame() {
+        let unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        let stack = TestStack::frame_chain();
+        let mut read_stack = |addr| stack.read(addr);
+        let mut cache = CacheX86_64::new();
+        let regs = UnwindRegsX86_64::new(0x100400, 0x10, 0x20);
+        let mut filter = |address: FrameAddress| match address.address() {
+            0x100200 => FrameFilterAction::Skip,
+            _ => FrameFilterAction::Yield,
+        };
+        let mut iter = unwinder
+            .iter_frames(0x100400, regs, &mut cache, &mut read_stack)
+            .with_frame_filter(&mut filter)
+            .with_max_depth(3)
+            .with_register_snapshots()
+            .with_provenance();
+        assert_eq!(frame_addresses(&mut iter), vec![0x100400, 0x100100]);
+        assert_eq!(iter.end_reason(), Some(UnwindEndReason::MaxDepth));
+
+        // The skipped frame still counts towards the maximum depth after resuming.
+        assert!(iter.resume_from(1));
+        assert_eq!(iter.provenance().len(), 2);
+        assert_eq!(iter.next(), Ok(None));
+        assert_eq!(iter.end_reason(), Some(UnwindEndReason::MaxDepth));
+
+        assert!(iter.resume_from(0));
+        assert_eq!(iter.provenance().len(), 1);
+        assert_eq!(
+            iter.next().map(|f| f.map(|f| f.address())),
+            Ok(Some(0x100100))
+        );
+        assert_eq!(iter.next(), Ok(None));
+    }
+
+    #[test]
+    fn test_resume_from_before_stack_switch() {
+        let stack = TestStack::zeroed(48)
+            // The previous stack segment, with the frame record of __morestack at 0x20.
+            .with_frame_record(0x20, 0x40, 0x100200)
+            .with_frame_record(0x40, 0x0, 0x100100)
+            // The new stack segment at 0x100, with the frame records of the callees at
+            // 0x100 and 0x110.
+            .with_frame_record(0x100, 0x110, 0x100300)
+            .with_frame_record(0x110, 0x20, 0x180500);
+        let stack = std::cell::RefCell::new(stack);
+        let mut read_stack = |addr| stack.borrow().read(addr);
+        let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        unwinder.add_stack_switch_range(0x180000..0x181000);
+        let mut cache = CacheX86_64::new();
+        let regs = UnwindRegsX86_64::new(0x100400, 0x100, 0x100);
+        let mut iter = unwinder
+            .iter_frames(0x100400, regs, &mut cache, &mut read_stack)
+            .with_stack_bounds(0x100..0x180)
+            .with_register_snapshots();
+        assert_eq!(
+            frame_addresses(&mut iter),
+            vec![0x100400, 0x100300, 0x180500, 0x100200, 0x100100]
+        );
+
+        // The stack bounds were dropped at the stack switch, but they still apply to
+        // the frames before it.
+        stack.borrow_mut().set(0x100, 0x200);
+        assert!(iter.resume_from(0));
+        assert_eq!(
+            iter.next().map(|f| f.map(|f| f.address())),
+            Ok(Some(0x100300))
+        );
+        assert_eq!(iter.next(), Err(Error::OutOfStackBounds(0x200)));
+    }
+
+    /// Alternates between two return addresses without moving the stack pointer,
+    /// which is what a broken unwind rule that just returns lr can do.
+    struct CyclingUnwinder;
+
+    impl Unwinder for CyclingUnwinder {
+        type UnwindRegs = UnwindRegsX86_64;
+        type Cache = CacheX86_64<Vec<u8>>;
+        type Module = ();
+
+        fn add_module(&mut self, _module: ()) {}
+        fn remove_module(&mut self, _module_avma_range_start: u64) {}
+        fn max_known_code_address(&self) -> u64 {
+            0
+        }
+
+        fn unwind_frame<F>(
+            &self,
+            _address: FrameAddress,
+            regs: &mut UnwindRegsX86_64,
+            _cache: &mut CacheX86_64<Vec<u8>>,
+            _read_stack: &mut F,
+        ) -> Result<Option<u64>, Error>
+        where
+            F: FnMut(u64) -> Result<u64, ()>,
+        {
+            let return_address = if regs.ip() == 0x100100 {
+                0x100200
+            } else {
+                0x100100
+            };
+            regs.set_ip(return_address);
+            Ok(Some(return_address))
+        }
+    }
+
+    #[test]
+    fn test_captured_stack() {
+        let stack: [u64; 16] = [
+            1, 2, 0x100300, 4, 0x40, 0x100200, 5, 6, 0x70, 0x100100, 7, 8, 9, 10, 0x0, 0x0,
+        ];
+        // Only the first 0x40 bytes were captured.
+        let bytes: Vec<u8> = stack[..8].iter().flat_map(|v| v.to_le_bytes()).collect();
+        let stack = StackSlice::new(0, bytes);
+        let mut read_stack = |addr: u64| stack.read_u64(addr).ok_or(());
+        let unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        let mut cache = CacheX86_64::new();
+        let mut iter = unwinder
+            .iter_frames(
+                0x100400,
+                UnwindRegsX86_64::new(0x100400, 0x10, 0x20),
+                &mut cache,
+                &mut read_stack,
+            )
+            .with_captured_stack(stack.address_range());
+        assert_eq!(
+            iter.next(),
+            Ok(Some(FrameAddress::from_instruction_pointer(0x100400)))
+        );
+        assert_eq!(
+            iter.next(),
+            Ok(Some(FrameAddress::from_return_address(0x100200).unwrap()))
+        );
+        assert_eq!(iter.next(), Err(Error::StackTruncated(0x40)));
+    }
+
+    #[test]
+    fn test_cycle_detection() {
+        let mut read_stack = |_addr: u64| Err(());
+        let mut cache = CacheX86_64::new();
+        let mut iter = CyclingUnwinder.iter_frames(
+            0x100400,
+            UnwindRegsX86_64::new(0x100400, 0x10, 0x20),
+            &mut cache,
+            &mut read_stack,
+        );
+        assert_eq!(
+            iter.next(),
+            Ok(Some(FrameAddress::from_instruction_pointer(0x100400)))
+        );
+        assert_eq!(
+            iter.next(),
+            Ok(Some(FrameAddress::from_return_address(0x100100).unwrap()))
+        );
+        assert_eq!(
+            iter.next(),
+            Ok(Some(FrameAddress::from_return_address(0x100200).unwrap()))
+        );
+        assert_eq!(iter.next(), Err(Error::UnwindingCycle(0x100100)));
+    }
+
+    #[test]
+    fn test_frame_confidence() {
+        let stack = TestStack::frame_chain();
+        let mut read_stack = |addr| stack.read(addr);
+        let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        unwinder.add_module(test_module(0x100150..0x100250, None));
+        let mut cache = CacheX86_64::new();
+        let mut iter = unwinder
+            .iter_frames(
+                0x100200,
+                UnwindRegsX86_64::new(0x100200, 0x10, 0x20),
+                &mut cache,
+                &mut read_stack,
+            )
+            .with_return_address_validation();
+        assert_eq!(
+            iter.next_with_confidence(),
+            Ok(Some((
+                FrameAddress::from_instruction_pointer(0x100200),
+                FrameConfidence::Exact
+            )))
+        );
+        assert_eq!(
+            iter.next_with_confidence(),
+            Ok(Some((
+                FrameAddress::from_return_address(0x100200).unwrap(),
+                FrameConfidence::FramePointer
+            )))
+        );
+        assert_eq!(
+            iter.next_with_confidence(),
+            Ok(Some((
+                FrameAddress::from_return_address(0x100100).unwrap(),
+                FrameConfidence::Implausible
+            )))
+        );
+        assert_eq!(iter.next_with_confidence(), Ok(None));
+    }
+}
@@ -0,0 +1,29 @@
+/// Caps on the amount of work that the unwinder does for a single frame.
+///
+/// Unwind information from untrusted binaries, for example from crash reports that
+/// were collected in the wild, can be malformed or even malicious. These limits make
+/// sure that unwinding a frame always terminates in reasonable time. Frames for which
+/// a limit is hit are treated like frames whose unwind information could not be
+/// parsed, i.e. the unwinder uses its fallback rule, or fails in
+/// [`UnwindMode::Strict`](crate::UnwindMode::Strict).
+///
+/// The defaults are far above what real compilers emit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnwindLimits {
+    /// The maximum number of DWARF call frame instructions, summed over the CIE and
+    /// the FDE, that are processed to compute the unwind rules for a single address.
+    pub max_cfi_instructions_per_frame: usize,
+    /// The maximum number of operations that are executed when evaluating a single
+    /// DWARF expression. DWARF expressions can contain backwards branches, so without
+    /// this limit a crafted expression could loop forever.
+    pub max_dwarf_expression_steps: u32,
+}
+
+impl Default for UnwindLimits {
+    fn default() -> Self {
+        Self {
+            max_cfi_instructions_per_frame: 10_000,
+            max_dwarf_expression_steps: 1_000,
+        }
+    }
+}
@@ -0,0 +1,20 @@
+/// How the unwinder deals with unwind information that it can't use for an address.
+///
+/// Set with the concrete unwinder's `set_mode` method. The default is
+/// [`UnwindMode::Lenient`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum UnwindMode {
+    /// Fall back to frame pointer unwinding when the unwind information is malformed,
+    /// doesn't cover the address, or hits one of the [`UnwindLimits`](crate::UnwindLimits).
+    /// This gives the most complete stacks, and is what profilers want.
+    #[default]
+    Lenient,
+    /// Fail with [`Error::UnusableUnwindInfo`](crate::Error::UnusableUnwindInfo)
+    /// instead of falling back. The error says which module and which address the
+    /// unwind information failed for, and why. Modules that were added without unwind information,
+    /// and addresses outside of all modules, still use frame pointer unwinding.
+    ///
+    /// This is meant for tools that check the unwind information of a build, for
+    /// example in CI, where every fallback points to a bug in the toolchain.
+    Strict,
+}
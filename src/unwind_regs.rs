@@ -0,0 +1,13 @@
+/// The functionality that is common to the unwind register types of all CPU
+/// architectures.
+pub trait UnwindRegs: Copy + PartialEq + std::fmt::Debug {
+    /// The stack pointer value.
+    fn sp(&self) -> u64;
+    /// The frame pointer value.
+    fn fp(&self) -> u64;
+    /// A copy of these registers in which every register other than the instruction
+    /// pointer and the stack pointer is set to `placeholder`. This is used to find the
+    /// frames which depend on unknown registers, see
+    /// [`UnwindIterator::with_unknown_registers`](crate::UnwindIterator::with_unknown_registers).
+    fn with_unknown_registers(&self, placeholder: u64) -> Self;
+}
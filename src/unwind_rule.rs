@@ -1,6 +1,6 @@
 use crate::error::Error;
 
-pub trait UnwindRule: Copy + std::fmt::Debug {
+pub trait UnwindRule: Copy + std::fmt::Debug + std::fmt::Display + PartialEq {
     type UnwindRegs;
 
     fn exec<F>(
@@ -15,4 +15,35 @@ pub trait UnwindRule: Copy + std::fmt::Debug {
     fn rule_for_stub_functions() -> Self;
     fn rule_for_function_start() -> Self;
     fn fallback_rule() -> Self;
+
+    /// The rule for the Linux `rt_sigreturn` trampoline, which restores the
+    /// interrupted register state from the signal frame on the stack.
+    fn rule_for_linux_sigreturn_trampoline() -> Self;
+
+    /// The rule for macOS's `_sigtramp`, which restores the interrupted register
+    /// state from the mcontext that the kernel saved on the stack.
+    fn rule_for_macos_sigtramp() -> Self;
+
+    /// The rule for functions which switch to a different stack and call the rest of
+    /// the program on it, such as `__morestack` for segmented stacks. These functions
+    /// keep a frame record on the previous stack, so the rule follows the frame
+    /// pointer, but it allows the stack pointer to jump to a lower address.
+    fn rule_for_stack_switch() -> Self;
+
+    /// Whether `exec` returns the instruction pointer of interrupted code, rather than
+    /// a return address.
+    fn resumes_interrupted_code(self) -> bool;
+}
+
+/// Catches frame pointers that can't possibly point at a frame record, so that
+/// they fail with a specific error rather than with a read of garbage memory.
+/// `alignment` is the pointer size of the architecture.
+pub fn check_frame_pointer(fp: u64, sp: u64, alignment: u64) -> Result<(), Error> {
+    if !fp.is_multiple_of(alignment) {
+        return Err(Error::MisalignedFramePointer(fp));
+    }
+    if fp < sp {
+        return Err(Error::FramePointerBelowStackPointer(fp));
+    }
+    Ok(())
 }
@@ -1,16 +1,32 @@
-use fallible_iterator::FallibleIterator;
 use gimli::{EndianReader, LittleEndian};
 
 use crate::arcdata::ArcData;
 use crate::arch::Arch;
 use crate::cache::{AllocationPolicy, Cache};
+#[cfg(feature = "tracing")]
+use crate::display_utils::HexNum;
 use crate::dwarf::{DwarfCfiIndex, DwarfUnwinder, DwarfUnwinding, UnwindSectionType};
-use crate::error::{Error, UnwinderError};
+use crate::error::{Error, ModuleError, UnwinderError};
+use crate::exception_handling::ExceptionHandlingInfo;
+use crate::fpo::{FpoIndex, FpoUnwinding};
+use crate::frame_confidence::FrameConfidence;
+use crate::frame_divergence::FrameDivergence;
+use crate::frame_pointer_chain::{FramePointerChain, FramePointerChainEnd};
+use crate::frame_provenance::{FallbackReason, FrameProvenance, FrameSource};
 use crate::instruction_analysis::InstructionAnalysis;
 use crate::macho::{
     CompactUnwindInfoUnwinder, CompactUnwindInfoUnwinding, CuiUnwindResult, TextBytes,
 };
+use crate::module_event::{ModuleEvent, ModuleEventSubscription};
+use crate::range_map::RangeMap;
 use crate::rule_cache::CacheResult;
+use crate::stack_hash::module_name_hash;
+use crate::stub_rules::StubRules;
+use crate::trace::{debug_event, trace_event};
+use crate::unwind_iterator::UnwindIterator;
+use crate::unwind_limits::UnwindLimits;
+use crate::unwind_mode::UnwindMode;
+use crate::unwind_regs::UnwindRegs;
 use crate::unwind_result::UnwindResult;
 use crate::unwind_rule::UnwindRule;
 use crate::FrameAddress;
@@ -18,7 +34,7 @@ use crate::FrameAddress;
 use std::marker::PhantomData;
 use std::sync::atomic::{AtomicU16, Ordering};
 use std::{
-    fmt::Debug,
+    fmt::{self, Debug},
     ops::{Deref, Range},
     sync::Arc,
 };
@@ -27,7 +43,7 @@ use std::{
 /// This trait's methods are what let you do the actual unwinding.
 pub trait Unwinder {
     /// The unwind registers type for the targeted CPU architecture.
-    type UnwindRegs;
+    type UnwindRegs: UnwindRegs;
 
     /// The unwind cache for the targeted CPU architecture.
     /// This is an associated type because the cache stores unwind rules, whose concrete
@@ -58,8 +74,87 @@ pub trait Unwinder {
     /// to make an educated guess at a pointer authentication mask for Aarch64 return addresses.
     fn max_known_code_address(&self) -> u64;
 
+    /// Returns whether `address` falls into the address range of one of the modules
+    /// that were added with `add_module`. The default implementation returns `false`.
+    fn is_known_code_address(&self, _address: u64) -> bool {
+        false
+    }
+
+    /// Returns the module that contains `address`, identified by the start of its
+    /// address range as in [`Unwinder::remove_module`], and `address` relative to the
+    /// module's base address. Returns `None` if `address` isn't in a known module.
+    /// The default implementation returns `None`.
+    fn module_relative_address(&self, _address: u64) -> Option<(u64, u32)> {
+        None
+    }
+
+    /// Returns the [id](Module::id) of the module that contains `address`, and
+    /// `address` relative to the module's base address. Unlike the start of the
+    /// module's address range, the id doesn't depend on where the module was loaded.
+    /// Returns `None` if `address` isn't in a known module. The default implementation
+    /// returns `None`.
+    fn module_id_and_relative_address(&self, _address: u64) -> Option<(u64, u32)> {
+        None
+    }
+
+    /// Returns whether `address` falls into one of the root address ranges, for example
+    /// the range of `_start` or `start_thread`. [`UnwindIterator`] ends the stack after
+    /// a frame in one of these ranges. Root ranges are added with the concrete unwinder's
+    /// `add_root_range` method. The default implementation returns `false`.
+    fn is_root_address(&self, _address: u64) -> bool {
+        false
+    }
+
+    /// Returns whether `address` falls into one of the stack switch ranges, for example
+    /// the range of `__morestack`. Unwinding a frame in one of these ranges moves to a
+    /// different stack, which may be at a lower address than the current one. Stack
+    /// switch ranges are added with the concrete unwinder's `add_stack_switch_range`
+    /// method. The default implementation returns `false`.
+    fn is_stack_switch_address(&self, _address: u64) -> bool {
+        false
+    }
+
+    /// Returns whether `address` falls into one of the async boundary ranges, for
+    /// example the range of an async runtime's task poll function. [`UnwindIterator`]
+    /// yields the logical frames of the suspended async tasks after a frame in one of
+    /// these ranges, see [`UnwindIterator::with_async_task_handler`]. Async boundary
+    /// ranges are added with the concrete unwinder's `add_async_boundary_range` method.
+    /// The default implementation returns `false`.
+    fn is_async_boundary_address(&self, _address: u64) -> bool {
+        false
+    }
+
+    /// Returns the address of the call instruction for a return address, using the
+    /// code bytes of the module if they were supplied. Without code bytes, or if the
+    /// instruction before the return address doesn't look like a call, this returns
+    /// [`FrameAddress::address_for_lookup`]. For instruction pointers, it returns the
+    /// address itself.
+    ///
+    /// This is useful for reporting accurate call sites, for example for source line
+    /// lookup. The default implementation has no code bytes, so it always returns
+    /// [`FrameAddress::address_for_lookup`].
+    fn call_site_address(&self, address: FrameAddress) -> u64 {
+        address.address_for_lookup()
+    }
+
+    /// Returns whether the instruction before a return address is a call instruction,
+    /// using the code bytes of the module. Returns `None` if this can't be checked,
+    /// because `address` is an instruction pointer or because there are no code bytes
+    /// for it.
+    ///
+    /// A return address which doesn't follow a call is a sign of a broken unwind, or
+    /// of a return-oriented programming attack. The default implementation returns
+    /// `None`.
+    fn is_preceded_by_call(&self, _address: FrameAddress) -> Option<bool> {
+        None
+    }
+
     /// Unwind a single frame, to recover return address and caller register values.
     /// This is the main entry point for unwinding.
+    ///
+    /// When unwinding through a signal trampoline, the returned address is the
+    /// instruction pointer of the interrupted code rather than a return address. Use
+    /// [`Unwinder::unwind_frame_with_confidence`] if you need to tell the two apart.
     fn unwind_frame<F>(
         &self,
         address: FrameAddress,
@@ -70,121 +165,130 @@ pub trait Unwinder {
     where
         F: FnMut(u64) -> Result<u64, ()>;
 
-    /// Return an iterator that unwinds frame by frame until the end of the stack is found.
-    fn iter_frames<'u, 'c, 'r, F>(
-        &'u self,
-        pc: u64,
-        regs: Self::UnwindRegs,
-        cache: &'c mut Self::Cache,
-        read_stack: &'r mut F,
-    ) -> UnwindIterator<'u, 'c, 'r, Self, F>
+    /// Like [`Unwinder::unwind_frame`], but also returns how the caller's address was
+    /// recovered.
+    ///
+    /// The caller's address is usually a [`FrameAddress::ReturnAddress`]. It is a
+    /// [`FrameAddress::InstructionPointer`] if unwinding went through a signal
+    /// trampoline and restored the context of the interrupted code: in that case, the
+    /// interrupted code did not make a call, so its address must not be adjusted for
+    /// lookup, and its frame must be unwound like a first frame.
+    ///
+    /// The default implementation calls [`Unwinder::unwind_frame`] and reports the
+    /// caller as an exact return address.
+    fn unwind_frame_with_confidence<F>(
+        &self,
+        address: FrameAddress,
+        regs: &mut Self::UnwindRegs,
+        cache: &mut Self::Cache,
+        read_stack: &mut F,
+    ) -> Result<Option<(FrameAddress, FrameConfidence)>, Error>
     where
         F: FnMut(u64) -> Result<u64, ()>,
     {
-        UnwindIterator::new(self, pc, regs, cache, read_stack)
+        match self.unwind_frame(address, regs, cache, read_stack)? {
+            Some(return_address) => match FrameAddress::from_return_address(return_address) {
+                Some(caller) => Ok(Some((caller, FrameConfidence::Exact))),
+                None => Err(Error::ReturnAddressIsNull),
+            },
+            None => Ok(None),
+        }
     }
-}
 
-/// An iterator for unwinding the entire stack, starting from the initial register values.
-///
-/// The first yielded frame is the instruction pointer. Subsequent addresses are return
-/// addresses.
-///
-/// This iterator attempts to detect if stack unwinding completed successfully, or if the
-/// stack was truncated prematurely. If it thinks that it successfully found the root
-/// function, it will complete with `Ok(None)`, otherwise it will complete with `Err(...)`.
-/// However, the detection does not work in all cases, so you should expect `Err(...)` to
-/// be returned even during normal operation. As a result, it is not recommended to use
-/// this iterator as a `FallibleIterator`, because you might lose the entire stack if the
-/// last iteration returns `Err(...)`.
-///
-/// Lifetimes:
-///
-///  - `'u`: The lifetime of the [`Unwinder`].
-///  - `'c`: The lifetime of the unwinder cache.
-///  - `'r`: The lifetime of the exclusive access to the `read_stack` callback.
-pub struct UnwindIterator<'u, 'c, 'r, U: Unwinder + ?Sized, F: FnMut(u64) -> Result<u64, ()>> {
-    unwinder: &'u U,
-    state: UnwindIteratorState,
-    regs: U::UnwindRegs,
-    cache: &'c mut U::Cache,
-    read_stack: &'r mut F,
-}
+    /// Like [`Unwinder::unwind_frame_with_confidence`], but also records how the frame
+    /// was unwound in `provenance`. This is slower, because it formats the executed
+    /// rule. The default implementation leaves `provenance` unchanged.
+    fn unwind_frame_with_provenance<F>(
+        &self,
+        address: FrameAddress,
+        regs: &mut Self::UnwindRegs,
+        cache: &mut Self::Cache,
+        read_stack: &mut F,
+        _provenance: &mut FrameProvenance,
+    ) -> Result<Option<(FrameAddress, FrameConfidence)>, Error>
+    where
+        F: FnMut(u64) -> Result<u64, ()>,
+    {
+        self.unwind_frame_with_confidence(address, regs, cache, read_stack)
+    }
 
-enum UnwindIteratorState {
-    Initial(u64),
-    Unwinding(FrameAddress),
-    Done,
-}
+    /// Unwind exactly one frame and return the caller's address and registers, without
+    /// changing `regs`. This is what a debugger needs to step out of a function, or to
+    /// evaluate expressions in the caller's context.
+    ///
+    /// The returned registers are the unwind registers, which are all the registers
+    /// that framehop recovers: the stack pointer, the frame pointer, and the
+    /// instruction pointer or link register. Returns `Ok(None)` if `address` is the
+    /// last frame of the stack.
+    fn step_out<F>(
+        &self,
+        address: FrameAddress,
+        regs: &Self::UnwindRegs,
+        cache: &mut Self::Cache,
+        read_stack: &mut F,
+    ) -> Result<Option<(FrameAddress, Self::UnwindRegs)>, Error>
+    where
+        F: FnMut(u64) -> Result<u64, ()>,
+    {
+        let mut caller_regs = *regs;
+        let caller =
+            self.unwind_frame_with_confidence(address, &mut caller_regs, cache, read_stack)?;
+        Ok(caller.map(|(caller_address, _confidence)| (caller_address, caller_regs)))
+    }
 
-impl<'u, 'c, 'r, U: Unwinder + ?Sized, F: FnMut(u64) -> Result<u64, ()>>
-    UnwindIterator<'u, 'c, 'r, U, F>
-{
-    /// Create a new iterator. You'd usually use [`Unwinder::iter_frames`] instead.
-    pub fn new(
-        unwinder: &'u U,
-        pc: u64,
-        regs: U::UnwindRegs,
-        cache: &'c mut U::Cache,
-        read_stack: &'r mut F,
-    ) -> Self {
-        Self {
-            unwinder,
-            state: UnwindIteratorState::Initial(pc),
-            regs,
-            cache,
-            read_stack,
-        }
+    /// Unwind a single frame with its frame record, allowing the stack pointer to move
+    /// to a lower address. This is how frames in the stack switch ranges are unwound
+    /// (see [`Unwinder::is_stack_switch_address`]), and how [`UnwindIterator`]
+    /// continues onto auxiliary stacks (see [`UnwindIterator::with_auxiliary_stack`]).
+    /// The default implementation can't follow a stack switch and returns `Ok(None)`.
+    fn unwind_frame_across_stack_switch<F>(
+        &self,
+        _address: FrameAddress,
+        _regs: &mut Self::UnwindRegs,
+        _read_stack: &mut F,
+    ) -> Result<Option<(FrameAddress, FrameConfidence)>, Error>
+    where
+        F: FnMut(u64) -> Result<u64, ()>,
+    {
+        Ok(None)
     }
-}
 
-impl<'u, 'c, 'r, U: Unwinder + ?Sized, F: FnMut(u64) -> Result<u64, ()>>
-    UnwindIterator<'u, 'c, 'r, U, F>
-{
-    /// Yield the next frame in the stack.
+    /// Unwind a single frame both with the module's unwind information and with frame
+    /// pointer unwinding, and compare the results. `regs` is not modified.
     ///
-    /// The first frame is `Ok(Some(FrameAddress::InstructionPointer(...)))`.
-    /// Subsequent frames are `Ok(Some(FrameAddress::ReturnAddress(...)))`.
+    /// Returns `None` if both methods agree, or if one of them can't be used for this
+    /// address, for example because the address isn't covered by unwind information or
+    /// because one of the methods fails. This bypasses the cache, so it is slower than
+    /// [`Unwinder::unwind_frame`].
     ///
-    /// If a root function has been reached, this iterator completes with `Ok(None)`.
-    /// Otherwise it completes with `Err(...)`, usually indicating that a certain stack
-    /// address could not be read.
-    #[allow(clippy::should_implement_trait)]
-    pub fn next(&mut self) -> Result<Option<FrameAddress>, Error> {
-        let next = match self.state {
-            UnwindIteratorState::Initial(pc) => {
-                self.state = UnwindIteratorState::Unwinding(FrameAddress::InstructionPointer(pc));
-                return Ok(Some(FrameAddress::InstructionPointer(pc)));
-            }
-            UnwindIteratorState::Unwinding(address) => {
-                self.unwinder
-                    .unwind_frame(address, &mut self.regs, self.cache, self.read_stack)?
-            }
-            UnwindIteratorState::Done => return Ok(None),
-        };
-        match next {
-            Some(return_address) => {
-                let return_address = FrameAddress::from_return_address(return_address)
-                    .ok_or(Error::ReturnAddressIsNull)?;
-                self.state = UnwindIteratorState::Unwinding(return_address);
-                Ok(Some(return_address))
-            }
-            None => {
-                self.state = UnwindIteratorState::Done;
-                Ok(None)
-            }
-        }
+    /// Divergences are only meaningful for functions that maintain a frame pointer;
+    /// frameless functions will always diverge. The default implementation returns
+    /// `None`.
+    fn check_frame_divergence<F>(
+        &self,
+        _address: FrameAddress,
+        _regs: &Self::UnwindRegs,
+        _cache: &mut Self::Cache,
+        _read_stack: &mut F,
+    ) -> Option<FrameDivergence>
+    where
+        F: FnMut(u64) -> Result<u64, ()>,
+    {
+        None
     }
-}
-
-impl<'u, 'c, 'r, U: Unwinder + ?Sized, F: FnMut(u64) -> Result<u64, ()>> FallibleIterator
-    for UnwindIterator<'u, 'c, 'r, U, F>
-{
-    type Item = FrameAddress;
-    type Error = Error;
 
-    fn next(&mut self) -> Result<Option<FrameAddress>, Error> {
-        self.next()
+    /// Return an iterator that unwinds frame by frame until the end of the stack is found.
+    fn iter_frames<'u, 'c, 'r, F>(
+        &'u self,
+        pc: u64,
+        regs: Self::UnwindRegs,
+        cache: &'c mut Self::Cache,
+        read_stack: &'r mut F,
+    ) -> UnwindIterator<'u, 'c, 'r, Self, F>
+    where
+        F: FnMut(u64) -> Result<u64, ()>,
+    {
+        UnwindIterator::new(self, pc, regs, cache, read_stack)
     }
 }
 
@@ -200,22 +304,49 @@ fn next_global_modules_generation() -> u16 {
     GLOBAL_MODULES_GENERATION.fetch_add(1, Ordering::Relaxed)
 }
 
+type ModuleEventSubscriber = Box<dyn FnMut(&ModuleEvent) + Send + Sync>;
+
 pub struct UnwinderInternal<
     D: Deref<Target = [u8]>,
-    A: Arch + DwarfUnwinding + CompactUnwindInfoUnwinding + InstructionAnalysis,
+    A: Arch + DwarfUnwinding + CompactUnwindInfoUnwinding + FpoUnwinding + InstructionAnalysis,
     P: AllocationPolicy<D>,
 > {
     /// sorted by avma_range.start
     modules: Vec<Module<D>>,
     /// Incremented every time modules is changed.
     modules_generation: u16,
+    /// Address ranges of functions at which the stack ends.
+    root_ranges: RangeMap<()>,
+    /// Address ranges of functions which switch to a different stack.
+    stack_switch_ranges: RangeMap<()>,
+    /// Address ranges of functions at which async runtimes poll their tasks.
+    async_boundary_ranges: RangeMap<()>,
+    /// Address ranges of PLT sections.
+    plt_ranges: RangeMap<()>,
+    /// Address ranges of the dynamic linker's lazy binding trampolines.
+    dl_trampoline_ranges: RangeMap<()>,
+    /// Stub rules which override the architecture's defaults, keyed by the start of
+    /// the module's address range.
+    module_stub_rules: Vec<(u64, StubRules<A::UnwindRule>)>,
+    /// Address ranges which map the code of a module a second time, with the start of
+    /// that module's address range.
+    module_aliases: RangeMap<u64>,
+    /// Callbacks which are notified of changes to the modules.
+    module_event_subscribers: Vec<(ModuleEventSubscription, ModuleEventSubscriber)>,
+    next_module_event_subscription: u64,
+    /// Whether return addresses are looked up at the start of the call instruction.
+    instruction_aware_lookup: bool,
+    /// Whether the fallback rule may use the frame pointer in the first frame.
+    trust_first_frame_pointer: bool,
+    limits: UnwindLimits,
+    mode: UnwindMode,
     _arch: PhantomData<A>,
     _allocation_policy: PhantomData<P>,
 }
 
 impl<
         D: Deref<Target = [u8]>,
-        A: Arch + DwarfUnwinding + CompactUnwindInfoUnwinding + InstructionAnalysis,
+        A: Arch + DwarfUnwinding + CompactUnwindInfoUnwinding + FpoUnwinding + InstructionAnalysis,
         P: AllocationPolicy<D>,
     > Default for UnwinderInternal<D, A, P>
 {
@@ -226,7 +357,7 @@ impl<
 
 impl<
         D: Deref<Target = [u8]>,
-        A: Arch + DwarfUnwinding + CompactUnwindInfoUnwinding + InstructionAnalysis,
+        A: Arch + DwarfUnwinding + CompactUnwindInfoUnwinding + FpoUnwinding + InstructionAnalysis,
         P: AllocationPolicy<D>,
     > UnwinderInternal<D, A, P>
 {
@@ -234,12 +365,35 @@ impl<
         Self {
             modules: Vec::new(),
             modules_generation: next_global_modules_generation(),
+            root_ranges: RangeMap::new(),
+            stack_switch_ranges: RangeMap::new(),
+            async_boundary_ranges: RangeMap::new(),
+            plt_ranges: RangeMap::new(),
+            dl_trampoline_ranges: RangeMap::new(),
+            module_stub_rules: Vec::new(),
+            module_aliases: RangeMap::new(),
+            module_event_subscribers: Vec::new(),
+            next_module_event_subscription: 0,
+            instruction_aware_lookup: false,
+            trust_first_frame_pointer: true,
+            limits: UnwindLimits::default(),
+            mode: UnwindMode::default(),
             _arch: PhantomData,
             _allocation_policy: PhantomData,
         }
     }
 
     pub fn add_module(&mut self, module: Module<D>) {
+        let event = ModuleEvent::Added {
+            name: module.name.clone(),
+            avma_range: module.avma_range.clone(),
+        };
+        self.insert_module(module);
+        self.modules_generation = next_global_modules_generation();
+        self.notify_module_event(event);
+    }
+
+    fn insert_module(&mut self, module: Module<D>) {
         let insertion_index = match self
             .modules
             .binary_search_by_key(&module.avma_range.start, |module| module.avma_range.start)
@@ -254,7 +408,6 @@ impl<
             Err(i) => i,
         };
         self.modules.insert(insertion_index, module);
-        self.modules_generation = next_global_modules_generation();
     }
 
     pub fn remove_module(&mut self, module_address_range_start: u64) {
@@ -264,16 +417,446 @@ impl<
                 module.avma_range.start
             })
         {
-            self.modules.remove(index);
+            let module = self.modules.remove(index);
+            self.module_stub_rules
+                .retain(|(start, _)| *start != module_address_range_start);
+            self.module_aliases
+                .retain(|_, start| *start != module_address_range_start);
             self.modules_generation = next_global_modules_generation();
+            self.notify_module_event(ModuleEvent::Removed {
+                avma_range: module.avma_range,
+            });
+        };
+    }
+
+    pub fn rebase_module(&mut self, module_address_range_start: u64, new_avma_range_start: u64) {
+        let index = match self
+            .modules
+            .binary_search_by_key(&module_address_range_start, |module| {
+                module.avma_range.start
+            }) {
+            Ok(index) => index,
+            Err(_) => return,
         };
+        let mut module = self.modules.remove(index);
+        let delta = new_avma_range_start.wrapping_sub(module_address_range_start);
+        let old_avma_range = module.avma_range.clone();
+        module.avma_range = new_avma_range_start..module.avma_range.end.wrapping_add(delta);
+        module.base_avma = module.base_avma.wrapping_add(delta);
+        if let Some(text_data) = &mut module.text_data {
+            text_data.avma_range = text_data.avma_range.start.wrapping_add(delta)
+                ..text_data.avma_range.end.wrapping_add(delta);
+        }
+        let new_avma_range = module.avma_range.clone();
+        for (start, _) in &mut self.module_stub_rules {
+            if *start == module_address_range_start {
+                *start = new_avma_range_start;
+            }
+        }
+        for start in self.module_aliases.values_mut() {
+            if *start == module_address_range_start {
+                *start = new_avma_range_start;
+            }
+        }
+        self.insert_module(module);
+        self.modules_generation = next_global_modules_generation();
+        self.notify_module_event(ModuleEvent::Rebased {
+            old_avma_range,
+            new_avma_range,
+        });
+    }
+
+    pub fn add_module_alias(&mut self, alias_avma_range: Range<u64>, module_avma_range_start: u64) {
+        self.module_aliases
+            .insert(alias_avma_range.clone(), module_avma_range_start);
+        // The cache may hold fallback rules for addresses in the alias range.
+        self.modules_generation = next_global_modules_generation();
+        self.notify_module_event(ModuleEvent::AliasAdded {
+            alias_avma_range,
+            module_avma_range_start,
+        });
+    }
+
+    pub fn remove_module_alias(&mut self, alias_avma_range_start: u64) {
+        let (alias_avma_range, _) = match self.module_aliases.remove(alias_avma_range_start) {
+            Some(alias) => alias,
+            None => return,
+        };
+        self.modules_generation = next_global_modules_generation();
+        self.notify_module_event(ModuleEvent::AliasRemoved { alias_avma_range });
+    }
+
+    pub fn subscribe_module_events<F>(&mut self, subscriber: F) -> ModuleEventSubscription
+    where
+        F: FnMut(&ModuleEvent) + Send + Sync + 'static,
+    {
+        let subscription = ModuleEventSubscription(self.next_module_event_subscription);
+        self.next_module_event_subscription += 1;
+        self.module_event_subscribers
+            .push((subscription, Box::new(subscriber)));
+        subscription
+    }
+
+    pub fn unsubscribe_module_events(&mut self, subscription: ModuleEventSubscription) {
+        self.module_event_subscribers
+            .retain(|(s, _)| *s != subscription);
+    }
+
+    fn notify_module_event(&mut self, event: ModuleEvent) {
+        for (_, subscriber) in &mut self.module_event_subscribers {
+            subscriber(&event);
+        }
+    }
+
+    /// The address in the module's own address range for an address in an alias range,
+    /// and `address` itself otherwise.
+    fn canonical_address(&self, address: u64) -> u64 {
+        match self.module_aliases.get(address) {
+            Some((range, module_start)) => module_start.wrapping_add(address - range.start),
+            None => address,
+        }
+    }
+
+    fn canonical_frame_address(&self, address: FrameAddress) -> FrameAddress {
+        match address {
+            FrameAddress::InstructionPointer(ip) => {
+                FrameAddress::InstructionPointer(self.canonical_address(ip))
+            }
+            FrameAddress::ReturnAddress(return_address) => {
+                FrameAddress::from_return_address(self.canonical_address(return_address.into()))
+                    .unwrap_or(address)
+            }
+        }
+    }
+
+    pub fn set_module_text_data(
+        &mut self,
+        module_address_range_start: u64,
+        text_data: Option<TextByteData<D>>,
+    ) {
+        if let Ok(index) = self
+            .modules
+            .binary_search_by_key(&module_address_range_start, |module| {
+                module.avma_range.start
+            })
+        {
+            self.modules[index].text_data = text_data;
+            // Cached rules may have been found without instruction analysis.
+            self.modules_generation = next_global_modules_generation();
+            let avma_range = self.modules[index].avma_range.clone();
+            self.notify_module_event(ModuleEvent::CodeChanged { avma_range });
+        };
+    }
+
+    pub fn set_module_stub_rules(
+        &mut self,
+        module_address_range_start: u64,
+        stub_rules: Option<StubRules<A::UnwindRule>>,
+    ) {
+        let avma_range = match self
+            .modules
+            .binary_search_by_key(&module_address_range_start, |module| {
+                module.avma_range.start
+            }) {
+            Ok(index) => self.modules[index].avma_range.clone(),
+            Err(_) => return,
+        };
+        self.module_stub_rules
+            .retain(|(start, _)| *start != module_address_range_start);
+        if let Some(stub_rules) = stub_rules {
+            self.module_stub_rules
+                .push((module_address_range_start, stub_rules));
+        }
+        self.modules_generation = next_global_modules_generation();
+        self.notify_module_event(ModuleEvent::CodeChanged { avma_range });
+    }
+
+    fn stub_rules_for_module(&self, module: &Module<D>) -> StubRules<A::UnwindRule> {
+        self.module_stub_rules
+            .iter()
+            .find(|(start, _)| *start == module.avma_range.start)
+            .map_or_else(StubRules::arch_default, |(_, stub_rules)| *stub_rules)
+    }
+
+    pub fn load_module_unwind_data(&mut self, module_address_range_start: u64) {
+        if let Ok(index) = self
+            .modules
+            .binary_search_by_key(&module_address_range_start, |module| {
+                module.avma_range.start
+            })
+        {
+            if self.modules[index].load_unwind_data() {
+                // The cache may hold frame pointer rules from before the data was loaded.
+                self.modules_generation = next_global_modules_generation();
+            }
+        };
+    }
+
+    pub fn unload_module_unwind_data(&mut self, module_address_range_start: u64) {
+        if let Ok(index) = self
+            .modules
+            .binary_search_by_key(&module_address_range_start, |module| {
+                module.avma_range.start
+            })
+        {
+            if let ModuleUnwindDataInternal::Lazy(lazy) = &mut self.modules[index].unwind_data {
+                if lazy.loaded.take().is_some() {
+                    // The cache holds rules from the unloaded data, which the module
+                    // doesn't have anymore.
+                    self.modules_generation = next_global_modules_generation();
+                }
+            }
+        };
+    }
+
+    pub fn add_root_range(&mut self, avma_range: Range<u64>) {
+        self.root_ranges.insert(avma_range, ());
+    }
+
+    pub fn remove_root_range(&mut self, avma_range_start: u64) {
+        self.root_ranges.remove(avma_range_start);
+    }
+
+    pub fn is_root_address(&self, address: u64) -> bool {
+        self.root_ranges.contains(address)
+    }
+
+    pub fn add_stack_switch_range(&mut self, avma_range: Range<u64>) {
+        self.stack_switch_ranges.insert(avma_range, ());
+    }
+
+    pub fn remove_stack_switch_range(&mut self, avma_range_start: u64) {
+        self.stack_switch_ranges.remove(avma_range_start);
+    }
+
+    pub fn is_stack_switch_address(&self, address: u64) -> bool {
+        self.stack_switch_ranges.contains(address)
+    }
+
+    pub fn add_async_boundary_range(&mut self, avma_range: Range<u64>) {
+        self.async_boundary_ranges.insert(avma_range, ());
+    }
+
+    pub fn remove_async_boundary_range(&mut self, avma_range_start: u64) {
+        self.async_boundary_ranges.remove(avma_range_start);
+    }
+
+    pub fn is_async_boundary_address(&self, address: u64) -> bool {
+        self.async_boundary_ranges.contains(address)
+    }
+
+    pub fn add_plt_range(&mut self, avma_range: Range<u64>) {
+        self.plt_ranges.insert(avma_range, ());
+    }
+
+    pub fn remove_plt_range(&mut self, avma_range_start: u64) {
+        self.plt_ranges.remove(avma_range_start);
+    }
+
+    /// The rule for an address in a PLT range, from the structure of the PLT stubs if
+    /// the module's code bytes cover the range, and the stub rule otherwise.
+    fn plt_rule(&self, address: u64) -> Option<A::UnwindRule> {
+        let (plt_range, _) = self.plt_ranges.get(address)?;
+        let module = self
+            .find_module_for_address(address)
+            .map(|(module_index, _)| &self.modules[module_index]);
+        let plt_bytes = module.and_then(|module| {
+            let text_data = module.text_data.as_ref()?;
+            let start = plt_range.start.checked_sub(text_data.avma_range.start)?;
+            let end = plt_range.end.min(text_data.avma_range.end);
+            let end = end.checked_sub(text_data.avma_range.start)?;
+            text_data
+                .bytes
+                .get(usize::try_from(start).ok()?..usize::try_from(end).ok()?)
+        });
+        let rule = plt_bytes.and_then(|plt_bytes| {
+            let pc_offset = usize::try_from(address - plt_range.start).ok()?;
+            if pc_offset > plt_bytes.len() {
+                return None;
+            }
+            A::rule_from_plt_analysis(plt_bytes, pc_offset)
+        });
+        let stub_rules = match module {
+            Some(module) => self.stub_rules_for_module(module),
+            None => StubRules::arch_default(),
+        };
+        Some(rule.unwrap_or(stub_rules.stub_functions))
+    }
+
+    pub fn add_dl_trampoline_range(&mut self, avma_range: Range<u64>) {
+        self.dl_trampoline_ranges.insert(avma_range, ());
+    }
+
+    pub fn remove_dl_trampoline_range(&mut self, avma_range_start: u64) {
+        self.dl_trampoline_ranges.remove(avma_range_start);
+    }
+
+    /// The rule for an address in a lazy binding trampoline of the dynamic linker,
+    /// from the trampoline's code bytes. Returns `None` if the address isn't in a
+    /// trampoline range, if the module has no code bytes for it, or if the stack
+    /// offset at the address can't be derived from the code.
+    fn dl_trampoline_rule(&self, address: FrameAddress) -> Option<A::UnwindRule> {
+        // The return address after the call to the resolver is decoded as is, so
+        // that it is on an instruction boundary.
+        let lookup_address = address.address_for_lookup();
+        let (range, _) = self.dl_trampoline_ranges.get(lookup_address)?;
+        let (module_index, _) = self.find_module_for_address(lookup_address)?;
+        let text_data = self.modules[module_index].text_data.as_ref()?;
+        let start = range.start.checked_sub(text_data.avma_range.start)?;
+        let end = range.end.min(text_data.avma_range.end);
+        let end = end.checked_sub(text_data.avma_range.start)?;
+        let function_bytes = text_data
+            .bytes
+            .get(usize::try_from(start).ok()?..usize::try_from(end).ok()?)?;
+        let pc_offset = usize::try_from(address.address() - range.start).ok()?;
+        if pc_offset > function_bytes.len() {
+            return None;
+        }
+        A::rule_from_dl_trampoline_analysis(function_bytes, pc_offset)
+    }
+
+    pub fn set_limits(&mut self, limits: UnwindLimits) {
+        self.limits = limits;
+        // The cache may hold fallback rules which were picked because a limit was hit.
+        self.modules_generation = next_global_modules_generation();
+    }
+
+    pub fn set_mode(&mut self, mode: UnwindMode) {
+        self.mode = mode;
+        // The cache may hold fallback rules which were picked in lenient mode.
+        self.modules_generation = next_global_modules_generation();
+    }
+
+    pub fn set_trust_first_frame_pointer(&mut self, trust_first_frame_pointer: bool) {
+        self.trust_first_frame_pointer = trust_first_frame_pointer;
+        // The cache may hold fallback rules, which can't be told apart from frame
+        // pointer rules from the unwind information.
+        self.modules_generation = next_global_modules_generation();
+    }
+
+    pub fn set_instruction_aware_lookup(&mut self, instruction_aware_lookup: bool) {
+        if self.instruction_aware_lookup != instruction_aware_lookup {
+            self.instruction_aware_lookup = instruction_aware_lookup;
+            // The cache may hold rules which were looked up at the other address.
+            self.modules_generation = next_global_modules_generation();
+        }
+    }
+
+    pub fn call_site_address(&self, address: FrameAddress) -> u64 {
+        self.call_instruction_start(address)
+            .unwrap_or_else(|| address.address_for_lookup())
+    }
+
+    pub fn is_preceded_by_call(&self, address: FrameAddress) -> Option<bool> {
+        let (text_bytes, return_address_offset) = self.text_bytes_before(address)?;
+        Some(A::call_instruction_len(text_bytes, return_address_offset).is_some())
+    }
+
+    /// The address at which to look up unwind information for `address`.
+    fn lookup_address(&self, address: FrameAddress) -> u64 {
+        if self.instruction_aware_lookup {
+            self.call_site_address(address)
+        } else {
+            address.address_for_lookup()
+        }
+    }
+
+    fn call_instruction_start(&self, address: FrameAddress) -> Option<u64> {
+        let (text_bytes, return_address_offset) = self.text_bytes_before(address)?;
+        let call_len = A::call_instruction_len(text_bytes, return_address_offset)?;
+        Some(address.address() - call_len as u64)
+    }
+
+    /// The code bytes of the module containing the return address `address`, and the
+    /// offset of the return address in them.
+    fn text_bytes_before(&self, address: FrameAddress) -> Option<(&[u8], usize)> {
+        let return_address = match address {
+            FrameAddress::ReturnAddress(return_address) => u64::from(return_address),
+            FrameAddress::InstructionPointer(_) => return None,
+        };
+        let (module_index, _) = self.find_module_for_address(address.address_for_lookup())?;
+        let text_data = self.modules[module_index].text_data.as_ref()?;
+        let offset = self
+            .canonical_address(return_address)
+            .checked_sub(text_data.avma_range.start)?;
+        let offset = usize::try_from(offset).ok()?;
+        if offset > text_data.bytes.len() {
+            return None;
+        }
+        Some((&text_data.bytes[..], offset))
     }
 
     pub fn max_known_code_address(&self) -> u64 {
         self.modules.last().map_or(0, |m| m.avma_range.end)
     }
 
+    pub fn is_known_code_address(&self, address: u64) -> bool {
+        self.find_module_for_address(address).is_some()
+    }
+
+    pub fn module_relative_address(&self, address: u64) -> Option<(u64, u32)> {
+        let (module_index, relative_address) = self.find_module_for_address(address)?;
+        Some((
+            self.modules[module_index].avma_range.start,
+            relative_address,
+        ))
+    }
+
+    pub fn module_id_and_relative_address(&self, address: u64) -> Option<(u64, u32)> {
+        let (module_index, relative_address) = self.find_module_for_address(address)?;
+        Some((self.modules[module_index].id, relative_address))
+    }
+
+    /// `address` as `name+0xrelative`, e.g. `libxul.so+0x1234`, if it is in a known
+    /// module with a name, and as the plain hex address otherwise. For log messages.
+    pub fn describe_address(&self, address: u64) -> String {
+        match self.find_module_for_address(address) {
+            Some((module_index, relative_address))
+                if !self.modules[module_index].name.is_empty() =>
+            {
+                format!(
+                    "{}+0x{:x}",
+                    self.modules[module_index].name, relative_address
+                )
+            }
+            _ => format!("0x{address:x}"),
+        }
+    }
+
+    /// The message of `error`, with the code address it refers to, if any, described
+    /// with [`describe_address`](Self::describe_address). For log messages, e.g.
+    /// "The unwind information is unusable: ... at libxul.so+0x1234".
+    pub fn describe_error(&self, error: &Error) -> String {
+        match error {
+            Error::UnusableUnwindInfo(module_error) => format!(
+                "The unwind information is unusable: {} at {}",
+                module_error.error,
+                self.describe_address(module_error.address)
+            ),
+            Error::NeedsUnknownRegister(address) => format!(
+                "Unwinding the frame at {} needs a register that was not supplied",
+                self.describe_address(*address)
+            ),
+            Error::UnwindingCycle(address) => format!(
+                "Unwinding returned to an earlier frame with return address {}, would loop",
+                self.describe_address(*address)
+            ),
+            error => error.to_string(),
+        }
+    }
+
+    /// The name of the module that contains `address`, for [`FrameProvenance`].
+    fn module_name(&self, address: u64) -> Option<String> {
+        let (module_index, _) = self.find_module_for_address(address)?;
+        let name = &self.modules[module_index].name;
+        if name.is_empty() {
+            return None;
+        }
+        Some(name.clone())
+    }
+
     fn find_module_for_address(&self, address: u64) -> Option<(usize, u32)> {
+        let address = self.canonical_address(address);
         let (module_index, module) = match self
             .modules
             .binary_search_by_key(&address, |m| m.avma_range.start)
@@ -290,88 +873,721 @@ impl<
                     // address is after this module
                     return None;
                 }
-                (i, module)
+                (i, module)
+            }
+        };
+        if address < module.base_avma {
+            // Invalid base address
+            return None;
+        }
+        let relative_address = u32::try_from(address - module.base_avma).ok()?;
+        Some((module_index, relative_address))
+    }
+
+    fn with_cache<F, G>(
+        &self,
+        address: FrameAddress,
+        regs: &mut A::UnwindRegs,
+        cache: &mut Cache<D, A::UnwindRule, P>,
+        read_stack: &mut F,
+        provenance: Option<&mut FrameProvenance>,
+        callback: G,
+    ) -> Result<Option<(FrameAddress, FrameConfidence)>, Error>
+    where
+        F: FnMut(u64) -> Result<u64, ()>,
+        G: FnOnce(
+            &Module<D>,
+            FrameAddress,
+            u32,
+            &mut A::UnwindRegs,
+            &mut Cache<D, A::UnwindRule, P>,
+            &mut F,
+            &UnwindLimits,
+            StubRules<A::UnwindRule>,
+        ) -> Result<UnwindResult<A::UnwindRule>, UnwinderError>,
+    {
+        let address = self.canonical_frame_address(address);
+        let lookup_address = self.lookup_address(address);
+        let is_first_frame = !address.is_return_address();
+        // Stack switch ranges can change without a new modules generation, so they
+        // are checked before the cache.
+        if self.is_stack_switch_address(lookup_address) {
+            trace_event!(address = ?HexNum(lookup_address), "unwinding across stack switch");
+            if let Some(provenance) = provenance {
+                *provenance = FrameProvenance {
+                    module: self.module_name(lookup_address),
+                    ..FrameProvenance::new(FrameSource::StackSwitch)
+                };
+            }
+            return self.unwind_frame_across_stack_switch(address, regs, read_stack);
+        }
+        // The same goes for PLT ranges.
+        if let Some(unwind_rule) = self.plt_rule(lookup_address) {
+            trace_event!(address = ?HexNum(lookup_address), rule = %unwind_rule, "in PLT range");
+            if let Some(provenance) = provenance {
+                *provenance = FrameProvenance {
+                    module: self.module_name(lookup_address),
+                    rule: Some(unwind_rule.to_string()),
+                    ..FrameProvenance::new(FrameSource::PltStub)
+                };
+            }
+            return Self::exec_rule(unwind_rule, is_first_frame, regs, read_stack);
+        }
+        // And for dynamic linker trampolines.
+        if let Some(unwind_rule) = self.dl_trampoline_rule(address) {
+            trace_event!(
+                address = ?HexNum(lookup_address),
+                rule = %unwind_rule,
+                "in dynamic linker trampoline"
+            );
+            if let Some(provenance) = provenance {
+                *provenance = FrameProvenance {
+                    module: self.module_name(lookup_address),
+                    rule: Some(unwind_rule.to_string()),
+                    ..FrameProvenance::new(FrameSource::DynamicLinkerTrampoline)
+                };
+            }
+            return Self::exec_rule(unwind_rule, is_first_frame, regs, read_stack);
+        }
+        let cache_handle = match cache
+            .rule_cache
+            .lookup(lookup_address, self.modules_generation)
+        {
+            CacheResult::Hit(unwind_rule) => {
+                trace_event!(
+                    address = ?HexNum(lookup_address),
+                    rule = %unwind_rule,
+                    "rule cache hit"
+                );
+                if let Some(provenance) = provenance {
+                    *provenance = FrameProvenance {
+                        source: self.frame_source(lookup_address, unwind_rule),
+                        module: self.module_name(lookup_address),
+                        rule: Some(unwind_rule.to_string()),
+                        from_cache: true,
+                        fallback: None,
+                    };
+                }
+                return Self::exec_rule(unwind_rule, is_first_frame, regs, read_stack);
+            }
+            CacheResult::Miss(handle) => handle,
+        };
+        trace_event!(address = ?HexNum(lookup_address), "rule cache miss");
+
+        let (unwind_rule, fallback) = match self.find_module_for_address(lookup_address) {
+            None => {
+                debug_event!(
+                    address = ?HexNum(lookup_address),
+                    "no module for address, using fallback rule"
+                );
+                (
+                    A::UnwindRule::fallback_rule(),
+                    Some(FallbackReason::NoModule),
+                )
+            }
+            Some((module_index, relative_lookup_address)) => {
+                let module = &self.modules[module_index];
+                trace_event!(
+                    module = %module.name,
+                    relative_address = ?HexNum(relative_lookup_address),
+                    "found module"
+                );
+                match callback(
+                    module,
+                    address,
+                    relative_lookup_address,
+                    regs,
+                    cache,
+                    read_stack,
+                    &self.limits,
+                    self.stub_rules_for_module(module),
+                ) {
+                    Ok(UnwindResult::ExecRule(rule)) => (rule, None),
+                    Ok(UnwindResult::Uncacheable(return_address)) => {
+                        if let Some(provenance) = provenance {
+                            *provenance = FrameProvenance {
+                                module: self.module_name(lookup_address),
+                                ..FrameProvenance::new(module.unwind_data().frame_source())
+                            };
+                        }
+                        let return_address = FrameAddress::from_return_address(return_address)
+                            .ok_or(Error::ReturnAddressIsNull)?;
+                        return Ok(Some((return_address, FrameConfidence::Exact)));
+                    }
+                    Err(UnwinderError::NoModuleUnwindData) => {
+                        debug_event!(
+                            module = %module.name,
+                            "module has no unwind data, using fallback rule"
+                        );
+                        (
+                            A::UnwindRule::fallback_rule(),
+                            Some(FallbackReason::NoUnwindData),
+                        )
+                    }
+                    Err(error) if self.mode == UnwindMode::Strict => {
+                        debug_event!(module = %module.name, error = %error, "unusable unwind info");
+                        return Err(Error::UnusableUnwindInfo(ModuleError {
+                            address: lookup_address,
+                            module_avma_range_start: module.avma_range.start,
+                            relative_address: relative_lookup_address,
+                            error,
+                        }));
+                    }
+                    Err(error) => {
+                        debug_event!(
+                            module = %module.name,
+                            error = %error,
+                            "unusable unwind info, using fallback rule"
+                        );
+                        (
+                            A::UnwindRule::fallback_rule(),
+                            Some(FallbackReason::UnusableUnwindInfo(error)),
+                        )
+                    }
+                }
+            }
+        };
+        if fallback.is_some() && !self.trust_first_frame_pointer {
+            // Fallback rules aren't cached, so that frame pointer rules from the cache
+            // are always ones from the unwind information.
+            if is_first_frame {
+                debug_event!("not trusting the frame pointer in the first frame");
+                return Err(Error::UntrustedFramePointer);
+            }
+        } else {
+            trace_event!(rule = %unwind_rule, "caching rule");
+            cache.rule_cache.insert(cache_handle, unwind_rule);
+        }
+        if let Some(provenance) = provenance {
+            *provenance = FrameProvenance {
+                source: self.frame_source(lookup_address, unwind_rule),
+                module: self.module_name(lookup_address),
+                rule: Some(unwind_rule.to_string()),
+                from_cache: false,
+                fallback,
+            };
+        }
+        Self::exec_rule(unwind_rule, is_first_frame, regs, read_stack)
+    }
+
+    /// Where a rule for `lookup_address` came from. Rules from the cache don't remember
+    /// this, so it is inferred from the rule and the module.
+    fn frame_source(&self, lookup_address: u64, unwind_rule: A::UnwindRule) -> FrameSource {
+        if unwind_rule.resumes_interrupted_code() {
+            return FrameSource::SignalTrampoline;
+        }
+        if unwind_rule == A::UnwindRule::fallback_rule() {
+            return FrameSource::FramePointer;
+        }
+        match self.find_module_for_address(lookup_address) {
+            Some((module_index, _)) => self.modules[module_index].unwind_data().frame_source(),
+            None => FrameSource::FramePointer,
+        }
+    }
+
+    fn exec_rule<F>(
+        unwind_rule: A::UnwindRule,
+        is_first_frame: bool,
+        regs: &mut A::UnwindRegs,
+        read_stack: &mut F,
+    ) -> Result<Option<(FrameAddress, FrameConfidence)>, Error>
+    where
+        F: FnMut(u64) -> Result<u64, ()>,
+    {
+        // The rule cache only stores rules, so we cannot tell whether a frame pointer
+        // rule came from the unwind info or from the fallback. Treat both the same.
+        let confidence = if unwind_rule == A::UnwindRule::fallback_rule() {
+            FrameConfidence::FramePointer
+        } else {
+            FrameConfidence::Exact
+        };
+        let address = match unwind_rule.exec(is_first_frame, regs, read_stack)? {
+            Some(address) => address,
+            None => return Ok(None),
+        };
+        let address = if unwind_rule.resumes_interrupted_code() {
+            FrameAddress::from_instruction_pointer(address)
+        } else {
+            FrameAddress::from_return_address(address).ok_or(Error::ReturnAddressIsNull)?
+        };
+        Ok(Some((address, confidence)))
+    }
+
+    pub fn unwind_frame<F>(
+        &self,
+        address: FrameAddress,
+        regs: &mut A::UnwindRegs,
+        cache: &mut Cache<D, A::UnwindRule, P>,
+        read_stack: &mut F,
+    ) -> Result<Option<u64>, Error>
+    where
+        F: FnMut(u64) -> Result<u64, ()>,
+    {
+        let result = self.unwind_frame_with_confidence(address, regs, cache, read_stack)?;
+        Ok(result.map(|(caller_address, _confidence)| caller_address.address()))
+    }
+
+    pub fn unwind_frame_with_confidence<F>(
+        &self,
+        address: FrameAddress,
+        regs: &mut A::UnwindRegs,
+        cache: &mut Cache<D, A::UnwindRule, P>,
+        read_stack: &mut F,
+    ) -> Result<Option<(FrameAddress, FrameConfidence)>, Error>
+    where
+        F: FnMut(u64) -> Result<u64, ()>,
+    {
+        self.with_cache(
+            address,
+            regs,
+            cache,
+            read_stack,
+            None,
+            Self::unwind_frame_impl,
+        )
+    }
+
+    pub fn unwind_frame_with_provenance<F>(
+        &self,
+        address: FrameAddress,
+        regs: &mut A::UnwindRegs,
+        cache: &mut Cache<D, A::UnwindRule, P>,
+        read_stack: &mut F,
+        provenance: &mut FrameProvenance,
+    ) -> Result<Option<(FrameAddress, FrameConfidence)>, Error>
+    where
+        F: FnMut(u64) -> Result<u64, ()>,
+    {
+        self.with_cache(
+            address,
+            regs,
+            cache,
+            read_stack,
+            Some(provenance),
+            Self::unwind_frame_impl,
+        )
+    }
+
+    pub fn unwind_frame_across_stack_switch<F>(
+        &self,
+        address: FrameAddress,
+        regs: &mut A::UnwindRegs,
+        read_stack: &mut F,
+    ) -> Result<Option<(FrameAddress, FrameConfidence)>, Error>
+    where
+        F: FnMut(u64) -> Result<u64, ()>,
+    {
+        let is_first_frame = !address.is_return_address();
+        let rule = A::UnwindRule::rule_for_stack_switch();
+        Self::exec_rule(rule, is_first_frame, regs, read_stack)
+    }
+
+    pub fn check_frame_pointer_chain<F>(
+        &self,
+        regs: &A::UnwindRegs,
+        read_stack: &mut F,
+        max_depth: usize,
+    ) -> FramePointerChain
+    where
+        F: FnMut(u64) -> Result<u64, ()>,
+    {
+        let mut regs = *regs;
+        let rule = A::UnwindRule::fallback_rule();
+        let mut depth = 0;
+        let mut unknown_return_addresses = 0;
+        let end = loop {
+            if depth >= max_depth {
+                break FramePointerChainEnd::MaxDepth;
+            }
+            // Not the first frame, so that the rule always reads a frame record.
+            match rule.exec(false, &mut regs, read_stack) {
+                Ok(Some(return_address)) => {
+                    depth += 1;
+                    if !self.is_known_code_address(return_address.wrapping_sub(1)) {
+                        unknown_return_addresses += 1;
+                    }
+                }
+                Ok(None) => break FramePointerChainEnd::Terminated,
+                Err(err) => break FramePointerChainEnd::Broken(err),
+            }
+        };
+        FramePointerChain {
+            depth,
+            unknown_return_addresses,
+            end,
+        }
+    }
+
+    pub fn check_frame_divergence<F>(
+        &self,
+        address: FrameAddress,
+        regs: &A::UnwindRegs,
+        cache: &mut Cache<D, A::UnwindRule, P>,
+        read_stack: &mut F,
+    ) -> Option<FrameDivergence>
+    where
+        F: FnMut(u64) -> Result<u64, ()>,
+    {
+        let address = self.canonical_frame_address(address);
+        let is_first_frame = !address.is_return_address();
+        let lookup_address = self.lookup_address(address);
+        // Stack switch frames are always unwound with their frame record.
+        if self.is_stack_switch_address(lookup_address) {
+            return None;
+        }
+        let (module_index, rel_lookup_address) = self.find_module_for_address(lookup_address)?;
+        let module = &self.modules[module_index];
+
+        let mut cfi_regs = *regs;
+        let cfi_return_address = match Self::unwind_frame_impl(
+            module,
+            address,
+            rel_lookup_address,
+            &mut cfi_regs,
+            cache,
+            read_stack,
+            &self.limits,
+            self.stub_rules_for_module(module),
+        )
+        .ok()?
+        {
+            // Frame pointers are not expected to be intact across signal frames.
+            UnwindResult::ExecRule(rule) if rule.resumes_interrupted_code() => return None,
+            UnwindResult::ExecRule(rule) => rule.exec(is_first_frame, &mut cfi_regs, read_stack),
+            UnwindResult::Uncacheable(return_address) => Ok(Some(return_address)),
+        }
+        .ok()??;
+
+        let mut fp_regs = *regs;
+        let frame_pointer_return_address = A::UnwindRule::fallback_rule()
+            .exec(is_first_frame, &mut fp_regs, read_stack)
+            .ok()??;
+
+        if cfi_return_address == frame_pointer_return_address && cfi_regs.sp() == fp_regs.sp() {
+            return None;
+        }
+        Some(FrameDivergence {
+            address,
+            cfi_return_address,
+            cfi_sp: cfi_regs.sp(),
+            frame_pointer_return_address,
+            frame_pointer_sp: fp_regs.sp(),
+        })
+    }
+
+    pub fn exception_handling_info(
+        &self,
+        address: FrameAddress,
+        cache: &mut Cache<D, A::UnwindRule, P>,
+    ) -> Result<Option<ExceptionHandlingInfo>, UnwinderError> {
+        let address = self.canonical_frame_address(address);
+        let lookup_address = self.lookup_address(address);
+        let (module_index, rel_lookup_address) = match self.find_module_for_address(lookup_address)
+        {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+        let module = &self.modules[module_index];
+        let (section_data, section_type, fde_offset) = match module.unwind_data() {
+            ModuleUnwindDataInternal::CompactUnwindInfoAndEhFrame(unwind_data, eh_frame_data) => {
+                let unwinder = CompactUnwindInfoUnwinder::<A>::new(
+                    &unwind_data[..],
+                    None,
+                    relative_range(&module.svma_info.stubs, module.svma_info.base_svma),
+                    relative_range(&module.svma_info.stub_helper, module.svma_info.base_svma),
+                );
+                let function = unwinder.function_for_address(rel_lookup_address)?;
+                // Functions which are described by a compact encoding keep their
+                // personality and LSDA in tables of __unwind_info, which aren't parsed.
+                let fde_offset = match A::describe_opcode(function.opcode).1 {
+                    Some(fde_offset) => fde_offset,
+                    None => return Ok(None),
+                };
+                match eh_frame_data {
+                    Some(eh_frame_data) => (eh_frame_data, UnwindSectionType::EhFrame, fde_offset),
+                    None => return Err(UnwinderError::NoDwarfData),
+                }
+            }
+            ModuleUnwindDataInternal::EhFrameHdrAndEhFrame(eh_frame_hdr, eh_frame_data) => {
+                let dwarf_unwinder = DwarfUnwinder::<_, A, P::GimliStorage>::new(
+                    EndianReader::new(ArcData(eh_frame_data.clone()), LittleEndian),
+                    UnwindSectionType::EhFrame,
+                    Some(&eh_frame_hdr[..]),
+                    &mut cache.gimli_unwind_context,
+                    &module.svma_info,
+                    &self.limits,
+                );
+                let fde_offset = dwarf_unwinder
+                    .get_fde_offset_for_relative_address(rel_lookup_address)
+                    .ok_or(UnwinderError::EhFrameHdrCouldNotFindAddress)?;
+                (eh_frame_data, UnwindSectionType::EhFrame, fde_offset)
+            }
+            ModuleUnwindDataInternal::DwarfCfiIndexAndEhFrame(index, data) => {
+                let fde_offset = index
+                    .fde_offset_for_relative_address(rel_lookup_address)
+                    .ok_or(UnwinderError::DwarfCfiIndexCouldNotFindAddress)?;
+                (data, UnwindSectionType::EhFrame, fde_offset)
+            }
+            ModuleUnwindDataInternal::DwarfCfiIndexAndDebugFrame(index, data) => {
+                let fde_offset = index
+                    .fde_offset_for_relative_address(rel_lookup_address)
+                    .ok_or(UnwinderError::DwarfCfiIndexCouldNotFindAddress)?;
+                (data, UnwindSectionType::DebugFrame, fde_offset)
+            }
+            // FPO data has no exception handling information.
+            ModuleUnwindDataInternal::Fpo(_) => return Ok(None),
+            ModuleUnwindDataInternal::Unparseable => {
+                return Err(UnwinderError::UnparseableModuleUnwindData)
+            }
+            // A lazy module whose data isn't loaded has no unwind data.
+            ModuleUnwindDataInternal::None | ModuleUnwindDataInternal::Lazy(_) => {
+                return Err(UnwinderError::NoModuleUnwindData)
+            }
+        };
+        let dwarf_unwinder = DwarfUnwinder::<_, A, P::GimliStorage>::new(
+            EndianReader::new(ArcData(section_data.clone()), LittleEndian),
+            section_type,
+            None,
+            &mut cache.gimli_unwind_context,
+            &module.svma_info,
+            &self.limits,
+        );
+        let info = dwarf_unwinder.exception_handling_info(fde_offset, module.base_avma)?;
+        Ok(Some(info))
+    }
+
+    pub fn dump_unwind_info<W: fmt::Write>(
+        &self,
+        address: FrameAddress,
+        cache: &mut Cache<D, A::UnwindRule, P>,
+        out: &mut W,
+    ) -> fmt::Result {
+        let address = self.canonical_frame_address(address);
+        let lookup_address = self.lookup_address(address);
+        let (module_index, rel_lookup_address) = match self.find_module_for_address(lookup_address)
+        {
+            Some(found) => found,
+            None => return writeln!(out, "0x{lookup_address:x} is not in any known module"),
+        };
+        let module = &self.modules[module_index];
+        writeln!(
+            out,
+            "0x{:x} is at 0x{:x} in module {} (0x{:x}..0x{:x})",
+            lookup_address,
+            rel_lookup_address,
+            module.name,
+            module.avma_range.start,
+            module.avma_range.end
+        )?;
+        if self.is_stack_switch_address(lookup_address) {
+            writeln!(
+                out,
+                "In a stack switch range, unwound with its frame record"
+            )?;
+        }
+        if let Some(rule) = self.plt_rule(lookup_address) {
+            writeln!(out, "In a PLT range: {rule}")?;
+        }
+        if let Some(rule) = self.dl_trampoline_rule(address) {
+            writeln!(out, "In a dynamic linker trampoline: {rule}")?;
+        }
+        if let Some(rule) = Self::detect_sigreturn_trampoline(module, address) {
+            writeln!(out, "In a signal trampoline: {rule}")?;
+        }
+        match module.unwind_data() {
+            ModuleUnwindDataInternal::CompactUnwindInfoAndEhFrame(unwind_data, eh_frame_data) => {
+                let unwinder = CompactUnwindInfoUnwinder::<A>::new(
+                    &unwind_data[..],
+                    None,
+                    relative_range(&module.svma_info.stubs, module.svma_info.base_svma),
+                    relative_range(&module.svma_info.stub_helper, module.svma_info.base_svma),
+                );
+                let function = match unwinder.function_for_address(rel_lookup_address) {
+                    Ok(function) => function,
+                    Err(err) => return writeln!(out, "{err}"),
+                };
+                let (description, fde_offset) = A::describe_opcode(function.opcode);
+                writeln!(
+                    out,
+                    "__unwind_info function 0x{:x}..0x{:x}, opcode 0x{:08x}: {}",
+                    function.start_address, function.end_address, function.opcode, description
+                )?;
+                match (fde_offset, eh_frame_data) {
+                    (Some(fde_offset), Some(eh_frame_data)) => {
+                        let mut dwarf_unwinder = DwarfUnwinder::<_, A, P::GimliStorage>::new(
+                            EndianReader::new(ArcData(eh_frame_data.clone()), LittleEndian),
+                            UnwindSectionType::EhFrame,
+                            None,
+                            &mut cache.gimli_unwind_context,
+                            &module.svma_info,
+                            &self.limits,
+                        );
+                        dwarf_unwinder.dump_fde(rel_lookup_address, fde_offset, out)
+                    }
+                    (Some(_), None) => writeln!(out, "{}", UnwinderError::NoDwarfData),
+                    (None, _) => Ok(()),
+                }
+            }
+            ModuleUnwindDataInternal::EhFrameHdrAndEhFrame(eh_frame_hdr, eh_frame_data) => {
+                let mut dwarf_unwinder = DwarfUnwinder::<_, A, P::GimliStorage>::new(
+                    EndianReader::new(ArcData(eh_frame_data.clone()), LittleEndian),
+                    UnwindSectionType::EhFrame,
+                    Some(&eh_frame_hdr[..]),
+                    &mut cache.gimli_unwind_context,
+                    &module.svma_info,
+                    &self.limits,
+                );
+                match dwarf_unwinder.get_fde_offset_for_relative_address(rel_lookup_address) {
+                    Some(fde_offset) => {
+                        dwarf_unwinder.dump_fde(rel_lookup_address, fde_offset, out)
+                    }
+                    None => writeln!(out, "{}", UnwinderError::EhFrameHdrCouldNotFindAddress),
+                }
+            }
+            ModuleUnwindDataInternal::DwarfCfiIndexAndEhFrame(index, data)
+            | ModuleUnwindDataInternal::DwarfCfiIndexAndDebugFrame(index, data) => {
+                let section_type = match module.unwind_data() {
+                    ModuleUnwindDataInternal::DwarfCfiIndexAndDebugFrame(..) => {
+                        UnwindSectionType::DebugFrame
+                    }
+                    _ => UnwindSectionType::EhFrame,
+                };
+                let fde_offset = match index.fde_offset_for_relative_address(rel_lookup_address) {
+                    Some(fde_offset) => fde_offset,
+                    None => {
+                        return writeln!(out, "{}", UnwinderError::DwarfCfiIndexCouldNotFindAddress)
+                    }
+                };
+                let mut dwarf_unwinder = DwarfUnwinder::<_, A, P::GimliStorage>::new(
+                    EndianReader::new(ArcData(data.clone()), LittleEndian),
+                    section_type,
+                    None,
+                    &mut cache.gimli_unwind_context,
+                    &module.svma_info,
+                    &self.limits,
+                );
+                dwarf_unwinder.dump_fde(rel_lookup_address, fde_offset, out)
+            }
+            ModuleUnwindDataInternal::Fpo(index) => {
+                let fpo = match index.lookup(rel_lookup_address) {
+                    Some(fpo) => fpo,
+                    None => return writeln!(out, "{}", UnwinderError::FpoDataCouldNotFindAddress),
+                };
+                writeln!(
+                    out,
+                    "FPO data for function 0x{:x}..0x{:x}: {:?}",
+                    fpo.function_start,
+                    fpo.function_start.saturating_add(fpo.function_size),
+                    fpo
+                )?;
+                let offset_within_function = rel_lookup_address - fpo.function_start;
+                match A::rule_for_fpo_data(
+                    fpo,
+                    !address.is_return_address(),
+                    offset_within_function,
+                ) {
+                    Ok(rule) => writeln!(out, "{rule}"),
+                    Err(err) => writeln!(out, "{err}"),
+                }
+            }
+            ModuleUnwindDataInternal::Unparseable => {
+                writeln!(out, "{}", UnwinderError::UnparseableModuleUnwindData)
+            }
+            ModuleUnwindDataInternal::None | ModuleUnwindDataInternal::Lazy(_) => {
+                writeln!(out, "{}", UnwinderError::NoModuleUnwindData)
             }
-        };
-        if address < module.base_avma {
-            // Invalid base address
-            return None;
         }
-        let relative_address = u32::try_from(address - module.base_avma).ok()?;
-        Some((module_index, relative_address))
     }
 
-    fn with_cache<F, G>(
+    #[cfg(feature = "rule-table")]
+    pub fn write_rule_table<W: fmt::Write>(
         &self,
-        address: FrameAddress,
-        regs: &mut A::UnwindRegs,
+        module_avma_range_start: u64,
+        regs: A::UnwindRegs,
         cache: &mut Cache<D, A::UnwindRule, P>,
-        read_stack: &mut F,
-        callback: G,
-    ) -> Result<Option<u64>, Error>
-    where
-        F: FnMut(u64) -> Result<u64, ()>,
-        G: FnOnce(
-            &Module<D>,
-            FrameAddress,
-            u32,
-            &mut A::UnwindRegs,
-            &mut Cache<D, A::UnwindRule, P>,
-            &mut F,
-        ) -> Result<UnwindResult<A::UnwindRule>, UnwinderError>,
-    {
-        let lookup_address = address.address_for_lookup();
-        let is_first_frame = !address.is_return_address();
-        let cache_handle = match cache
-            .rule_cache
-            .lookup(lookup_address, self.modules_generation)
+        out: &mut W,
+    ) -> fmt::Result {
+        let module = match self
+            .modules
+            .iter()
+            .find(|module| module.avma_range.start == module_avma_range_start)
         {
-            CacheResult::Hit(unwind_rule) => {
-                return unwind_rule.exec(is_first_frame, regs, read_stack);
+            Some(module) => module,
+            None => return writeln!(out, "# no module at 0x{module_avma_range_start:x}"),
+        };
+        let avma_range = match &module.svma_info.text {
+            Some(text) if text.start >= module.svma_info.base_svma => {
+                let start = module.base_avma + (text.start - module.svma_info.base_svma);
+                start..start + (text.end - text.start)
             }
-            CacheResult::Miss(handle) => handle,
+            _ => module.avma_range.clone(),
         };
+        writeln!(out, "# {}", module.name)?;
+        writeln!(out, "# start\tend\trule")?;
 
-        let unwind_rule = match self.find_module_for_address(lookup_address) {
-            None => A::UnwindRule::fallback_rule(),
-            Some((module_index, relative_lookup_address)) => {
-                let module = &self.modules[module_index];
-                match callback(
-                    module,
-                    address,
-                    relative_lookup_address,
-                    regs,
-                    cache,
-                    read_stack,
-                ) {
-                    Ok(UnwindResult::ExecRule(rule)) => rule,
-                    Ok(UnwindResult::Uncacheable(return_address)) => {
-                        return Ok(Some(return_address))
-                    }
-                    Err(_err) => {
-                        // eprintln!("Unwinder error: {}", err);
-                        A::UnwindRule::fallback_rule()
+        // Consecutive addresses with the same entry are merged into one row, which
+        // keeps the table small: most rules cover many instructions.
+        let mut row: Option<(u32, u32, String)> = None;
+        for address in avma_range {
+            let relative_address = match address
+                .checked_sub(module.base_avma)
+                .and_then(|offset| u32::try_from(offset).ok())
+            {
+                Some(relative_address) => relative_address,
+                None => continue,
+            };
+            let mut regs = regs;
+            // Rules which need the full register state read the stack while they're
+            // computed. They aren't rules that can be listed, so reading always succeeds.
+            let mut read_stack = |_| Ok(0);
+            let entry = match Self::unwind_frame_impl(
+                module,
+                FrameAddress::from_instruction_pointer(address),
+                relative_address,
+                &mut regs,
+                cache,
+                &mut read_stack,
+                &self.limits,
+                self.stub_rules_for_module(module),
+            ) {
+                Ok(UnwindResult::ExecRule(rule)) => rule.to_string(),
+                Ok(UnwindResult::Uncacheable(_)) => "uncacheable".to_string(),
+                Err(err) => format!("error: {err}"),
+            };
+            match &mut row {
+                Some((_, end, row_entry)) if *row_entry == entry => *end = relative_address + 1,
+                _ => {
+                    if let Some((start, end, entry)) = row.take() {
+                        writeln!(out, "0x{start:x}\t0x{end:x}\t{entry}")?;
                     }
+                    row = Some((relative_address, relative_address + 1, entry));
                 }
             }
-        };
-        cache.rule_cache.insert(cache_handle, unwind_rule);
-        unwind_rule.exec(is_first_frame, regs, read_stack)
+        }
+        if let Some((start, end, entry)) = row {
+            writeln!(out, "0x{start:x}\t0x{end:x}\t{entry}")?;
+        }
+        Ok(())
     }
 
-    pub fn unwind_frame<F>(
-        &self,
+    /// Signal handlers return into a trampoline which doesn't always have usable unwind
+    /// information, so we recognize it by its instructions. We look at the unadjusted
+    /// address here, because the trampoline is entered by "returning" to its first
+    /// instruction.
+    fn detect_sigreturn_trampoline(
+        module: &Module<D>,
         address: FrameAddress,
-        regs: &mut A::UnwindRegs,
-        cache: &mut Cache<D, A::UnwindRule, P>,
-        read_stack: &mut F,
-    ) -> Result<Option<u64>, Error>
-    where
-        F: FnMut(u64) -> Result<u64, ()>,
-    {
-        self.with_cache(address, regs, cache, read_stack, Self::unwind_frame_impl)
+    ) -> Option<A::UnwindRule> {
+        let text_data = module.text_data.as_ref()?;
+        let pc_offset = address.address().checked_sub(text_data.avma_range.start)?;
+        let pc_offset = usize::try_from(pc_offset).ok()?;
+        if pc_offset > text_data.bytes.len() {
+            return None;
+        }
+        A::rule_from_sigreturn_trampoline_analysis(&text_data.bytes, pc_offset)
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn unwind_frame_impl<F>(
         module: &Module<D>,
         address: FrameAddress,
@@ -379,42 +1595,36 @@ impl<
         regs: &mut A::UnwindRegs,
         cache: &mut Cache<D, A::UnwindRule, P>,
         read_stack: &mut F,
+        limits: &UnwindLimits,
+        stub_rules: StubRules<A::UnwindRule>,
     ) -> Result<UnwindResult<A::UnwindRule>, UnwinderError>
     where
         F: FnMut(u64) -> Result<u64, ()>,
     {
         let is_first_frame = !address.is_return_address();
-        let unwind_result = match &module.unwind_data {
+        if let Some(rule) = Self::detect_sigreturn_trampoline(module, address) {
+            trace_event!(rule = %rule, "detected signal trampoline");
+            return Ok(UnwindResult::ExecRule(rule));
+        }
+        let text_bytes = module.text_data.as_ref().and_then(|data| {
+            let offset_from_base =
+                u32::try_from(data.avma_range.start.checked_sub(module.base_avma)?).ok()?;
+            Some(TextBytes::new(offset_from_base, &data.bytes[..]))
+        });
+        trace_event!(unwinder = ?module.unwind_data().frame_source(), "selected unwinder");
+        let unwind_result = match module.unwind_data() {
             ModuleUnwindDataInternal::CompactUnwindInfoAndEhFrame(unwind_data, eh_frame_data) => {
-                // eprintln!("unwinding with cui and eh_frame in module {}", module.name);
-                let text_bytes = module.text_data.as_ref().and_then(|data| {
-                    let offset_from_base =
-                        u32::try_from(data.avma_range.start.checked_sub(module.base_avma)?).ok()?;
-                    Some(TextBytes::new(offset_from_base, &data.bytes[..]))
-                });
-                let stubs_range = if let Some(stubs_range) = &module.svma_info.stubs {
-                    (
-                        (stubs_range.start - module.svma_info.base_svma) as u32,
-                        (stubs_range.end - module.svma_info.base_svma) as u32,
-                    )
-                } else {
-                    (0, 0)
-                };
+                let stubs_range =
+                    relative_range(&module.svma_info.stubs, module.svma_info.base_svma);
                 let stub_helper_range =
-                    if let Some(stub_helper_range) = &module.svma_info.stub_helper {
-                        (
-                            (stub_helper_range.start - module.svma_info.base_svma) as u32,
-                            (stub_helper_range.end - module.svma_info.base_svma) as u32,
-                        )
-                    } else {
-                        (0, 0)
-                    };
+                    relative_range(&module.svma_info.stub_helper, module.svma_info.base_svma);
                 let mut unwinder = CompactUnwindInfoUnwinder::<A>::new(
                     &unwind_data[..],
                     text_bytes,
                     stubs_range,
                     stub_helper_range,
                 );
+                unwinder.set_stub_rules(stub_rules);
 
                 let unwind_result = unwinder.unwind_frame(rel_lookup_address, is_first_frame)?;
                 match unwind_result {
@@ -430,7 +1640,11 @@ impl<
                             None,
                             &mut cache.gimli_unwind_context,
                             &module.svma_info,
+                            limits,
                         );
+                        // Compact unwind info means this is a macOS module.
+                        dwarf_unwinder
+                            .set_signal_trampoline_rule(A::UnwindRule::rule_for_macos_sigtramp());
                         dwarf_unwinder.unwind_frame_with_fde(
                             regs,
                             is_first_frame,
@@ -450,7 +1664,9 @@ impl<
                     Some(eh_frame_hdr_data),
                     &mut cache.gimli_unwind_context,
                     &module.svma_info,
+                    limits,
                 );
+                dwarf_unwinder.set_text_bytes(text_bytes);
                 let fde_offset = dwarf_unwinder
                     .get_fde_offset_for_relative_address(rel_lookup_address)
                     .ok_or(UnwinderError::EhFrameHdrCouldNotFindAddress)?;
@@ -470,7 +1686,9 @@ impl<
                     None,
                     &mut cache.gimli_unwind_context,
                     &module.svma_info,
+                    limits,
                 );
+                dwarf_unwinder.set_text_bytes(text_bytes);
                 let fde_offset = index
                     .fde_offset_for_relative_address(rel_lookup_address)
                     .ok_or(UnwinderError::DwarfCfiIndexCouldNotFindAddress)?;
@@ -490,7 +1708,9 @@ impl<
                     None,
                     &mut cache.gimli_unwind_context,
                     &module.svma_info,
+                    limits,
                 );
+                dwarf_unwinder.set_text_bytes(text_bytes);
                 let fde_offset = index
                     .fde_offset_for_relative_address(rel_lookup_address)
                     .ok_or(UnwinderError::DwarfCfiIndexCouldNotFindAddress)?;
@@ -502,12 +1722,59 @@ impl<
                     read_stack,
                 )?
             }
-            ModuleUnwindDataInternal::None => return Err(UnwinderError::NoModuleUnwindData),
+            ModuleUnwindDataInternal::Fpo(index) => {
+                let fpo = index
+                    .lookup(rel_lookup_address)
+                    .ok_or(UnwinderError::FpoDataCouldNotFindAddress)?;
+                let offset_within_function = rel_lookup_address - fpo.function_start;
+                // FPO data only describes the frame after the prologue. If we have the
+                // code bytes, decode the prologue up to the address.
+                let prologue_rule = text_bytes
+                    .filter(|_| is_first_frame)
+                    .and_then(|text_bytes| {
+                        let function_bytes = text_bytes.bytes_for_relative_range(
+                            fpo.function_start,
+                            fpo.function_start.checked_add(fpo.function_size)?,
+                        )?;
+                        A::rule_from_function_start_analysis(
+                            function_bytes,
+                            offset_within_function as usize,
+                        )
+                    });
+                match prologue_rule {
+                    Some(rule) => UnwindResult::ExecRule(rule),
+                    None => UnwindResult::ExecRule(A::rule_for_fpo_data(
+                        fpo,
+                        is_first_frame,
+                        offset_within_function,
+                    )?),
+                }
+            }
+            ModuleUnwindDataInternal::Unparseable => {
+                return Err(UnwinderError::UnparseableModuleUnwindData)
+            }
+            // A lazy module whose data isn't loaded has no unwind data.
+            ModuleUnwindDataInternal::None | ModuleUnwindDataInternal::Lazy(_) => {
+                return Err(UnwinderError::NoModuleUnwindData)
+            }
         };
         Ok(unwind_result)
     }
 }
 
+/// Converts an SVMA range into a range relative to `base_svma`, or returns an empty
+/// range if the range is absent or doesn't fit.
+fn relative_range(svma_range: &Option<Range<u64>>, base_svma: u64) -> (u32, u32) {
+    let relative = |svma: u64| u32::try_from(svma.checked_sub(base_svma)?).ok();
+    match svma_range {
+        Some(range) => match (relative(range.start), relative(range.end)) {
+            (Some(start), Some(end)) => (start, end),
+            _ => (0, 0),
+        },
+        None => (0, 0),
+    }
+}
+
 /// The unwind data that should be used when unwinding addresses inside this module.
 /// Unwind data describes how to recover register values of the caller frame.
 ///
@@ -536,6 +1803,12 @@ pub enum ModuleUnwindData<D: Deref<Target = [u8]>> {
     /// DWARF CFI. We create a binary index for the FDEs when a module with this unwind
     /// data type is added.
     DebugFrame(D),
+    /// Used with 32-bit x86 Windows binaries: the `FPO_DATA` records of the module, from
+    /// its PDB or from the `IMAGE_DEBUG_TYPE_FPO` entry of its debug directory. See
+    /// [`FpoData`](crate::FpoData). Addresses without a record fall back to the frame
+    /// pointer, like addresses which other unwind data doesn't cover. Only used by
+    /// [`UnwinderX86`](crate::x86::UnwinderX86).
+    Fpo(D),
     /// No unwind information is used. Unwinding in this module will use a fallback rule
     /// (usually frame pointer unwinding).
     None,
@@ -546,7 +1819,18 @@ enum ModuleUnwindDataInternal<D: Deref<Target = [u8]>> {
     EhFrameHdrAndEhFrame(D, Arc<D>),
     DwarfCfiIndexAndEhFrame(DwarfCfiIndex, Arc<D>),
     DwarfCfiIndexAndDebugFrame(DwarfCfiIndex, Arc<D>),
+    Fpo(FpoIndex),
+    /// The module's unwind data could not be indexed.
+    Unparseable,
     None,
+    /// The unwind data is loaded on request, see [`Module::new_lazy`].
+    Lazy(LazyUnwindData<D>),
+}
+
+/// The loader of a lazily loaded module, and the unwind data once it is loaded.
+struct LazyUnwindData<D: Deref<Target = [u8]>> {
+    load: Box<dyn Fn() -> ModuleUnwindData<D> + Send + Sync>,
+    loaded: Option<Box<ModuleUnwindDataInternal<D>>>,
 }
 
 impl<D: Deref<Target = [u8]>> ModuleUnwindDataInternal<D> {
@@ -563,7 +1847,7 @@ impl<D: Deref<Target = [u8]>> ModuleUnwindDataInternal<D> {
                     Ok(index) => {
                         ModuleUnwindDataInternal::DwarfCfiIndexAndEhFrame(index, Arc::new(eh_frame))
                     }
-                    Err(_) => ModuleUnwindDataInternal::None,
+                    Err(_) => ModuleUnwindDataInternal::Unparseable,
                 }
             }
             ModuleUnwindData::DebugFrame(debug_frame) => {
@@ -572,12 +1856,49 @@ impl<D: Deref<Target = [u8]>> ModuleUnwindDataInternal<D> {
                         index,
                         Arc::new(debug_frame),
                     ),
-                    Err(_) => ModuleUnwindDataInternal::None,
+                    Err(_) => ModuleUnwindDataInternal::Unparseable,
                 }
             }
+            ModuleUnwindData::Fpo(fpo_data) => match FpoIndex::try_new(&fpo_data) {
+                Some(index) => ModuleUnwindDataInternal::Fpo(index),
+                None => ModuleUnwindDataInternal::Unparseable,
+            },
             ModuleUnwindData::None => ModuleUnwindDataInternal::None,
         }
     }
+
+    /// The unwind data to use. This is the loaded data of a lazy module, or the lazy
+    /// data itself if it isn't loaded, which is then treated like `None`.
+    fn resolve(&self) -> &Self {
+        match self {
+            ModuleUnwindDataInternal::Lazy(LazyUnwindData {
+                loaded: Some(loaded),
+                ..
+            }) => loaded,
+            _ => self,
+        }
+    }
+
+    /// The source of the frames that are unwound with this unwind data. Frames in
+    /// modules without usable unwind data are unwound with frame pointers.
+    fn frame_source(&self) -> FrameSource {
+        match self {
+            ModuleUnwindDataInternal::CompactUnwindInfoAndEhFrame(..) => {
+                FrameSource::CompactUnwindInfo
+            }
+            ModuleUnwindDataInternal::EhFrameHdrAndEhFrame(..) => FrameSource::EhFrameHdr,
+            ModuleUnwindDataInternal::DwarfCfiIndexAndEhFrame(..) => FrameSource::EhFrame,
+            ModuleUnwindDataInternal::DwarfCfiIndexAndDebugFrame(..) => FrameSource::DebugFrame,
+            ModuleUnwindDataInternal::Fpo(..) => FrameSource::Fpo,
+            ModuleUnwindDataInternal::Unparseable | ModuleUnwindDataInternal::None => {
+                FrameSource::FramePointer
+            }
+            ModuleUnwindDataInternal::Lazy(lazy) => match &lazy.loaded {
+                Some(loaded) => loaded.frame_source(),
+                None => FrameSource::FramePointer,
+            },
+        }
+    }
 }
 
 /// Used to supply raw instruction bytes to the unwinder, which uses it to analyze
@@ -589,7 +1910,14 @@ impl<D: Deref<Target = [u8]>> ModuleUnwindDataInternal<D> {
 ///
 /// On Linux, compilers produce `.eh_frame` and `.debug_frame` which provides correct
 /// unwind information for all instructions including those in function prologues and
-/// epilogues, so instruction analysis is not needed.
+/// epilogues, so instruction analysis is not needed there. But the instruction bytes
+/// are also used on all platforms to detect signal trampolines, to find call
+/// instructions for [`Unwinder::call_site_address`] and
+/// [`Unwinder::is_preceded_by_call`], and to repair frames from last branch records.
+///
+/// The bytes can be supplied when the module is created, or later with the concrete
+/// unwinder's `set_module_text_data` method, for example once a module's code has been
+/// read from the profiled process because a frame in it was seen.
 ///
 /// Type arguments:
 ///
@@ -639,9 +1967,12 @@ impl<D: Deref<Target = [u8]>> TextByteData<D> {
 ///    a file or a different process, for example. It just needs to provide a slice of
 ///    bytes via its `Deref` implementation.
 pub struct Module<D: Deref<Target = [u8]>> {
-    /// The name or file path of the module. Unused, it's just there for easier debugging.
-    #[allow(unused)]
+    /// The name or file path of the module, e.g. `libxul.so`. Used in tracing output, in
+    /// [`FrameProvenance`] and by the concrete unwinder's `describe_address` method. Can
+    /// be empty if the module has no useful name.
     name: String,
+    /// Identifies the module in stack hashes, see [`Module::id`].
+    id: u64,
     /// The address range where this module is mapped into the process.
     avma_range: Range<u64>,
     /// The base address of this module, in the process's address space. On Linux, the base
@@ -666,7 +1997,11 @@ pub struct Module<D: Deref<Target = [u8]>> {
 /// or as relative addresses. For example, DWARF CFI can have code addresses expressed as
 /// relative-to-.text addresses or as absolute SVMAs. And mach-O compact unwind info
 /// contains addresses relative to the image base address.
+///
+/// The [`Default`] value has no sections and an address size of 8, so that only the
+/// fields which apply to a module need to be set.
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ModuleSvmaInfo {
     /// The image base address, as stated in the object. For mach-O objects, this is the
     /// vmaddr of the `__TEXT` segment. For ELF objects, this is zero.
@@ -710,6 +2045,33 @@ pub struct ModuleSvmaInfo {
     /// The address range of the `.got` section (Global Offset Table). This is used
     /// during DWARF CFI processing, to resolve got-relative addresses.
     pub got: Option<Range<u64>>,
+    /// The size of an address in the module's unwind information, in bytes. This is 8,
+    /// except for modules of the Linux x32 ABI, which are ELF32 objects for x86_64 and
+    /// use 4. It determines the size of absolute pointers in `.eh_frame` and
+    /// `.eh_frame_hdr`, and of the addresses in `.debug_frame`.
+    #[cfg_attr(feature = "serde", serde(default = "default_address_size"))]
+    pub address_size: u8,
+}
+
+impl Default for ModuleSvmaInfo {
+    fn default() -> Self {
+        Self {
+            base_svma: 0,
+            text: None,
+            text_env: None,
+            stubs: None,
+            stub_helper: None,
+            eh_frame: None,
+            eh_frame_hdr: None,
+            got: None,
+            address_size: 8,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+fn default_address_size() -> u8 {
+    8
 }
 
 impl<D: Deref<Target = [u8]>> Module<D> {
@@ -723,6 +2085,7 @@ impl<D: Deref<Target = [u8]>> Module<D> {
     ) -> Self {
         let unwind_data = ModuleUnwindDataInternal::new(unwind_data, &svma_info);
         Self {
+            id: module_name_hash(&name),
             name,
             avma_range,
             base_avma,
@@ -731,4 +2094,1073 @@ impl<D: Deref<Target = [u8]>> Module<D> {
             text_data,
         }
     }
+
+    /// Create a module whose unwind data is loaded on request, by calling
+    /// `load_unwind_data`. This is useful for agents that track many processes and
+    /// don't want to keep the unwind sections of all their modules in memory.
+    ///
+    /// The data is loaded by the concrete unwinder's `load_module_unwind_data` method,
+    /// for example when a sample hits the module, and can be dropped again with its
+    /// `unload_module_unwind_data` method. Unwinding never loads the data itself,
+    /// because loading allocates and indexes the unwind sections, which must not
+    /// happen during unwinding with [`MustNotAllocateDuringUnwind`](crate::MustNotAllocateDuringUnwind).
+    /// Until the data is loaded, the module is unwound like a module without unwind
+    /// data, i.e. with frame pointers.
+    ///
+    /// If the data can't be fetched, `load_unwind_data` can return
+    /// [`ModuleUnwindData::None`]; the module is then unwound with frame pointers until
+    /// its data is unloaded and loaded again.
+    pub fn new_lazy<L>(
+        name: String,
+        avma_range: std::ops::Range<u64>,
+        base_avma: u64,
+        svma_info: ModuleSvmaInfo,
+        load_unwind_data: L,
+        text_data: Option<TextByteData<D>>,
+    ) -> Self
+    where
+        L: Fn() -> ModuleUnwindData<D> + Send + Sync + 'static,
+    {
+        Self {
+            id: module_name_hash(&name),
+            name,
+            avma_range,
+            base_avma,
+            svma_info,
+            unwind_data: ModuleUnwindDataInternal::Lazy(LazyUnwindData {
+                load: Box::new(load_unwind_data),
+                loaded: None,
+            }),
+            text_data,
+        }
+    }
+
+    /// The name or file path of the module, as passed to the constructor.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Identify the module by `id` instead of by a hash of its name, for example by a
+    /// hash of its build ID, if the name isn't unique or isn't the same in every run.
+    pub fn with_id(mut self, id: u64) -> Self {
+        self.id = id;
+        self
+    }
+
+    /// The id which identifies the module in
+    /// [stack hashes](crate::UnwindIterator::with_stack_hash). Unless it was set with
+    /// [`Module::with_id`], it is a hash of the module's name, so it is the same in
+    /// every run and at every load address.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// The unwind data of this module. A lazy module has no unwind data until its
+    /// data is loaded.
+    fn unwind_data(&self) -> &ModuleUnwindDataInternal<D> {
+        self.unwind_data.resolve()
+    }
+
+    /// Load the unwind data of a lazy module, if it isn't loaded yet. Returns whether
+    /// the data was loaded.
+    fn load_unwind_data(&mut self) -> bool {
+        match &mut self.unwind_data {
+            ModuleUnwindDataInternal::Lazy(lazy) if lazy.loaded.is_none() => {
+                let loaded = ModuleUnwindDataInternal::new((lazy.load)(), &self.svma_info);
+                lazy.loaded = Some(Box::new(loaded));
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dwarf::DwarfUnwinderError;
+    use crate::test_utils::{eh_frame_module, hot_and_cold_eh_frame, test_module, TestStack};
+    use crate::x86_64::{CacheX86_64, UnwindRegsX86_64, UnwindRuleX86_64, UnwinderX86_64};
+    use crate::UnwindEndReason;
+    use fallible_iterator::FallibleIterator;
+
+    #[test]
+    fn test_root_ranges() {
+        let stack = TestStack::frame_chain();
+        let mut read_stack = |addr| stack.read(addr);
+        let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        unwinder.add_root_range(0x100180..0x100280);
+        let mut cache = CacheX86_64::new();
+        let mut iter = unwinder.iter_frames(
+            0x100400,
+            UnwindRegsX86_64::new(0x100400, 0x10, 0x20),
+            &mut cache,
+            &mut read_stack,
+        );
+        assert_eq!(
+            iter.next(),
+            Ok(Some(FrameAddress::from_instruction_pointer(0x100400)))
+        );
+        assert_eq!(
+            iter.next(),
+            Ok(Some(FrameAddress::from_return_address(0x100200).unwrap()))
+        );
+        assert_eq!(iter.next(), Ok(None));
+        assert_eq!(iter.end_reason(), Some(UnwindEndReason::ReachedRoot));
+
+        unwinder.remove_root_range(0x100180);
+        assert!(!unwinder.is_root_address(0x100200));
+    }
+
+    #[test]
+    fn test_stack_switch_ranges() {
+        let stack = TestStack::zeroed(48)
+            // The previous stack segment, with the frame record of __morestack at 0x20.
+            .with_frame_record(0x20, 0x40, 0x100200)
+            .with_frame_record(0x40, 0x0, 0x100100)
+            // The new stack segment at 0x100, with the frame record of the callee at 0x110.
+            .with_frame_record(0x110, 0x20, 0x180500);
+        let mut read_stack = |addr| stack.read(addr);
+        let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        let mut cache = CacheX86_64::new();
+        let regs = UnwindRegsX86_64::new(0x100400, 0x100, 0x110);
+
+        let mut iter = unwinder.iter_frames(0x100400, regs, &mut cache, &mut read_stack);
+        assert_eq!(
+            iter.by_ref().count(),
+            Err(Error::FramePointerBelowStackPointer(0x20))
+        );
+
+        unwinder.add_stack_switch_range(0x180000..0x181000);
+        let iter = unwinder
+            .iter_frames(0x100400, regs, &mut cache, &mut read_stack)
+            .with_stack_bounds(0x100..0x180);
+        assert_eq!(
+            iter.collect::<Vec<_>>(),
+            Ok(vec![
+                FrameAddress::from_instruction_pointer(0x100400),
+                FrameAddress::from_return_address(0x180500).unwrap(),
+                FrameAddress::from_return_address(0x100200).unwrap(),
+                FrameAddress::from_return_address(0x100100).unwrap(),
+            ])
+        );
+
+        unwinder.remove_stack_switch_range(0x180000);
+        assert!(!unwinder.is_stack_switch_address(0x180500));
+    }
+
+    #[test]
+    fn test_plt_ranges() {
+        // A lazy binding .plt at 0x100000 with PLT0 and one entry.
+        let mut text = vec![0x90; 0x400];
+        text[0..0x20].copy_from_slice(&[
+            0xff, 0x35, 0xe2, 0x2f, 0x00, 0x00, 0xff, 0x25, 0xe4, 0x2f, 0x00, 0x00, 0x0f, 0x1f,
+            0x40, 0x00, 0xff, 0x25, 0xe2, 0x2f, 0x00, 0x00, 0x68, 0x00, 0x00, 0x00, 0x00, 0xe9,
+            0xe0, 0xff, 0xff, 0xff,
+        ]);
+        let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        unwinder.add_module(test_module(0x100000..0x100400, Some(text)));
+        // The PLT entry has pushed its relocation index 0 above the return address,
+        // and rbp still belongs to the caller of the PLT caller.
+        let stack = TestStack::from([1, 2, 0, 0x100200, 0x40, 0x100100, 7, 8, 0x0, 0x0]);
+        let mut read_stack = |addr| stack.read(addr);
+        let mut cache = CacheX86_64::new();
+        let regs = UnwindRegsX86_64::new(0x10001b, 0x10, 0x20);
+
+        // Without the PLT range, the frame pointer fallback skips the PLT caller.
+        let without_plt = Ok(vec![
+            FrameAddress::from_instruction_pointer(0x10001b),
+            FrameAddress::from_return_address(0x100100).unwrap(),
+        ]);
+        let mut iter = unwinder.iter_frames(0x10001b, regs, &mut cache, &mut read_stack);
+        assert_eq!(iter.by_ref().collect::<Vec<_>>(), without_plt);
+
+        unwinder.add_plt_range(0x100000..0x100020);
+        let mut iter = unwinder
+            .iter_frames(0x10001b, regs, &mut cache, &mut read_stack)
+            .with_provenance();
+        assert_eq!(
+            iter.by_ref().collect::<Vec<_>>(),
+            Ok(vec![
+                FrameAddress::from_instruction_pointer(0x10001b),
+                FrameAddress::from_return_address(0x100200).unwrap(),
+                FrameAddress::from_return_address(0x100100).unwrap(),
+            ])
+        );
+        assert_eq!(iter.provenance()[1].source, FrameSource::PltStub);
+
+        unwinder.remove_plt_range(0x100000);
+        let mut iter = unwinder.iter_frames(0x10001b, regs, &mut cache, &mut read_stack);
+        assert_eq!(iter.by_ref().collect::<Vec<_>>(), without_plt);
+    }
+
+    #[test]
+    fn test_dl_trampoline_ranges() {
+        let mut text = vec![0; 0x400];
+        // endbr64; push rbx; mov rbx, rsp
+        text[..8].copy_from_slice(&[0xf3, 0x0f, 0x1e, 0xfa, 0x53, 0x48, 0x89, 0xe3]);
+        let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        unwinder.add_module(test_module(0x100000..0x100400, Some(text)));
+        // rbx, the link map and the relocation index are above sp, then the return
+        // address. bp is still the caller's.
+        let stack = TestStack::from([1, 2, 3, 4, 0, 0x100200, 5, 6, 0x0, 0x100100, 0x0, 0x0]);
+        let mut read_stack = |addr| stack.read(addr);
+        let mut cache = CacheX86_64::new();
+        let regs = UnwindRegsX86_64::new(0x100005, 0x10, 0x40);
+
+        // Without the range, the frame pointer skips the caller.
+        let mut iter = unwinder.iter_frames(0x100005, regs, &mut cache, &mut read_stack);
+        assert_eq!(iter.by_ref().count(), Ok(2));
+
+        unwinder.add_dl_trampoline_range(0x100000..0x100020);
+        let mut iter = unwinder
+            .iter_frames(0x100005, regs, &mut cache, &mut read_stack)
+            .with_provenance();
+        assert_eq!(
+            iter.by_ref().collect::<Vec<_>>(),
+            Ok(vec![
+                FrameAddress::from_instruction_pointer(0x100005),
+                FrameAddress::from_return_address(0x100200).unwrap(),
+                FrameAddress::from_return_address(0x100100).unwrap(),
+            ])
+        );
+        assert_eq!(
+            iter.provenance()[1].source,
+            FrameSource::DynamicLinkerTrampoline
+        );
+    }
+
+    #[test]
+    fn test_frame_pointer_chain() {
+        let stack = TestStack::frame_chain();
+        let mut read_stack = |addr| stack.read(addr);
+        let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        let regs = UnwindRegsX86_64::new(0x100300, 0x10, 0x20);
+
+        // Without modules, the return addresses can't be checked.
+        let chain = unwinder.check_frame_pointer_chain(&regs, &mut read_stack, 10);
+        assert_eq!(
+            chain,
+            FramePointerChain {
+                depth: 2,
+                unknown_return_addresses: 2,
+                end: FramePointerChainEnd::Terminated,
+            }
+        );
+        assert!(!chain.is_intact());
+
+        unwinder.add_module(test_module(0x100000..0x100400, None));
+        let chain = unwinder.check_frame_pointer_chain(&regs, &mut read_stack, 10);
+        assert_eq!(chain.unknown_return_addresses, 0);
+        assert!(chain.is_intact());
+        let chain = unwinder.check_frame_pointer_chain(&regs, &mut read_stack, 1);
+        assert_eq!(chain.depth, 1);
+        assert_eq!(chain.end, FramePointerChainEnd::MaxDepth);
+        assert!(chain.is_intact());
+
+        // bp holds something other than a frame pointer.
+        let regs = UnwindRegsX86_64::new(0x100300, 0x10, 0x8);
+        let chain = unwinder.check_frame_pointer_chain(&regs, &mut read_stack, 10);
+        assert_eq!(chain.depth, 0);
+        assert_eq!(
+            chain.end,
+            FramePointerChainEnd::Broken(Error::FramePointerBelowStackPointer(0x8))
+        );
+        assert!(!chain.is_intact());
+    }
+
+    #[test]
+    fn test_module_stub_rules() {
+        // A JIT region without code bytes, whose stubs push one value before they jump.
+        let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        unwinder.add_module(test_module(0x100000..0x100400, None));
+        unwinder.add_plt_range(0x100000..0x100020);
+        let stack = TestStack::from([1, 2, 0, 0x100200, 0x40, 0x100100, 7, 8, 0x0, 0x0]);
+        let mut read_stack = |addr| stack.read(addr);
+        let mut cache = CacheX86_64::new();
+        let regs = UnwindRegsX86_64::new(0x100010, 0x10, 0x20);
+
+        // The default stub rule mistakes the pushed value for a null return address.
+        let mut iter = unwinder.iter_frames(0x100010, regs, &mut cache, &mut read_stack);
+        assert_eq!(iter.by_ref().count(), Ok(1));
+
+        let stub_rules = StubRules {
+            stub_functions: UnwindRuleX86_64::OffsetSp { sp_offset_by_8: 2 },
+            function_start: UnwindRuleX86_64::JustReturn,
+        };
+        unwinder.set_module_stub_rules(0x100000, Some(stub_rules));
+        let mut iter = unwinder.iter_frames(0x100010, regs, &mut cache, &mut read_stack);
+        assert_eq!(
+            iter.by_ref().collect::<Vec<_>>(),
+            Ok(vec![
+                FrameAddress::from_instruction_pointer(0x100010),
+                FrameAddress::from_return_address(0x100200).unwrap(),
+                FrameAddress::from_return_address(0x100100).unwrap(),
+            ])
+        );
+
+        unwinder.set_module_stub_rules(0x100000, None);
+        let mut iter = unwinder.iter_frames(0x100010, regs, &mut cache, &mut read_stack);
+        assert_eq!(iter.by_ref().count(), Ok(1));
+    }
+
+    #[test]
+    fn test_x32() {
+        // The upper halves of the 8-byte slots hold garbage, which x32 code ignores.
+        let garbage = 0xdead_beef_0000_0000;
+        let stack = TestStack::from([
+            1,
+            2,
+            0x100300,
+            4,
+            garbage | 0x40,
+            garbage | 0x100200,
+            5,
+            6,
+            garbage | 0x70,
+            garbage | 0x100100,
+            7,
+            8,
+            9,
+            10,
+            garbage,
+            garbage,
+        ]);
+        let mut read_stack = |addr| stack.read(addr);
+        let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        unwinder.set_x32(true);
+        let mut cache = CacheX86_64::new();
+        let regs = UnwindRegsX86_64::new(0x100400, 0x10, 0x20);
+        let iter = unwinder.iter_frames(0x100400, regs, &mut cache, &mut read_stack);
+        assert_eq!(
+            iter.collect::<Vec<_>>(),
+            Ok(vec![
+                FrameAddress::from_instruction_pointer(0x100400),
+                FrameAddress::from_return_address(0x100200).unwrap(),
+                FrameAddress::from_return_address(0x100100).unwrap(),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_call_site_address() {
+        // nop; call 0x1234; nop
+        let text = vec![0x90, 0xe8, 0x34, 0x12, 0x00, 0x00, 0x90];
+        let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        unwinder.add_module(test_module(0x100000..0x100007, Some(text)));
+        let return_address = FrameAddress::from_return_address(0x100006).unwrap();
+        assert_eq!(unwinder.call_site_address(return_address), 0x100001);
+        let return_address = FrameAddress::from_return_address(0x100007).unwrap();
+        assert_eq!(unwinder.call_site_address(return_address), 0x100006);
+        let ip = FrameAddress::from_instruction_pointer(0x100006);
+        assert_eq!(unwinder.call_site_address(ip), 0x100006);
+
+        let return_address = FrameAddress::from_return_address(0x100006).unwrap();
+        assert_eq!(unwinder.is_preceded_by_call(return_address), Some(true));
+        let return_address = FrameAddress::from_return_address(0x100007).unwrap();
+        assert_eq!(unwinder.is_preceded_by_call(return_address), Some(false));
+        let return_address = FrameAddress::from_return_address(0x200000).unwrap();
+        assert_eq!(unwinder.is_preceded_by_call(return_address), None);
+        assert_eq!(unwinder.is_preceded_by_call(ip), None);
+
+        // Code bytes can be removed and attached later.
+        unwinder.set_module_text_data(0x100000, None);
+        let return_address = FrameAddress::from_return_address(0x100006).unwrap();
+        assert_eq!(unwinder.call_site_address(return_address), 0x100005);
+        let text = vec![0x90, 0xe8, 0x34, 0x12, 0x00, 0x00, 0x90];
+        unwinder.set_module_text_data(0x100000, Some(TextByteData::new(text, 0x100000..0x100007)));
+        assert_eq!(unwinder.call_site_address(return_address), 0x100001);
+    }
+
+    #[test]
+    fn test_unwind_mode() {
+        let stack = TestStack::frame_chain();
+        let mut read_stack = |addr| stack.read(addr);
+        let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        // An empty .eh_frame, which covers no address.
+        unwinder.add_module(Module::new(
+            "lib".to_string(),
+            0x100000..0x101000,
+            0x100000,
+            ModuleSvmaInfo {
+                text: Some(0..0x1000),
+                eh_frame: Some(0x1000..0x1000),
+                ..Default::default()
+            },
+            ModuleUnwindData::EhFrame(Vec::new()),
+            None,
+        ));
+        let mut cache = CacheX86_64::new();
+        let regs = UnwindRegsX86_64::new(0x100400, 0x10, 0x20);
+        let mut iter = unwinder.iter_frames(0x100400, regs, &mut cache, &mut read_stack);
+        assert_eq!(
+            iter.next(),
+            Ok(Some(FrameAddress::from_instruction_pointer(0x100400)))
+        );
+        assert_eq!(
+            iter.next(),
+            Ok(Some(FrameAddress::from_return_address(0x100200).unwrap()))
+        );
+
+        unwinder.set_mode(UnwindMode::Strict);
+        let mut iter = unwinder.iter_frames(0x100400, regs, &mut cache, &mut read_stack);
+        assert_eq!(
+            iter.next(),
+            Ok(Some(FrameAddress::from_instruction_pointer(0x100400)))
+        );
+        let result = iter.next();
+        assert_eq!(
+            result,
+            Err(Error::UnusableUnwindInfo(ModuleError {
+                address: 0x100400,
+                module_avma_range_start: 0x100000,
+                relative_address: 0x400,
+                error: UnwinderError::DwarfCfiIndexCouldNotFindAddress,
+            }))
+        );
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "The unwind information is unusable: Failed to look up the address in the \
+             DwarfCfiIndex search table at 0x100400 (module 0x100000 + 0x400)"
+        );
+    }
+
+    #[test]
+    fn test_unusable_unwind_info_error() {
+        let stack = TestStack::frame_chain();
+        let mut read_stack = |addr| stack.read(addr);
+        let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        // An empty .eh_frame, which covers no address.
+        unwinder.add_module(eh_frame_module(Vec::new(), None));
+        unwinder.set_mode(UnwindMode::Strict);
+        let mut cache = CacheX86_64::new();
+
+        // Return addresses are looked up inside the call instruction.
+        let mut regs = UnwindRegsX86_64::new(0x100301, 0x10, 0x20);
+        let error = unwinder
+            .unwind_frame(
+                FrameAddress::from_return_address(0x100301).unwrap(),
+                &mut regs,
+                &mut cache,
+                &mut read_stack,
+            )
+            .unwrap_err();
+        let module_error = ModuleError {
+            address: 0x100300,
+            module_avma_range_start: 0x100000,
+            relative_address: 0x300,
+            error: UnwinderError::DwarfCfiIndexCouldNotFindAddress,
+        };
+        assert_eq!(error, Error::UnusableUnwindInfo(module_error));
+
+        // The error chain leads to the cause.
+        let source = std::error::Error::source(&error).unwrap();
+        assert_eq!(source.to_string(), module_error.to_string());
+        let cause = source.source().unwrap();
+        assert_eq!(
+            cause.to_string(),
+            UnwinderError::DwarfCfiIndexCouldNotFindAddress.to_string()
+        );
+    }
+
+    #[test]
+    fn test_cold_function_part() {
+        let stack = TestStack::from([0, 0, 0x100300, 0, 0x100200, 0, 0, 0]);
+        let mut read_stack = |addr| stack.read(addr);
+        let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        unwinder.add_module(eh_frame_module(
+            hot_and_cold_eh_frame(),
+            Some(vec![0x90; 0x1000]),
+        ));
+        let mut cache = CacheX86_64::new();
+
+        // At the start of the cold part, the return address is where the CFI says, not
+        // where it would be at a function entry.
+        let regs = UnwindRegsX86_64::new(0x100400, 0x10, 0x20);
+        let mut iter = unwinder.iter_frames(0x100400, regs, &mut cache, &mut read_stack);
+        assert_eq!(
+            iter.next(),
+            Ok(Some(FrameAddress::from_instruction_pointer(0x100400)))
+        );
+        assert_eq!(
+            iter.next(),
+            Ok(Some(FrameAddress::from_return_address(0x100200).unwrap()))
+        );
+
+        let info = |address| {
+            unwinder
+                .exception_handling_info(
+                    FrameAddress::from_instruction_pointer(address),
+                    &mut CacheX86_64::new(),
+                )
+                .unwrap()
+                .unwrap()
+        };
+        assert_eq!(info(0x100408).function_start, 0x100400);
+        assert!(!info(0x100408).is_function_entry);
+        assert!(info(0x100308).is_function_entry);
+    }
+
+    #[test]
+    fn test_unwind_limits() {
+        let mut eh_frame = hot_and_cold_eh_frame();
+        #[rustfmt::skip]
+        eh_frame.extend([
+            // FDE for 0x500..0x510, whose CFA expression loops forever:
+            // DW_CFA_def_cfa_expression (DW_OP_skip -3).
+            0x1c, 0x00, 0x00, 0x00, 0x4c, 0x00, 0x00, 0x00,
+            0x00, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x0f, 0x03, 0x2f, 0xfd, 0xff, 0x00, 0x00, 0x00,
+        ]);
+        let stack = TestStack::frame_chain();
+        let mut read_stack = |addr| stack.read(addr);
+        let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        unwinder.add_module(eh_frame_module(eh_frame, None));
+        unwinder.set_mode(UnwindMode::Strict);
+        let mut cache = CacheX86_64::new();
+        let mut unwind = |unwinder: &UnwinderX86_64<Vec<u8>>, address| {
+            let mut regs = UnwindRegsX86_64::new(address, 0x10, 0x20);
+            unwinder.unwind_frame(
+                FrameAddress::from_instruction_pointer(address),
+                &mut regs,
+                &mut cache,
+                &mut read_stack,
+            )
+        };
+        let unusable = |address, error| {
+            Err(Error::UnusableUnwindInfo(ModuleError {
+                address,
+                module_avma_range_start: 0x100000,
+                relative_address: (address - 0x100000) as u32,
+                error: UnwinderError::Dwarf(error),
+            }))
+        };
+
+        // The expression step limit ends the loop.
+        assert_eq!(
+            unwind(&unwinder, 0x100500),
+            unusable(0x100500, DwarfUnwinderError::CouldNotRecoverCfa)
+        );
+
+        // The CIE has four instructions, including padding, and the FDE of the cold
+        // part has three more.
+        unwinder.set_limits(UnwindLimits {
+            max_cfi_instructions_per_frame: 5,
+            ..Default::default()
+        });
+        assert_eq!(unwind(&unwinder, 0x100300), Ok(Some(0x100300)));
+        assert_eq!(
+            unwind(&unwinder, 0x100400),
+            unusable(0x100400, DwarfUnwinderError::TooManyCfiInstructions)
+        );
+
+        // In lenient mode, the frame pointer fallback is cached. Raising the limit
+        // again invalidates it, and the CFI rule reads the return address from sp+16.
+        unwinder.set_mode(UnwindMode::Lenient);
+        assert_eq!(unwind(&unwinder, 0x100400), Ok(Some(0x100200)));
+        unwinder.set_limits(UnwindLimits::default());
+        assert_eq!(unwind(&unwinder, 0x100400), Ok(Some(0x40)));
+    }
+
+    #[test]
+    fn test_malformed_unwind_data() {
+        let stack = TestStack::frame_chain();
+        let mut read_stack = |addr| stack.read(addr);
+        let mut cache = CacheX86_64::new();
+        let mut unwind_all = |unwinder: &UnwinderX86_64<Vec<u8>>| {
+            for address in [0x100000, 0x100300, 0x100400, 0x100fff] {
+                let mut regs = UnwindRegsX86_64::new(address, 0x10, 0x20);
+                let _ = unwinder.unwind_frame(
+                    FrameAddress::from_instruction_pointer(address),
+                    &mut regs,
+                    &mut cache,
+                    &mut read_stack,
+                );
+            }
+        };
+
+        // Every truncation of a valid .eh_frame.
+        let eh_frame = hot_and_cold_eh_frame();
+        for len in 0..eh_frame.len() {
+            let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+            unwinder.add_module(eh_frame_module(eh_frame[..len].to_vec(), None));
+            unwind_all(&unwinder);
+        }
+
+        // Section addresses below the base address, and a base address which makes the
+        // lookup address wrap around.
+        for (base_svma, unwind_data) in [
+            (
+                0x2000,
+                ModuleUnwindData::CompactUnwindInfoAndEhFrame(Vec::new(), None),
+            ),
+            (u64::MAX - 0xff, ModuleUnwindData::EhFrame(eh_frame.clone())),
+        ] {
+            let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+            unwinder.add_module(Module::new(
+                "lib".to_string(),
+                0x100000..0x101000,
+                0x100000,
+                ModuleSvmaInfo {
+                    base_svma,
+                    text: Some(0..0x1000),
+                    stubs: Some(0x100..0x200),
+                    stub_helper: Some(0x200..0x300),
+                    eh_frame: Some(0x1000..0x1000 + eh_frame.len() as u64),
+                    ..Default::default()
+                },
+                unwind_data,
+                Some(TextByteData::new(vec![0x90; 0x1000], 0x100000..0x101000)),
+            ));
+            unwind_all(&unwinder);
+        }
+    }
+
+    #[test]
+    fn test_fpo() {
+        use crate::x86::{CacheX86, UnwindRegsX86, UnwinderX86};
+
+        let fpo_record = |function_start: u32, locals_size_by_4: u32, bits: u16| {
+            let mut record = Vec::new();
+            record.extend_from_slice(&function_start.to_le_bytes());
+            record.extend_from_slice(&0x100u32.to_le_bytes());
+            record.extend_from_slice(&locals_size_by_4.to_le_bytes());
+            record.extend_from_slice(&0u16.to_le_bytes());
+            record.extend_from_slice(&bits.to_le_bytes());
+            record
+        };
+        let unwinder_with_fpo_data = |fpo_data: Vec<u8>| {
+            let mut unwinder = UnwinderX86::<Vec<u8>>::new();
+            unwinder.add_module(Module::new(
+                "lib.dll".to_string(),
+                0x400000..0x410000,
+                0x400000,
+                ModuleSvmaInfo {
+                    text: Some(0x1000..0x10000),
+                    address_size: 4,
+                    ..Default::default()
+                },
+                ModuleUnwindData::Fpo(fpo_data),
+                None,
+            ));
+            unwinder
+        };
+
+        // The stack has 4 byte slots, starting at 0x100. The function without a record
+        // at 0x403000 falls back to the ebp chain.
+        let slots: [u32; 10] = [1, 2, 3, 0x402020, 4, 5, 0x120, 0x403000, 0, 0];
+        let stack: Vec<u8> = slots.iter().flat_map(|slot| slot.to_le_bytes()).collect();
+        let mut read_stack = |addr: u64| {
+            let offset = addr.checked_sub(0x100).ok_or(())? as usize;
+            let bytes = stack.get(offset..offset + 8).ok_or(())?;
+            Ok(u64::from_le_bytes(bytes.try_into().map_err(|_| ())?))
+        };
+        let regs = UnwindRegsX86::new(0x401010, 0x100, 0x118);
+
+        // A frameless function with 3 locals and no saved registers, and a function
+        // with an ebp frame.
+        let unwinder = unwinder_with_fpo_data(
+            [fpo_record(0x1000, 3, 0x0000), fpo_record(0x2000, 0, 0xd000)].concat(),
+        );
+        let mut cache = CacheX86::new();
+        let mut iter = unwinder.iter_frames(0x401010, regs, &mut cache, &mut read_stack);
+        let mut frames = Vec::new();
+        while let Ok(Some(frame)) = iter.next() {
+            frames.push(frame);
+        }
+        assert_eq!(
+            frames,
+            vec![
+                FrameAddress::from_instruction_pointer(0x401010),
+                FrameAddress::from_return_address(0x402020).unwrap(),
+                FrameAddress::from_return_address(0x403000).unwrap(),
+            ]
+        );
+
+        let mut dump = String::new();
+        unwinder
+            .dump_unwind_info(
+                FrameAddress::from_instruction_pointer(0x401010),
+                &mut cache,
+                &mut dump,
+            )
+            .unwrap();
+        assert!(dump.contains("sp' = sp + 0x10; bp' = bp; ra = *(sp' - 4)"));
+
+        // If the frameless function has 2 locals and 1 saved register instead, ebp
+        // may have been that register, and 0x118 may be any value it was overwritten
+        // with. The ebp chain isn't followed from there.
+        let unwinder = unwinder_with_fpo_data(
+            [fpo_record(0x1000, 2, 0x0100), fpo_record(0x2000, 0, 0xd000)].concat(),
+        );
+        let mut cache = CacheX86::new();
+        let mut iter = unwinder.iter_frames(0x401010, regs, &mut cache, &mut read_stack);
+        let mut frames = Vec::new();
+        while let Ok(Some(frame)) = iter.next() {
+            frames.push(frame);
+        }
+        assert_eq!(
+            frames,
+            vec![
+                FrameAddress::from_instruction_pointer(0x401010),
+                FrameAddress::from_return_address(0x402020).unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lazy_module() {
+        use std::sync::atomic::AtomicUsize;
+
+        let stack = TestStack::frame_chain();
+        let mut read_stack = |addr| stack.read(addr);
+        let loads = Arc::new(AtomicUsize::new(0));
+        let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        unwinder.set_mode(UnwindMode::Strict);
+        unwinder.add_module(Module::new_lazy(
+            "lib".to_string(),
+            0x100000..0x101000,
+            0x100000,
+            ModuleSvmaInfo {
+                text: Some(0..0x1000),
+                eh_frame: Some(0x1000..0x1000),
+                ..Default::default()
+            },
+            {
+                let loads = loads.clone();
+                move || {
+                    loads.fetch_add(1, Ordering::Relaxed);
+                    // An empty .eh_frame, which covers no address.
+                    ModuleUnwindData::EhFrame(Vec::new())
+                }
+            },
+            None,
+        ));
+        assert_eq!(loads.load(Ordering::Relaxed), 0);
+
+        let mut cache = CacheX86_64::new();
+        let regs = UnwindRegsX86_64::new(0x100400, 0x10, 0x20);
+        let unusable = Err(Error::UnusableUnwindInfo(ModuleError {
+            address: 0x100400,
+            module_avma_range_start: 0x100000,
+            relative_address: 0x400,
+            error: UnwinderError::DwarfCfiIndexCouldNotFindAddress,
+        }));
+        let mut second_frame = |unwinder: &UnwinderX86_64<Vec<u8>>| {
+            let mut iter = unwinder.iter_frames(0x100400, regs, &mut cache, &mut read_stack);
+            iter.next().unwrap();
+            iter.next()
+        };
+        let frame_pointer = Ok(Some(FrameAddress::from_return_address(0x100200).unwrap()));
+
+        // Unwinding doesn't load the data; until it is loaded, the module is unwound
+        // like a module without unwind data, even in strict mode.
+        assert_eq!(second_frame(&unwinder), frame_pointer);
+        assert_eq!(loads.load(Ordering::Relaxed), 0);
+
+        unwinder.load_module_unwind_data(0x100000);
+        assert_eq!(loads.load(Ordering::Relaxed), 1);
+        assert_eq!(second_frame(&unwinder), unusable);
+        unwinder.load_module_unwind_data(0x100000);
+        assert_eq!(second_frame(&unwinder), unusable);
+        assert_eq!(loads.load(Ordering::Relaxed), 1);
+
+        // Unloading drops the cached rules of the data.
+        unwinder.unload_module_unwind_data(0x100000);
+        assert_eq!(second_frame(&unwinder), frame_pointer);
+        unwinder.load_module_unwind_data(0x100000);
+        assert_eq!(second_frame(&unwinder), unusable);
+        assert_eq!(loads.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_module_alias() {
+        let stack = TestStack::frame_chain();
+        let mut read_stack = |addr| stack.read(addr);
+        let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        unwinder.set_mode(UnwindMode::Strict);
+        unwinder.add_module(Module::new(
+            "jit".to_string(),
+            0x100000..0x101000,
+            0x100000,
+            ModuleSvmaInfo {
+                text: Some(0..0x1000),
+                eh_frame: Some(0x1000..0x1000),
+                ..Default::default()
+            },
+            // An empty .eh_frame, which covers no address.
+            ModuleUnwindData::EhFrame(Vec::new()),
+            None,
+        ));
+        unwinder.add_module_alias(0x900000..0x901000, 0x100000);
+        assert_eq!(
+            unwinder.module_relative_address(0x900400),
+            Some((0x100000, 0x400))
+        );
+
+        // The alias address is looked up in the module's unwind data.
+        let mut cache = CacheX86_64::new();
+        let regs = UnwindRegsX86_64::new(0x900400, 0x10, 0x20);
+        let mut iter = unwinder.iter_frames(0x900400, regs, &mut cache, &mut read_stack);
+        assert_eq!(
+            iter.next(),
+            Ok(Some(FrameAddress::from_instruction_pointer(0x900400)))
+        );
+        assert_eq!(
+            iter.next(),
+            Err(Error::UnusableUnwindInfo(ModuleError {
+                address: 0x100400,
+                module_avma_range_start: 0x100000,
+                relative_address: 0x400,
+                error: UnwinderError::DwarfCfiIndexCouldNotFindAddress,
+            }))
+        );
+
+        unwinder.remove_module_alias(0x900000);
+        assert_eq!(unwinder.module_relative_address(0x900400), None);
+        let mut iter = unwinder.iter_frames(0x900400, regs, &mut cache, &mut read_stack);
+        iter.next().unwrap();
+        assert_eq!(
+            iter.next(),
+            Ok(Some(FrameAddress::from_return_address(0x100200).unwrap()))
+        );
+    }
+
+    #[test]
+    fn test_module_events() {
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        let subscription = unwinder.subscribe_module_events({
+            let events = events.clone();
+            move |event| events.lock().unwrap().push(event.clone())
+        });
+        let svma_info = ModuleSvmaInfo::default();
+        unwinder.add_module(Module::new(
+            "lib".to_string(),
+            0x100000..0x101000,
+            0x100000,
+            svma_info,
+            ModuleUnwindData::None,
+            None,
+        ));
+        unwinder.add_module_alias(0x900000..0x901000, 0x100000);
+        unwinder.rebase_module(0x100000, 0x200000);
+        assert_eq!(
+            unwinder.module_relative_address(0x200400),
+            Some((0x200000, 0x400))
+        );
+        assert_eq!(
+            unwinder.module_relative_address(0x900400),
+            Some((0x200000, 0x400))
+        );
+        // Unknown modules don't produce events.
+        unwinder.remove_module(0x100000);
+        unwinder.remove_module(0x200000);
+        unwinder.unsubscribe_module_events(subscription);
+        unwinder.add_module_alias(0x900000..0x901000, 0x300000);
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                ModuleEvent::Added {
+                    name: "lib".to_string(),
+                    avma_range: 0x100000..0x101000,
+                },
+                ModuleEvent::AliasAdded {
+                    alias_avma_range: 0x900000..0x901000,
+                    module_avma_range_start: 0x100000,
+                },
+                ModuleEvent::Rebased {
+                    old_avma_range: 0x100000..0x101000,
+                    new_avma_range: 0x200000..0x201000,
+                },
+                ModuleEvent::Removed {
+                    avma_range: 0x200000..0x201000,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_untrusted_first_frame_pointer() {
+        let stack = TestStack::frame_chain();
+        let mut read_stack = |addr| stack.read(addr);
+        let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        unwinder.add_module(test_module(0x100000..0x100400, None));
+        let mut cache = CacheX86_64::new();
+        let regs = UnwindRegsX86_64::new(0x100300, 0x10, 0x20);
+        unwinder.set_trust_first_frame_pointer(false);
+        let mut iter = unwinder.iter_frames(0x100300, regs, &mut cache, &mut read_stack);
+        assert_eq!(
+            iter.next(),
+            Ok(Some(FrameAddress::from_instruction_pointer(0x100300)))
+        );
+        assert_eq!(iter.next(), Err(Error::UntrustedFramePointer));
+        assert_eq!(
+            iter.end_reason(),
+            Some(UnwindEndReason::Error(Error::UntrustedFramePointer))
+        );
+
+        // The fallback rule wasn't cached, so it is still refused for the first frame.
+        let mut iter = unwinder.iter_frames(0x100300, regs, &mut cache, &mut read_stack);
+        iter.next().unwrap();
+        assert_eq!(iter.next(), Err(Error::UntrustedFramePointer));
+
+        unwinder.set_trust_first_frame_pointer(true);
+        let iter = unwinder.iter_frames(0x100300, regs, &mut cache, &mut read_stack);
+        assert_eq!(iter.count(), Ok(3));
+    }
+
+    #[test]
+    fn test_describe_address() {
+        let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        let svma_info = ModuleSvmaInfo::default();
+        unwinder.add_module(Module::new(
+            "libxul.so".to_string(),
+            0x100000..0x100400,
+            0x100000,
+            svma_info.clone(),
+            ModuleUnwindData::None,
+            None,
+        ));
+        unwinder.add_module(Module::new(
+            String::new(),
+            0x200000..0x200400,
+            0x200000,
+            svma_info,
+            ModuleUnwindData::None,
+            None,
+        ));
+        assert_eq!(unwinder.describe_address(0x101234), "0x101234");
+        assert_eq!(unwinder.describe_address(0x100234), "libxul.so+0x234");
+        assert_eq!(unwinder.describe_address(0x200234), "0x200234");
+        let error = Error::UnusableUnwindInfo(ModuleError {
+            address: 0x100234,
+            module_avma_range_start: 0x100000,
+            relative_address: 0x234,
+            error: UnwinderError::NoModuleUnwindData,
+        });
+        assert_eq!(
+            unwinder.describe_error(&error),
+            "The unwind information is unusable: No unwind data for the module containing \
+             the address at libxul.so+0x234"
+        );
+        assert_eq!(
+            unwinder.describe_error(&Error::DidNotAdvance),
+            Error::DidNotAdvance.to_string()
+        );
+    }
+
+    #[test]
+    fn test_step_out() {
+        let unwinder = UnwinderX86_64::<Vec<u8>>::new();
+        let stack = TestStack::from([1, 2, 0x100300, 4, 0x40, 0x100200, 5, 6, 0x0, 0x0]);
+        let mut read_stack = |addr| stack.read(addr);
+        let mut cache = CacheX86_64::new();
+        let regs = UnwindRegsX86_64::new(0x100300, 0x10, 0x20);
+        let address = FrameAddress::from_instruction_pointer(0x100300);
+        let (caller, caller_regs) = unwinder
+            .step_out(address, &regs, &mut cache, &mut read_stack)
+            .unwrap()
+            .unwrap();
+        assert_eq!(caller, FrameAddress::from_return_address(0x100200).unwrap());
+        assert_eq!(caller_regs, UnwindRegsX86_64::new(0x100200, 0x30, 0x40));
+        // The callee's registers are unchanged.
+        assert_eq!(regs, UnwindRegsX86_64::new(0x100300, 0x10, 0x20));
+
+        let caller_regs = UnwindRegsX86_64::new(0x100200, 0x30, 0x40);
+        assert_eq!(
+            unwinder.step_out(caller, &caller_regs, &mut cache, &mut read_stack),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn test_aarch64_kernel_rt_sigreturn() {
+        use crate::aarch64::{CacheAarch64, UnwindRegsAarch64, UnwinderAarch64};
+
+        // A vDSO with a nop followed by __kernel_rt_sigreturn at 0x10004.
+        let text = vec![
+            0x1f, 0x20, 0x03, 0xd5, 0x68, 0x11, 0x80, 0xd2, 0x01, 0x00, 0x00, 0xd4,
+        ];
+        let mut unwinder = UnwinderAarch64::<Vec<u8>>::new();
+        unwinder.add_module(test_module(0x10000..0x1000c, Some(text)));
+
+        // The rt_sigframe is at sp, with the interrupted x29, x30, sp and pc
+        // stored in its sigcontext.
+        let stack = TestStack::zeroed(72)
+            .with(0x220, 0x1234)
+            .with(0x228, 0x100600)
+            .with(0x230, 0x400)
+            .with(0x238, 0x100500);
+        let mut read_stack = |addr| stack.read(addr);
+        let mut cache = CacheAarch64::new();
+
+        // The signal handler returned to the start of the trampoline.
+        let mut regs = UnwindRegsAarch64::new(0x10004, 0x0, 0x20);
+        let res = unwinder.unwind_frame_with_confidence(
+            FrameAddress::from_return_address(0x10004).unwrap(),
+            &mut regs,
+            &mut cache,
+            &mut read_stack,
+        );
+        assert_eq!(
+            res,
+            Ok(Some((
+                FrameAddress::from_instruction_pointer(0x100500),
+                FrameConfidence::Exact
+            )))
+        );
+        assert_eq!(regs.lr(), 0x100600);
+        assert_eq!(regs.sp(), 0x400);
+        assert_eq!(regs.fp(), 0x1234);
+
+        // The sample was taken while executing the svc instruction.
+        let mut regs = UnwindRegsAarch64::new(0x10004, 0x0, 0x20);
+        let res = unwinder.unwind_frame(
+            FrameAddress::from_instruction_pointer(0x10008),
+            &mut regs,
+            &mut cache,
+            &mut read_stack,
+        );
+        assert_eq!(res, Ok(Some(0x100500)));
+    }
+
+    #[test]
+    fn test_late_text_data() {
+        use crate::aarch64::{CacheAarch64, UnwindRegsAarch64, UnwinderAarch64};
+
+        // The vDSO from test_aarch64_kernel_rt_sigreturn, whose code bytes are only
+        // attached after a sample in it was unwound.
+        let text = vec![
+            0x1f, 0x20, 0x03, 0xd5, 0x68, 0x11, 0x80, 0xd2, 0x01, 0x00, 0x00, 0xd4,
+        ];
+        let mut unwinder = UnwinderAarch64::<Vec<u8>>::new();
+        unwinder.add_module(test_module(0x10000..0x1000c, None));
+        let stack = TestStack::zeroed(72)
+            .with(0x220, 0x1234)
+            .with(0x228, 0x100600)
+            .with(0x230, 0x400)
+            .with(0x238, 0x100500);
+        let mut read_stack = |addr| stack.read(addr);
+        let mut cache = CacheAarch64::new();
+        let mut unwind = |unwinder: &UnwinderAarch64<Vec<u8>>| {
+            let mut regs = UnwindRegsAarch64::new(0x10004, 0x0, 0x20);
+            unwinder.unwind_frame(
+                FrameAddress::from_instruction_pointer(0x10008),
+                &mut regs,
+                &mut cache,
+                &mut read_stack,
+            )
+        };
+
+        // Without the code bytes, the trampoline isn't recognized, and the frame
+        // pointer doesn't lead anywhere.
+        assert_eq!(unwind(&unwinder), Ok(None));
+
+        // The rule which was cached for the address is not used anymore.
+        unwinder.set_module_text_data(0x10000, Some(TextByteData::new(text, 0x10000..0x1000c)));
+        assert_eq!(unwind(&unwinder), Ok(Some(0x100500)));
+    }
 }
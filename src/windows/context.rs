@@ -0,0 +1,136 @@
+use windows_sys::Win32::Foundation::HANDLE;
+use windows_sys::Win32::System::Diagnostics::Debug::{GetThreadContext, CONTEXT};
+
+#[cfg(target_arch = "aarch64")]
+use crate::aarch64::UnwindRegsAarch64;
+#[cfg(target_arch = "x86_64")]
+use crate::x86_64::UnwindRegsX86_64;
+use crate::UnwindRegsNative;
+
+/// `CONTEXT_AMD64 | CONTEXT_CONTROL | CONTEXT_INTEGER`. We need rip and rsp from the
+/// control registers and rbp from the integer registers.
+#[cfg(target_arch = "x86_64")]
+const CONTEXT_FLAGS_FOR_UNWINDING: u32 = 0x0010_0000 | 0x1 | 0x2;
+
+/// `CONTEXT_ARM64 | CONTEXT_CONTROL | CONTEXT_INTEGER`. We need pc, sp, fp and lr;
+/// fp and lr are part of the control registers on ARM64.
+#[cfg(target_arch = "aarch64")]
+const CONTEXT_FLAGS_FOR_UNWINDING: u32 = 0x0040_0000 | 0x1 | 0x2;
+
+/// Capture the registers of the given thread with `GetThreadContext` and convert them
+/// into the instruction pointer and the unwind registers.
+///
+/// The thread handle needs `THREAD_GET_CONTEXT` access, and the thread should be
+/// suspended with `SuspendThread` while the registers are read and for as long as its
+/// stack is being read. Only threads of the native CPU architecture are supported.
+pub fn get_thread_regs(thread: HANDLE) -> std::io::Result<(u64, UnwindRegsNative)> {
+    // Safety: CONTEXT is a plain C struct; all-zero is a valid value.
+    let mut context: CONTEXT = unsafe { std::mem::zeroed() };
+    context.ContextFlags = CONTEXT_FLAGS_FOR_UNWINDING;
+    // Safety: context is a properly aligned CONTEXT for the native architecture.
+    if unsafe { GetThreadContext(thread, &mut context) } == 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(unwind_regs_from_context(&context))
+}
+
+/// Convert a `CONTEXT` into the instruction pointer and the unwind registers.
+pub fn unwind_regs_from_context(context: &CONTEXT) -> (u64, UnwindRegsNative) {
+    #[cfg(target_arch = "x86_64")]
+    let pc = context.Rip;
+    #[cfg(target_arch = "aarch64")]
+    let pc = context.Pc;
+    (pc, UnwindRegsNative::from(context))
+}
+
+/// Requires `CONTEXT_CONTROL` and `CONTEXT_INTEGER` to have been captured.
+#[cfg(target_arch = "x86_64")]
+impl From<&CONTEXT> for UnwindRegsX86_64 {
+    fn from(context: &CONTEXT) -> Self {
+        UnwindRegsX86_64::new(context.Rip, context.Rsp, context.Rbp)
+    }
+}
+
+/// Requires `CONTEXT_CONTROL` to have been captured.
+#[cfg(target_arch = "aarch64")]
+impl From<&CONTEXT> for UnwindRegsAarch64 {
+    fn from(context: &CONTEXT) -> Self {
+        // Safety: Both union variants describe the same 31 general purpose registers.
+        let (fp, lr) = unsafe { (context.Anonymous.X[29], context.Anonymous.X[30]) };
+        UnwindRegsAarch64::new(lr, context.Sp, fp)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use windows_sys::Win32::System::Threading::{ResumeThread, SuspendThread};
+
+    use super::*;
+    use crate::test_utils::with_blocked_thread;
+
+    #[test]
+    fn test_get_thread_regs() {
+        let result = with_blocked_thread(|thread| {
+            // Safety: The handle belongs to a live thread, which is resumed right after.
+            unsafe { SuspendThread(thread) };
+            let result = get_thread_regs(thread);
+            unsafe { ResumeThread(thread) };
+            result
+        });
+        let (pc, regs) = result.unwrap();
+        assert_ne!(pc, 0);
+        assert_ne!(regs.sp(), 0);
+    }
+
+    #[test]
+    fn test_get_thread_regs_invalid_handle() {
+        assert!(get_thread_regs(0).is_err());
+    }
+
+    fn zeroed_context() -> CONTEXT {
+        // Safety: CONTEXT is a plain C struct; all-zero is a valid value.
+        unsafe { std::mem::zeroed() }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_unwind_regs_from_context() {
+        let mut context = zeroed_context();
+        context.Rip = 0x100200;
+        context.Rsp = 0x7ff0;
+        context.Rbp = 0x8010;
+        context.Rax = 0x1234;
+
+        let regs = UnwindRegsX86_64::from(&context);
+        assert_eq!(regs.ip(), 0x100200);
+        assert_eq!(regs.sp(), 0x7ff0);
+        assert_eq!(regs.bp(), 0x8010);
+
+        let (pc, regs) = unwind_regs_from_context(&context);
+        assert_eq!(pc, 0x100200);
+        assert_eq!(regs.sp(), 0x7ff0);
+        assert_eq!(regs.bp(), 0x8010);
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    #[test]
+    fn test_unwind_regs_from_context() {
+        let mut context = zeroed_context();
+        let mut x = [0; 31];
+        x[0] = 0x1234;
+        x[29] = 0x8010;
+        x[30] = 0x100104;
+        context.Anonymous.X = x;
+        context.Pc = 0x100200;
+        context.Sp = 0x7ff0;
+
+        let regs = UnwindRegsAarch64::from(&context);
+        assert_eq!(regs.lr(), 0x100104);
+        assert_eq!(regs.sp(), 0x7ff0);
+        assert_eq!(regs.fp(), 0x8010);
+
+        let (pc, regs) = unwind_regs_from_context(&context);
+        assert_eq!(pc, 0x100200);
+        assert_eq!(regs.lr(), 0x100104);
+    }
+}
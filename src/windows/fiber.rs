@@ -0,0 +1,86 @@
+use std::ops::Range;
+
+use windows_sys::Win32::System::Diagnostics::Debug::CONTEXT;
+
+use super::context::unwind_regs_from_context;
+use super::thread_start::function_range;
+use crate::{FrameAddress, UnwindRegsNative};
+
+/// The functions that every fiber starts in, innermost last. `BaseFiberStart` only
+/// exists on older versions of Windows.
+const FIBER_START_FUNCTIONS: [(&[u8], &[u8]); 2] = [
+    (b"ntdll.dll\0", b"RtlUserFiberStart\0"),
+    (b"kernel32.dll\0", b"BaseFiberStart\0"),
+];
+
+/// Return the address ranges of the functions at the bottom of every fiber's stack.
+///
+/// Pass them to both `add_root_range` and `add_stack_switch_range` of the unwinder.
+/// Stacks of fibers then end cleanly at the fiber start function, and an
+/// [`UnwindIterator::with_stack_switch_handler`](crate::UnwindIterator::with_stack_switch_handler)
+/// handler gets the chance to continue the walk on the stack of the fiber that
+/// switched to the current one, for example with [`fiber_resume_frame`]. Fiber-based
+/// job systems otherwise only get the frames of the current job.
+///
+/// Like [`thread_start_root_ranges`](super::thread_start_root_ranges), the addresses
+/// are looked up in the current process.
+pub fn fiber_start_ranges() -> Vec<Range<u64>> {
+    FIBER_START_FUNCTIONS
+        .iter()
+        .filter_map(|(module_name, function_name)| function_range(module_name, function_name))
+        .collect()
+}
+
+/// Convert the `CONTEXT` that `SwitchToFiber` saved for a suspended fiber into the
+/// frame at which that fiber continues, and the unwind registers for that frame.
+///
+/// `SwitchToFiber` saves the state that it restores when it switches back, i.e. the
+/// state right after it returns to its caller. So the frame is a return address into
+/// the caller of `SwitchToFiber`. Returns `None` if the context has a null
+/// instruction pointer, for example because the fiber has never run.
+///
+/// How to find the context of a fiber is up to the caller: fiber-based job systems
+/// usually know which fiber switched to the current one. The context itself is part of
+/// the undocumented fiber structure that `CreateFiber` returns a pointer to.
+pub fn fiber_resume_frame(context: &CONTEXT) -> Option<(FrameAddress, UnwindRegsNative)> {
+    let (pc, regs) = unwind_regs_from_context(context);
+    Some((FrameAddress::from_return_address(pc)?, regs))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fiber_start_ranges() {
+        // BaseFiberStart is missing on current versions of Windows, so only check the
+        // ranges which were found.
+        let ranges = fiber_start_ranges();
+        assert!(!ranges.is_empty());
+        assert!(ranges.iter().all(|range| !range.is_empty()));
+    }
+
+    #[test]
+    fn test_fiber_resume_frame() {
+        // Safety: CONTEXT is a plain C struct; all-zero is a valid value.
+        let mut context: CONTEXT = unsafe { std::mem::zeroed() };
+        assert!(fiber_resume_frame(&context).is_none());
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            context.Rip = 0x100200;
+            context.Rsp = 0x7ff0;
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            context.Pc = 0x100200;
+            context.Sp = 0x7ff0;
+        }
+        let (address, regs) = fiber_resume_frame(&context).unwrap();
+        assert_eq!(
+            address,
+            FrameAddress::from_return_address(0x100200).unwrap()
+        );
+        assert_eq!(regs.sp(), 0x7ff0);
+    }
+}
@@ -0,0 +1,11 @@
+mod context;
+mod fiber;
+#[cfg(feature = "windows-sampling")]
+mod sampling;
+mod thread_start;
+
+pub use context::*;
+pub use fiber::*;
+#[cfg(feature = "windows-sampling")]
+pub use sampling::*;
+pub use thread_start::*;
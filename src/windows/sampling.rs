@@ -0,0 +1,100 @@
+use windows_sys::Win32::Foundation::HANDLE;
+use windows_sys::Win32::System::Threading::{ResumeThread, SuspendThread};
+
+use super::context::get_thread_regs;
+use crate::stack_copy::copy_process_memory;
+use crate::{StackSlice, UnwindRegsNative};
+
+/// The registers and the stack bytes of a thread, captured by [`sample_thread`].
+pub struct ThreadSample {
+    /// The instruction pointer.
+    pub pc: u64,
+    /// The unwind registers.
+    pub regs: UnwindRegsNative,
+    /// The stack bytes, starting at the stack pointer.
+    pub stack: StackSlice<Vec<u8>>,
+}
+
+/// Suspend the thread, capture its registers, copy up to `max_stack_bytes` of its
+/// stack starting at the stack pointer, and resume it.
+///
+/// `process` is the process which the thread belongs to; it needs `PROCESS_VM_READ`
+/// access. `thread` needs `THREAD_SUSPEND_RESUME` and `THREAD_GET_CONTEXT` access.
+/// The thread is resumed before this function returns, even if capturing fails.
+///
+/// The stack copy stops early at the first unreadable page, so the returned stack
+/// can be shorter than `max_stack_bytes`. This is normal when the copy reaches the
+/// end of the thread's stack.
+///
+/// The stack buffer is allocated before the thread is suspended, so this function can
+/// also be used to sample other threads of the current process, without the risk of
+/// deadlocking on a heap lock which is held by the suspended thread.
+pub fn sample_thread(
+    process: HANDLE,
+    thread: HANDLE,
+    max_stack_bytes: usize,
+) -> std::io::Result<ThreadSample> {
+    let mut buffer = vec![0; max_stack_bytes];
+    let (pc, regs, stack_len) = {
+        let _suspended = SuspendedThread::suspend(thread)?;
+        let (pc, regs) = get_thread_regs(thread)?;
+        let stack_len = copy_process_memory(process, regs.sp(), &mut buffer);
+        (pc, regs, stack_len)
+    };
+    buffer.truncate(stack_len);
+    Ok(ThreadSample {
+        pc,
+        regs,
+        stack: StackSlice::new(regs.sp(), buffer),
+    })
+}
+
+/// Keeps a thread suspended for as long as it is alive.
+struct SuspendedThread(HANDLE);
+
+impl SuspendedThread {
+    fn suspend(thread: HANDLE) -> std::io::Result<Self> {
+        // Safety: SuspendThread validates the handle.
+        if unsafe { SuspendThread(thread) } == u32::MAX {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(Self(thread))
+    }
+}
+
+impl Drop for SuspendedThread {
+    fn drop(&mut self) {
+        // Safety: The thread was successfully suspended with this handle.
+        unsafe {
+            ResumeThread(self.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use windows_sys::Win32::System::Threading::GetCurrentProcess;
+
+    use super::*;
+    use crate::test_utils::with_blocked_thread;
+
+    #[test]
+    fn test_sample_thread() {
+        // Safety: GetCurrentProcess returns a pseudo handle and cannot fail.
+        let process = unsafe { GetCurrentProcess() };
+        let sample = with_blocked_thread(|thread| sample_thread(process, thread, 0x1000)).unwrap();
+        assert_ne!(sample.pc, 0);
+        // The copy can stop early at the end of the thread's stack, but it includes
+        // at least the slot at the stack pointer.
+        let range = sample.stack.address_range();
+        assert_eq!(range.start, sample.regs.sp());
+        assert!(range.end >= range.start + 8 && range.end <= range.start + 0x1000);
+    }
+
+    #[test]
+    fn test_sample_thread_invalid_handle() {
+        // Safety: GetCurrentProcess returns a pseudo handle and cannot fail.
+        let process = unsafe { GetCurrentProcess() };
+        assert!(sample_thread(process, 0, 0x1000).is_err());
+    }
+}
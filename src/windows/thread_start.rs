@@ -0,0 +1,143 @@
+use std::ops::Range;
+
+use windows_sys::Win32::System::Diagnostics::Debug::RtlLookupFunctionEntry;
+use windows_sys::Win32::System::LibraryLoader::{GetModuleHandleA, GetProcAddress};
+
+/// The functions that every thread starts in, innermost last.
+const THREAD_START_FUNCTIONS: [(&[u8], &[u8]); 2] = [
+    (b"ntdll.dll\0", b"RtlUserThreadStart\0"),
+    (b"kernel32.dll\0", b"BaseThreadInitThunk\0"),
+];
+
+/// Return the address ranges of `RtlUserThreadStart` and `BaseThreadInitThunk`, the
+/// functions at the bottom of every thread's stack. Pass them to the unwinder's
+/// `add_root_range`, so that every stack ends at the same root frame, instead of
+/// trailing into uninitialized stack memory.
+///
+/// The addresses are looked up in the current process. System DLLs are mapped at the
+/// same address in all processes of the same architecture until the next reboot, so
+/// the ranges can also be used when sampling other processes.
+pub fn thread_start_root_ranges() -> Vec<Range<u64>> {
+    THREAD_START_FUNCTIONS
+        .iter()
+        .filter_map(|(module_name, function_name)| function_range(module_name, function_name))
+        .collect()
+}
+
+/// Find the address range of an exported function, using the function table of its
+/// module. Both names must be nul-terminated.
+pub(super) fn function_range(module_name: &[u8], function_name: &[u8]) -> Option<Range<u64>> {
+    // Safety: Both names are nul-terminated, and the module handle is only used for
+    // the lookup below. ntdll and kernel32 are never unloaded.
+    let function = unsafe {
+        let module = GetModuleHandleA(module_name.as_ptr());
+        if module == 0 {
+            return None;
+        }
+        GetProcAddress(module, function_name.as_ptr())?
+    };
+    let address = function as usize;
+    let mut image_base = 0;
+    // Safety: address is inside a loaded module, and a null history table is allowed.
+    let entry = unsafe {
+        RtlLookupFunctionEntry(address as _, &mut image_base, std::ptr::null_mut()).as_ref()?
+    };
+    let image_base = image_base as u64;
+    let start = image_base + u64::from(entry.BeginAddress);
+    Some(start..start + u64::from(function_length(image_base, entry)?))
+}
+
+#[cfg(target_arch = "x86_64")]
+fn function_length(
+    _image_base: u64,
+    entry: &windows_sys::Win32::System::Diagnostics::Debug::IMAGE_RUNTIME_FUNCTION_ENTRY,
+) -> Option<u32> {
+    entry.EndAddress.checked_sub(entry.BeginAddress)
+}
+
+/// ARM64 function table entries store the function length in the packed unwind data,
+/// or in the header of the .xdata record if the unwind data is not packed.
+#[cfg(target_arch = "aarch64")]
+fn function_length(
+    image_base: u64,
+    entry: &windows_sys::Win32::System::Diagnostics::Debug::IMAGE_ARM64_RUNTIME_FUNCTION_ENTRY,
+) -> Option<u32> {
+    // Safety: Both union variants are a u32.
+    let unwind_data = unsafe { entry.Anonymous.UnwindData };
+    let flag = unwind_data & 0b11;
+    if flag != 0 {
+        return Some(((unwind_data >> 2) & 0x7ff) * 4);
+    }
+    let xdata = (image_base + u64::from(unwind_data)) as *const u32;
+    // Safety: For unpacked entries, UnwindData is the RVA of the .xdata record, which
+    // is mapped as part of the module.
+    let header = unsafe { xdata.read_unaligned() };
+    Some((header & 0x3ffff) * 4)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_thread_start_root_ranges() {
+        let ranges = thread_start_root_ranges();
+        assert_eq!(ranges.len(), THREAD_START_FUNCTIONS.len());
+        assert!(ranges.iter().all(|range| !range.is_empty()));
+    }
+
+    #[test]
+    fn test_unknown_function() {
+        assert_eq!(function_range(b"ntdll.dll\0", b"NoSuchFunction\0"), None);
+        assert_eq!(
+            function_range(b"nosuchmodule.dll\0", b"NoSuchFunction\0"),
+            None
+        );
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_function_length() {
+        use windows_sys::Win32::System::Diagnostics::Debug::{
+            IMAGE_RUNTIME_FUNCTION_ENTRY, IMAGE_RUNTIME_FUNCTION_ENTRY_0,
+        };
+
+        let entry = |begin, end| IMAGE_RUNTIME_FUNCTION_ENTRY {
+            BeginAddress: begin,
+            EndAddress: end,
+            Anonymous: IMAGE_RUNTIME_FUNCTION_ENTRY_0 { UnwindData: 0x5000 },
+        };
+        assert_eq!(function_length(0, &entry(0x1000, 0x1040)), Some(0x40));
+        assert_eq!(function_length(0, &entry(0x1040, 0x1000)), None);
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    #[test]
+    fn test_function_length() {
+        use windows_sys::Win32::System::Diagnostics::Debug::{
+            IMAGE_ARM64_RUNTIME_FUNCTION_ENTRY, IMAGE_ARM64_RUNTIME_FUNCTION_ENTRY_0,
+        };
+
+        let entry = |unwind_data| IMAGE_ARM64_RUNTIME_FUNCTION_ENTRY {
+            BeginAddress: 0x1000,
+            Anonymous: IMAGE_ARM64_RUNTIME_FUNCTION_ENTRY_0 {
+                UnwindData: unwind_data,
+            },
+        };
+
+        // Packed: Flag = 1, FunctionLength = 0x10 instructions, and RegF = 7 and
+        // FrameSize = 0x1ff in the bits above.
+        let packed = 1 | (0x10 << 2) | (7 << 13) | (0x1ff << 23);
+        assert_eq!(function_length(0, &entry(packed)), Some(0x40));
+
+        // Unpacked: UnwindData is the RVA of the .xdata record, whose header has
+        // FunctionLength = 0x123 instructions, followed by Vers, X, E and EpilogCount.
+        let xdata: [u32; 2] = [0x123 | (1 << 20) | (1 << 21) | (2 << 22), 0];
+        let xdata_rva = 0x100;
+        let image_base = xdata.as_ptr() as u64 - u64::from(xdata_rva);
+        assert_eq!(
+            function_length(image_base, &entry(xdata_rva)),
+            Some(0x123 * 4)
+        );
+    }
+}
@@ -0,0 +1,10 @@
+use super::unwind_rule::UnwindRuleX86;
+use super::unwindregs::UnwindRegsX86;
+use crate::arch::Arch;
+
+/// The 32-bit x86 CPU architecture.
+pub struct ArchX86;
+impl Arch for ArchX86 {
+    type UnwindRule = UnwindRuleX86;
+    type UnwindRegs = UnwindRegsX86;
+}
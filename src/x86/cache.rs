@@ -0,0 +1,27 @@
+use std::ops::Deref;
+
+use super::unwind_rule::*;
+use crate::cache::*;
+
+/// The unwinder cache type for [`UnwinderX86`](super::UnwinderX86).
+pub struct CacheX86<D: Deref<Target = [u8]>, P: AllocationPolicy<D> = MayAllocateDuringUnwind>(
+    pub Cache<D, UnwindRuleX86, P>,
+);
+
+impl<D: Deref<Target = [u8]>, P: AllocationPolicy<D>> CacheX86<D, P> {
+    /// Create a new cache.
+    pub fn new() -> Self {
+        Self(Cache::new())
+    }
+
+    /// Returns a snapshot of the cache usage statistics.
+    pub fn stats(&self) -> CacheStats {
+        self.0.rule_cache.stats()
+    }
+}
+
+impl<D: Deref<Target = [u8]>, P: AllocationPolicy<D>> Default for CacheX86<D, P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
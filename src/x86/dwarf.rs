@@ -0,0 +1,160 @@
+use gimli::{
+    CfaRule, Encoding, EvaluationStorage, Reader, Register, RegisterRule, UnwindContextStorage,
+    UnwindTableRow, X86,
+};
+
+use super::{arch::ArchX86, unwind_rule::UnwindRuleX86, unwindregs::UnwindRegsX86};
+use crate::dwarf::{
+    eval_cfa_rule, eval_register_rule, ConversionError, DwarfUnwindRegs, DwarfUnwinderError,
+    DwarfUnwinding,
+};
+use crate::unwind_result::UnwindResult;
+
+impl DwarfUnwindRegs for UnwindRegsX86 {
+    fn get(&self, register: Register) -> Option<u64> {
+        match register {
+            X86::RA => Some(self.ip()),
+            X86::ESP => Some(self.sp()),
+            X86::EBP => Some(self.bp()),
+            _ => None,
+        }
+    }
+}
+
+impl DwarfUnwinding for ArchX86 {
+    fn unwind_frame<F, R, S>(
+        unwind_info: &UnwindTableRow<R, S>,
+        encoding: Encoding,
+        max_expression_steps: u32,
+        regs: &mut Self::UnwindRegs,
+        is_first_frame: bool,
+        read_stack: &mut F,
+    ) -> Result<UnwindResult<Self::UnwindRule>, DwarfUnwinderError>
+    where
+        F: FnMut(u64) -> Result<u64, ()>,
+        R: Reader,
+        S: UnwindContextStorage<R> + EvaluationStorage<R>,
+    {
+        let cfa_rule = unwind_info.cfa();
+        let bp_rule = unwind_info.register(X86::EBP);
+        let ra_rule = unwind_info.register(X86::RA);
+
+        if let Ok(unwind_rule) = translate_into_unwind_rule(cfa_rule, &bp_rule, &ra_rule) {
+            return Ok(UnwindResult::ExecRule(unwind_rule));
+        }
+
+        let cfa = eval_cfa_rule::<R, _, S>(cfa_rule, encoding, max_expression_steps, regs)
+            .ok_or(DwarfUnwinderError::CouldNotRecoverCfa)?;
+
+        let ip = regs.ip();
+        let bp = regs.bp();
+        let sp = regs.sp();
+
+        let new_bp = eval_register_rule::<R, F, _, S>(
+            bp_rule,
+            cfa,
+            encoding,
+            max_expression_steps,
+            bp,
+            regs,
+            read_stack,
+        )
+        .unwrap_or(bp);
+
+        let return_address = match eval_register_rule::<R, F, _, S>(
+            ra_rule,
+            cfa,
+            encoding,
+            max_expression_steps,
+            ip,
+            regs,
+            read_stack,
+        ) {
+            Some(ra) => ra,
+            None => cfa
+                .checked_sub(4)
+                .and_then(|ra_location| read_stack(ra_location).ok())
+                .ok_or(DwarfUnwinderError::CouldNotRecoverReturnAddress)?,
+        };
+
+        if cfa == sp && return_address == ip {
+            return Err(DwarfUnwinderError::DidNotAdvance);
+        }
+        if !is_first_frame && cfa < regs.sp() {
+            return Err(DwarfUnwinderError::StackPointerMovedBackwards);
+        }
+
+        regs.set_ip(return_address);
+        regs.set_bp(new_bp);
+        regs.set_sp(cfa);
+
+        Ok(UnwindResult::Uncacheable(return_address))
+    }
+
+    fn register_name(register: Register) -> Option<&'static str> {
+        X86::register_name(register)
+    }
+
+    fn rule_if_uncovered_by_fde() -> Self::UnwindRule {
+        UnwindRuleX86::JustReturnIfFirstFrameOtherwiseFp
+    }
+}
+
+fn register_rule_to_cfa_offset<R: gimli::Reader>(
+    rule: &RegisterRule<R>,
+) -> Result<Option<i64>, ConversionError> {
+    match *rule {
+        RegisterRule::Undefined | RegisterRule::SameValue => Ok(None),
+        RegisterRule::Offset(offset) => Ok(Some(offset)),
+        _ => Err(ConversionError::RegisterNotStoredRelativeToCfa),
+    }
+}
+
+fn translate_into_unwind_rule<R: gimli::Reader>(
+    cfa_rule: &CfaRule<R>,
+    bp_rule: &RegisterRule<R>,
+    ra_rule: &RegisterRule<R>,
+) -> Result<UnwindRuleX86, ConversionError> {
+    match ra_rule {
+        // The return address is at [CFA-4].
+        RegisterRule::Undefined => {}
+        RegisterRule::Offset(-4) => {}
+        RegisterRule::Offset(_) => {
+            return Err(ConversionError::ReturnAddressRuleWithUnexpectedOffset);
+        }
+        _ => return Err(ConversionError::ReturnAddressRuleWasWeird),
+    }
+
+    match cfa_rule {
+        CfaRule::RegisterAndOffset { register, offset } => match *register {
+            X86::ESP => {
+                let sp_offset_by_4 =
+                    u16::try_from(offset / 4).map_err(|_| ConversionError::SpOffsetDoesNotFit)?;
+                match register_rule_to_cfa_offset(bp_rule)? {
+                    None => Ok(UnwindRuleX86::OffsetSp { sp_offset_by_4 }),
+                    Some(bp_cfa_offset) => {
+                        let bp_storage_offset_from_sp_by_4 = offset
+                            .checked_add(bp_cfa_offset)
+                            .and_then(|bp_offset| i16::try_from(bp_offset / 4).ok())
+                            .ok_or(ConversionError::FpStorageOffsetDoesNotFit)?;
+                        Ok(UnwindRuleX86::OffsetSpAndRestoreBp {
+                            sp_offset_by_4,
+                            bp_storage_offset_from_sp_by_4,
+                        })
+                    }
+                }
+            }
+            X86::EBP => {
+                let bp_cfa_offset = register_rule_to_cfa_offset(bp_rule)?
+                    .ok_or(ConversionError::FramePointerRuleDoesNotRestoreBp)?;
+                if *offset == 8 && bp_cfa_offset == -8 {
+                    Ok(UnwindRuleX86::UseFramePointer)
+                } else {
+                    Err(ConversionError::FramePointerRuleHasStrangeBpOffset)
+                }
+            }
+            _ => Err(ConversionError::CfaIsOffsetFromUnknownRegister),
+        },
+        CfaRule::Expression(_) => Err(ConversionError::CfaIsExpression),
+    }
+}
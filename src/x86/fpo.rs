@@ -0,0 +1,48 @@
+use super::arch::ArchX86;
+use super::unwind_rule::UnwindRuleX86;
+use crate::fpo::{FpoData, FpoFrameType, FpoUnwinderError, FpoUnwinding};
+
+impl FpoUnwinding for ArchX86 {
+    fn rule_for_fpo_data(
+        fpo: &FpoData,
+        is_first_frame: bool,
+        offset_within_function: u32,
+    ) -> Result<UnwindRuleX86, FpoUnwinderError> {
+        match fpo.frame_type {
+            FpoFrameType::Fpo | FpoFrameType::NonFpo => {}
+            FpoFrameType::Trap | FpoFrameType::Tss => {
+                return Err(FpoUnwinderError::UnsupportedFrameType)
+            }
+        }
+        if is_first_frame && offset_within_function == 0 {
+            return Ok(UnwindRuleX86::JustReturn);
+        }
+        if is_first_frame && offset_within_function < u32::from(fpo.prolog_size) {
+            // FPO data describes the frame after the prologue, and doesn't say which of
+            // the prologue's pushes have been done at this point.
+            return Err(FpoUnwinderError::InsidePrologue);
+        }
+        if fpo.uses_bp {
+            // The function has a `push ebp; mov ebp, esp` prologue, and ebp points at
+            // its frame record.
+            return Ok(UnwindRuleX86::UseFramePointer);
+        }
+        // After the prologue, the locals are right above sp, followed by the saved
+        // registers and the return address. Parameters which the function pops with
+        // `ret imm16` aren't included, so the caller's sp is the one right after the
+        // return address.
+        let sp_offset_by_4 = fpo
+            .locals_size_by_4
+            .checked_add(u32::from(fpo.saved_regs) + 1)
+            .and_then(|sp_offset_by_4| u16::try_from(sp_offset_by_4).ok())
+            .ok_or(FpoUnwinderError::StackSizeDoesNotFit)?;
+        if fpo.saved_regs == 0 {
+            // ebp isn't saved, so the function doesn't touch it.
+            return Ok(UnwindRuleX86::OffsetSp { sp_offset_by_4 });
+        }
+        // ebp may be one of the saved registers and be used as a general purpose
+        // register, and FPO data doesn't say where it is saved. Its current value can't
+        // be trusted as the caller's frame pointer.
+        Ok(UnwindRuleX86::OffsetSpWithUnknownBp { sp_offset_by_4 })
+    }
+}
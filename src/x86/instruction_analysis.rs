@@ -0,0 +1,291 @@
+use super::arch::ArchX86;
+use super::unwind_rule::UnwindRuleX86;
+use crate::instruction_analysis::InstructionAnalysis;
+
+impl InstructionAnalysis for ArchX86 {
+    fn rule_from_prologue_analysis(
+        text_bytes: &[u8],
+        pc_offset: usize,
+    ) -> Option<Self::UnwindRule> {
+        unwind_rule_from_detected_prologue(text_bytes, pc_offset)
+    }
+
+    fn rule_from_function_start_analysis(
+        function_bytes: &[u8],
+        pc_offset: usize,
+    ) -> Option<Self::UnwindRule> {
+        unwind_rule_from_prologue_from_function_start(function_bytes, pc_offset)
+    }
+
+    fn rule_from_epilogue_analysis(
+        text_bytes: &[u8],
+        pc_offset: usize,
+    ) -> Option<Self::UnwindRule> {
+        unwind_rule_from_detected_epilogue(&text_bytes[pc_offset..])
+    }
+
+    fn call_instruction_len(text_bytes: &[u8], return_address_offset: usize) -> Option<usize> {
+        call_instruction_len_before(&text_bytes[..return_address_offset])
+    }
+
+    // The Linux sigreturn trampolines, PLT stubs and dynamic linker trampolines of
+    // 32-bit processes aren't analyzed.
+    fn rule_from_sigreturn_trampoline_analysis(
+        _text_bytes: &[u8],
+        _pc_offset: usize,
+    ) -> Option<Self::UnwindRule> {
+        None
+    }
+
+    fn rule_from_plt_analysis(_plt_bytes: &[u8], _pc_offset: usize) -> Option<Self::UnwindRule> {
+        None
+    }
+
+    fn rule_from_dl_trampoline_analysis(
+        _function_bytes: &[u8],
+        _pc_offset: usize,
+    ) -> Option<Self::UnwindRule> {
+        None
+    }
+}
+
+/// If the instruction at pc_offset is one of the first instructions of a
+/// `push ebp; mov ebp, esp` prologue, finds the stack depth by looking at the pushes
+/// before it.
+fn unwind_rule_from_detected_prologue(
+    text_bytes: &[u8],
+    pc_offset: usize,
+) -> Option<UnwindRuleX86> {
+    let (slice_from_start, slice_to_end) = text_bytes.split_at(pc_offset);
+    match slice_to_end {
+        // push ebp
+        [0x55, ..]
+        // mov ebp, esp
+        | [0x8b, 0xec, ..]
+        | [0x89, 0xe5, ..] => {}
+        _ => return None,
+    }
+    // As on x86_64, looking backwards is guesswork, but a run of push instructions
+    // right before the frame pointer setup is very likely part of the prologue.
+    let pushes = slice_from_start
+        .iter()
+        .rev()
+        .take_while(|byte| *byte & 0xf8 == 0x50)
+        .count();
+    let sp_offset_by_4 = u16::try_from(pushes + 1).ok()?;
+    Some(UnwindRuleX86::OffsetSp { sp_offset_by_4 })
+}
+
+/// Decodes the instructions from the start of the function up to pc_offset. Returns
+/// None as soon as an instruction is found that doesn't belong in a prologue.
+fn unwind_rule_from_prologue_from_function_start(
+    function_bytes: &[u8],
+    pc_offset: usize,
+) -> Option<UnwindRuleX86> {
+    let mut bytes = &function_bytes[..pc_offset];
+    // The number of 4 byte slots between sp and the return address, including the
+    // return address itself.
+    let mut sp_offset_by_4: u16 = 1;
+    // The value of sp_offset_by_4 right after ebp was pushed.
+    let mut sp_offset_by_4_after_bp_push = None;
+    let mut has_frame_pointer = false;
+    while !bytes.is_empty() {
+        let len = match *bytes {
+            // mov edi, edi, the hotpatch point of Windows functions
+            [0x8b, 0xff, ..] => 2,
+            // push ebp
+            [0x55, ..] => {
+                sp_offset_by_4 = sp_offset_by_4.checked_add(1)?;
+                sp_offset_by_4_after_bp_push = Some(sp_offset_by_4);
+                1
+            }
+            // mov ebp, esp
+            [0x8b, 0xec, ..] | [0x89, 0xe5, ..] if sp_offset_by_4_after_bp_push.is_some() => {
+                has_frame_pointer = true;
+                2
+            }
+            // push r32
+            [0x50..=0x57, ..] => {
+                sp_offset_by_4 = sp_offset_by_4.checked_add(1)?;
+                1
+            }
+            // sub esp, imm8 / imm32
+            [0x83, 0xec, imm, ..] => {
+                sp_offset_by_4 = add_stack_size(sp_offset_by_4, u32::from(imm))?;
+                3
+            }
+            [0x81, 0xec, a, b, c, d, ..] => {
+                let imm = u32::from_le_bytes([a, b, c, d]);
+                sp_offset_by_4 = add_stack_size(sp_offset_by_4, imm)?;
+                6
+            }
+            _ => return None,
+        };
+        // None if pc_offset is in the middle of an instruction.
+        bytes = bytes.get(len..)?;
+    }
+    if has_frame_pointer {
+        return Some(UnwindRuleX86::UseFramePointer);
+    }
+    match sp_offset_by_4_after_bp_push {
+        Some(after_bp_push) => Some(UnwindRuleX86::OffsetSpAndRestoreBp {
+            sp_offset_by_4,
+            bp_storage_offset_from_sp_by_4: i16::try_from(sp_offset_by_4 - after_bp_push).ok()?,
+        }),
+        None if sp_offset_by_4 == 1 => Some(UnwindRuleX86::JustReturn),
+        None => Some(UnwindRuleX86::OffsetSp { sp_offset_by_4 }),
+    }
+}
+
+fn add_stack_size(sp_offset_by_4: u16, stack_size: u32) -> Option<u16> {
+    if !stack_size.is_multiple_of(4) {
+        return None;
+    }
+    sp_offset_by_4.checked_add(u16::try_from(stack_size / 4).ok()?)
+}
+
+/// Detects the pops and the `ret` at the end of a function, starting at the
+/// instruction at pc.
+fn unwind_rule_from_detected_epilogue(bytes_from_pc: &[u8]) -> Option<UnwindRuleX86> {
+    if let [0xc9, 0xc3 | 0xc2, ..] = bytes_from_pc {
+        // leave; ret
+        return Some(UnwindRuleX86::UseFramePointer);
+    }
+    let mut sp_offset_by_4: u16 = 1;
+    let mut bp_storage_offset_from_sp_by_4 = None;
+    for byte in bytes_from_pc {
+        match *byte {
+            // ret / ret imm16
+            0xc3 | 0xc2 => {
+                return Some(match bp_storage_offset_from_sp_by_4 {
+                    Some(bp_storage_offset_from_sp_by_4) => UnwindRuleX86::OffsetSpAndRestoreBp {
+                        sp_offset_by_4,
+                        bp_storage_offset_from_sp_by_4,
+                    },
+                    None if sp_offset_by_4 == 1 => UnwindRuleX86::JustReturn,
+                    None => UnwindRuleX86::OffsetSp { sp_offset_by_4 },
+                });
+            }
+            // pop ebp
+            0x5d => {
+                bp_storage_offset_from_sp_by_4 = Some(i16::try_from(sp_offset_by_4 - 1).ok()?);
+                sp_offset_by_4 = sp_offset_by_4.checked_add(1)?;
+            }
+            // pop r32
+            0x58..=0x5f => sp_offset_by_4 = sp_offset_by_4.checked_add(1)?,
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// Returns the length of the call instruction which ends at the end of
+/// `bytes_before_return_address`, if the bytes look like a call. As on x86_64, this
+/// checks the common encodings, most common first.
+fn call_instruction_len_before(bytes_before_return_address: &[u8]) -> Option<usize> {
+    let bytes = bytes_before_return_address;
+    let ends_with = |len: usize, matches: &dyn Fn(&[u8]) -> bool| {
+        bytes.len() >= len && matches(&bytes[bytes.len() - len..])
+    };
+    // Detect call rel32 [0xe8 XX XX XX XX]
+    if ends_with(5, &|b| b[0] == 0xe8) {
+        return Some(5);
+    }
+    // Detect call [disp32] [0xff 0x15 XX XX XX XX], e.g. calls through the IAT
+    if ends_with(6, &|b| b[0..2] == [0xff, 0x15]) {
+        return Some(6);
+    }
+    // Detect call eXX [0xff 0xd0+r]
+    if ends_with(2, &|b| b[0] == 0xff && b[1] & 0xf8 == 0xd0) {
+        return Some(2);
+    }
+    // Detect call [eXX + disp8] [0xff 0x50+r XX], excluding esp which needs a SIB byte
+    if ends_with(3, &|b| b[0] == 0xff && b[1] & 0xf8 == 0x50 && b[1] != 0x54) {
+        return Some(3);
+    }
+    // Detect call [eXX + disp32] [0xff 0x90+r XX XX XX XX]
+    if ends_with(6, &|b| b[0] == 0xff && b[1] & 0xf8 == 0x90 && b[1] != 0x94) {
+        return Some(6);
+    }
+    // Detect call [eXX + eYY*s + disp8] [0xff 0x54 SIB XX]
+    if ends_with(4, &|b| b[0..2] == [0xff, 0x54]) {
+        return Some(4);
+    }
+    // Detect call [eXX] [0xff 0x10+r], excluding esp and disp32
+    if ends_with(2, &|b| {
+        b[0] == 0xff && b[1] & 0xf8 == 0x10 && b[1] != 0x14 && b[1] != 0x15
+    }) {
+        return Some(2);
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_prologue_from_function_start() {
+        #[rustfmt::skip]
+        let bytes = [
+            // mov edi, edi; push ebp; mov ebp, esp; sub esp, 0x10
+            0x8b, 0xff,
+            0x55,
+            0x8b, 0xec,
+            0x83, 0xec, 0x10,
+        ];
+        let rule = |pc_offset| unwind_rule_from_prologue_from_function_start(&bytes, pc_offset);
+        assert_eq!(rule(0), Some(UnwindRuleX86::JustReturn));
+        assert_eq!(rule(2), Some(UnwindRuleX86::JustReturn));
+        assert_eq!(
+            rule(3),
+            Some(UnwindRuleX86::OffsetSpAndRestoreBp {
+                sp_offset_by_4: 2,
+                bp_storage_offset_from_sp_by_4: 0
+            })
+        );
+        assert_eq!(rule(5), Some(UnwindRuleX86::UseFramePointer));
+        assert_eq!(rule(4), None);
+
+        // push esi; push edi; sub esp, 0x20
+        let bytes = [0x56, 0x57, 0x83, 0xec, 0x20];
+        assert_eq!(
+            unwind_rule_from_prologue_from_function_start(&bytes, 5),
+            Some(UnwindRuleX86::OffsetSp { sp_offset_by_4: 11 })
+        );
+    }
+
+    #[test]
+    fn test_epilogue() {
+        // pop edi; pop ebp; ret 8
+        let bytes = [0x5f, 0x5d, 0xc2, 0x08, 0x00];
+        assert_eq!(
+            unwind_rule_from_detected_epilogue(&bytes),
+            Some(UnwindRuleX86::OffsetSpAndRestoreBp {
+                sp_offset_by_4: 3,
+                bp_storage_offset_from_sp_by_4: 1
+            })
+        );
+        assert_eq!(
+            unwind_rule_from_detected_epilogue(&bytes[2..]),
+            Some(UnwindRuleX86::JustReturn)
+        );
+        // leave; ret
+        assert_eq!(
+            unwind_rule_from_detected_epilogue(&[0xc9, 0xc3]),
+            Some(UnwindRuleX86::UseFramePointer)
+        );
+        assert_eq!(unwind_rule_from_detected_epilogue(&[0x8b, 0xec]), None);
+    }
+
+    #[test]
+    fn test_call_instruction_len() {
+        // call dword [__imp_Sleep]
+        let bytes = [0x90, 0xff, 0x15, 0x00, 0x20, 0x40, 0x00];
+        assert_eq!(call_instruction_len_before(&bytes), Some(6));
+        // call eax
+        assert_eq!(call_instruction_len_before(&[0x90, 0xff, 0xd0]), Some(2));
+        // mov ebp, esp
+        assert_eq!(call_instruction_len_before(&[0x8b, 0xec]), None);
+    }
+}
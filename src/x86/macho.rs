@@ -0,0 +1,208 @@
+use super::arch::ArchX86;
+use super::unwind_rule::UnwindRuleX86;
+use crate::instruction_analysis::InstructionAnalysis;
+use crate::macho::{CompactUnwindInfoUnwinderError, CompactUnwindInfoUnwinding, CuiUnwindResult};
+use macho_unwind_info::Function;
+
+// The 32-bit x86 encodings of compact_unwind_encoding.h. macho-unwind-info only
+// parses the x86_64 and arm64 encodings.
+const MODE_MASK: u32 = 0x0f00_0000;
+const MODE_EBP_FRAME: u32 = 0x0100_0000;
+const MODE_STACK_IMMD: u32 = 0x0200_0000;
+const MODE_STACK_IND: u32 = 0x0300_0000;
+const MODE_DWARF: u32 = 0x0400_0000;
+const FRAMELESS_STACK_SIZE_MASK: u32 = 0x00ff_0000;
+const FRAMELESS_STACK_ADJUST_MASK: u32 = 0x0000_e000;
+const FRAMELESS_STACK_REG_COUNT_MASK: u32 = 0x0000_1c00;
+const FRAMELESS_STACK_REG_PERMUTATION_MASK: u32 = 0x0000_03ff;
+const DWARF_SECTION_OFFSET_MASK: u32 = 0x00ff_ffff;
+const REG_EBP: u8 = 6;
+
+impl CompactUnwindInfoUnwinding for ArchX86 {
+    fn unwind_frame(
+        function: Function,
+        is_first_frame: bool,
+        address_offset_within_function: usize,
+        function_bytes: Option<&[u8]>,
+    ) -> Result<CuiUnwindResult<UnwindRuleX86>, CompactUnwindInfoUnwinderError> {
+        let opcode = function.opcode;
+        let mode = opcode & MODE_MASK;
+        if is_first_frame {
+            // As on x86_64, the opcodes only describe the function body, so check for
+            // prologues and epilogues first.
+            if let Some(function_bytes) = function_bytes {
+                if let Some(rule) = Self::rule_from_instruction_analysis(
+                    function_bytes,
+                    address_offset_within_function,
+                ) {
+                    return Ok(CuiUnwindResult::ExecRule(rule));
+                }
+            }
+            if opcode == 0 {
+                return Ok(CuiUnwindResult::ExecRule(UnwindRuleX86::JustReturn));
+            }
+        }
+
+        let r = match mode {
+            _ if opcode == 0 => return Err(CompactUnwindInfoUnwinderError::FunctionHasNoInfo),
+            MODE_EBP_FRAME => CuiUnwindResult::ExecRule(UnwindRuleX86::UseFramePointer),
+            MODE_STACK_IMMD => {
+                let stack_size_in_bytes = ((opcode & FRAMELESS_STACK_SIZE_MASK) >> 16) * 4;
+                CuiUnwindResult::ExecRule(frameless_rule(opcode, stack_size_in_bytes)?)
+            }
+            MODE_STACK_IND => {
+                let function_bytes = function_bytes.ok_or(
+                    CompactUnwindInfoUnwinderError::NoTextBytesToLookUpIndirectStackOffset,
+                )?;
+                let immediate_offset = ((opcode & FRAMELESS_STACK_SIZE_MASK) >> 16) as usize;
+                let sub_immediate_bytes = function_bytes
+                    .get(immediate_offset..immediate_offset + 4)
+                    .ok_or(CompactUnwindInfoUnwinderError::IndirectStackOffsetOutOfBounds)?;
+                let sub_immediate = u32::from_le_bytes([
+                    sub_immediate_bytes[0],
+                    sub_immediate_bytes[1],
+                    sub_immediate_bytes[2],
+                    sub_immediate_bytes[3],
+                ]);
+                let stack_adjust_in_bytes = ((opcode & FRAMELESS_STACK_ADJUST_MASK) >> 13) * 4;
+                let stack_size_in_bytes = sub_immediate
+                    .checked_add(stack_adjust_in_bytes)
+                    .ok_or(CompactUnwindInfoUnwinderError::StackAdjustOverflow)?;
+                CuiUnwindResult::ExecRule(frameless_rule(opcode, stack_size_in_bytes)?)
+            }
+            MODE_DWARF => CuiUnwindResult::NeedDwarf(opcode & DWARF_SECTION_OFFSET_MASK),
+            _ => {
+                return Err(CompactUnwindInfoUnwinderError::BadOpcodeKind(
+                    (mode >> 24) as u8,
+                ))
+            }
+        };
+        Ok(r)
+    }
+
+    fn describe_opcode(opcode: u32) -> (String, Option<u32>) {
+        match opcode & MODE_MASK {
+            _ if opcode == 0 => ("null".to_string(), None),
+            MODE_EBP_FRAME => ("frame-based".to_string(), None),
+            MODE_STACK_IMMD => {
+                let stack_size_in_bytes = ((opcode & FRAMELESS_STACK_SIZE_MASK) >> 16) * 4;
+                (format!("frameless, stack size {stack_size_in_bytes}"), None)
+            }
+            MODE_STACK_IND => ("frameless, indirect stack size".to_string(), None),
+            MODE_DWARF => {
+                let fde_offset = opcode & DWARF_SECTION_OFFSET_MASK;
+                (format!("dwarf 0x{fde_offset:x}"), Some(fde_offset))
+            }
+            mode => (format!("unrecognized kind {}", mode >> 24), None),
+        }
+    }
+
+    fn rule_for_stub_helper(
+        offset: u32,
+    ) -> Result<CuiUnwindResult<UnwindRuleX86>, CompactUnwindInfoUnwinderError> {
+        //     shared:
+        //  +0x0  68 xx xx xx xx         push  dyld_ImageLoaderCache
+        //  +0x5  FF 25 xx xx xx xx      jmp  dword [dyld_stub_binder] ; tail call
+        //  +0xb  90                     nop
+        //    first stub:
+        //  +0xc  68 xx xx xx xx         push  lazy_info_offset
+        // +0x11  E9 xx xx xx xx         jmp  shared
+        let rule = if offset < 0x5 {
+            // pop 1 and return
+            UnwindRuleX86::OffsetSp { sp_offset_by_4: 2 }
+        } else if offset < 0xc {
+            // pop 2 and return
+            UnwindRuleX86::OffsetSp { sp_offset_by_4: 3 }
+        } else {
+            let offset_within_stub = (offset - 0xc) % 10;
+            if offset_within_stub < 5 {
+                UnwindRuleX86::JustReturn
+            } else {
+                // pop 1 and return
+                UnwindRuleX86::OffsetSp { sp_offset_by_4: 2 }
+            }
+        };
+        Ok(CuiUnwindResult::ExecRule(rule))
+    }
+}
+
+/// The rule for a frameless function with a stack of `stack_size_in_bytes`, which
+/// includes the return address. The saved registers are pushed right below the
+/// return address, and ebp is restored if it is one of them.
+fn frameless_rule(
+    opcode: u32,
+    stack_size_in_bytes: u32,
+) -> Result<UnwindRuleX86, CompactUnwindInfoUnwinderError> {
+    let sp_offset_by_4 = u16::try_from(stack_size_in_bytes / 4)
+        .map_err(|_| CompactUnwindInfoUnwinderError::StackSizeDoesNotFit)?;
+    let reg_count = ((opcode & FRAMELESS_STACK_REG_COUNT_MASK) >> 10) as usize;
+    let permutation = opcode & FRAMELESS_STACK_REG_PERMUTATION_MASK;
+    let saved_regs = decode_register_permutation(reg_count, permutation)
+        .ok_or(CompactUnwindInfoUnwinderError::InvalidFrameless)?;
+    match saved_regs[..reg_count]
+        .iter()
+        .position(|reg| *reg == REG_EBP)
+    {
+        Some(index) => {
+            // Register i of n is stored at sp + stack_size - 4 * (n - i + 1).
+            let bp_offset_from_sp =
+                i64::from(stack_size_in_bytes) - 4 * (reg_count - index + 1) as i64;
+            let bp_storage_offset_from_sp_by_4 = i16::try_from(bp_offset_from_sp / 4)
+                .map_err(|_| CompactUnwindInfoUnwinderError::BpOffsetDoesNotFit)?;
+            Ok(UnwindRuleX86::OffsetSpAndRestoreBp {
+                sp_offset_by_4,
+                bp_storage_offset_from_sp_by_4,
+            })
+        }
+        None => Ok(UnwindRuleX86::OffsetSp { sp_offset_by_4 }),
+    }
+}
+
+/// Decode the order of the saved registers from the permutation number, as in
+/// libunwind's `stepWithCompactEncodingFrameless`. The registers are numbered from 1
+/// to 6: ebx, ecx, edx, edi, esi, ebp.
+fn decode_register_permutation(reg_count: usize, permutation: u32) -> Option<[u8; 6]> {
+    // The permutation is a number in a mixed radix system, with one digit per saved
+    // register: the index of the register among the registers which weren't used yet.
+    let radixes: &[u32] = match reg_count {
+        0 => &[],
+        1 => &[6],
+        2 => &[6, 5],
+        3 => &[6, 5, 4],
+        4 => &[6, 5, 4, 3],
+        5 | 6 => &[6, 5, 4, 3, 2],
+        _ => return None,
+    };
+    let mut digits = [0u32; 6];
+    let mut remaining = permutation;
+    for (i, digit) in digits.iter_mut().enumerate().take(radixes.len()) {
+        let place: u32 = radixes[i + 1..].iter().product();
+        *digit = remaining / place;
+        remaining -= *digit * place;
+    }
+    let mut saved_regs = [0u8; 6];
+    let mut used = [false; 7];
+    for (digit, saved_reg) in digits.iter().zip(saved_regs.iter_mut()).take(reg_count) {
+        let reg = (1..7u8)
+            .filter(|reg| !used[*reg as usize])
+            .nth(*digit as usize)?;
+        used[reg as usize] = true;
+        *saved_reg = reg;
+    }
+    Some(saved_regs)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_register_permutation() {
+        // ebx, esi, ebp, as encoded by ld64.
+        assert_eq!(
+            decode_register_permutation(3, 3 * 4 + 3),
+            Some([1, 5, 6, 0, 0, 0])
+        );
+        assert_eq!(decode_register_permutation(6, 0), Some([1, 2, 3, 4, 5, 6]));
+    }
+}
@@ -0,0 +1,15 @@
+mod arch;
+mod cache;
+mod dwarf;
+mod fpo;
+mod instruction_analysis;
+mod macho;
+mod unwind_rule;
+mod unwinder;
+mod unwindregs;
+
+pub use arch::*;
+pub use cache::*;
+pub use unwind_rule::*;
+pub use unwinder::*;
+pub use unwindregs::*;
@@ -0,0 +1,283 @@
+use super::unwindregs::UnwindRegsX86;
+use crate::add_signed::checked_add_signed;
+use crate::display_utils::{Load, RegOffset};
+use crate::error::Error;
+use crate::unwind_rule::{check_frame_pointer, UnwindRule};
+
+use std::fmt::Display;
+
+/// For all of these: return address is *(new_sp - 4)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnwindRuleX86 {
+    /// (sp, bp) = (sp + 4, bp)
+    JustReturn,
+    /// (sp, bp) = if is_first_frame (sp + 4, bp) else (bp + 8, *bp)
+    JustReturnIfFirstFrameOtherwiseFp,
+    /// (sp, bp) = (sp + 4x, bp)
+    OffsetSp { sp_offset_by_4: u16 },
+    /// (sp, bp) = (sp + 4x, 0)
+    /// Used when the function may have overwritten bp without saying where it saved
+    /// the caller's value. bp is set to 0, so that a frame pointer rule in the caller
+    /// ends the walk instead of following whatever bp was overwritten with.
+    OffsetSpWithUnknownBp { sp_offset_by_4: u16 },
+    /// (sp, bp) = (sp + 4x, *(sp + 4y))
+    OffsetSpAndRestoreBp {
+        sp_offset_by_4: u16,
+        bp_storage_offset_from_sp_by_4: i16,
+    },
+    /// (sp, bp) = (bp + 8, *bp)
+    UseFramePointer,
+    /// (sp, bp) = (bp + 8, *bp), where bp + 8 may be below sp
+    /// Used in functions which run their callee on a different stack and keep their
+    /// own frame record on the previous stack.
+    UseFramePointerAcrossStackSwitch,
+}
+
+/// Prints the rule as formulas for the caller's registers, where `sp'` and `bp'` are
+/// the caller's values and `ra` is the return address.
+impl Display for UnwindRuleX86 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            UnwindRuleX86::JustReturn => {
+                write!(f, "sp' = sp + 0x4; bp' = bp; ra = *(sp' - 4)")
+            }
+            UnwindRuleX86::JustReturnIfFirstFrameOtherwiseFp => write!(
+                f,
+                "if first frame: sp' = sp + 0x4; bp' = bp; \
+                 otherwise: sp' = bp + 0x8; bp' = *bp; ra = *(sp' - 4)"
+            ),
+            UnwindRuleX86::OffsetSp { sp_offset_by_4 } => {
+                let sp_offset = i64::from(sp_offset_by_4) * 4;
+                write!(
+                    f,
+                    "sp' = {}; bp' = bp; ra = *(sp' - 4)",
+                    RegOffset("sp", sp_offset)
+                )
+            }
+            UnwindRuleX86::OffsetSpWithUnknownBp { sp_offset_by_4 } => {
+                let sp_offset = i64::from(sp_offset_by_4) * 4;
+                write!(
+                    f,
+                    "sp' = {}; bp' = unknown; ra = *(sp' - 4)",
+                    RegOffset("sp", sp_offset)
+                )
+            }
+            UnwindRuleX86::OffsetSpAndRestoreBp {
+                sp_offset_by_4,
+                bp_storage_offset_from_sp_by_4,
+            } => {
+                let sp_offset = i64::from(sp_offset_by_4) * 4;
+                let bp_storage_offset = i64::from(bp_storage_offset_from_sp_by_4) * 4;
+                write!(
+                    f,
+                    "sp' = {}; bp' = {}; ra = *(sp' - 4)",
+                    RegOffset("sp", sp_offset),
+                    Load("sp", bp_storage_offset)
+                )
+            }
+            UnwindRuleX86::UseFramePointer => {
+                write!(f, "sp' = bp + 0x8; bp' = *bp; ra = *(sp' - 4)")
+            }
+            UnwindRuleX86::UseFramePointerAcrossStackSwitch => write!(
+                f,
+                "sp' = bp + 0x8 (may switch stacks); bp' = *bp; ra = *(sp' - 4)"
+            ),
+        }
+    }
+}
+
+impl UnwindRule for UnwindRuleX86 {
+    type UnwindRegs = UnwindRegsX86;
+
+    fn rule_for_stub_functions() -> Self {
+        UnwindRuleX86::JustReturn
+    }
+    fn rule_for_function_start() -> Self {
+        UnwindRuleX86::JustReturn
+    }
+    fn fallback_rule() -> Self {
+        UnwindRuleX86::UseFramePointer
+    }
+    // Signal frames of 32-bit processes aren't supported. Signal trampolines are
+    // unwound with the frame pointer, which usually still points at the frame record
+    // of the interrupted function's caller.
+    fn rule_for_linux_sigreturn_trampoline() -> Self {
+        UnwindRuleX86::UseFramePointer
+    }
+    fn rule_for_macos_sigtramp() -> Self {
+        UnwindRuleX86::UseFramePointer
+    }
+    fn rule_for_stack_switch() -> Self {
+        UnwindRuleX86::UseFramePointerAcrossStackSwitch
+    }
+    fn resumes_interrupted_code(self) -> bool {
+        false
+    }
+
+    fn exec<F>(
+        self,
+        is_first_frame: bool,
+        regs: &mut UnwindRegsX86,
+        read_stack: &mut F,
+    ) -> Result<Option<u64>, Error>
+    where
+        F: FnMut(u64) -> Result<u64, ()>,
+    {
+        let sp = regs.sp();
+        let (new_sp, new_bp) = match self {
+            UnwindRuleX86::JustReturn => {
+                let new_sp = sp.checked_add(4).ok_or(Error::IntegerOverflow)?;
+                (new_sp, regs.bp())
+            }
+            UnwindRuleX86::JustReturnIfFirstFrameOtherwiseFp => {
+                if is_first_frame {
+                    let new_sp = sp.checked_add(4).ok_or(Error::IntegerOverflow)?;
+                    (new_sp, regs.bp())
+                } else {
+                    let bp = regs.bp();
+                    let new_sp = bp.checked_add(8).ok_or(Error::IntegerOverflow)?;
+                    check_frame_pointer(bp, sp, 4)?;
+                    if new_sp <= sp {
+                        return Err(Error::FramepointerUnwindingMovedBackwards);
+                    }
+                    let new_bp = read_stack(bp).map_err(|_| Error::CouldNotReadStack(bp))?;
+                    if new_bp == bp {
+                        return Err(Error::FramePointerPointsToItself(bp));
+                    }
+                    (new_sp, new_bp)
+                }
+            }
+            UnwindRuleX86::OffsetSp { sp_offset_by_4 } => {
+                let sp_offset = u64::from(sp_offset_by_4) * 4;
+                let new_sp = sp.checked_add(sp_offset).ok_or(Error::IntegerOverflow)?;
+                (new_sp, regs.bp())
+            }
+            UnwindRuleX86::OffsetSpWithUnknownBp { sp_offset_by_4 } => {
+                let sp_offset = u64::from(sp_offset_by_4) * 4;
+                let new_sp = sp.checked_add(sp_offset).ok_or(Error::IntegerOverflow)?;
+                (new_sp, 0)
+            }
+            UnwindRuleX86::OffsetSpAndRestoreBp {
+                sp_offset_by_4,
+                bp_storage_offset_from_sp_by_4,
+            } => {
+                let sp_offset = u64::from(sp_offset_by_4) * 4;
+                let new_sp = sp.checked_add(sp_offset).ok_or(Error::IntegerOverflow)?;
+                let bp_storage_offset_from_sp = i64::from(bp_storage_offset_from_sp_by_4) * 4;
+                let bp_location = checked_add_signed(sp, bp_storage_offset_from_sp)
+                    .ok_or(Error::IntegerOverflow)?;
+                let new_bp = match read_stack(bp_location) {
+                    Ok(new_bp) => new_bp,
+                    // As on x86_64, epilogues can have bp stored below sp in the first frame.
+                    Err(()) if is_first_frame && bp_location < sp => regs.bp(),
+                    Err(()) => return Err(Error::CouldNotReadStack(bp_location)),
+                };
+                (new_sp, new_bp)
+            }
+            UnwindRuleX86::UseFramePointer => {
+                // The frame record is the same as on x86_64, with 4 byte slots:
+                //
+                // push ebp
+                // mov  ebp, esp
+                //
+                // *ebp is the caller's frame pointer, and *(ebp + 4) is the return address.
+                let bp = regs.bp();
+                if bp == 0 {
+                    return Ok(None);
+                }
+                let new_sp = bp.checked_add(8).ok_or(Error::IntegerOverflow)?;
+                check_frame_pointer(bp, sp, 4)?;
+                if new_sp <= sp {
+                    return Err(Error::FramepointerUnwindingMovedBackwards);
+                }
+                let new_bp = read_stack(bp).map_err(|_| Error::CouldNotReadStack(bp))?;
+                if new_bp == bp {
+                    return Err(Error::FramePointerPointsToItself(bp));
+                }
+                (new_sp, new_bp)
+            }
+            UnwindRuleX86::UseFramePointerAcrossStackSwitch => {
+                let bp = regs.bp();
+                if bp == 0 {
+                    return Ok(None);
+                }
+                let new_sp = bp.checked_add(8).ok_or(Error::IntegerOverflow)?;
+                if !bp.is_multiple_of(4) {
+                    return Err(Error::MisalignedFramePointer(bp));
+                }
+                let new_bp = read_stack(bp).map_err(|_| Error::CouldNotReadStack(bp))?;
+                if new_bp == bp {
+                    return Err(Error::FramePointerPointsToItself(bp));
+                }
+                (new_sp, new_bp)
+            }
+        };
+        let return_address_location = new_sp.checked_sub(4).ok_or(Error::IntegerOverflow)?;
+        let return_address = read_stack(return_address_location)
+            .map_err(|_| Error::CouldNotReadStack(return_address_location))?;
+        if return_address == 0 {
+            return Ok(None);
+        }
+        if new_sp == sp && return_address == regs.ip() {
+            return Err(Error::DidNotAdvance);
+        }
+        regs.set_ip(return_address);
+        regs.set_sp(new_sp);
+        regs.set_bp(new_bp);
+        Ok(Some(return_address))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let rule = UnwindRuleX86::OffsetSpAndRestoreBp {
+            sp_offset_by_4: 5,
+            bp_storage_offset_from_sp_by_4: 3,
+        };
+        assert_eq!(
+            rule.to_string(),
+            "sp' = sp + 0x14; bp' = *(sp + 0xc); ra = *(sp' - 4)"
+        );
+        assert_eq!(
+            UnwindRuleX86::UseFramePointer.to_string(),
+            "sp' = bp + 0x8; bp' = *bp; ra = *(sp' - 4)"
+        );
+    }
+
+    #[test]
+    fn test_basic() {
+        let stack: [u64; 16] = [
+            1, 2, 0x1300, 4, 0x20, 0x1200, 5, 6, 0x38, 0x1100, 7, 8, 9, 10, 0x0, 0x0,
+        ];
+        let mut read_stack = |addr| Ok(stack[(addr / 4) as usize]);
+        let mut regs = UnwindRegsX86::new(0x1400, 0x8, 0x10);
+        let res =
+            UnwindRuleX86::OffsetSp { sp_offset_by_4: 1 }.exec(true, &mut regs, &mut read_stack);
+        assert_eq!(res, Ok(Some(0x1300)));
+        assert_eq!(regs.sp(), 0xc);
+        assert_eq!(regs.bp(), 0x10);
+        let res = UnwindRuleX86::UseFramePointer.exec(false, &mut regs, &mut read_stack);
+        assert_eq!(res, Ok(Some(0x1200)));
+        assert_eq!(regs.sp(), 0x18);
+        assert_eq!(regs.bp(), 0x20);
+        let res = UnwindRuleX86::UseFramePointer.exec(false, &mut regs, &mut read_stack);
+        assert_eq!(res, Ok(Some(0x1100)));
+        assert_eq!(regs.sp(), 0x28);
+        assert_eq!(regs.bp(), 0x38);
+        let res = UnwindRuleX86::UseFramePointer.exec(false, &mut regs, &mut read_stack);
+        assert_eq!(res, Ok(None));
+    }
+
+    #[test]
+    fn test_misaligned_frame_pointer() {
+        let stack: [u64; 8] = [1, 2, 3, 4, 5, 6, 7, 8];
+        let mut read_stack = |addr| Ok(stack[(addr / 4) as usize]);
+        let mut regs = UnwindRegsX86::new(0x1400, 0x8, 0x12);
+        let res = UnwindRuleX86::UseFramePointer.exec(false, &mut regs, &mut read_stack);
+        assert_eq!(res, Err(Error::MisalignedFramePointer(0x12)));
+    }
+}
@@ -0,0 +1,71 @@
+use std::fmt::Debug;
+
+use crate::display_utils::HexNum;
+use crate::unwind_regs::UnwindRegs;
+
+/// The registers of 32-bit x86 code which are needed for unwinding: eip, esp and
+/// ebp. The values are 32 bits wide, and are stored as `u64` like on the other
+/// architectures.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct UnwindRegsX86 {
+    ip: u64,
+    sp: u64,
+    bp: u64,
+}
+
+impl UnwindRegsX86 {
+    pub fn new(ip: u64, sp: u64, bp: u64) -> Self {
+        Self { ip, sp, bp }
+    }
+
+    #[inline(always)]
+    pub fn ip(&self) -> u64 {
+        self.ip
+    }
+    #[inline(always)]
+    pub fn set_ip(&mut self, ip: u64) {
+        self.ip = ip
+    }
+
+    #[inline(always)]
+    pub fn sp(&self) -> u64 {
+        self.sp
+    }
+    #[inline(always)]
+    pub fn set_sp(&mut self, sp: u64) {
+        self.sp = sp
+    }
+
+    #[inline(always)]
+    pub fn bp(&self) -> u64 {
+        self.bp
+    }
+    #[inline(always)]
+    pub fn set_bp(&mut self, bp: u64) {
+        self.bp = bp
+    }
+}
+
+impl UnwindRegs for UnwindRegsX86 {
+    fn sp(&self) -> u64 {
+        self.sp
+    }
+    fn fp(&self) -> u64 {
+        self.bp
+    }
+    fn with_unknown_registers(&self, placeholder: u64) -> Self {
+        let mut regs = *self;
+        regs.set_bp(placeholder);
+        regs
+    }
+}
+
+impl Debug for UnwindRegsX86 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UnwindRegsX86")
+            .field("ip", &HexNum(self.ip))
+            .field("sp", &HexNum(self.sp))
+            .field("bp", &HexNum(self.bp))
+            .finish()
+    }
+}
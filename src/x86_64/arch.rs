@@ -1,6 +1,7 @@
 use super::unwind_rule::UnwindRuleX86_64;
 use super::unwindregs::UnwindRegsX86_64;
 use crate::arch::Arch;
+use crate::fpo::FpoUnwinding;
 
 /// The x86_64 CPU architecture.
 pub struct ArchX86_64;
@@ -8,3 +9,6 @@ impl Arch for ArchX86_64 {
     type UnwindRule = UnwindRuleX86_64;
     type UnwindRegs = UnwindRegsX86_64;
 }
+
+// FPO data only exists for 32-bit x86 code.
+impl FpoUnwinding for ArchX86_64 {}
@@ -1,6 +1,6 @@
 use gimli::{
-    CfaRule, Encoding, EvaluationStorage, Reader, Register, RegisterRule, UnwindContextStorage,
-    UnwindTableRow, X86_64,
+    CfaRule, Encoding, EvaluationStorage, Expression, Reader, Register, RegisterRule,
+    UnwindContextStorage, UnwindTableRow, X86_64,
 };
 
 use super::{arch::ArchX86_64, unwind_rule::UnwindRuleX86_64, unwindregs::UnwindRegsX86_64};
@@ -25,6 +25,7 @@ impl DwarfUnwinding for ArchX86_64 {
     fn unwind_frame<F, R, S>(
         unwind_info: &UnwindTableRow<R, S>,
         encoding: Encoding,
+        max_expression_steps: u32,
         regs: &mut Self::UnwindRegs,
         is_first_frame: bool,
         read_stack: &mut F,
@@ -46,22 +47,39 @@ impl DwarfUnwinding for ArchX86_64 {
             }
         }
 
-        let cfa = eval_cfa_rule::<R, _, S>(cfa_rule, encoding, regs)
+        let cfa = eval_cfa_rule::<R, _, S>(cfa_rule, encoding, max_expression_steps, regs)
             .ok_or(DwarfUnwinderError::CouldNotRecoverCfa)?;
 
         let ip = regs.ip();
         let bp = regs.bp();
         let sp = regs.sp();
 
-        let new_bp = eval_register_rule::<R, F, _, S>(bp_rule, cfa, encoding, bp, regs, read_stack)
-            .unwrap_or(bp);
+        let new_bp = eval_register_rule::<R, F, _, S>(
+            bp_rule,
+            cfa,
+            encoding,
+            max_expression_steps,
+            bp,
+            regs,
+            read_stack,
+        )
+        .unwrap_or(bp);
 
-        let return_address =
-            match eval_register_rule::<R, F, _, S>(ra_rule, cfa, encoding, ip, regs, read_stack) {
-                Some(ra) => ra,
-                None => read_stack(cfa - 8)
-                    .map_err(|_| DwarfUnwinderError::CouldNotRecoverReturnAddress)?,
-            };
+        let return_address = match eval_register_rule::<R, F, _, S>(
+            ra_rule,
+            cfa,
+            encoding,
+            max_expression_steps,
+            ip,
+            regs,
+            read_stack,
+        ) {
+            Some(ra) => ra,
+            None => cfa
+                .checked_sub(8)
+                .and_then(|ra_location| read_stack(ra_location).ok())
+                .ok_or(DwarfUnwinderError::CouldNotRecoverReturnAddress)?,
+        };
 
         if cfa == sp && return_address == ip {
             return Err(DwarfUnwinderError::DidNotAdvance);
@@ -77,6 +95,10 @@ impl DwarfUnwinding for ArchX86_64 {
         Ok(UnwindResult::Uncacheable(return_address))
     }
 
+    fn register_name(register: Register) -> Option<&'static str> {
+        X86_64::register_name(register)
+    }
+
     fn rule_if_uncovered_by_fde() -> Self::UnwindRule {
         UnwindRuleX86_64::JustReturnIfFirstFrameOtherwiseFp
     }
@@ -124,9 +146,10 @@ fn translate_into_unwind_rule<R: gimli::Reader>(
                 match fp_cfa_offset {
                     None => Ok(UnwindRuleX86_64::OffsetSp { sp_offset_by_8 }),
                     Some(bp_cfa_offset) => {
-                        let bp_storage_offset_from_sp_by_8 =
-                            i16::try_from((offset + bp_cfa_offset) / 8)
-                                .map_err(|_| ConversionError::FpStorageOffsetDoesNotFit)?;
+                        let bp_storage_offset_from_sp_by_8 = offset
+                            .checked_add(bp_cfa_offset)
+                            .and_then(|bp_offset| i16::try_from(bp_offset / 8).ok())
+                            .ok_or(ConversionError::FpStorageOffsetDoesNotFit)?;
                         Ok(UnwindRuleX86_64::OffsetSpAndRestoreBp {
                             sp_offset_by_8,
                             bp_storage_offset_from_sp_by_8,
@@ -153,6 +176,44 @@ fn translate_into_unwind_rule<R: gimli::Reader>(
             }
             _ => Err(ConversionError::CfaIsOffsetFromUnknownRegister),
         },
-        CfaRule::Expression(_) => Err(ConversionError::CfaIsExpression),
+        CfaRule::Expression(expr) => {
+            // Functions which realign the stack store the CFA below their frame
+            // record, and describe it with the expression `DW_OP_breg6 (rbp): offset;
+            // DW_OP_deref`. rbp is saved at `DW_OP_breg6 (rbp): 0`.
+            let cfa_storage_offset = match rbp_relative_expression(expr) {
+                Some((offset, true)) => offset,
+                _ => return Err(ConversionError::CfaIsExpression),
+            };
+            match bp_rule {
+                RegisterRule::Expression(bp_expr)
+                    if rbp_relative_expression(bp_expr) == Some((0, false)) => {}
+                _ => return Err(ConversionError::FramePointerRuleDoesNotRestoreBp),
+            }
+            let sp_storage_offset_from_bp_by_8 = i16::try_from(cfa_storage_offset / 8)
+                .map_err(|_| ConversionError::SpOffsetFromFpDoesNotFit)?;
+            Ok(UnwindRuleX86_64::UseFramePointerWithRealignedStack {
+                sp_storage_offset_from_bp_by_8,
+            })
+        }
+    }
+}
+
+/// Matches the expression `DW_OP_breg6 (rbp): offset`, optionally followed by
+/// `DW_OP_deref`. Returns the offset and whether the expression dereferences.
+fn rbp_relative_expression<R: gimli::Reader>(expr: &Expression<R>) -> Option<(i64, bool)> {
+    let mut reader = expr.0.clone();
+    if reader.read_u8().ok()? != gimli::constants::DW_OP_breg6.0 {
+        return None;
+    }
+    let offset = reader.read_sleb128().ok()?;
+    if offset % 8 != 0 {
+        return None;
+    }
+    if reader.is_empty() {
+        return Some((offset, false));
+    }
+    if reader.read_u8().ok()? == gimli::constants::DW_OP_deref.0 && reader.is_empty() {
+        return Some((offset, true));
     }
+    None
 }
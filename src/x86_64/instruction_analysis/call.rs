@@ -0,0 +1,83 @@
+/// Checks whether the bytes are one of the call encodings.
+type CallFormCheck = fn(&[u8]) -> bool;
+
+/// The `ff /2` call encodings without a REX prefix, as their length and a check of
+/// their bytes.
+const INDIRECT_CALL_FORMS: [(usize, CallFormCheck); 8] = [
+    // call [rXX + rYY*s + disp32] [0xff 0x94 SIB XX XX XX XX]
+    (7, |b| b[0..2] == [0xff, 0x94]),
+    // call [rip + disp32] [0xff 0x15 XX XX XX XX]
+    (6, |b| b[0..2] == [0xff, 0x15]),
+    // call [rXX + disp32] [0xff 0x90+r XX XX XX XX], excluding rsp which needs a SIB byte
+    (6, |b| b[0] == 0xff && b[1] & 0xf8 == 0x90 && b[1] != 0x94),
+    // call [rXX + rYY*s + disp8] [0xff 0x54 SIB XX]
+    (4, |b| b[0..2] == [0xff, 0x54]),
+    // call [rXX + disp8] [0xff 0x50+r XX], excluding rsp which needs a SIB byte
+    (3, |b| b[0] == 0xff && b[1] & 0xf8 == 0x50 && b[1] != 0x54),
+    // call [rXX + rYY*s] [0xff 0x14 SIB]
+    (3, |b| b[0..2] == [0xff, 0x14]),
+    // call rXX [0xff 0xd0+r]
+    (2, |b| b[0] == 0xff && b[1] & 0xf8 == 0xd0),
+    // call [rXX] [0xff 0x10+r], excluding rsp and rip-relative
+    (2, |b| {
+        b[0] == 0xff && b[1] & 0xf8 == 0x10 && b[1] != 0x14 && b[1] != 0x15
+    }),
+];
+
+/// Returns the length of the call instruction which ends at `return_address_offset`,
+/// if the bytes before it look like a call.
+///
+/// x86 is a variable length encoding, so looking backwards is guesswork. The indirect
+/// calls can have a REX prefix (0x40-0x4f), e.g. `call r11` is [0x41 0xff 0xd3], and
+/// the same bytes without the prefix are a valid shorter call. So the longest
+/// encodings are checked first.
+pub fn call_instruction_len_before(
+    text_bytes: &[u8],
+    return_address_offset: usize,
+) -> Option<usize> {
+    let bytes = &text_bytes[..return_address_offset];
+    let is_rex_prefix = |byte: u8| byte & 0xf0 == 0x40;
+    let is_call_of_len = |len: usize| {
+        let candidate = match bytes.len().checked_sub(len) {
+            Some(start) => &bytes[start..],
+            None => return false,
+        };
+        // call rel32 [0xe8 XX XX XX XX]
+        if len == 5 && candidate[0] == 0xe8 {
+            return true;
+        }
+        INDIRECT_CALL_FORMS.iter().any(|&(form_len, matches)| {
+            (form_len == len && matches(candidate))
+                || (form_len + 1 == len && is_rex_prefix(candidate[0]) && matches(&candidate[1..]))
+        })
+    };
+    (2..=8).rev().find(|len| is_call_of_len(*len))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_call_instruction_len() {
+        // call 0x1234
+        let bytes = [0x90, 0xe8, 0x34, 0x12, 0x00, 0x00, 0x90];
+        assert_eq!(call_instruction_len_before(&bytes, 6), Some(5));
+        // call r11
+        let bytes = [0x90, 0x41, 0xff, 0xd3, 0x90];
+        assert_eq!(call_instruction_len_before(&bytes, 4), Some(3));
+        // call qword [rax + 0x18]
+        let bytes = [0x90, 0xff, 0x50, 0x18, 0x90];
+        assert_eq!(call_instruction_len_before(&bytes, 4), Some(3));
+        // call qword [rsp + 0x8]
+        let bytes = [0x90, 0xff, 0x54, 0x24, 0x08, 0x90];
+        assert_eq!(call_instruction_len_before(&bytes, 5), Some(4));
+        // call qword [r9 + 0x18]
+        let bytes = [0x90, 0x49, 0xff, 0x51, 0x18, 0x90];
+        assert_eq!(call_instruction_len_before(&bytes, 5), Some(4));
+        // Not a call
+        let bytes = [0x48, 0x89, 0xe5];
+        assert_eq!(call_instruction_len_before(&bytes, 3), None);
+        assert_eq!(call_instruction_len_before(&bytes, 0), None);
+    }
+}
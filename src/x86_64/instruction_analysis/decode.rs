@@ -0,0 +1,190 @@
+/// The instructions that prologue and epilogue analysis cares about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    /// `endbr64`
+    Endbr64,
+    /// `push rbp`
+    PushRbp,
+    /// `push rXX`, for any register other than rbp
+    Push,
+    /// `mov rbp, rsp`
+    MovRbpRsp,
+    /// `sub rsp, imm`
+    SubRsp(u32),
+    /// `pop rbp`
+    PopRbp,
+    /// `pop rXX`, for any register other than rbp
+    Pop,
+    /// `ret`
+    Ret,
+    /// `jmp`, direct or indirect
+    Jmp,
+    /// Any other instruction. Only the `iced-x86` backend knows the length of other
+    /// instructions, the built-in decoder returns `None` for them.
+    #[cfg_attr(not(feature = "iced-x86"), allow(dead_code))]
+    Other,
+}
+
+/// Decodes the instruction at the start of `bytes`, and returns it together with its
+/// length. Returns `None` if the bytes don't start with a complete instruction that
+/// the decoder understands.
+///
+/// With the `iced-x86` feature, this uses a full x86_64 decoder. Otherwise it uses a
+/// small built-in decoder which only knows the encodings that compilers commonly emit
+/// in prologues and epilogues.
+#[cfg(not(feature = "iced-x86"))]
+pub fn decode_instruction(bytes: &[u8]) -> Option<(Instruction, usize)> {
+    match bytes {
+        [0xf3, 0x0f, 0x1e, 0xfa, ..] => Some((Instruction::Endbr64, 4)),
+        [0x55, ..] => Some((Instruction::PushRbp, 1)),
+        [0x50..=0x57, ..] => Some((Instruction::Push, 1)),
+        [0x40 | 0x41, 0x50..=0x57, ..] => Some((Instruction::Push, 2)),
+        [0x48, 0x89, 0xe5, ..] => Some((Instruction::MovRbpRsp, 3)),
+        [0x48, 0x83, 0xec, imm, ..] => sub_rsp(i32::from(*imm as i8), 4),
+        [0x48, 0x81, 0xec, a, b, c, d, ..] => sub_rsp(i32::from_le_bytes([*a, *b, *c, *d]), 7),
+        [0x5d, ..] => Some((Instruction::PopRbp, 1)),
+        [0x58..=0x5f, ..] => Some((Instruction::Pop, 1)),
+        [0x40 | 0x41, 0x58..=0x5f, ..] => Some((Instruction::Pop, 2)),
+        [0xc3, ..] => Some((Instruction::Ret, 1)),
+        [0xeb, _, ..] => Some((Instruction::Jmp, 2)),
+        [0xe9, _, _, _, _, ..] => Some((Instruction::Jmp, 5)),
+        [0xff, modrm, ..] if (modrm >> 3) & 0b111 == 4 => {
+            let len = 1 + modrm_len(&bytes[1..])?;
+            Some((Instruction::Jmp, len))
+        }
+        [0x41, 0xff, modrm, ..] if (modrm >> 3) & 0b111 == 4 => {
+            let len = 2 + modrm_len(&bytes[2..])?;
+            Some((Instruction::Jmp, len))
+        }
+        _ => None,
+    }
+}
+
+/// `sub rsp, imm` with the sign-extended immediate `imm`. A negative immediate releases
+/// stack space, which prologues don't do, so it isn't decoded.
+#[cfg(not(feature = "iced-x86"))]
+fn sub_rsp(imm: i32, len: usize) -> Option<(Instruction, usize)> {
+    let stack_size = u32::try_from(imm).ok()?;
+    Some((Instruction::SubRsp(stack_size), len))
+}
+
+/// The length of the ModRM byte at the start of `bytes`, including the SIB byte and
+/// the displacement, if the bytes are long enough.
+#[cfg(not(feature = "iced-x86"))]
+fn modrm_len(bytes: &[u8]) -> Option<usize> {
+    let modrm = *bytes.first()?;
+    let mode = modrm >> 6;
+    let rm = modrm & 0b111;
+    let has_sib = mode != 0b11 && rm == 0b100;
+    let sib_base = match bytes.get(1) {
+        Some(sib) if has_sib => sib & 0b111,
+        None if has_sib => return None,
+        _ => 0,
+    };
+    let displacement_len = match mode {
+        0b00 if rm == 0b101 || (has_sib && sib_base == 0b101) => 4,
+        0b01 => 1,
+        0b10 => 4,
+        _ => 0,
+    };
+    let len = 1 + usize::from(has_sib) + displacement_len;
+    if bytes.len() < len {
+        return None;
+    }
+    Some(len)
+}
+
+/// Decodes the instruction at the start of `bytes`, and returns it together with its
+/// length. Returns `None` if the bytes don't start with a complete instruction that
+/// the decoder understands.
+///
+/// With the `iced-x86` feature, this uses a full x86_64 decoder. Otherwise it uses a
+/// small built-in decoder which only knows the encodings that compilers commonly emit
+/// in prologues and epilogues.
+#[cfg(feature = "iced-x86")]
+pub fn decode_instruction(bytes: &[u8]) -> Option<(Instruction, usize)> {
+    use iced_x86::{Decoder, DecoderOptions, Mnemonic, OpKind, Register};
+
+    let mut decoder = Decoder::with_ip(64, bytes, 0, DecoderOptions::NONE);
+    let instruction = decoder.decode();
+    if instruction.is_invalid() {
+        return None;
+    }
+    let is_register = |operand: u32, register: Register| {
+        instruction.op_kind(operand) == OpKind::Register
+            && instruction.op_register(operand) == register
+    };
+    let decoded = match instruction.mnemonic() {
+        Mnemonic::Endbr64 => Instruction::Endbr64,
+        Mnemonic::Push if is_register(0, Register::RBP) => Instruction::PushRbp,
+        Mnemonic::Push if instruction.op0_kind() == OpKind::Register => Instruction::Push,
+        Mnemonic::Mov if is_register(0, Register::RBP) && is_register(1, Register::RSP) => {
+            Instruction::MovRbpRsp
+        }
+        Mnemonic::Sub
+            if is_register(0, Register::RSP)
+                && matches!(
+                    instruction.op1_kind(),
+                    OpKind::Immediate8to64 | OpKind::Immediate32to64
+                ) =>
+        {
+            match u32::try_from(instruction.immediate(1)) {
+                Ok(stack_size) => Instruction::SubRsp(stack_size),
+                Err(_) => Instruction::Other,
+            }
+        }
+        Mnemonic::Pop if is_register(0, Register::RBP) => Instruction::PopRbp,
+        Mnemonic::Pop if instruction.op0_kind() == OpKind::Register => Instruction::Pop,
+        Mnemonic::Ret => Instruction::Ret,
+        Mnemonic::Jmp => Instruction::Jmp,
+        _ => Instruction::Other,
+    };
+    Some((decoded, instruction.len()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decode_instruction() {
+        assert_eq!(
+            decode_instruction(&[0x55, 0x48, 0x89, 0xe5]),
+            Some((Instruction::PushRbp, 1))
+        );
+        assert_eq!(
+            decode_instruction(&[0x41, 0x57]),
+            Some((Instruction::Push, 2))
+        );
+        assert_eq!(
+            decode_instruction(&[0x48, 0x81, 0xec, 0x80, 0x00, 0x00, 0x00]),
+            Some((Instruction::SubRsp(0x80), 7))
+        );
+        assert_eq!(
+            decode_instruction(&[0x48, 0x83, 0xec, 0x78]),
+            Some((Instruction::SubRsp(0x78), 4))
+        );
+        // The imm8 is sign-extended, so this is sub rsp, -0x80.
+        assert!(!matches!(
+            decode_instruction(&[0x48, 0x83, 0xec, 0x80]),
+            Some((Instruction::SubRsp(_), _))
+        ));
+        assert_eq!(
+            decode_instruction(&[0x5d, 0xc3]),
+            Some((Instruction::PopRbp, 1))
+        );
+        assert_eq!(decode_instruction(&[0xc3]), Some((Instruction::Ret, 1)));
+        // jmp qword [rip + 0x1234]
+        assert_eq!(
+            decode_instruction(&[0xff, 0x25, 0x34, 0x12, 0x00, 0x00]),
+            Some((Instruction::Jmp, 6))
+        );
+        // jmp r11
+        assert_eq!(
+            decode_instruction(&[0x41, 0xff, 0xe3]),
+            Some((Instruction::Jmp, 3))
+        );
+        // Truncated sub rsp, 0x80
+        assert_eq!(decode_instruction(&[0x48, 0x81, 0xec, 0x80]), None);
+    }
+}
@@ -0,0 +1,150 @@
+use super::super::unwind_rule::UnwindRuleX86_64;
+
+/// The bytes which the PLT pushed before it jumped to the trampoline: the relocation
+/// index and the link map.
+const PLT_PUSHED: u64 = 16;
+
+/// The lazy binding trampolines of the dynamic linker, e.g. glibc's
+/// `_dl_runtime_resolve_xsave`, are entered with the return address 16 bytes above
+/// sp, and then save a large register block:
+///
+/// ```plain
+/// endbr64
+/// push   rbx
+/// mov    rbx, rsp              ; from here on, the CFA is only known from rbx
+/// and    rsp, -0x40
+/// sub    rsp, ...              ; space for the register block, e.g. xsave state
+/// ...
+/// call   _dl_fixup
+/// ...
+/// mov    rsp, rbx
+/// mov    rbx, [rsp]
+/// add    rsp, 0x18             ; drop rbx and the two values pushed by the PLT
+/// jmp    r11                   ; jump to the resolved function
+/// ```
+///
+/// Variants which don't realign the stack only use `sub rsp` and `add rsp`.
+///
+/// The stack offset is found by decoding from the start of the function up to
+/// `pc_offset`, or, in the epilogue, from `pc_offset` up to the final jump. In the
+/// body of realigning variants, the offset is only known from rbx, which isn't
+/// tracked, so this returns `None` there. `function_bytes` starts at the start of the
+/// trampoline.
+pub fn unwind_rule_from_dl_trampoline(
+    function_bytes: &[u8],
+    pc_offset: usize,
+) -> Option<UnwindRuleX86_64> {
+    let pushed = pushed_bytes_in_prologue(function_bytes, pc_offset)
+        .or_else(|| pushed_bytes_in_epilogue(function_bytes.get(pc_offset..)?))?;
+    match pushed {
+        0 => Some(UnwindRuleX86_64::JustReturn),
+        pushed => Some(UnwindRuleX86_64::OffsetSp {
+            sp_offset_by_8: u16::try_from(pushed / 8 + 1).ok()?,
+        }),
+    }
+}
+
+fn pushed_bytes_in_prologue(function_bytes: &[u8], pc_offset: usize) -> Option<u64> {
+    let mut pushed = PLT_PUSHED;
+    let mut offset = 0;
+    while offset < pc_offset {
+        let (len, sp_change) = match function_bytes.get(offset..)? {
+            // endbr64
+            [0xf3, 0x0f, 0x1e, 0xfa, ..] => (4, 0),
+            // push r64
+            [0x50..=0x57, ..] => (1, 8),
+            [0x41, 0x50..=0x57, ..] => (2, 8),
+            // sub rsp, imm8 / imm32
+            [0x48, 0x83, 0xec, imm, ..] => (4, u64::from(*imm)),
+            [0x48, 0x81, 0xec, a, b, c, d, ..] => {
+                (7, u64::from(u32::from_le_bytes([*a, *b, *c, *d])))
+            }
+            // Anything else, including `mov rbx, rsp`, ends the prologue.
+            _ => return None,
+        };
+        pushed += sp_change;
+        offset += len;
+    }
+    if offset != pc_offset {
+        // pc_offset is in the middle of an instruction.
+        return None;
+    }
+    Some(pushed)
+}
+
+fn pushed_bytes_in_epilogue(bytes_from_pc: &[u8]) -> Option<u64> {
+    let mut popped = 0;
+    let mut offset = 0;
+    loop {
+        let (len, sp_change) = match bytes_from_pc.get(offset..)? {
+            // jmp r11
+            [0x41, 0xff, 0xe3, ..] => return Some(popped),
+            // mov rbx, [rsp]
+            [0x48, 0x8b, 0x1c, 0x24, ..] => (4, 0),
+            // pop r64
+            [0x58..=0x5f, ..] => (1, 8),
+            [0x41, 0x58..=0x5f, ..] => (2, 8),
+            // add rsp, imm8 / imm32
+            [0x48, 0x83, 0xc4, imm, ..] => (4, u64::from(*imm)),
+            [0x48, 0x81, 0xc4, a, b, c, d, ..] => {
+                (7, u64::from(u32::from_le_bytes([*a, *b, *c, *d])))
+            }
+            // Anything else, including `mov rsp, rbx`, isn't part of the epilogue.
+            _ => return None,
+        };
+        popped += sp_change;
+        offset += len;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_realigning_trampoline() {
+        #[rustfmt::skip]
+        let bytes = [
+            // endbr64; push rbx; mov rbx, rsp; and rsp, -0x40
+            0xf3, 0x0f, 0x1e, 0xfa,
+            0x53,
+            0x48, 0x89, 0xe3,
+            0x48, 0x83, 0xe4, 0xc0,
+            // call _dl_fixup
+            0xe8, 0x00, 0x00, 0x00, 0x00,
+            // mov rsp, rbx; mov rbx, [rsp]; add rsp, 0x18; jmp r11
+            0x48, 0x89, 0xdc,
+            0x48, 0x8b, 0x1c, 0x24,
+            0x48, 0x83, 0xc4, 0x18,
+            0x41, 0xff, 0xe3,
+        ];
+        let offset_sp = |sp_offset_by_8| Some(UnwindRuleX86_64::OffsetSp { sp_offset_by_8 });
+        assert_eq!(unwind_rule_from_dl_trampoline(&bytes, 0), offset_sp(3));
+        assert_eq!(unwind_rule_from_dl_trampoline(&bytes, 4), offset_sp(3));
+        assert_eq!(unwind_rule_from_dl_trampoline(&bytes, 5), offset_sp(4));
+        // The body and the return address after the call need rbx.
+        assert_eq!(unwind_rule_from_dl_trampoline(&bytes, 8), None);
+        assert_eq!(unwind_rule_from_dl_trampoline(&bytes, 17), None);
+        assert_eq!(unwind_rule_from_dl_trampoline(&bytes, 20), offset_sp(4));
+        assert_eq!(unwind_rule_from_dl_trampoline(&bytes, 24), offset_sp(4));
+        assert_eq!(
+            unwind_rule_from_dl_trampoline(&bytes, 28),
+            Some(UnwindRuleX86_64::JustReturn)
+        );
+    }
+
+    #[test]
+    fn test_sub_rsp_trampoline() {
+        #[rustfmt::skip]
+        let bytes = [
+            // sub rsp, 0x38; call _dl_fixup; add rsp, 0x48; jmp r11
+            0x48, 0x83, 0xec, 0x38,
+            0xe8, 0x00, 0x00, 0x00, 0x00,
+            0x48, 0x83, 0xc4, 0x48,
+            0x41, 0xff, 0xe3,
+        ];
+        let offset_sp = |sp_offset_by_8| Some(UnwindRuleX86_64::OffsetSp { sp_offset_by_8 });
+        assert_eq!(unwind_rule_from_dl_trampoline(&bytes, 4), offset_sp(10));
+        assert_eq!(unwind_rule_from_dl_trampoline(&bytes, 9), offset_sp(10));
+    }
+}
@@ -0,0 +1,141 @@
+use super::super::unwind_rule::UnwindRuleX86_64;
+
+/// What a PLT instruction does to the stack.
+enum PltInstruction {
+    Push,
+    Jump,
+    Other,
+}
+
+/// Decodes the instructions which appear in `.plt`, `.plt.sec`, `.plt.got` and `.iplt`.
+fn decode(bytes: &[u8]) -> Option<(usize, PltInstruction)> {
+    let (len, instruction) = match bytes {
+        // endbr64
+        [0xf3, 0x0f, 0x1e, 0xfa, ..] => (4, PltInstruction::Other),
+        // jmp [rip + disp32], bnd jmp [rip + disp32]
+        [0xff, 0x25, ..] => (6, PltInstruction::Jump),
+        [0xf2, 0xff, 0x25, ..] => (7, PltInstruction::Jump),
+        // jmp rel32, bnd jmp rel32
+        [0xe9, ..] => (5, PltInstruction::Jump),
+        [0xf2, 0xe9, ..] => (6, PltInstruction::Jump),
+        // push [rip + disp32], push imm32
+        [0xff, 0x35, ..] => (6, PltInstruction::Push),
+        [0x68, ..] => (5, PltInstruction::Push),
+        // The nops which pad the stubs to 16 bytes.
+        [0x90, ..] => (1, PltInstruction::Other),
+        [0x66, 0x90, ..] => (2, PltInstruction::Other),
+        [0x0f, 0x1f, 0x00, ..] => (3, PltInstruction::Other),
+        [0x0f, 0x1f, 0x40, ..] => (4, PltInstruction::Other),
+        [0x0f, 0x1f, 0x44, ..] => (5, PltInstruction::Other),
+        [0x66, 0x0f, 0x1f, 0x44, ..] => (6, PltInstruction::Other),
+        [0x0f, 0x1f, 0x80, ..] => (7, PltInstruction::Other),
+        [0x0f, 0x1f, 0x84, ..] => (8, PltInstruction::Other),
+        [0x66, 0x0f, 0x1f, 0x84, ..] => (9, PltInstruction::Other),
+        [0x66, 0x2e, 0x0f, 0x1f, 0x84, ..] => (10, PltInstruction::Other),
+        _ => return None,
+    };
+    if bytes.len() < len {
+        return None;
+    }
+    Some((len, instruction))
+}
+
+/// PLT stubs don't set up a frame, but the lazy binding path pushes values before it
+/// jumps to the dynamic linker: each `.plt` entry pushes its relocation index and
+/// jumps to PLT0, the first entry, which pushes the link map. So the return address
+/// is up to 16 bytes above sp.
+///
+/// The stubs are 16-byte aligned, relative to the start of `plt_bytes`. The stub
+/// which contains `pc_offset` is decoded from its start, counting the pushes; stubs
+/// in `.plt.got` are only 8 bytes long, so the count restarts after every jump. In
+/// PLT0, the value that the jumping entry pushed is counted too.
+pub fn unwind_rule_from_plt(plt_bytes: &[u8], pc_offset: usize) -> Option<UnwindRuleX86_64> {
+    let stub_start = pc_offset & !15;
+    let mut offset = stub_start;
+    if let [0xf3, 0x0f, 0x1e, 0xfa, ..] = plt_bytes.get(offset..)? {
+        offset += 4;
+    }
+    let is_plt0 = stub_start == 0 && plt_bytes.get(offset..)?.starts_with(&[0xff, 0x35]);
+    let mut pushed: u16 = if is_plt0 { 1 } else { 0 };
+    offset = stub_start;
+    while offset < pc_offset {
+        let (len, instruction) = decode(&plt_bytes[offset..])?;
+        match instruction {
+            PltInstruction::Push => pushed += 1,
+            PltInstruction::Jump => pushed = 0,
+            PltInstruction::Other => {}
+        }
+        offset += len;
+    }
+    if offset != pc_offset {
+        // pc_offset is in the middle of an instruction.
+        return None;
+    }
+    match pushed {
+        0 => Some(UnwindRuleX86_64::JustReturn),
+        pushed => Some(UnwindRuleX86_64::OffsetSp {
+            sp_offset_by_8: pushed + 1,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_lazy_plt() {
+        #[rustfmt::skip]
+        let bytes = [
+            // PLT0: push [rip + 0x2fe2]; jmp [rip + 0x2fe4]; nop4
+            0xff, 0x35, 0xe2, 0x2f, 0x00, 0x00,
+            0xff, 0x25, 0xe4, 0x2f, 0x00, 0x00,
+            0x0f, 0x1f, 0x40, 0x00,
+            // PLT1: jmp [rip + 0x2fe2]; push 0; jmp PLT0
+            0xff, 0x25, 0xe2, 0x2f, 0x00, 0x00,
+            0x68, 0x00, 0x00, 0x00, 0x00,
+            0xe9, 0xe0, 0xff, 0xff, 0xff,
+        ];
+        let offset_sp = |sp_offset_by_8| Some(UnwindRuleX86_64::OffsetSp { sp_offset_by_8 });
+        assert_eq!(unwind_rule_from_plt(&bytes, 0), offset_sp(2));
+        assert_eq!(unwind_rule_from_plt(&bytes, 6), offset_sp(3));
+        assert_eq!(
+            unwind_rule_from_plt(&bytes, 16),
+            Some(UnwindRuleX86_64::JustReturn)
+        );
+        assert_eq!(
+            unwind_rule_from_plt(&bytes, 22),
+            Some(UnwindRuleX86_64::JustReturn)
+        );
+        assert_eq!(unwind_rule_from_plt(&bytes, 27), offset_sp(2));
+        assert_eq!(unwind_rule_from_plt(&bytes, 3), None);
+    }
+
+    #[test]
+    fn test_ibt_plt() {
+        #[rustfmt::skip]
+        let bytes = [
+            // .plt entry: endbr64; push 1; bnd jmp PLT0; nop
+            0xf3, 0x0f, 0x1e, 0xfa,
+            0x68, 0x01, 0x00, 0x00, 0x00,
+            0xf2, 0xe9, 0xd1, 0xff, 0xff, 0xff,
+            0x90,
+            // .plt.sec entry: endbr64; bnd jmp [rip + 0x2f75]; nop5
+            0xf3, 0x0f, 0x1e, 0xfa,
+            0xf2, 0xff, 0x25, 0x75, 0x2f, 0x00, 0x00,
+            0x0f, 0x1f, 0x44, 0x00, 0x00,
+        ];
+        assert_eq!(
+            unwind_rule_from_plt(&bytes, 4),
+            Some(UnwindRuleX86_64::JustReturn)
+        );
+        assert_eq!(
+            unwind_rule_from_plt(&bytes, 9),
+            Some(UnwindRuleX86_64::OffsetSp { sp_offset_by_8: 2 })
+        );
+        assert_eq!(
+            unwind_rule_from_plt(&bytes, 20),
+            Some(UnwindRuleX86_64::JustReturn)
+        );
+    }
+}
@@ -1,4 +1,5 @@
 use super::super::unwind_rule::UnwindRuleX86_64;
+use super::decode::{decode_instruction, Instruction};
 
 pub fn unwind_rule_from_detected_prologue(
     text_bytes: &[u8],
@@ -15,7 +16,7 @@ pub fn unwind_rule_from_detected_prologue(
     // Let's do it anyway and hope our heuristics are good enough so that
     // they work in more cases than they fail in.
     let mut cursor = slice_from_start.len();
-    let mut sp_offset_by_8 = 0;
+    let mut sp_offset_by_8: u16 = 0;
     loop {
         if cursor >= 4 {
             // Detect push rbp; mov rbp, rsp [0x55, 0x48 0x89 0xe5]
@@ -27,7 +28,7 @@ pub fn unwind_rule_from_detected_prologue(
             // Detect push rXX with optional prefix
             let byte = slice_from_start[cursor - 1];
             if byte & 0xf8 == 0x50 {
-                sp_offset_by_8 += 1;
+                sp_offset_by_8 = sp_offset_by_8.checked_add(1)?;
                 cursor -= 1;
 
                 // Consume prefix, if present
@@ -40,10 +41,72 @@ pub fn unwind_rule_from_detected_prologue(
         }
         break;
     }
-    sp_offset_by_8 += 1; // Add one for popping the return address.
+    sp_offset_by_8 = sp_offset_by_8.checked_add(1)?; // Add one for popping the return address.
     Some(UnwindRuleX86_64::OffsetSp { sp_offset_by_8 })
 }
 
+/// Decodes the instructions from the start of the function up to pc_offset. Unlike
+/// `unwind_rule_from_detected_prologue`, this doesn't need to guess instruction
+/// boundaries, so it also handles `sub rsp` and stale `rbp` values correctly.
+/// Returns None as soon as an instruction is found that doesn't belong in a prologue.
+pub fn unwind_rule_from_prologue_from_function_start(
+    function_bytes: &[u8],
+    pc_offset: usize,
+) -> Option<UnwindRuleX86_64> {
+    let mut bytes = &function_bytes[..pc_offset];
+    // The number of 8 byte slots between sp and the return address, including the
+    // return address itself.
+    let mut sp_offset_by_8: u16 = 1;
+    // The value of sp_offset_by_8 right after rbp was pushed.
+    let mut bp_pushed_at = None;
+    let mut has_frame_pointer = false;
+    while !bytes.is_empty() {
+        // An instruction which straddles pc_offset doesn't decode, so it ends the
+        // analysis too.
+        let (instruction, len) = decode_instruction(bytes)?;
+        match instruction {
+            Instruction::Endbr64 => {}
+            Instruction::PushRbp => {
+                sp_offset_by_8 = sp_offset_by_8.checked_add(1)?;
+                bp_pushed_at = Some(sp_offset_by_8);
+            }
+            Instruction::Push => {
+                sp_offset_by_8 = sp_offset_by_8.checked_add(1)?;
+            }
+            Instruction::MovRbpRsp => {
+                // This only sets up a frame pointer if rbp was pushed right before.
+                if bp_pushed_at != Some(sp_offset_by_8) {
+                    return None;
+                }
+                has_frame_pointer = true;
+            }
+            Instruction::SubRsp(stack_size) => {
+                sp_offset_by_8 = add_stack_size(sp_offset_by_8, stack_size)?;
+            }
+            // We've left the prologue.
+            _ => return None,
+        }
+        bytes = &bytes[len..];
+    }
+    if has_frame_pointer {
+        return Some(UnwindRuleX86_64::UseFramePointer);
+    }
+    match bp_pushed_at {
+        Some(bp_pushed_at) => Some(UnwindRuleX86_64::OffsetSpAndRestoreBp {
+            sp_offset_by_8,
+            bp_storage_offset_from_sp_by_8: i16::try_from(sp_offset_by_8 - bp_pushed_at).ok()?,
+        }),
+        None => Some(UnwindRuleX86_64::OffsetSp { sp_offset_by_8 }),
+    }
+}
+
+fn add_stack_size(sp_offset_by_8: u16, stack_size: u32) -> Option<u16> {
+    if !stack_size.is_multiple_of(8) {
+        return None;
+    }
+    sp_offset_by_8.checked_add(u16::try_from(stack_size / 8).ok()?)
+}
+
 fn is_next_instruction_expected_in_prologue(bytes: &[u8]) -> bool {
     if bytes.len() < 4 {
         return false;
@@ -98,3 +161,50 @@ fn is_next_instruction_expected_in_prologue(bytes: &[u8]) -> bool {
 // 442405  53           push  rbx
 // 442406  48 83 EC 18  sub  rsp, 0x18
 // 44240a  48 8B 07     mov  rax, qword [rdi]
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_prologue_from_function_start() {
+        // push rbp; push rbx; sub rsp, 0x18; mov rbx, rdi
+        let bytes = [0x55, 0x53, 0x48, 0x83, 0xec, 0x18, 0x48, 0x89, 0xfb];
+        assert_eq!(
+            unwind_rule_from_prologue_from_function_start(&bytes, 0),
+            Some(UnwindRuleX86_64::OffsetSp { sp_offset_by_8: 1 })
+        );
+        assert_eq!(
+            unwind_rule_from_prologue_from_function_start(&bytes, 1),
+            Some(UnwindRuleX86_64::OffsetSpAndRestoreBp {
+                sp_offset_by_8: 2,
+                bp_storage_offset_from_sp_by_8: 0
+            })
+        );
+        assert_eq!(
+            unwind_rule_from_prologue_from_function_start(&bytes, 6),
+            Some(UnwindRuleX86_64::OffsetSpAndRestoreBp {
+                sp_offset_by_8: 6,
+                bp_storage_offset_from_sp_by_8: 4
+            })
+        );
+        assert_eq!(
+            unwind_rule_from_prologue_from_function_start(&bytes, 9),
+            None
+        );
+
+        // endbr64; push rbp; mov rbp, rsp; push r15
+        let bytes = [0xf3, 0x0f, 0x1e, 0xfa, 0x55, 0x48, 0x89, 0xe5, 0x41, 0x57];
+        assert_eq!(
+            unwind_rule_from_prologue_from_function_start(&bytes, 5),
+            Some(UnwindRuleX86_64::OffsetSpAndRestoreBp {
+                sp_offset_by_8: 2,
+                bp_storage_offset_from_sp_by_8: 0
+            })
+        );
+        assert_eq!(
+            unwind_rule_from_prologue_from_function_start(&bytes, 10),
+            Some(UnwindRuleX86_64::UseFramePointer)
+        );
+    }
+}
@@ -0,0 +1,51 @@
+use super::super::unwind_rule::UnwindRuleX86_64;
+
+/// `mov rax, 15; syscall`, the body of glibc's and musl's `__restore_rt`.
+const RESTORE_RT: [u8; 9] = [0x48, 0xc7, 0xc0, 0x0f, 0x00, 0x00, 0x00, 0x0f, 0x05];
+
+/// Offset of the `syscall` instruction in `RESTORE_RT`.
+const SYSCALL_OFFSET: usize = 7;
+
+pub fn unwind_rule_from_detected_sigreturn_trampoline(
+    text_bytes: &[u8],
+    pc_offset: usize,
+) -> Option<UnwindRuleX86_64> {
+    // The handler returns to the start of the trampoline. If we were interrupted
+    // in the trampoline itself, pc can also be on the syscall instruction.
+    let at_start = text_bytes[pc_offset..].starts_with(&RESTORE_RT);
+    let at_syscall = pc_offset
+        .checked_sub(SYSCALL_OFFSET)
+        .is_some_and(|start| text_bytes[start..].starts_with(&RESTORE_RT));
+    if at_start || at_syscall {
+        Some(UnwindRuleX86_64::RestoreFromLinuxSigframe)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_restore_rt() {
+        let bytes = [0x90, 0x48, 0xc7, 0xc0, 0x0f, 0x00, 0x00, 0x00, 0x0f, 0x05];
+        let rule = Some(UnwindRuleX86_64::RestoreFromLinuxSigframe);
+        assert_eq!(
+            unwind_rule_from_detected_sigreturn_trampoline(&bytes, 0),
+            None
+        );
+        assert_eq!(
+            unwind_rule_from_detected_sigreturn_trampoline(&bytes, 1),
+            rule
+        );
+        assert_eq!(
+            unwind_rule_from_detected_sigreturn_trampoline(&bytes, 8),
+            rule
+        );
+        assert_eq!(
+            unwind_rule_from_detected_sigreturn_trampoline(&bytes, 10),
+            None
+        );
+    }
+}
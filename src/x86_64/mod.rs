@@ -9,9 +9,6 @@ mod unwindregs;
 
 pub use arch::*;
 pub use cache::*;
-pub use dwarf::*;
-pub use instruction_analysis::*;
-pub use macho::*;
 pub use unwind_rule::*;
 pub use unwinder::*;
 pub use unwindregs::*;
@@ -1,7 +1,10 @@
 use super::unwindregs::UnwindRegsX86_64;
 use crate::add_signed::checked_add_signed;
+use crate::display_utils::{Load, RegOffset};
 use crate::error::Error;
-use crate::unwind_rule::UnwindRule;
+use crate::unwind_rule::{check_frame_pointer, UnwindRule};
+
+use std::fmt::Display;
 
 /// For all of these: return address is *(new_sp - 8)
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -19,6 +22,89 @@ pub enum UnwindRuleX86_64 {
     },
     /// (sp, bp) = (bp + 16, *bp)
     UseFramePointer,
+    /// (sp, bp) = (*(bp + 8x), *bp)
+    /// Used in functions which realign the stack, e.g. with `and rsp, -32` for AVX
+    /// locals. GCC saves the original stack pointer in a register, e.g. with
+    /// `lea r10, [rsp + 8]`, before realigning, and pushes that register below the
+    /// frame record, so the caller's sp is read from the stack rather than computed
+    /// from bp.
+    UseFramePointerWithRealignedStack { sp_storage_offset_from_bp_by_8: i16 },
+    /// (ip, sp, bp) = (*(sp + 168), *(sp + 160), *(sp + 120))
+    /// Used in the Linux `__restore_rt` trampoline, where sp points to the `ucontext_t`
+    /// of the signal frame. The new ip is the interrupted instruction, not a return address.
+    RestoreFromLinuxSigframe,
+    /// (ip, sp, bp) = (*(m + 144), *(m + 72), *(m + 64)) with m = sp + 8 or sp + 16
+    /// Used in macOS's `_sigtramp`, where the kernel stores the mcontext right above
+    /// the 16 byte aligned stack pointer. The new ip is the interrupted instruction,
+    /// not a return address.
+    RestoreFromMacosSigtramp,
+    /// (sp, bp) = (bp + 16, *bp), where bp + 16 may be below sp
+    /// Used in functions like `__morestack`, which run their callee on a new stack
+    /// segment and keep their own frame record on the previous segment.
+    UseFramePointerAcrossStackSwitch,
+}
+
+/// Prints the rule as formulas for the caller's registers, where `sp'` and `bp'` are
+/// the caller's values and `ra` is the return address.
+impl Display for UnwindRuleX86_64 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            UnwindRuleX86_64::JustReturn => {
+                write!(f, "sp' = sp + 0x8; bp' = bp; ra = *(sp' - 8)")
+            }
+            UnwindRuleX86_64::JustReturnIfFirstFrameOtherwiseFp => write!(
+                f,
+                "if first frame: sp' = sp + 0x8; bp' = bp; \
+                 otherwise: sp' = bp + 0x10; bp' = *bp; ra = *(sp' - 8)"
+            ),
+            UnwindRuleX86_64::OffsetSp { sp_offset_by_8 } => {
+                let sp_offset = i64::from(sp_offset_by_8) * 8;
+                write!(
+                    f,
+                    "sp' = {}; bp' = bp; ra = *(sp' - 8)",
+                    RegOffset("sp", sp_offset)
+                )
+            }
+            UnwindRuleX86_64::OffsetSpAndRestoreBp {
+                sp_offset_by_8,
+                bp_storage_offset_from_sp_by_8,
+            } => {
+                let sp_offset = i64::from(sp_offset_by_8) * 8;
+                let bp_storage_offset = i64::from(bp_storage_offset_from_sp_by_8) * 8;
+                write!(
+                    f,
+                    "sp' = {}; bp' = {}; ra = *(sp' - 8)",
+                    RegOffset("sp", sp_offset),
+                    Load("sp", bp_storage_offset)
+                )
+            }
+            UnwindRuleX86_64::UseFramePointer => {
+                write!(f, "sp' = bp + 0x10; bp' = *bp; ra = *(sp' - 8)")
+            }
+            UnwindRuleX86_64::UseFramePointerWithRealignedStack {
+                sp_storage_offset_from_bp_by_8,
+            } => {
+                let sp_storage_offset = i64::from(sp_storage_offset_from_bp_by_8) * 8;
+                write!(
+                    f,
+                    "sp' = {}; bp' = *bp; ra = *(sp' - 8)",
+                    Load("bp", sp_storage_offset)
+                )
+            }
+            UnwindRuleX86_64::RestoreFromLinuxSigframe => write!(
+                f,
+                "ip' = *(sp + 0xa8); sp' = *(sp + 0xa0); bp' = *(sp + 0x78)"
+            ),
+            UnwindRuleX86_64::RestoreFromMacosSigtramp => write!(
+                f,
+                "m = sp + 0x8 or sp + 0x10; ip' = *(m + 0x90); sp' = *(m + 0x48); bp' = *(m + 0x40)"
+            ),
+            UnwindRuleX86_64::UseFramePointerAcrossStackSwitch => write!(
+                f,
+                "sp' = bp + 0x10 (may switch stacks); bp' = *bp; ra = *(sp' - 8)"
+            ),
+        }
+    }
 }
 
 impl UnwindRule for UnwindRuleX86_64 {
@@ -33,6 +119,21 @@ impl UnwindRule for UnwindRuleX86_64 {
     fn fallback_rule() -> Self {
         UnwindRuleX86_64::UseFramePointer
     }
+    fn rule_for_linux_sigreturn_trampoline() -> Self {
+        UnwindRuleX86_64::RestoreFromLinuxSigframe
+    }
+    fn rule_for_macos_sigtramp() -> Self {
+        UnwindRuleX86_64::RestoreFromMacosSigtramp
+    }
+    fn rule_for_stack_switch() -> Self {
+        UnwindRuleX86_64::UseFramePointerAcrossStackSwitch
+    }
+    fn resumes_interrupted_code(self) -> bool {
+        matches!(
+            self,
+            UnwindRuleX86_64::RestoreFromLinuxSigframe | UnwindRuleX86_64::RestoreFromMacosSigtramp
+        )
+    }
 
     fn exec<F>(
         self,
@@ -57,10 +158,14 @@ impl UnwindRule for UnwindRuleX86_64 {
                     let sp = regs.sp();
                     let bp = regs.bp();
                     let new_sp = bp.checked_add(16).ok_or(Error::IntegerOverflow)?;
+                    check_frame_pointer(bp, sp, 8)?;
                     if new_sp <= sp {
                         return Err(Error::FramepointerUnwindingMovedBackwards);
                     }
                     let new_bp = read_stack(bp).map_err(|_| Error::CouldNotReadStack(bp))?;
+                    if new_bp == bp {
+                        return Err(Error::FramePointerPointsToItself(bp));
+                    }
                     (new_sp, new_bp)
                 }
             }
@@ -141,6 +246,7 @@ impl UnwindRule for UnwindRuleX86_64 {
                     return Ok(None);
                 }
                 let new_sp = bp.checked_add(16).ok_or(Error::IntegerOverflow)?;
+                check_frame_pointer(bp, sp, 8)?;
                 if new_sp <= sp {
                     return Err(Error::FramepointerUnwindingMovedBackwards);
                 }
@@ -150,12 +256,114 @@ impl UnwindRule for UnwindRuleX86_64 {
                 // it's moving in the right direction. But if the caller is using bp as a general
                 // purpose register, then any value (including zero) would be a valid value.
                 // At this point we don't know how the caller uses bp, so we leave new_bp unchecked.
+                // The one exception is a frame record that points at itself: following it would
+                // produce the same frame over and over.
+                if new_bp == bp {
+                    return Err(Error::FramePointerPointsToItself(bp));
+                }
 
                 (new_sp, new_bp)
             }
+            UnwindRuleX86_64::UseFramePointerWithRealignedStack {
+                sp_storage_offset_from_bp_by_8,
+            } => {
+                // Function prologue example (GCC):
+                // lea    r10, [rsp + 0x8]      ; r10 = the caller's sp
+                // and    rsp, -0x20            ; realign the stack
+                // push   qword ptr [r10 - 0x8] ; copy the return address
+                // push   rbp
+                // mov    rbp, rsp
+                // push   r10                   ; store the caller's sp at rbp - 8
+                //
+                // The copied return address makes this look like a regular frame
+                // record, but bp + 16 is somewhere in the padding from the realignment.
+                let bp = regs.bp();
+                if bp == 0 {
+                    return Ok(None);
+                }
+                check_frame_pointer(bp, sp, 8)?;
+                let sp_storage_offset = i64::from(sp_storage_offset_from_bp_by_8) * 8;
+                let sp_location =
+                    checked_add_signed(bp, sp_storage_offset).ok_or(Error::IntegerOverflow)?;
+                let new_sp =
+                    read_stack(sp_location).map_err(|_| Error::CouldNotReadStack(sp_location))?;
+                if new_sp <= sp {
+                    return Err(Error::FramepointerUnwindingMovedBackwards);
+                }
+                let new_bp = read_stack(bp).map_err(|_| Error::CouldNotReadStack(bp))?;
+                if new_bp == bp {
+                    return Err(Error::FramePointerPointsToItself(bp));
+                }
+                (new_sp, new_bp)
+            }
+            UnwindRuleX86_64::UseFramePointerAcrossStackSwitch => {
+                // Like UseFramePointer, but the stack segments are separate allocations,
+                // so the previous segment can be at any address.
+                let bp = regs.bp();
+                if bp == 0 {
+                    return Ok(None);
+                }
+                let new_sp = bp.checked_add(16).ok_or(Error::IntegerOverflow)?;
+                if !bp.is_multiple_of(8) {
+                    return Err(Error::MisalignedFramePointer(bp));
+                }
+                let new_bp = read_stack(bp).map_err(|_| Error::CouldNotReadStack(bp))?;
+                if new_bp == bp {
+                    return Err(Error::FramePointerPointsToItself(bp));
+                }
+                (new_sp, new_bp)
+            }
+            UnwindRuleX86_64::RestoreFromLinuxSigframe => {
+                // The uc_mcontext.gregs array starts at offset 40 in the ucontext_t,
+                // and holds rbp, rsp and rip at indexes 10, 15 and 16.
+                let read = |read_stack: &mut F, offset: u64| {
+                    let location = sp.checked_add(offset).ok_or(Error::IntegerOverflow)?;
+                    read_stack(location).map_err(|_| Error::CouldNotReadStack(location))
+                };
+                let new_bp = read(read_stack, 120)?;
+                let new_sp = read(read_stack, 160)?;
+                let new_ip = read(read_stack, 168)?;
+                if new_ip == 0 {
+                    return Ok(None);
+                }
+                regs.set_ip(new_ip);
+                regs.set_sp(new_sp);
+                regs.set_bp(new_bp);
+                return Ok(Some(new_ip));
+            }
+            UnwindRuleX86_64::RestoreFromMacosSigtramp => {
+                // The mcontext starts with the 16 byte exception state, followed by
+                // the thread state, which has rbp, rsp, rip and cs at indexes 6, 7,
+                // 16 and 18. Depending on the alignment of the interrupted stack
+                // pointer, the mcontext is 8 or 16 bytes above sp. The saved cs is
+                // always the 64 bit user code segment, so use it to find out which.
+                let read = |read_stack: &mut F, offset: u64| {
+                    let location = sp.checked_add(offset).ok_or(Error::IntegerOverflow)?;
+                    read_stack(location).map_err(|_| Error::CouldNotReadStack(location))
+                };
+                const USER_CODE_SEGMENT: u64 = 0x2b;
+                let mcontext_offset = if read(read_stack, 8 + 160)? == USER_CODE_SEGMENT {
+                    8
+                } else if read(read_stack, 16 + 160)? == USER_CODE_SEGMENT {
+                    16
+                } else {
+                    return Err(Error::CouldNotFindSignalContext);
+                };
+                let new_bp = read(read_stack, mcontext_offset + 64)?;
+                let new_sp = read(read_stack, mcontext_offset + 72)?;
+                let new_ip = read(read_stack, mcontext_offset + 144)?;
+                if new_ip == 0 {
+                    return Ok(None);
+                }
+                regs.set_ip(new_ip);
+                regs.set_sp(new_sp);
+                regs.set_bp(new_bp);
+                return Ok(Some(new_ip));
+            }
         };
-        let return_address =
-            read_stack(new_sp - 8).map_err(|_| Error::CouldNotReadStack(new_sp - 8))?;
+        let return_address_location = new_sp.checked_sub(8).ok_or(Error::IntegerOverflow)?;
+        let return_address = read_stack(return_address_location)
+            .map_err(|_| Error::CouldNotReadStack(return_address_location))?;
         if return_address == 0 {
             return Ok(None);
         }
@@ -172,6 +380,55 @@ impl UnwindRule for UnwindRuleX86_64 {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::test_utils::TestStack;
+
+    #[test]
+    fn test_display() {
+        let rule = UnwindRuleX86_64::OffsetSpAndRestoreBp {
+            sp_offset_by_8: 5,
+            bp_storage_offset_from_sp_by_8: 3,
+        };
+        assert_eq!(
+            rule.to_string(),
+            "sp' = sp + 0x28; bp' = *(sp + 0x18); ra = *(sp' - 8)"
+        );
+        let rule = UnwindRuleX86_64::OffsetSpAndRestoreBp {
+            sp_offset_by_8: 1,
+            bp_storage_offset_from_sp_by_8: -2,
+        };
+        assert_eq!(
+            rule.to_string(),
+            "sp' = sp + 0x8; bp' = *(sp - 0x10); ra = *(sp' - 8)"
+        );
+        assert_eq!(
+            UnwindRuleX86_64::UseFramePointer.to_string(),
+            "sp' = bp + 0x10; bp' = *bp; ra = *(sp' - 8)"
+        );
+    }
+
+    #[test]
+    fn test_realigned_stack() {
+        // The function was called with sp = 0x58, so the return address is at 0x50.
+        // It realigned the stack to 0x40, copied the return address to 0x38, pushed
+        // the caller's bp to 0x30 and stored the caller's sp at 0x28.
+        let stack = TestStack::zeroed(16)
+            .with(0x28, 0x58)
+            .with_frame_record(0x30, 0x90, 0x100200)
+            .with(0x50, 0x100200);
+        let mut read_stack = |addr| stack.read(addr);
+        let rule = UnwindRuleX86_64::UseFramePointerWithRealignedStack {
+            sp_storage_offset_from_bp_by_8: -1,
+        };
+        assert_eq!(
+            rule.to_string(),
+            "sp' = *(bp - 0x8); bp' = *bp; ra = *(sp' - 8)"
+        );
+        let mut regs = UnwindRegsX86_64::new(0x100300, 0x20, 0x30);
+        let res = rule.exec(false, &mut regs, &mut read_stack);
+        assert_eq!(res, Ok(Some(0x100200)));
+        assert_eq!(regs.sp(), 0x58);
+        assert_eq!(regs.bp(), 0x90);
+    }
 
     #[test]
     fn test_basic() {
@@ -224,4 +481,85 @@ mod test {
         let res = UnwindRuleX86_64::UseFramePointer.exec(true, &mut regs, &mut read_stack);
         assert_eq!(res, Err(Error::IntegerOverflow));
     }
+
+    #[test]
+    fn test_degenerate_frame_pointers() {
+        let stack = TestStack::from([1, 2, 0x100300, 4, 0x20, 0x100200, 5, 6]);
+        let mut read_stack = |addr| stack.read(addr);
+
+        // bp points at a frame record which points at itself.
+        let mut regs = UnwindRegsX86_64::new(0x100400, 0x10, 0x20);
+        let res = UnwindRuleX86_64::UseFramePointer.exec(false, &mut regs, &mut read_stack);
+        assert_eq!(res, Err(Error::FramePointerPointsToItself(0x20)));
+        let res = UnwindRuleX86_64::JustReturnIfFirstFrameOtherwiseFp.exec(
+            false,
+            &mut regs,
+            &mut read_stack,
+        );
+        assert_eq!(res, Err(Error::FramePointerPointsToItself(0x20)));
+
+        // bp is below sp.
+        let mut regs = UnwindRegsX86_64::new(0x100400, 0x18, 0x10);
+        let res = UnwindRuleX86_64::UseFramePointer.exec(false, &mut regs, &mut read_stack);
+        assert_eq!(res, Err(Error::FramePointerBelowStackPointer(0x10)));
+
+        // bp is misaligned.
+        let mut regs = UnwindRegsX86_64::new(0x100400, 0x10, 0x23);
+        let res = UnwindRuleX86_64::UseFramePointer.exec(false, &mut regs, &mut read_stack);
+        assert_eq!(res, Err(Error::MisalignedFramePointer(0x23)));
+        assert_eq!(regs.bp(), 0x23);
+    }
+
+    #[test]
+    fn test_stack_switch() {
+        // The callee runs on a new stack segment at 0x80, the frame record of
+        // __morestack is on the previous segment at 0x20.
+        let stack = TestStack::zeroed(24).with_frame_record(0x20, 0x40, 0x100200);
+        let mut read_stack = |addr| stack.read(addr);
+        let mut regs = UnwindRegsX86_64::new(0x100400, 0x80, 0x20);
+        let res = UnwindRuleX86_64::UseFramePointer.exec(false, &mut regs, &mut read_stack);
+        assert_eq!(res, Err(Error::FramePointerBelowStackPointer(0x20)));
+        let res = UnwindRuleX86_64::UseFramePointerAcrossStackSwitch.exec(
+            false,
+            &mut regs,
+            &mut read_stack,
+        );
+        assert_eq!(res, Ok(Some(0x100200)));
+        assert_eq!(regs.sp(), 0x30);
+        assert_eq!(regs.bp(), 0x40);
+    }
+
+    #[test]
+    fn test_linux_sigframe() {
+        let stack = TestStack::zeroed(32)
+            .with(15 * 8, 0x1234) // rbp
+            .with(20 * 8, 0x400) // rsp
+            .with(21 * 8, 0x100500); // rip
+        let mut read_stack = |addr| stack.read(addr);
+        let mut regs = UnwindRegsX86_64::new(0x100400, 0x0, 0x20);
+        let res =
+            UnwindRuleX86_64::RestoreFromLinuxSigframe.exec(false, &mut regs, &mut read_stack);
+        assert_eq!(res, Ok(Some(0x100500)));
+        assert_eq!(regs.ip(), 0x100500);
+        assert_eq!(regs.sp(), 0x400);
+        assert_eq!(regs.bp(), 0x1234);
+    }
+
+    #[test]
+    fn test_macos_sigtramp() {
+        // The mcontext starts at sp + 16.
+        let stack = TestStack::zeroed(32)
+            .with((2 + 2 + 6) * 8, 0x1234) // rbp
+            .with((2 + 2 + 7) * 8, 0x400) // rsp
+            .with((2 + 2 + 16) * 8, 0x100500) // rip
+            .with((2 + 2 + 18) * 8, 0x2b); // cs
+        let mut read_stack = |addr| stack.read(addr);
+        let mut regs = UnwindRegsX86_64::new(0x100400, 0x0, 0x20);
+        let res =
+            UnwindRuleX86_64::RestoreFromMacosSigtramp.exec(false, &mut regs, &mut read_stack);
+        assert_eq!(res, Ok(Some(0x100500)));
+        assert_eq!(regs.ip(), 0x100500);
+        assert_eq!(regs.sp(), 0x400);
+        assert_eq!(regs.bp(), 0x1234);
+    }
 }
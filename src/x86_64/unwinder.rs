@@ -1,13 +1,17 @@
-use std::ops::Deref;
+use std::ops::{Deref, Range};
 
 use super::arch::ArchX86_64;
 use super::cache::CacheX86_64;
+use super::unwind_rule::UnwindRuleX86_64;
 use super::unwindregs::UnwindRegsX86_64;
 use crate::cache::{AllocationPolicy, MayAllocateDuringUnwind};
-use crate::error::Error;
+use crate::error::{Error, UnwinderError};
 use crate::unwinder::UnwinderInternal;
-use crate::unwinder::{Module, Unwinder};
-use crate::FrameAddress;
+use crate::unwinder::{Module, TextByteData, Unwinder};
+use crate::{
+    ExceptionHandlingInfo, FrameAddress, FrameConfidence, FrameDivergence, FramePointerChain,
+    FrameProvenance, ModuleEvent, ModuleEventSubscription, StubRules, UnwindLimits, UnwindMode,
+};
 
 /// The unwinder for the x86_64 CPU architecture. Use the [`Unwinder`] trait for unwinding.
 ///
@@ -15,9 +19,15 @@ use crate::FrameAddress;
 ///
 ///  - `D`: The type for unwind section data in the modules. See [`Module`].
 /// -  `P`: The [`AllocationPolicy`].
-pub struct UnwinderX86_64<D: Deref<Target = [u8]>, P: AllocationPolicy<D> = MayAllocateDuringUnwind>(
-    UnwinderInternal<D, ArchX86_64, P>,
-);
+pub struct UnwinderX86_64<D: Deref<Target = [u8]>, P: AllocationPolicy<D> = MayAllocateDuringUnwind>
+{
+    internal: UnwinderInternal<D, ArchX86_64, P>,
+    /// Whether the process uses the x32 ABI, see [`UnwinderX86_64::set_x32`].
+    x32: bool,
+}
+
+/// x32 pointers are 32 bits wide.
+const X32_ADDRESS_MASK: u64 = 0xffff_ffff;
 
 impl<D: Deref<Target = [u8]>, P: AllocationPolicy<D>> Default for UnwinderX86_64<D, P> {
     fn default() -> Self {
@@ -28,7 +38,320 @@ impl<D: Deref<Target = [u8]>, P: AllocationPolicy<D>> Default for UnwinderX86_64
 impl<D: Deref<Target = [u8]>, P: AllocationPolicy<D>> UnwinderX86_64<D, P> {
     /// Create an unwinder for a process.
     pub fn new() -> Self {
-        Self(UnwinderInternal::new())
+        Self {
+            internal: UnwinderInternal::new(),
+            x32: false,
+        }
+    }
+
+    /// Set the limits that protect against malformed or malicious unwind information.
+    /// See [`UnwindLimits`] for the defaults.
+    pub fn set_limits(&mut self, limits: UnwindLimits) {
+        self.internal.set_limits(limits);
+    }
+
+    /// Choose whether malformed or missing unwind information makes unwinding fail,
+    /// or falls back to frame pointer unwinding. See [`UnwindMode`].
+    pub fn set_mode(&mut self, mode: UnwindMode) {
+        self.internal.set_mode(mode);
+    }
+
+    /// Declare whether the code can be unwound with the frame pointer when it has no
+    /// usable unwind information. This is on by default. Turn it off if the sampled
+    /// code is built without frame pointers: then bp holds an arbitrary value in the
+    /// first frame, and walking it would produce a bogus stack. Instead, unwinding
+    /// such a first frame fails with [`Error::UntrustedFramePointer`](crate::Error::UntrustedFramePointer),
+    /// which leaves a stack with a single frame. Caller frames still fall back to the
+    /// frame pointer.
+    pub fn set_trust_first_frame_pointer(&mut self, trust_first_frame_pointer: bool) {
+        self.internal
+            .set_trust_first_frame_pointer(trust_first_frame_pointer);
+    }
+
+    /// Declare whether the process uses the Linux x32 ABI, where code runs with the
+    /// x86_64 register file but pointers are 32 bits wide. This is off by default.
+    ///
+    /// When it is on, only the low 4 bytes of each value read from the stack are used,
+    /// and the stack addresses and the registers are truncated to 32 bits, like the
+    /// address arithmetic of x32 code. Modules of an x32 process need an
+    /// [`address_size`](crate::ModuleSvmaInfo::address_size) of 4.
+    pub fn set_x32(&mut self, x32: bool) {
+        self.x32 = x32;
+    }
+
+    fn address_mask(&self) -> u64 {
+        if self.x32 {
+            X32_ADDRESS_MASK
+        } else {
+            u64::MAX
+        }
+    }
+
+    /// Look up unwind information for return addresses at the start of the call
+    /// instruction, found with the module's code bytes, instead of at the return
+    /// address minus one. This is off by default. It only makes a difference if the
+    /// unwind information is imprecise about instruction boundaries, and it costs an
+    /// extra module lookup per frame.
+    pub fn set_instruction_aware_lookup(&mut self, instruction_aware_lookup: bool) {
+        self.internal
+            .set_instruction_aware_lookup(instruction_aware_lookup);
+    }
+
+    /// Add the address range of a function at which stacks end, such as `_start`,
+    /// `__libc_start_main` or `start_thread`. Stack walks stop cleanly after a frame
+    /// in this range, instead of trying to unwind one more frame.
+    pub fn add_root_range(&mut self, avma_range: Range<u64>) {
+        self.internal.add_root_range(avma_range);
+    }
+
+    /// Attach code bytes to a module that was added before using `add_module`, keyed
+    /// by the start address of that module's address range, or remove them with
+    /// `None`. If no match is found, the call is ignored. See [`TextByteData`] for what
+    /// the code bytes are used for.
+    pub fn set_module_text_data(
+        &mut self,
+        module_avma_range_start: u64,
+        text_data: Option<TextByteData<D>>,
+    ) {
+        self.internal
+            .set_module_text_data(module_avma_range_start, text_data);
+    }
+
+    /// Follow the frame pointer chain that starts at bp in `regs`, up to `max_depth`
+    /// frame records, and report how deep it goes and whether it is intact. This only
+    /// reads the frame records, so it is much cheaper than a full stack walk. See
+    /// [`FramePointerChain`].
+    pub fn check_frame_pointer_chain<F>(
+        &self,
+        regs: &UnwindRegsX86_64,
+        read_stack: &mut F,
+        max_depth: usize,
+    ) -> FramePointerChain
+    where
+        F: FnMut(u64) -> Result<u64, ()>,
+    {
+        self.internal.check_frame_pointer_chain(
+            regs,
+            &mut mask_reads(read_stack, self.address_mask()),
+            max_depth,
+        )
+    }
+
+    /// Override the rules for stub functions and function starts for a module that was
+    /// added before using `add_module`, keyed by the start address of that module's
+    /// address range, or go back to the defaults with `None`. If no match is found, the
+    /// call is ignored. See [`StubRules`].
+    pub fn set_module_stub_rules(
+        &mut self,
+        module_avma_range_start: u64,
+        stub_rules: Option<StubRules<UnwindRuleX86_64>>,
+    ) {
+        self.internal
+            .set_module_stub_rules(module_avma_range_start, stub_rules);
+    }
+
+    /// Load the unwind data of a module that was created with [`Module::new_lazy`],
+    /// keyed by the start address of that module's address range. Call this outside
+    /// of unwinding, for example when a sample hits the module. If no match is found,
+    /// if the module's data isn't loaded lazily, or if it is already loaded, the call
+    /// is ignored.
+    pub fn load_module_unwind_data(&mut self, module_avma_range_start: u64) {
+        self.internal
+            .load_module_unwind_data(module_avma_range_start);
+    }
+
+    /// Drop the unwind data of a module that was created with [`Module::new_lazy`],
+    /// keyed by the start address of that module's address range. The module is
+    /// unwound with frame pointers until its data is loaded again. If no match is
+    /// found, or if the module's data isn't loaded lazily, the call is ignored.
+    pub fn unload_module_unwind_data(&mut self, module_avma_range_start: u64) {
+        self.internal
+            .unload_module_unwind_data(module_avma_range_start);
+    }
+
+    /// Map the code of the module whose address range starts at
+    /// `module_avma_range_start` a second time, at `alias_avma_range`. Addresses in the
+    /// alias range are unwound like the addresses at the same offset in the module's
+    /// range, with the module's unwind data. This is for JITs which map their code
+    /// both at an executable and at a writable address. The alias range must not
+    /// overlap with any module. The alias is removed together with the module.
+    pub fn add_module_alias(&mut self, alias_avma_range: Range<u64>, module_avma_range_start: u64) {
+        self.internal
+            .add_module_alias(alias_avma_range, module_avma_range_start);
+    }
+
+    /// Remove an alias that was added with `add_module_alias`, keyed by the start of
+    /// its address range.
+    pub fn remove_module_alias(&mut self, alias_avma_range_start: u64) {
+        self.internal.remove_module_alias(alias_avma_range_start);
+    }
+
+    /// Move the module whose address range starts at `module_avma_range_start` so that
+    /// its range starts at `new_avma_range_start`, keeping its unwind data, code bytes,
+    /// stub rules and aliases. This is for modules which are mapped again at a
+    /// different address, for example after a JIT moved its code. If no match is found,
+    /// the call is ignored.
+    pub fn rebase_module(&mut self, module_avma_range_start: u64, new_avma_range_start: u64) {
+        self.internal
+            .rebase_module(module_avma_range_start, new_avma_range_start);
+    }
+
+    /// Call `subscriber` with a [`ModuleEvent`] for every later change to the modules
+    /// of this unwinder: added, removed and rebased modules, replaced code bytes or
+    /// stub rules, and added and removed aliases. The subscriber is called after the
+    /// change was made.
+    pub fn subscribe_module_events<F>(&mut self, subscriber: F) -> ModuleEventSubscription
+    where
+        F: FnMut(&ModuleEvent) + Send + Sync + 'static,
+    {
+        self.internal.subscribe_module_events(subscriber)
+    }
+
+    /// Remove a subscriber that was added with `subscribe_module_events`.
+    pub fn unsubscribe_module_events(&mut self, subscription: ModuleEventSubscription) {
+        self.internal.unsubscribe_module_events(subscription);
+    }
+
+    /// `address` as `name+0xrelative`, e.g. `libxul.so+0x1234`, if it is in a known
+    /// module with a name, and as the plain hex address otherwise. For log messages.
+    pub fn describe_address(&self, address: u64) -> String {
+        self.internal.describe_address(address)
+    }
+
+    /// The message of `error`, with the code address it refers to, if any, described
+    /// with [`describe_address`](Self::describe_address).
+    pub fn describe_error(&self, error: &Error) -> String {
+        self.internal.describe_error(error)
+    }
+
+    /// Remove a root range that was added with `add_root_range`, keyed by its start
+    /// address.
+    pub fn remove_root_range(&mut self, avma_range_start: u64) {
+        self.internal.remove_root_range(avma_range_start);
+    }
+
+    /// Add the address range of a function which runs the rest of the program on a
+    /// different stack, such as `__morestack` for segmented stacks (`-fsplit-stack`,
+    /// gccgo, and old versions of Rust). Frames in this range are unwound with their
+    /// frame record, even if that moves the stack pointer to a lower address, so that
+    /// the stack walk continues on the previous stack segment.
+    pub fn add_stack_switch_range(&mut self, avma_range: Range<u64>) {
+        self.internal.add_stack_switch_range(avma_range);
+    }
+
+    /// Remove a stack switch range that was added with `add_stack_switch_range`, keyed
+    /// by its start address.
+    pub fn remove_stack_switch_range(&mut self, avma_range_start: u64) {
+        self.internal.remove_stack_switch_range(avma_range_start);
+    }
+
+    /// Add the address range of a function at which an async runtime polls its tasks,
+    /// for example the poll function of an executor. The stack walk yields the logical
+    /// frames of the polled task chain after frames in this range, if the iterator has
+    /// a handler which finds them; see
+    /// [`UnwindIterator::with_async_task_handler`](crate::UnwindIterator::with_async_task_handler).
+    pub fn add_async_boundary_range(&mut self, avma_range: Range<u64>) {
+        self.internal.add_async_boundary_range(avma_range);
+    }
+
+    /// Remove an async boundary range that was added with `add_async_boundary_range`,
+    /// keyed by its start address.
+    pub fn remove_async_boundary_range(&mut self, avma_range_start: u64) {
+        self.internal.remove_async_boundary_range(avma_range_start);
+    }
+
+    /// Add the address range of an ELF PLT section: `.plt`, `.plt.sec`, `.plt.got` or
+    /// `.iplt`, from the module's section headers. The stubs in these sections often
+    /// have no unwind information, so frames in this range are unwound with a rule for
+    /// the known structure of PLT stubs, which is derived from the module's code bytes
+    /// if they cover the range. On x86_64,
+    /// this accounts for the values that the lazy binding stubs push before they jump
+    /// to the dynamic linker.
+    pub fn add_plt_range(&mut self, avma_range: Range<u64>) {
+        self.internal.add_plt_range(avma_range);
+    }
+
+    /// Remove a PLT range that was added with `add_plt_range`, keyed by its start
+    /// address.
+    pub fn remove_plt_range(&mut self, avma_range_start: u64) {
+        self.internal.remove_plt_range(avma_range_start);
+    }
+
+    /// Add the address range of a lazy binding trampoline of the dynamic linker, such
+    /// as glibc's `_dl_runtime_resolve_xsave`, from the dynamic linker's symbol table.
+    /// These trampolines are entered from PLT0 with values pushed below the return
+    /// address and save large register blocks, so frames in this range are unwound
+    /// with a rule derived from the trampoline's code bytes, if the module has them.
+    /// PLT0 itself is covered by `add_plt_range`.
+    /// On x86_64, the trampolines which realign the stack can only be unwound at
+    /// their start and end, and their body is unwound with the module's unwind
+    /// information as usual.
+    pub fn add_dl_trampoline_range(&mut self, avma_range: Range<u64>) {
+        self.internal.add_dl_trampoline_range(avma_range);
+    }
+
+    /// Remove a trampoline range that was added with `add_dl_trampoline_range`, keyed
+    /// by its start address.
+    pub fn remove_dl_trampoline_range(&mut self, avma_range_start: u64) {
+        self.internal.remove_dl_trampoline_range(avma_range_start);
+    }
+
+    /// Look up the personality routine and the language-specific data area (LSDA)
+    /// of the function containing `address`, for the search and cleanup phases of a
+    /// two-phase exception handling runtime. Returns `None` if the address isn't in
+    /// a known module, or if the function is described by a compact unwind encoding
+    /// instead of a DWARF FDE.
+    ///
+    /// Framehop only recovers the registers that it needs for finding return
+    /// addresses, so it can't install a landing pad's register state or resume
+    /// execution in a frame. Pair this with a full-register unwinder for that.
+    pub fn exception_handling_info(
+        &self,
+        address: FrameAddress,
+        cache: &mut CacheX86_64<D, P>,
+    ) -> Result<Option<ExceptionHandlingInfo>, UnwinderError> {
+        self.internal.exception_handling_info(address, &mut cache.0)
+    }
+
+    /// Write the unwind information which covers `address` to `out`, for debugging:
+    /// the module, the `__unwind_info` opcode, and the DWARF FDE with its unwind table,
+    /// whichever the module has. Problems with the unwind information are written to
+    /// `out` as well. This is framehop's equivalent of `llvm-dwarfdump --eh-frame`,
+    /// limited to one address.
+    ///
+    /// As with unwinding, the unwind information for a return address is looked up
+    /// inside the call instruction.
+    pub fn dump_unwind_info<W: std::fmt::Write>(
+        &self,
+        address: FrameAddress,
+        cache: &mut CacheX86_64<D, P>,
+        out: &mut W,
+    ) -> std::fmt::Result {
+        self.internal.dump_unwind_info(address, &mut cache.0, out)
+    }
+
+    /// Write the unwind rule for every address in the text section of the module at
+    /// `module_avma_range_start` to `out`, as a table which can be diffed across
+    /// framehop versions or against other unwinders. Each row is a tab-separated
+    /// half-open range of module-relative addresses followed by the rule which applies
+    /// to instruction pointers in that range, formatted with its `Display`
+    /// implementation. Addresses without a usable rule list the error instead, and
+    /// addresses whose DWARF CFI needs the full register state list `uncacheable`.
+    /// Lines starting with `#` are comments.
+    ///
+    /// This looks up the rule of every single address, so it is a debugging aid
+    /// rather than something to call while profiling. It needs the `rule-table`
+    /// feature.
+    #[cfg(feature = "rule-table")]
+    pub fn write_rule_table<W: std::fmt::Write>(
+        &self,
+        module_avma_range_start: u64,
+        cache: &mut CacheX86_64<D, P>,
+        out: &mut W,
+    ) -> std::fmt::Result {
+        let regs = UnwindRegsX86_64::new(0, 0, 0);
+        self.internal
+            .write_rule_table(module_avma_range_start, regs, &mut cache.0, out)
     }
 }
 
@@ -38,15 +361,47 @@ impl<D: Deref<Target = [u8]>, P: AllocationPolicy<D>> Unwinder for UnwinderX86_6
     type Module = Module<D>;
 
     fn add_module(&mut self, module: Module<D>) {
-        self.0.add_module(module);
+        self.internal.add_module(module);
     }
 
     fn remove_module(&mut self, module_address_range_start: u64) {
-        self.0.remove_module(module_address_range_start);
+        self.internal.remove_module(module_address_range_start);
     }
 
     fn max_known_code_address(&self) -> u64 {
-        self.0.max_known_code_address()
+        self.internal.max_known_code_address()
+    }
+
+    fn is_known_code_address(&self, address: u64) -> bool {
+        self.internal.is_known_code_address(address)
+    }
+
+    fn module_relative_address(&self, address: u64) -> Option<(u64, u32)> {
+        self.internal.module_relative_address(address)
+    }
+
+    fn module_id_and_relative_address(&self, address: u64) -> Option<(u64, u32)> {
+        self.internal.module_id_and_relative_address(address)
+    }
+
+    fn is_root_address(&self, address: u64) -> bool {
+        self.internal.is_root_address(address)
+    }
+
+    fn is_stack_switch_address(&self, address: u64) -> bool {
+        self.internal.is_stack_switch_address(address)
+    }
+
+    fn is_async_boundary_address(&self, address: u64) -> bool {
+        self.internal.is_async_boundary_address(address)
+    }
+
+    fn call_site_address(&self, address: FrameAddress) -> u64 {
+        self.internal.call_site_address(address)
+    }
+
+    fn is_preceded_by_call(&self, address: FrameAddress) -> Option<bool> {
+        self.internal.is_preceded_by_call(address)
     }
 
     fn unwind_frame<F>(
@@ -59,6 +414,102 @@ impl<D: Deref<Target = [u8]>, P: AllocationPolicy<D>> Unwinder for UnwinderX86_6
     where
         F: FnMut(u64) -> Result<u64, ()>,
     {
-        self.0.unwind_frame(address, regs, &mut cache.0, read_stack)
+        let result = self.unwind_frame_with_confidence(address, regs, cache, read_stack)?;
+        Ok(result.map(|(caller_address, _confidence)| caller_address.address()))
+    }
+
+    fn unwind_frame_with_confidence<F>(
+        &self,
+        address: FrameAddress,
+        regs: &mut UnwindRegsX86_64,
+        cache: &mut CacheX86_64<D, P>,
+        read_stack: &mut F,
+    ) -> Result<Option<(FrameAddress, FrameConfidence)>, Error>
+    where
+        F: FnMut(u64) -> Result<u64, ()>,
+    {
+        let mask = self.address_mask();
+        let mut read_stack = mask_reads(read_stack, mask);
+        let result = self.internal.unwind_frame_with_confidence(
+            address,
+            regs,
+            &mut cache.0,
+            &mut read_stack,
+        );
+        mask_regs(regs, mask);
+        result
+    }
+
+    fn unwind_frame_with_provenance<F>(
+        &self,
+        address: FrameAddress,
+        regs: &mut UnwindRegsX86_64,
+        cache: &mut CacheX86_64<D, P>,
+        read_stack: &mut F,
+        provenance: &mut FrameProvenance,
+    ) -> Result<Option<(FrameAddress, FrameConfidence)>, Error>
+    where
+        F: FnMut(u64) -> Result<u64, ()>,
+    {
+        let mask = self.address_mask();
+        let mut read_stack = mask_reads(read_stack, mask);
+        let result = self.internal.unwind_frame_with_provenance(
+            address,
+            regs,
+            &mut cache.0,
+            &mut read_stack,
+            provenance,
+        );
+        mask_regs(regs, mask);
+        result
+    }
+
+    fn unwind_frame_across_stack_switch<F>(
+        &self,
+        address: FrameAddress,
+        regs: &mut UnwindRegsX86_64,
+        read_stack: &mut F,
+    ) -> Result<Option<(FrameAddress, FrameConfidence)>, Error>
+    where
+        F: FnMut(u64) -> Result<u64, ()>,
+    {
+        let mask = self.address_mask();
+        let mut read_stack = mask_reads(read_stack, mask);
+        let result = self
+            .internal
+            .unwind_frame_across_stack_switch(address, regs, &mut read_stack);
+        mask_regs(regs, mask);
+        result
     }
+
+    fn check_frame_divergence<F>(
+        &self,
+        address: FrameAddress,
+        regs: &UnwindRegsX86_64,
+        cache: &mut CacheX86_64<D, P>,
+        read_stack: &mut F,
+    ) -> Option<FrameDivergence>
+    where
+        F: FnMut(u64) -> Result<u64, ()>,
+    {
+        let mut read_stack = mask_reads(read_stack, self.address_mask());
+        self.internal
+            .check_frame_divergence(address, regs, &mut cache.0, &mut read_stack)
+    }
+}
+
+/// Wrap `read_stack` so that stack addresses and the values read from the stack are
+/// masked with `mask`. For x32 processes, this reads the low 4 bytes of each 8-byte
+/// slot.
+fn mask_reads<F>(read_stack: &mut F, mask: u64) -> impl FnMut(u64) -> Result<u64, ()> + '_
+where
+    F: FnMut(u64) -> Result<u64, ()>,
+{
+    move |address| read_stack(address & mask).map(|value| value & mask)
+}
+
+fn mask_regs(regs: &mut UnwindRegsX86_64, mask: u64) {
+    regs.set_ip(regs.ip() & mask);
+    regs.set_sp(regs.sp() & mask);
+    regs.set_bp(regs.bp() & mask);
 }
@@ -1,6 +1,7 @@
 use std::fmt::Debug;
 
 use crate::display_utils::HexNum;
+use crate::unwind_regs::UnwindRegs;
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub struct UnwindRegsX86_64 {
@@ -42,6 +43,20 @@ impl UnwindRegsX86_64 {
     }
 }
 
+impl UnwindRegs for UnwindRegsX86_64 {
+    fn sp(&self) -> u64 {
+        self.sp
+    }
+    fn fp(&self) -> u64 {
+        self.bp
+    }
+    fn with_unknown_registers(&self, placeholder: u64) -> Self {
+        let mut regs = *self;
+        regs.set_bp(placeholder);
+        regs
+    }
+}
+
 impl Debug for UnwindRegsX86_64 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("UnwindRegsX86_64")
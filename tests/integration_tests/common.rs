@@ -100,6 +100,7 @@ where
             eh_frame: svma_range(&eh_frame),
             eh_frame_hdr: svma_range(&eh_frame_hdr),
             got: svma_range(&got),
+            address_size: if file.is_64() { 8 } else { 4 },
         },
         unwind_data,
         text_data,
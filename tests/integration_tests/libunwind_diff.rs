@@ -0,0 +1,122 @@
+//! Differential tests which walk the current thread's stack with framehop and with the
+//! system unwinder (`_Unwind_Backtrace` from libgcc_s or LLVM libunwind), and compare
+//! the resulting return addresses.
+//!
+//! Only live stacks of the test process are compared, on Linux. Captured stacks from
+//! other processes are not replayed, and there is no Windows backend.
+
+use std::ffi::{c_int, c_void};
+
+use framehop::{CacheNative, MayAllocateDuringUnwind, Unwinder, UnwinderNative};
+
+use super::process_modules::{current_thread_stack, loaded_modules};
+
+extern "C" {
+    fn _Unwind_Backtrace(
+        trace: extern "C" fn(ctx: *mut c_void, arg: *mut c_void) -> c_int,
+        arg: *mut c_void,
+    ) -> c_int;
+    fn _Unwind_GetIP(ctx: *mut c_void) -> usize;
+}
+
+const URC_NO_REASON: c_int = 0;
+
+/// Returns the addresses of the system unwinder's frames. The first address is in the
+/// function which calls `libunwind_frames`, the others are return addresses.
+#[inline(never)]
+fn libunwind_frames() -> Vec<u64> {
+    extern "C" fn trace(ctx: *mut c_void, arg: *mut c_void) -> c_int {
+        let frames = unsafe { &mut *(arg as *mut Vec<u64>) };
+        // The outermost frame can report a null address, where framehop ends the stack.
+        let address = unsafe { _Unwind_GetIP(ctx) } as u64;
+        if address != 0 {
+            frames.push(address);
+        }
+        URC_NO_REASON
+    }
+    let mut frames = Vec::new();
+    unsafe { _Unwind_Backtrace(trace, &mut frames as *mut Vec<u64> as *mut c_void) };
+    frames
+}
+
+/// Returns the addresses of framehop's frames. The first address is in
+/// `framehop_frames`, the others are return addresses.
+#[inline(never)]
+fn framehop_frames() -> Vec<u64> {
+    let mut unwinder = UnwinderNative::<Vec<u8>, MayAllocateDuringUnwind>::new();
+    for module in loaded_modules() {
+        unwinder.add_module(module);
+    }
+    let mut cache = CacheNative::new();
+    let stack = current_thread_stack();
+    let mut read_stack = |address: u64| {
+        if !address.is_multiple_of(8) || !stack.contains(&address) {
+            return Err(());
+        }
+        Ok(unsafe { *(address as *const u64) })
+    };
+    let (pc, regs) = framehop::capture_regs();
+    let mut iter = unwinder.iter_frames(pc, regs, &mut cache, &mut read_stack);
+    let mut frames = Vec::new();
+    while let Ok(Some(frame)) = iter.next() {
+        frames.push(frame.address());
+    }
+    frames
+}
+
+/// Compares the frames from both unwinders and panics with a table of both lists if
+/// they differ. Both walks were started from different call sites in the same
+/// function, so the comparison starts at that function's return address.
+fn assert_same_frames(framehop: &[u64], libunwind: &[u64]) {
+    let framehop = &framehop[2..];
+    let first = framehop[0];
+    let libunwind = match libunwind.iter().position(|&address| address == first) {
+        Some(index) => &libunwind[index..],
+        None => panic!("libunwind did not find the caller frame {first:#x}: {libunwind:#x?}"),
+    };
+    if framehop == libunwind {
+        return;
+    }
+    let mut table = String::new();
+    for index in 0..framehop.len().max(libunwind.len()) {
+        let framehop = framehop.get(index);
+        let libunwind = libunwind.get(index);
+        let marker = if framehop == libunwind { " " } else { "!" };
+        let cell = |address: Option<&u64>| match address {
+            Some(address) => format!("{address:#18x}"),
+            None => format!("{:>18}", "-"),
+        };
+        table += &format!(
+            "{marker} {index:3}: {} {}\n",
+            cell(framehop),
+            cell(libunwind)
+        );
+    }
+    panic!("framehop and libunwind frames differ:\n      framehop           libunwind\n{table}");
+}
+
+#[inline(never)]
+fn compare_at_depth(depth: u32) -> (Vec<u64>, Vec<u64>) {
+    if depth > 0 {
+        let result = compare_at_depth(std::hint::black_box(depth - 1));
+        std::hint::black_box(depth);
+        return result;
+    }
+    let framehop = framehop_frames();
+    let libunwind = libunwind_frames();
+    (framehop, libunwind)
+}
+
+#[test]
+fn test_libunwind_diff_current_thread() {
+    let (framehop, libunwind) = compare_at_depth(5);
+    assert!(framehop.len() > 5);
+    assert_same_frames(&framehop, &libunwind);
+}
+
+#[test]
+fn test_libunwind_diff_spawned_thread() {
+    let (framehop, libunwind) = std::thread::spawn(|| compare_at_depth(20)).join().unwrap();
+    assert!(framehop.len() > 20);
+    assert_same_frames(&framehop, &libunwind);
+}
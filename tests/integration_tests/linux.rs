@@ -2,8 +2,8 @@ use std::path::Path;
 
 use framehop::aarch64::*;
 use framehop::x86_64::*;
-use framehop::FrameAddress;
 use framehop::Unwinder;
+use framehop::{EhPointer, ExceptionHandlingInfo, FrameAddress};
 
 use super::common;
 
@@ -376,3 +376,181 @@ fn test_libc_syscall_no_fde() {
     assert_eq!(regs.sp(), 0x338);
     assert_eq!(regs.bp(), 0x348);
 }
+
+#[test]
+fn test_dump_unwind_info() {
+    let mut cache = CacheX86_64::<_>::new();
+    let mut unwinder = UnwinderX86_64::new();
+    common::add_object(
+        &mut unwinder,
+        &Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/linux/x86_64/fp/nightly-firefox-bin"),
+        0x1000000,
+    );
+
+    // The .plt section, see test_plt_cfa_expr.
+    let mut out = String::new();
+    unwinder
+        .dump_unwind_info(
+            FrameAddress::from_instruction_pointer(0x1000000 + 0xc0db),
+            &mut cache,
+            &mut out,
+        )
+        .unwrap();
+    let mut lines = out.lines();
+    assert_eq!(
+        lines
+            .next()
+            .map(|line| line.starts_with("0x100c0db is at 0xc0db in module ")),
+        Some(true)
+    );
+    assert_eq!(
+        lines.next().map(|line| line.starts_with("FDE at offset ")),
+        Some(true)
+    );
+    assert!(out
+        .lines()
+        .any(|line| line.starts_with('>') && line.contains("CFA=<expression>")));
+
+    let mut out = String::new();
+    unwinder
+        .dump_unwind_info(
+            FrameAddress::from_instruction_pointer(0x2000),
+            &mut cache,
+            &mut out,
+        )
+        .unwrap();
+    assert_eq!(out, "0x2000 is not in any known module\n");
+}
+
+#[cfg(feature = "rule-table")]
+#[test]
+fn test_write_rule_table() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/linux/aarch64/vdso.so");
+    let mut cache = CacheAarch64::<_>::new();
+    let mut unwinder = UnwinderAarch64::new();
+    common::add_object(&mut unwinder, &path, 0x0);
+    let mut out = String::new();
+    unwinder
+        .write_rule_table(0x0, &mut cache, &mut out)
+        .unwrap();
+    let mut lines = out.lines();
+    assert_eq!(
+        lines.next().map(|line| line.ends_with("vdso.so")),
+        Some(true)
+    );
+    assert_eq!(lines.next(), Some("# start\tend\trule"));
+
+    // The rows cover .text (0x300..0x5c8) without gaps or overlaps.
+    let rows: Vec<(u64, u64, &str)> = lines
+        .map(|line| {
+            let mut columns = line.splitn(3, '\t');
+            let mut address = || {
+                let column = columns.next().unwrap();
+                u64::from_str_radix(column.trim_start_matches("0x"), 16).unwrap()
+            };
+            (address(), address(), columns.next().unwrap())
+        })
+        .collect();
+    assert!(rows.len() > 2);
+    assert_eq!(rows.first().map(|row| row.0), Some(0x300));
+    assert_eq!(rows.last().map(|row| row.1), Some(0x5c8));
+    assert!(rows.windows(2).all(|pair| pair[0].1 == pair[1].0));
+    assert_eq!(
+        rows[0].2,
+        "if first frame: sp' = sp; fp' = fp; lr' = lr; otherwise: end of stack"
+    );
+
+    // After the prologue at 0x420..0x434, fp and lr are saved on the stack.
+    let (_, _, rule) = rows
+        .iter()
+        .find(|(start, end, _)| (*start..*end).contains(&0x434))
+        .unwrap();
+    assert_eq!(*rule, "sp' = sp + 0x10; fp' = *sp; lr' = *(sp + 0x8)");
+}
+
+#[test]
+fn test_exception_handling_info() {
+    let mut cache = CacheX86_64::<_>::new();
+    let mut unwinder = UnwinderX86_64::new();
+    common::add_object(
+        &mut unwinder,
+        &Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/linux/x86_64/nofp/dump_syms-regular"),
+        0x1000000,
+    );
+
+    // The FDE for 0xc3780..0xc38be uses a CIE with the "zPLR" augmentation. The
+    // personality is reached through the GOT slot at 0x758008.
+    let info = unwinder
+        .exception_handling_info(
+            FrameAddress::from_instruction_pointer(0x1000000 + 0xc37a0),
+            &mut cache,
+        )
+        .unwrap();
+    assert_eq!(
+        info,
+        Some(ExceptionHandlingInfo {
+            function_start: 0x1000000 + 0xc3780,
+            is_function_entry: true,
+            personality: Some(EhPointer::Indirect(0x1000000 + 0x758008)),
+            lsda: Some(EhPointer::Direct(0x1000000 + 0x6af594)),
+        })
+    );
+
+    // The FDE for 0xc25a0..0xc25c6 has no personality.
+    let info = unwinder
+        .exception_handling_info(
+            FrameAddress::from_instruction_pointer(0x1000000 + 0xc25b0),
+            &mut cache,
+        )
+        .unwrap();
+    assert_eq!(
+        info,
+        Some(ExceptionHandlingInfo {
+            function_start: 0x1000000 + 0xc25a0,
+            is_function_entry: true,
+            personality: None,
+            lsda: None,
+        })
+    );
+
+    // Addresses outside of all modules have no information.
+    let info = unwinder
+        .exception_handling_info(FrameAddress::from_instruction_pointer(0x10), &mut cache)
+        .unwrap();
+    assert_eq!(info, None);
+}
+
+#[cfg(feature = "from-file")]
+#[test]
+fn test_module_from_file() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/linux/x86_64/nofp/libc.so.6");
+    let mut unwinder = UnwinderX86_64::<framehop::MmapSection>::new();
+    unwinder.add_module(framehop::Module::from_file(&path, 0x7f0000000000).unwrap());
+    let mut cache = CacheX86_64::new();
+    assert!(unwinder.is_known_code_address(0x7f000012973d));
+
+    // Same as test_libc_syscall_no_fde, with the module at a different address.
+    let mut read_stack = |addr| {
+        if addr < 0x330 {
+            return Err(());
+        }
+        if addr == 0x330 {
+            return Ok(0x123456);
+        };
+        Ok(addr - 0x330)
+    };
+    let mut regs = UnwindRegsX86_64::new(0x7f000012973d, 0x330, 0x348);
+    let res = unwinder.unwind_frame(
+        FrameAddress::from_instruction_pointer(0x7f000012973d),
+        &mut regs,
+        &mut cache,
+        &mut read_stack,
+    );
+    assert_eq!(res, Ok(Some(0x123456)));
+    assert_eq!(regs.sp(), 0x338);
+
+    assert!(matches!(
+        framehop::Module::from_file(path.with_file_name("missing"), 0),
+        Err(framehop::ModuleFromFileError::Io(_))
+    ));
+}
@@ -92,6 +92,7 @@ fn test_root_doc_comment() {
             eh_frame: Some(0x100237f80..0x100237ffc),
             eh_frame_hdr: None,
             got: Some(0x100238000..0x100238010),
+            address_size: 8,
         },
         ModuleUnwindData::CompactUnwindInfoAndEhFrame(vec![/* __unwind_info */], None),
         Some(TextByteData::new(
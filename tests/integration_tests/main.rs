@@ -1,3 +1,21 @@
 mod common;
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+mod libunwind_diff;
 mod linux;
 mod macos;
+#[cfg(feature = "minidump")]
+mod minidump;
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+mod process_modules;
+#[cfg(all(
+    feature = "signal-sampling",
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+mod signal_sampling;
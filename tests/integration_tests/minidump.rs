@@ -0,0 +1,62 @@
+use std::path::Path;
+
+use ::minidump::{
+    Minidump, MinidumpMemoryList, MinidumpModuleList, MinidumpSystemInfo, MinidumpThreadList,
+};
+
+use framehop::minidump::*;
+use framehop::x86_64::*;
+use framehop::{Module, ModuleSvmaInfo, ModuleUnwindData, Unwinder};
+
+#[test]
+fn test_linux_x86_64() {
+    // A synthetic dump with one thread, which was generated by
+    // fixtures/minidump/generate.py.
+    let dump = Minidump::read_path(
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/minidump/linux-x86_64.dmp"),
+    )
+    .unwrap();
+    let module_list: MinidumpModuleList = dump.get_stream().unwrap();
+    let memory_list: MinidumpMemoryList = dump.get_stream().unwrap();
+    let system_info: MinidumpSystemInfo = dump.get_stream().unwrap();
+    let thread_list: MinidumpThreadList = dump.get_stream().unwrap();
+
+    let module_infos = module_infos(&module_list);
+    assert_eq!(module_infos.len(), 1);
+    assert_eq!(module_infos[0].name, "/usr/lib/libexample.so");
+    assert_eq!(module_infos[0].avma_range, 0x100000..0x101000);
+
+    let context = thread_list.threads[0].context(&system_info, None).unwrap();
+    let (pc, regs) = match thread_regs_from_context(&context) {
+        Some(MinidumpThreadRegs::X86_64(pc, regs)) => (pc, regs),
+        other => panic!("Unexpected thread regs {:?}", other),
+    };
+    assert_eq!(pc, 0x100400);
+    assert_eq!(regs.sp(), 0x7ff000);
+    assert_eq!(regs.bp(), 0x7ff010);
+
+    let reader = MinidumpStackReader::new(&memory_list);
+    assert_eq!(reader.read_u64(0x7ff010), Some(0x7ff040));
+    assert_eq!(reader.read_u64(0x7ff100), None);
+
+    // Minidumps don't contain unwind info, and the module binary isn't available, so
+    // the walk follows the frame pointer chain on the captured stack.
+    let mut unwinder = UnwinderX86_64::<Vec<u8>>::new();
+    let info = &module_infos[0];
+    unwinder.add_module(Module::new(
+        info.name.clone(),
+        info.avma_range.clone(),
+        info.avma_range.start,
+        ModuleSvmaInfo::default(),
+        ModuleUnwindData::None,
+        None,
+    ));
+    let mut cache = CacheX86_64::new();
+    let mut read_stack = |addr| reader.read_u64(addr).ok_or(());
+    let mut iter = unwinder.iter_frames(pc, regs, &mut cache, &mut read_stack);
+    let mut frames = Vec::new();
+    while let Some(frame) = iter.next().unwrap() {
+        frames.push(frame.address());
+    }
+    assert_eq!(frames, vec![0x100400, 0x100300, 0x100200]);
+}
@@ -0,0 +1,115 @@
+//! Framehop modules for the images which are loaded in the current process, for the
+//! tests which unwind the test process itself.
+
+use std::ffi::{c_int, c_void, CStr};
+use std::ops::Range;
+
+use framehop::{Module, ModuleSvmaInfo, ModuleUnwindData};
+
+const DW_EH_PE_ABSPTR: u8 = 0x00;
+const DW_EH_PE_PCREL_SDATA4: u8 = 0x1b;
+
+/// The address range of the current thread's stack.
+pub fn current_thread_stack() -> Range<u64> {
+    unsafe {
+        let mut attr = std::mem::zeroed();
+        assert_eq!(libc::pthread_getattr_np(libc::pthread_self(), &mut attr), 0);
+        let mut stack_addr = std::ptr::null_mut();
+        let mut stack_size = 0;
+        assert_eq!(
+            libc::pthread_attr_getstack(&attr, &mut stack_addr, &mut stack_size),
+            0
+        );
+        libc::pthread_attr_destroy(&mut attr);
+        let start = stack_addr as u64;
+        start..start + stack_size as u64
+    }
+}
+
+/// Creates framehop modules for all images in the current process which have an
+/// `.eh_frame_hdr`, with the unwind sections copied from memory.
+pub fn loaded_modules() -> Vec<Module<Vec<u8>>> {
+    unsafe extern "C" fn callback(
+        info: *mut libc::dl_phdr_info,
+        _size: usize,
+        data: *mut c_void,
+    ) -> c_int {
+        let modules = &mut *(data as *mut Vec<Module<Vec<u8>>>);
+        if let Some(module) = module_for_image(&*info) {
+            modules.push(module);
+        }
+        0
+    }
+    let mut modules = Vec::new();
+    unsafe {
+        libc::dl_iterate_phdr(
+            Some(callback),
+            &mut modules as *mut Vec<Module<Vec<u8>>> as *mut c_void,
+        )
+    };
+    modules
+}
+
+unsafe fn module_for_image(info: &libc::dl_phdr_info) -> Option<Module<Vec<u8>>> {
+    let bias = info.dlpi_addr;
+    let phdrs = std::slice::from_raw_parts(info.dlpi_phdr, info.dlpi_phnum as usize);
+    let loads: Vec<Range<u64>> = phdrs
+        .iter()
+        .filter(|phdr| phdr.p_type == libc::PT_LOAD)
+        .map(|phdr| phdr.p_vaddr..phdr.p_vaddr + phdr.p_memsz)
+        .collect();
+    let text = phdrs
+        .iter()
+        .find(|phdr| phdr.p_type == libc::PT_LOAD && phdr.p_flags & libc::PF_X != 0)
+        .map(|phdr| phdr.p_vaddr..phdr.p_vaddr + phdr.p_memsz)?;
+    let eh_frame_hdr = phdrs
+        .iter()
+        .find(|phdr| phdr.p_type == libc::PT_GNU_EH_FRAME)
+        .map(|phdr| phdr.p_vaddr..phdr.p_vaddr + phdr.p_memsz)?;
+    let eh_frame_hdr_data = read_memory(bias, &eh_frame_hdr);
+
+    // The header stores the address of .eh_frame but not its size. Assume that
+    // .eh_frame extends to the end of its segment; framehop only reads the FDEs that
+    // the header's table points to.
+    let eh_frame_ptr_address = bias + eh_frame_hdr.start + 4;
+    let eh_frame_start = match *eh_frame_hdr_data.get(1)? {
+        DW_EH_PE_PCREL_SDATA4 => {
+            let offset = i32::from_ne_bytes(eh_frame_hdr_data.get(4..8)?.try_into().ok()?);
+            eh_frame_ptr_address.wrapping_add(offset as i64 as u64) - bias
+        }
+        DW_EH_PE_ABSPTR => {
+            u64::from_ne_bytes(eh_frame_hdr_data.get(4..12)?.try_into().ok()?) - bias
+        }
+        _ => return None,
+    };
+    let eh_frame_segment = loads.iter().find(|load| load.contains(&eh_frame_start))?;
+    let eh_frame = eh_frame_start..eh_frame_segment.end;
+    let eh_frame_data = read_memory(bias, &eh_frame);
+
+    let start = loads.iter().map(|load| load.start).min()?;
+    let end = loads.iter().map(|load| load.end).max()?;
+    let name = CStr::from_ptr(info.dlpi_name).to_string_lossy().to_string();
+    Some(Module::new(
+        name,
+        bias + start..bias + end,
+        bias,
+        ModuleSvmaInfo {
+            base_svma: 0,
+            text: Some(text),
+            text_env: None,
+            stubs: None,
+            stub_helper: None,
+            eh_frame: Some(eh_frame),
+            eh_frame_hdr: Some(eh_frame_hdr),
+            got: None,
+            address_size: 8,
+        },
+        ModuleUnwindData::EhFrameHdrAndEhFrame(eh_frame_hdr_data, eh_frame_data),
+        None,
+    ))
+}
+
+unsafe fn read_memory(bias: u64, svma_range: &Range<u64>) -> Vec<u8> {
+    let start = (bias + svma_range.start) as *const u8;
+    std::slice::from_raw_parts(start, (svma_range.end - svma_range.start) as usize).to_vec()
+}
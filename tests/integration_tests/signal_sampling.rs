@@ -0,0 +1,118 @@
+//! End-to-end test of the `SIGPROF` sampler: sample a busy thread of the test process
+//! and unwind the sample.
+//!
+//! Run with `cargo test --features signal-sampling`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::time::Duration;
+
+use framehop::signal_sampling::{current_thread_id, SignalSampler};
+use framehop::{CacheNative, MayAllocateDuringUnwind, Unwinder, UnwinderNative};
+
+use super::process_modules::loaded_modules;
+
+static STOP: AtomicBool = AtomicBool::new(false);
+
+/// Only one sampler can exist at a time, so the tests take turns.
+static SAMPLER_LOCK: Mutex<()> = Mutex::new(());
+
+fn lock_sampler() -> std::sync::MutexGuard<'static, ()> {
+    SAMPLER_LOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn set_sigprof_blocked(blocked: bool) {
+    let how = if blocked {
+        libc::SIG_BLOCK
+    } else {
+        libc::SIG_UNBLOCK
+    };
+    // Safety: The signal set is initialized by sigemptyset before it is used.
+    unsafe {
+        let mut set: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, libc::SIGPROF);
+        assert_eq!(libc::pthread_sigmask(how, &set, std::ptr::null_mut()), 0);
+    }
+}
+
+#[inline(never)]
+fn spin_at_depth(depth: u32, ready: &mpsc::Sender<libc::pid_t>) {
+    if depth > 0 {
+        spin_at_depth(std::hint::black_box(depth - 1), ready);
+        std::hint::black_box(depth);
+        return;
+    }
+    ready.send(current_thread_id()).unwrap();
+    while !STOP.load(Ordering::Relaxed) {
+        std::hint::spin_loop();
+    }
+}
+
+#[test]
+fn test_sample_busy_thread() {
+    let _lock = lock_sampler();
+    let (ready_sender, ready_receiver) = mpsc::channel();
+    let thread = std::thread::spawn(move || spin_at_depth(20, &ready_sender));
+    let tid = ready_receiver.recv().unwrap();
+
+    let mut unwinder = UnwinderNative::<Vec<u8>, MayAllocateDuringUnwind>::new();
+    for module in loaded_modules() {
+        unwinder.add_module(module);
+    }
+    let mut cache = CacheNative::new();
+    let sampler = SignalSampler::new(256 * 1024).unwrap();
+    assert!(SignalSampler::new(0).is_err());
+
+    // Sample a few times, so that some samples hit the signal-safety-sensitive spots
+    // in the loop, and check that every sample unwinds through all 20 frames.
+    for _ in 0..10 {
+        let sample = sampler.sample_thread(tid, Duration::from_secs(5)).unwrap();
+        assert!(unwinder.is_known_code_address(sample.pc));
+        let mut read_stack = |address| sample.stack.read_u64(address).ok_or(());
+        let mut iter = unwinder.iter_frames(sample.pc, sample.regs, &mut cache, &mut read_stack);
+        let mut frame_count = 0;
+        while let Ok(Some(_)) = iter.next() {
+            frame_count += 1;
+        }
+        assert!(frame_count > 20, "only {frame_count} frames");
+    }
+
+    // The current thread can sample itself.
+    let sample = sampler
+        .sample_thread(current_thread_id(), Duration::from_secs(5))
+        .unwrap();
+    assert!(unwinder.is_known_code_address(sample.pc));
+
+    STOP.store(true, Ordering::Relaxed);
+    thread.join().unwrap();
+}
+
+#[test]
+fn test_drop_with_pending_signal() {
+    let _lock = lock_sampler();
+    let (ready_sender, ready_receiver) = mpsc::channel();
+    let (unblock_sender, unblock_receiver) = mpsc::channel();
+    let thread = std::thread::spawn(move || {
+        set_sigprof_blocked(true);
+        ready_sender.send(current_thread_id()).unwrap();
+        unblock_receiver.recv().unwrap();
+        // The signal of the timed-out request is delivered now.
+        set_sigprof_blocked(false);
+    });
+    let tid = ready_receiver.recv().unwrap();
+
+    let sampler = SignalSampler::new(4096).unwrap();
+    let err = sampler
+        .sample_thread(tid, Duration::from_millis(10))
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+    drop(sampler);
+
+    // With the default action, the pending SIGPROF would terminate the test process.
+    unblock_sender.send(()).unwrap();
+    thread.join().unwrap();
+}